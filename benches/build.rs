@@ -0,0 +1,120 @@
+//! Construction of FSTs: insertion in the builder, placement in the Dart,
+//! and both, against the fst crate.
+
+extern crate atlatl;
+#[macro_use] extern crate criterion;
+extern crate fst;
+#[macro_use] extern crate lazy_static;
+extern crate rand;
+
+mod corpus;
+
+use criterion::{black_box, BatchSize, Criterion};
+
+use atlatl::fst::*;
+use corpus::*;
+
+
+type Pairs = [(Vec<u8>, u32)];
+
+type Build = fn(&Pairs) -> Result<Builder<usize, u32>, Error>;
+
+fn checked(pairs : &Pairs) -> Result<Builder<usize, u32>, Error> {
+    Builder::from_iter(pairs.iter().map(|&(ref k, v)| (k, v)))
+}
+
+fn unchecked(pairs : &Pairs) -> Result<Builder<usize, u32>, Error> {
+    Builder::from_sorted_iter_unchecked(pairs.iter().map(|&(ref k, v)| (k, v)))
+}
+
+fn unminimized(pairs : &Pairs) -> Result<Builder<usize, u32>, Error> {
+    let mut builder = Builder::new().minimize(false);
+    builder.extend(pairs.iter().map(|&(ref k, v)| (k, v))) ?;
+    builder.finish() ?;
+    Ok(builder)
+}
+
+fn inserted_all(pairs : &Pairs) -> Result<Builder<usize, u32>, Error> {
+    let mut builder = Builder::new();
+    builder.insert_all(pairs) ?;
+    builder.finish() ?;
+    Ok(builder)
+}
+
+fn insertion(c : &mut Criterion) {
+    let builds : [(&str, &Pairs, Build); 11] = [
+        ("medium_checked", &medium, checked),
+        ("medium_unchecked", &medium, unchecked),
+        ("large_checked", &large, checked),
+        ("large_unchecked", &large, unchecked),
+        ("long_keys", &long, checked),
+        ("medium_unminimized", &medium, unminimized),
+        ("large_unminimized", &large, unminimized),
+        ("long_keys_unminimized", &long, unminimized),
+        ("paths_extend", &paths, checked),
+        ("paths_insert_all", &paths, inserted_all),
+        ("wide", &wide, checked)
+    ];
+
+    let mut group = c.benchmark_group("build");
+    for &(name, source, build) in &builds {
+        group.bench_function(name, |b| b.iter(|| black_box(build(source).unwrap())));
+    }
+    group.finish();
+}
+
+fn placement(c : &mut Criterion) {
+    let mut group = c.benchmark_group("place");
+    for &(name, source) in &[("medium", &*medium), ("large", &*large), ("paths", &*paths)] {
+        let builder = checked(source).unwrap();
+        group.bench_function(name, |b| b.iter(|| black_box(FST::from_builder(&builder).unwrap())));
+        group.bench_function(format!("{}_owned", name), |b| {
+            b.iter_batched(|| builder.clone(), |builder| black_box(builder.into_fst().unwrap()), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+/// From sorted pairs to a queryable FST, against the fst crate.
+fn end_to_end(c : &mut Criterion) {
+    let mut group = c.benchmark_group("from_iter");
+    for &(name, source) in &[("medium", &*medium), ("large", &*large), ("paths", &*paths)] {
+        let pairs = || source.iter().map(|&(ref k, v)| (k.as_slice(), v));
+        group.bench_function(format!("{}_fst", name), |b| {
+            b.iter(|| black_box(FST::<usize, u32>::from_iter(pairs()).unwrap()))
+        });
+        group.bench_function(format!("{}_rawfst", name), |b| b.iter(|| {
+            use fst::raw::{Builder, Output};
+
+            let mut builder = Builder::memory();
+            builder.extend_iter(pairs().map(|(k, v)| (k, Output::new(v as u64)))).unwrap();
+            black_box(builder.into_inner().unwrap())
+        }));
+    }
+    group.finish();
+}
+
+/// Many small FSTs, each of 100 keys, built anew or over a common context.
+fn small_builds(c : &mut Criterion) {
+    let sessions : Vec<&Pairs> = medium.chunks(100).cycle().take(10_000).collect();
+    let pairs = |s : &'static Pairs| s.iter().map(|&(ref k, v)| (k, v));
+    let mut group = c.benchmark_group("small_builds");
+    group.sample_size(10);
+
+    group.bench_function("fresh", |b| b.iter(|| for &session in &sessions {
+        black_box(FST::<usize, u32>::from_iter(pairs(session)).unwrap());
+    }));
+    group.bench_function("context", |b| {
+        let mut context = BuildContext::<usize, u32>::new();
+        b.iter(|| for &session in &sessions {
+            let mut builder = Builder::with_context(&mut context);
+            builder.extend(pairs(session)).unwrap();
+            builder.finish().unwrap();
+            black_box(builder.into_fst_in(&mut context).unwrap());
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, insertion, placement, end_to_end, small_builds);
+criterion_main!(benches);
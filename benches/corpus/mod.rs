@@ -0,0 +1,90 @@
+//! Corpora shared by the benchmarks, generated from a fixed seed so that
+//! runs compare alike.
+#![allow(dead_code, non_upper_case_globals)]
+
+use rand::distributions::{Distribution, Standard, Uniform};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+
+/// The seed of every corpus.
+const SEED : u64 = 0x61_74_6C_61_74_6C;
+
+lazy_static! {
+    pub static ref small : Vec<(Vec<u8>, u32)> = pairs(1, 1000, (0, 16));
+    pub static ref sample_s_s : Vec<&'static [u8]> = key_sample(&small, 4, 16);
+    pub static ref sample_s_m : Vec<&'static [u8]> = key_sample(&small, 8, 16);
+    pub static ref sample_s_l : Vec<&'static [u8]> = key_sample(&small, 16, 16);
+
+    pub static ref medium : Vec<(Vec<u8>, u32)> = pairs(2, 10000, (0, 16));
+    pub static ref sample_m_s : Vec<&'static [u8]> = key_sample(&medium, 4, 16);
+    pub static ref sample_m_m : Vec<&'static [u8]> = key_sample(&medium, 8, 16);
+    pub static ref sample_m_l : Vec<&'static [u8]> = key_sample(&medium, 16, 16);
+
+    pub static ref large : Vec<(Vec<u8>, u32)> = pairs(3, 50000, (0, 16));
+    pub static ref sample_l_s : Vec<&'static [u8]> = key_sample(&large, 4, 16);
+    pub static ref sample_l_m : Vec<&'static [u8]> = key_sample(&large, 8, 16);
+    pub static ref sample_l_l : Vec<&'static [u8]> = key_sample(&large, 16, 16);
+
+    pub static ref long : Vec<(Vec<u8>, u32)> = pairs(4, 10000, (64, 256));
+    /// Keys of 16 random bytes, whose first bytes fan out across the alphabet.
+    pub static ref wide : Vec<(Vec<u8>, u32)> = pairs(5, 200000, (16, 17));
+
+    pub static ref paths : Vec<(Vec<u8>, u32)> = path_pairs(50000);
+    pub static ref nested : Vec<(Vec<u8>, u32)> = nested_pairs(50000);
+    pub static ref sample_n : Vec<&'static [u8]> = key_sample(&nested, 16, 16);
+}
+
+
+/// The generator of the corpus numbered `n`.
+fn rng(n : u64) -> StdRng { StdRng::seed_from_u64(SEED ^ n) }
+
+/// Random keys of lengths within `l .. r`, sorted, with random values.
+fn pairs<U>(corpus : u64, n : usize, (l, r) : (usize, usize)) -> Vec<(Vec<u8>, U)>
+    where Standard : Distribution<U>, U : Ord
+{
+    let mut rng = rng(corpus);
+    let key_length = Uniform::from(l .. r);
+    let mut v : Vec<(Vec<u8>, U)> = (0 .. n)
+        .map(|_| {
+            let k_l = key_length.sample(&mut rng);
+            let key = (&mut rng).sample_iter::<u8, _>(Standard).take(k_l).collect();
+            (key, rng.gen())
+        })
+        .collect();
+    v.sort();
+    v.dedup_by(|a, b| a.0 == b.0);
+    v
+}
+
+/// Sorted file paths, sharing long prefixes.
+fn path_pairs(n : usize) -> Vec<(Vec<u8>, u32)> {
+    let dirs = ["src", "tests", "benches", "target/debug/deps", "target/release/build"];
+    let mut v : Vec<(Vec<u8>, u32)> = (0 .. n)
+        .map(|i| {
+            let path = format!("/home/user/projects/{}/{}/{:x}/module_{}.rs",
+                               i % 7, dirs[i % dirs.len()], i / 97, i);
+            (path.into_bytes(), i as u32)
+        })
+        .collect();
+    v.sort();
+    v
+}
+
+/// Sorted decimal numerals, most of which are prefixes of others, so that
+/// lookups end in states with inner outputs.
+fn nested_pairs(n : usize) -> Vec<(Vec<u8>, u32)> {
+    let mut v : Vec<(Vec<u8>, u32)> = (0 .. n)
+        .map(|i| (i.to_string().into_bytes(), (i as u32).wrapping_mul(2_654_435_761)))
+        .collect();
+    v.sort();
+    v
+}
+
+/// Up to `amount` keys of the corpus no longer than `max_len`.
+fn key_sample<T>(kvs : &'static [(Vec<u8>, T)], max_len : usize, amount : usize) -> Vec<&'static [u8]> {
+    let keys = kvs.iter()
+        .map(|(k, _)| k.as_slice())
+        .filter(|k| k.len() <= max_len);
+    keys.choose_multiple(&mut rng(max_len as u64 ^ kvs.len() as u64), amount)
+}
@@ -0,0 +1,163 @@
+//! Lookups in FSTs, against hash maps, B-trees, and the fst crate.
+
+extern crate atlatl;
+#[macro_use] extern crate criterion;
+extern crate fnv;
+extern crate fst;
+#[macro_use] extern crate lazy_static;
+extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+mod corpus;
+
+use criterion::{black_box, Criterion};
+use fnv::FnvHashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::iter::FromIterator;
+
+use atlatl::fst::*;
+use corpus::*;
+
+
+/// Look up every key of the sample in each collection built from the source.
+fn lookups(c : &mut Criterion, name : &str, source : &[(Vec<u8>, u32)], sample : &[&[u8]]) {
+    let pairs = || source.iter().map(|&(ref k, v)| (k.as_slice(), v));
+    let mut group = c.benchmark_group(name);
+
+    let map : HashMap<_, _> = HashMap::from_iter(pairs());
+    group.bench_function("hashmap", |b| b.iter(|| for &k in sample { black_box(map.get(k)); }));
+
+    let map : FnvHashMap<_, _> = FnvHashMap::from_iter(pairs());
+    group.bench_function("fnvhashmap", |b| b.iter(|| for &k in sample { black_box(map.get(k)); }));
+
+    let map : BTreeMap<_, _> = BTreeMap::from_iter(pairs());
+    group.bench_function("btree", |b| b.iter(|| for &k in sample { black_box(map.get(k)); }));
+
+    let fst : FST<usize, u32> = FST::from_iter(pairs()).unwrap();
+    group.bench_function("fst", |b| b.iter(|| for &k in sample { black_box(fst.get(k)); }));
+
+    let compact = fst.to_compact();
+    group.bench_function("compact", |b| b.iter(|| for &k in sample { black_box(compact.get(k)); }));
+
+    let frozen = fst.freeze();
+    group.bench_function("frozen", |b| b.iter(|| for &k in sample { black_box(frozen.get(k)); }));
+
+    let raw = {
+        use fst::raw::{Builder, Fst, Output};
+
+        let mut builder = Builder::memory();
+        builder.extend_iter(pairs().map(|(k, v)| (k, Output::new(v as u64)))).unwrap();
+        Fst::new(builder.into_inner().unwrap()).unwrap()
+    };
+    group.bench_function("rawfst", |b| b.iter(|| for &k in sample { black_box(raw.get(k)); }));
+
+    group.finish();
+}
+
+fn get(c : &mut Criterion) {
+    lookups(c, "get_small_short", &small, &sample_s_s);
+    lookups(c, "get_small_mid", &small, &sample_s_m);
+    lookups(c, "get_small_long", &small, &sample_s_l);
+    lookups(c, "get_medium_short", &medium, &sample_m_s);
+    lookups(c, "get_medium_mid", &medium, &sample_m_m);
+    lookups(c, "get_medium_long", &medium, &sample_m_l);
+    lookups(c, "get_large_short", &large, &sample_l_s);
+    lookups(c, "get_large_mid", &large, &sample_l_m);
+    lookups(c, "get_large_long", &large, &sample_l_l);
+    lookups(c, "get_nested", &nested, &sample_n);
+}
+
+/// Look up the sample in FSTs laid out in each order of placement.
+fn orders(c : &mut Criterion, name : &str, source : &[(Vec<u8>, u32)], sample : &[&[u8]]) {
+    let builder = Builder::from_iter(source.iter().map(|&(ref k, v)| (k, v))).unwrap();
+    let mut group = c.benchmark_group(name);
+
+    for (order, label) in [(Order::DepthFirst, "depth_first"), (Order::BreadthFirst, "breadth_first"), (Order::Registration, "registration")] {
+        let fst : FST<usize, u32> = FST::from_builder_with(&builder, BuildOptions::new().order(order)).unwrap();
+        group.bench_function(label, |b| b.iter(|| for &k in sample { black_box(fst.get(k)); }));
+    }
+
+    group.finish();
+}
+
+fn order(c : &mut Criterion) {
+    orders(c, "order_small_mid", &small, &sample_s_m);
+    orders(c, "order_small_long", &small, &sample_s_l);
+    orders(c, "order_medium_mid", &medium, &sample_m_m);
+    orders(c, "order_medium_long", &medium, &sample_m_l);
+}
+
+/// Check every key of the large corpus, then the same keys with their last
+/// byte changed, which are all but certain to miss only at the end.
+fn contains(c : &mut Criterion) {
+    let fst : FST<usize, u32> = FST::from_iter(large.iter().map(|&(ref k, v)| (k, v))).unwrap();
+    let hits : Vec<&[u8]> = large.iter().map(|(k, _)| k.as_slice()).collect();
+    let misses : Vec<Vec<u8>> = hits.iter()
+        .filter(|k| !k.is_empty())
+        .map(|k| { let mut k = k.to_vec(); *k.last_mut().unwrap() ^= 0x80; k })
+        .filter(|k| !fst.contains(k))
+        .collect();
+    let mut group = c.benchmark_group("contains_large");
+
+    group.bench_function("get_hit", |b| b.iter(|| for k in &hits { black_box(fst.get(k).is_some()); }));
+    group.bench_function("contains_hit", |b| b.iter(|| for k in &hits { black_box(fst.contains(k)); }));
+    group.bench_function("get_miss", |b| b.iter(|| for k in &misses { black_box(fst.get(k).is_some()); }));
+    group.bench_function("contains_miss", |b| b.iter(|| for k in &misses { black_box(fst.contains(k)); }));
+
+    group.finish();
+}
+
+/// Look up every key of the wide corpus in random order, one at a time and
+/// in pipelined batches, so that most lookups miss the cache.
+fn pipelined(c : &mut Criterion) {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use std::convert::TryInto;
+
+    let fst : FST<usize, u32> = FST::from_iter(wide.iter().map(|&(ref k, v)| (k, v))).unwrap();
+    let mut keys : Vec<&[u8]> = wide.iter().map(|(k, _)| k.as_slice()).collect();
+    keys.shuffle(&mut StdRng::seed_from_u64(619));
+    let mut group = c.benchmark_group("pipelined_wide");
+
+    group.bench_function("get", |b| b.iter(|| for &k in &keys { black_box(fst.get(k)); }));
+    group.bench_function("pipelined_4", |b| b.iter(|| for batch in keys.chunks_exact(4) {
+        black_box(fst.get_pipelined::<4>(batch.try_into().unwrap()));
+    }));
+    group.bench_function("pipelined_8", |b| b.iter(|| for batch in keys.chunks_exact(8) {
+        black_box(fst.get_pipelined::<8>(batch.try_into().unwrap()));
+    }));
+
+    group.finish();
+}
+
+/// Look up every key of the large corpus at once, sequentially and across
+/// pools of 1 to 8 threads.
+#[cfg(feature = "rayon")]
+fn par_get(c : &mut Criterion) {
+    use criterion::BenchmarkId;
+    use rayon::ThreadPoolBuilder;
+
+    let fst : FST<usize, u32> = FST::from_iter(large.iter().map(|&(ref k, v)| (k, v))).unwrap();
+    let keys : Vec<&[u8]> = large.iter().map(|(k, _)| k.as_slice()).collect();
+    let mut group = c.benchmark_group("par_get_large");
+
+    group.bench_function("sequential", |b| b.iter(|| {
+        black_box(keys.iter().map(|k| fst.get(k)).collect::<Vec<_>>())
+    }));
+    for threads in [1, 2, 4, 8] {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        group.bench_with_input(BenchmarkId::new("par_get_many", threads), &keys, |b, keys| {
+            b.iter(|| pool.install(|| black_box(fst.par_get_many(keys))))
+        });
+    }
+
+    group.finish();
+}
+
+#[cfg(not(feature = "rayon"))]
+fn par_get(_ : &mut Criterion) {}
+
+criterion_group!(benches, get, order, contains, pipelined, par_get);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+//! Lookups on arbitrary FSTs: every key is found with its value, and
+//! queries agree across `get`, `contains`, and `reap`.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate atlatl;
+
+use atlatl::fst::FST;
+use atlatl::fst::fuzz::Query;
+
+fuzz_target!(|input : (FST<u32, u16>, Vec<Query>)| {
+    let (fst, queries) = input;
+
+    for (key, value) in fst.iter() {
+        assert_eq!(fst.get(&key), Some(value));
+        assert!(fst.contains(&key));
+    }
+
+    for query in &queries {
+        let key = query.key(&fst);
+        let value = fst.get(&key);
+        assert_eq!(value.is_some(), fst.contains(&key));
+
+        // Prefixes are reaped shortest first, the last being the key itself
+        // should it be accepted.
+        let reaped : Vec<(usize, u16)> = fst.reap(&key).collect();
+        assert!(reaped.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(reaped.iter().all(|&(len, _)| len <= key.len()));
+        assert_eq!(reaped.last().filter(|&&(len, _)| len == key.len()).map(|&(_, v)| v), value);
+    }
+});
@@ -0,0 +1,99 @@
+//! Dictionaries in the double-array format of darts-clone.
+//!
+//! A darts-clone file is an array of 32-bit little-endian units, in blocks
+//! of 256, with no header. A unit holds either a transition, as its label,
+//! the offset of its destination's transitions, and whether a key ends
+//! there, or the value of a key, in the unit found at the offset itself.
+//! Keys may not contain NUL, which marks their end.
+
+use std::fs;
+use std::path::Path;
+
+use fst::FST;
+use fst::builder::Builder;
+use fst::error::{DecodeError, Error, Result};
+use index::Index;
+
+
+/// The number of units in a block.
+const BLOCK : usize = 256;
+
+#[derive(Copy, Clone, Debug)]
+struct Unit(u32);
+
+impl Unit {
+    #[inline] fn has_leaf(self) -> bool { (self.0 >> 8) & 1 == 1 }
+    #[inline] fn is_leaf(self) -> bool { self.0 >> 31 == 1 }
+    #[inline] fn value(self) -> u32 { self.0 & ((1 << 31) - 1) }
+    /// The label, which no byte matches in a leaf.
+    #[inline] fn label(self) -> u32 { self.0 & ((1 << 31) | 0xFF) }
+    /// The offset, relative to the unit's position by exclusive or.
+    #[inline] fn offset(self) -> usize { ((self.0 >> 10) << ((self.0 & (1 << 9)) >> 6)) as usize }
+}
+
+/// Read a darts-clone dictionary, enumerating its keys in lexicographic
+/// order into an FST of their values.
+///
+/// States may be shared, as darts-clone shares the identical subtrees of
+/// the DAWG it builds from.
+///
+/// Fails with `Decode` errors if the array does not end on a block, or if a
+/// transition leads outside it, around a cycle, or to a key without a value.
+pub fn from_bytes<I>(bytes : &[u8]) -> Result<FST<I, u32>> where I : Index {
+    let units = units(bytes) ?;
+    let invalid = |pos : usize| Error::Decode(DecodeError::Invalid { section : "darts", offset : pos * 4 });
+
+    let mut builder = Builder::new();
+    // The units from the root to the current one, a shared unit being
+    // revisited once the keys through it are done.
+    let mut path = Vec::new();
+    let mut on_path = vec![false; units.len()];
+    // Transitions are pushed in reverse, so that keys are popped in order.
+    let mut stack = vec![(0, Vec::new())];
+    while let Some((pos, key)) = stack.pop() {
+        for done in path.drain(key.len() ..) { on_path[done] = false }
+        if on_path[pos] { return Err(invalid(pos)) }
+        on_path[pos] = true;
+        path.push(pos);
+
+        let unit = units[pos];
+        let base = pos ^ unit.offset();
+        let leaf = if unit.has_leaf() { Some(units.get(base).ok_or_else(|| invalid(pos)) ?) } else { None };
+        for label in (1 ..= 255u8).rev() {
+            let child = base ^ label as usize;
+            if units.get(child).is_some_and(|u| u.label() == label as u32) {
+                let mut suffixed = key.clone();
+                suffixed.push(label);
+                stack.push((child, suffixed));
+            }
+        }
+        match leaf {
+            Some(leaf) if leaf.is_leaf() => builder.insert(&key, leaf.value()) ?,
+            Some(_) => return Err(invalid(base)),
+            None => ()
+        }
+    }
+    builder.finish() ?;
+
+    builder.into_fst()
+}
+
+/// Read a darts-clone dictionary from a file, as with `from_bytes`.
+pub fn from_path<I, P>(path : P) -> Result<FST<I, u32>>
+    where I : Index
+        , P : AsRef<Path>
+{
+    from_bytes(&fs::read(path).map_err(Error::from) ?)
+}
+
+fn units(bytes : &[u8]) -> Result<Vec<Unit>> {
+    if bytes.len() < 4 * BLOCK {
+        return Err(Error::Decode(DecodeError::Truncated { section : "darts", offset : bytes.len() }))
+    }
+    let whole = bytes.len() / (4 * BLOCK) * (4 * BLOCK);
+    if whole != bytes.len() {
+        return Err(Error::Decode(DecodeError::Trailing { offset : whole }))
+    }
+
+    Ok(bytes.chunks(4).map(|b| Unit(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))).collect())
+}
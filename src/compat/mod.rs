@@ -0,0 +1,3 @@
+//! Dictionaries in the formats of other libraries, read into FSTs.
+
+pub mod darts;
@@ -0,0 +1,199 @@
+//! Diagnostics on the structure of a built FST.
+
+use fnv::{FnvHashMap, FnvHashSet};
+use std::collections::hash_map::Entry;
+use std::iter;
+
+use fst::{FST, Output, Terminal};
+use fst::error::{ValidationError, Violation};
+use fst::store::DartStore;
+use index::Index;
+use label::Label;
+
+
+/// The number of pairs of equivalent states kept in a report.
+const MAX_EXAMPLES : usize = 16;
+
+/// The number of violations kept in a validation error.
+const MAX_VIOLATIONS : usize = 8;
+
+/// A state as seen from its right language: its final output, if final, and
+/// its transitions, leading to states identified by class.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+struct Shape<O, L> {
+    final_output : Option<O>,
+    arcs : Vec<(L, O, usize)>
+}
+
+/// The states of an FST which could be merged without changing its
+/// language or values.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MinimalityReport<I> {
+    /// The number of states reachable from the root.
+    pub states : usize,
+    /// The number of states equivalent to another, which a minimal FST
+    /// would lack.
+    pub redundant : usize,
+    /// Some pairs of equivalent states, by their base in the Dart.
+    pub examples : Vec<(I, I)>
+}
+
+impl<I> MinimalityReport<I> {
+    pub fn is_minimal(&self) -> bool { self.redundant == 0 }
+}
+
+/// Look for equivalent states: those with the same final output and the same
+/// transitions, by label and output, to equivalent states.
+///
+/// States are classified from the leaves up, in a single traversal.
+pub fn minimality_report<I, O, L>(fst : &FST<I, O, L>) -> MinimalityReport<I>
+    where I : Index, O : Output, L : Label
+{
+    let mut report = MinimalityReport::default();
+    if fst.is_empty() { return report }
+
+    let mut class_of : FnvHashMap<I, usize> = FnvHashMap::default();
+    let mut classes : FnvHashMap<Shape<O, L>, (usize, I)> = FnvHashMap::default();
+    // States are visited once their destinations are classified.
+    let mut stack = vec![(I::zero(), fst.da.stipe.terminal(0), false)];
+    while let Some((state, terminal, expanded)) = stack.pop() {
+        if class_of.contains_key(&state) { continue }
+        if !expanded {
+            stack.push((state, terminal, true));
+            for (_, e) in fst.arcs(state) {
+                stack.push((fst.da.next[e], fst.da.stipe.terminal(e), false));
+            }
+            continue
+        }
+
+        let shape = Shape {
+            final_output : match terminal {
+                Terminal::Not => None,
+                Terminal::Empty => Some(O::zero()),
+                Terminal::Inner => fst.state_output.get(state)
+            },
+            arcs : fst.arcs(state)
+                .map(|(label, e)| (label, fst.da.output(e), class_of[&fst.da.next[e]]))
+                .collect()
+        };
+        let fresh = classes.len();
+        let class = match classes.entry(shape) {
+            Entry::Occupied(e) => {
+                let &(class, first) = e.get();
+                report.redundant += 1;
+                if report.examples.len() < MAX_EXAMPLES { report.examples.push((first, state)) }
+                class
+            },
+            Entry::Vacant(e) => { e.insert((fresh, state)); fresh }
+        };
+        class_of.insert(state, class);
+        report.states += 1;
+    }
+
+    report
+}
+
+/// Check that the Dart and the state outputs are consistent: every
+/// destination lies within the Dart, every slot with a destination belongs to
+/// a state by its check label, no transition leads back to a state on its
+/// path from the root, and the state outputs are exactly those of the states
+/// reached by final transitions with inner output.
+///
+/// Vacant slots, whose destination is zero, are not checked.
+pub fn validate<I, O, L>(fst : &FST<I, O, L>) -> Result<(), ValidationError>
+    where I : Index, O : Output, L : Label
+{
+    let (da, len) = (&fst.da, fst.da.stipe.len());
+    let mut violations = Vec::new();
+    // Every state is the root or the destination of a transition.
+    let states : FnvHashSet<usize> = iter::once(0)
+        .chain(da.next.iter().map(|next| next.as_usize()).filter(|&next| next != 0))
+        .collect();
+    let mut inner = FnvHashSet::default();
+
+    if da.stipe.first().is_some_and(|root| root.terminal.is_inner()) {
+        inner.insert(0);
+        if !fst.state_output.contains_key(I::zero()) {
+            violations.push(Violation::MissingStateOutput { slot : 0, state : 0 });
+        }
+    }
+    for e in 1 .. len {
+        let next = da.next[e].as_usize();
+        if next == 0 { continue }
+        if next >= len { violations.push(Violation::NextOutOfRange { slot : e, next }) }
+        let check = da.stipe.check(e).as_usize();
+        if e < 1 + check || !states.contains(&(e - 1 - check)) {
+            violations.push(Violation::CheckOutOfPlace { slot : e, check });
+        }
+        if da.stipe.terminal(e).is_inner() {
+            inner.insert(next);
+            if !fst.state_output.contains_key(da.next[e]) {
+                violations.push(Violation::MissingStateOutput { slot : e, state : next });
+            }
+        }
+    }
+
+    if let Some(slot) = cycle(fst) { violations.push(Violation::Cycle { slot }) }
+
+    let mut orphans : Vec<usize> = fst.state_output.states()
+        .map(|state| state.as_usize())
+        .filter(|state| !inner.contains(state))
+        .collect();
+    orphans.sort_unstable();
+    violations.extend(orphans.into_iter().map(|state| Violation::OrphanStateOutput { state }));
+
+    match violations.len() {
+        0 => Ok(()),
+        total => {
+            violations.truncate(MAX_VIOLATIONS);
+            Err(ValidationError { violations, total })
+        }
+    }
+}
+
+/// The first slot whose destination lies outside the Dart, if any.
+pub(crate) fn stray_next<I, O, L, S>(store : &S) -> Option<usize>
+    where I : Index, O : Output, L : Label, S : DartStore<I, O, L>
+{
+    (0 .. store.slots()).find(|&e| store.next(e).as_usize() >= store.slots())
+}
+
+/// The slot of a transition leading back to a state on its path from the
+/// root, if any, whence traversals would never end.
+///
+/// States are walked depth first, each once, and marked while on the path.
+/// Destinations outside the Dart are not followed.
+pub(crate) fn cycle<I, O, L, S>(store : &S) -> Option<usize>
+    where I : Index, O : Output, L : Label, S : DartStore<I, O, L>
+{
+    if store.slots() == 0 { return None }
+
+    // Whether each state reached is still on the path.
+    let mut on_path : FnvHashMap<I, bool> = FnvHashMap::default();
+    on_path.insert(I::zero(), true);
+    // The states on the path, each with the least label not yet followed.
+    let mut path = vec![(I::zero(), 0)];
+    while let Some(top) = path.last_mut() {
+        let state = top.0;
+        match (top.1 .. L::ALPHABET).find_map(|label| store.arc(state, L::from_usize(label))) {
+            Some(e) => {
+                top.1 = e - state.as_usize();
+                let next = store.next(e);
+                if next.as_usize() >= store.slots() { continue }
+                match on_path.get(&next) {
+                    Some(&true) => return Some(e),
+                    Some(&false) => {},
+                    None => {
+                        on_path.insert(next, true);
+                        path.push((next, 0));
+                    }
+                }
+            },
+            None => {
+                on_path.insert(state, false);
+                path.pop();
+            }
+        }
+    }
+    None
+}
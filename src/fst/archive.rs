@@ -0,0 +1,130 @@
+//! FSTs archived with rkyv, and read in place from the archive.
+//!
+//! An FST is archived as its binary encoding, as written by `FST::to_bytes`,
+//! so that the archived FST shares its lookups with `FstRef`. Validating an
+//! archive checks the encoding, its checksums, and that traversals of the
+//! Dart end, as `FstRef::new` does.
+
+use rkyv::{Archive, Deserialize, Place, Portable, Serialize};
+use rkyv::bytecheck::CheckBytes;
+use rkyv::rancor::{Fallible, Source};
+use rkyv::ser::{Allocator, Writer};
+use rkyv::vec::{ArchivedVec, VecResolver};
+use std::fmt;
+use std::marker::PhantomData;
+
+use fst::{FST, FstRef, Output};
+use fst::format::{self, Codec};
+use index::Index;
+use label::Label;
+
+
+/// An archived FST, whose lookups read its encoding in place.
+#[repr(transparent)]
+pub struct ArchivedFST<I, O, L = u8> {
+    bytes : ArchivedVec<u8>,
+    types : PhantomData<(I, O, L)>
+}
+
+// Safety: the archived FST is an archived vector of bytes, which is portable.
+unsafe impl<I, O, L> Portable for ArchivedFST<I, O, L> {}
+
+impl<I, O, L> ArchivedFST<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    /// A view of the FST, for lookups and iteration.
+    pub fn view(&self) -> FstRef<'_, I, O, L> {
+        FstRef::new_unchecked(self.as_bytes()).expect("archived FST checked on access")
+    }
+
+    /// Get the value associated to the key, if any.
+    pub fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[L]>
+    {
+        self.view().get(key)
+    }
+
+    /// Returns whether the key is accepted by the FST.
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[L]>
+    {
+        self.view().contains(key)
+    }
+
+    /// The encoded FST, as archived.
+    pub fn as_bytes(&self) -> &[u8] { self.bytes.as_slice() }
+}
+
+impl<I, O, L> fmt::Debug for ArchivedFST<I, O, L> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArchivedFST").field("bytes", &self.bytes.len()).finish()
+    }
+}
+
+/// Checks the archived bytes, then their encoding, checksums, and that the
+/// traversals of their Dart end.
+unsafe impl<I, O, L, C> CheckBytes<C> for ArchivedFST<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+        , C : Fallible + ?Sized
+        , ArchivedVec<u8> : CheckBytes<C>
+        , C::Error : Source
+{
+    unsafe fn check_bytes(value : *const Self, context : &mut C) -> Result<(), C::Error> {
+        ArchivedVec::<u8>::check_bytes(value as *const ArchivedVec<u8>, context) ?;
+        let bytes = (*value).as_bytes();
+        let (header, sections) = format::split::<I, O, L>(bytes).map_err(C::Error::new) ?;
+        format::in_place(&header).map_err(C::Error::new) ?;
+        format::verify(&header, &sections).map_err(C::Error::new) ?;
+        format::traversable(&header, &FstRef::<I, O, L>::from_sections(header, sections)).map_err(C::Error::new)
+    }
+}
+
+
+/// The position of the archived encoding, and its length.
+pub struct FstResolver {
+    bytes : VecResolver,
+    len : usize
+}
+
+impl<I, O, L> Archive for FST<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    type Archived = ArchivedFST<I, O, L>;
+    type Resolver = FstResolver;
+
+    fn resolve(&self, resolver : FstResolver, out : Place<ArchivedFST<I, O, L>>) {
+        // Safety: the archived FST is a transparent wrapper of its bytes.
+        let bytes = unsafe { out.cast_unchecked::<ArchivedVec<u8>>() };
+        ArchivedVec::resolve_from_len(resolver.len, resolver.bytes, bytes);
+    }
+}
+
+impl<I, O, L, S> Serialize<S> for FST<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+        , S : Fallible + Allocator + Writer + ?Sized
+{
+    fn serialize(&self, serializer : &mut S) -> Result<FstResolver, S::Error> {
+        let bytes = self.to_bytes();
+        Ok(FstResolver { bytes : ArchivedVec::serialize_from_slice(&bytes, serializer) ?, len : bytes.len() })
+    }
+}
+
+impl<I, O, L, D> Deserialize<FST<I, O, L>, D> for ArchivedFST<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+        , D : Fallible + ?Sized
+        , D::Error : Source
+{
+    fn deserialize(&self, _ : &mut D) -> Result<FST<I, O, L>, D::Error> {
+        FST::from_bytes(self.as_bytes()).map_err(D::Error::new)
+    }
+}
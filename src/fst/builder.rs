@@ -1,58 +1,85 @@
-use fnv::FnvHashMap;
 use std::cmp;
-use std::collections::hash_map::Entry;
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::fmt;
+use std::mem;
+use std::result;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "serialization")]
+use std::borrow::Cow;
+#[cfg(feature = "serialization")]
+use std::io;
 
-use fst::error::{Error, Result};
+#[cfg(feature = "serialization")]
+use bincode;
+#[cfg(feature = "serialization")]
+use serde::{Serialize, de::DeserializeOwned};
+use smallvec::SmallVec;
+
+use fst::FST;
+use fst::error::{BuildOrSourceError, Error, Result};
+use fst::intermediate::Intermediary;
+use fst::memory::{self, MemoryUsage};
 use fst::output::Output;
+use fst::registry::{self, Arena, Registry};
 use index::Index;
+use label::Label;
+
 
+/// The number of labels of an overlong key kept in its error.
+const KEY_PREVIEW : usize = 32;
 
-pub type Label = u8;
+/// The transitions of a builder state. Most states have few, held inline.
+pub type Transitions<I, O, L> = SmallVec<[Transition<I, O, L>; 4]>;
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
-pub struct Transition<I, O> {
-    pub label : Label,
+pub struct Transition<I, O, L = u8> {
+    pub label : L,
     pub output : O,
     pub destination : I,
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
-pub struct State<I, O> {
+pub struct State<I, O, L = u8> {
     pub terminal : bool,
     pub final_output : O,
-    pub transitions : Vec<Transition<I, O>>
+    pub transitions : Transitions<I, O, L>
 }
 
 
 /// A transition without a fixed destination state.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
-struct DanglingArc<O> {
-    label : Label,
+struct DanglingArc<O, L> {
+    label : L,
     output : O
 }
 
-impl<O> DanglingArc<O> where O : Output {
-    fn from_label(label : Label) -> DanglingArc<O> {
+impl<O, L> DanglingArc<O, L> where O : Output, L : Label {
+    fn from_label(label : L) -> DanglingArc<O, L> {
         DanglingArc { label, ..DanglingArc::default() }
     }
 }
 
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
-struct DanglingState<I, O> {
-    pub state : State<I, O>,
-    pub last_arc : Option<DanglingArc<O>>
+struct DanglingState<I, O, L> {
+    pub state : State<I, O, L>,
+    pub last_arc : Option<DanglingArc<O, L>>
 }
 
-impl<I, O> DanglingState<I, O> where I : Index, O : Output {
-    fn from_label(label : Label) -> DanglingState<I, O> {
+impl<I, O, L> DanglingState<I, O, L> where I : Index, O : Output, L : Label {
+    fn from_label(label : L) -> DanglingState<I, O, L> {
         DanglingState {
             last_arc : Some(DanglingArc::from_label(label)),
             state : State::default()
         }
     }
 
-    fn empty_terminal() -> DanglingState<I, O> {
+    fn empty_terminal() -> DanglingState<I, O, L> {
         DanglingState {
             state : State { terminal : true, ..State::default() },
             last_arc : None
@@ -65,21 +92,30 @@ impl<I, O> DanglingState<I, O> where I : Index, O : Output {
         }
     }
 
-    fn redistribute_output(&mut self, diff : O) {
+    fn redistribute_output(&mut self, diff : O) -> Option<()> {
         if diff != O::zero() {
-            if self.state.terminal { self.state.final_output.mappend_assign(diff) }
-            if let Some(ref mut t) = self.last_arc { t.output.mappend_assign(diff) }
-            for t in &mut self.state.transitions { t.output.mappend_assign(diff) }
+            // The difference precedes the outputs it is pushed onto.
+            if self.state.terminal {
+                self.state.final_output = diff.checked_mappend(self.state.final_output) ?;
+            }
+            if let Some(ref mut t) = self.last_arc { t.output = diff.checked_mappend(t.output) ? }
+            for t in &mut self.state.transitions { t.output = diff.checked_mappend(t.output) ? }
         }
+        Some(())
     }
 }
 
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
-struct DanglingPath<I, O> { stack : Vec<DanglingState<I, O>> }
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct DanglingPath<I, O, L> { stack : Vec<DanglingState<I, O, L>> }
 
-impl<I, O> DanglingPath<I, O> where I : Index, O : Output {
-    fn new() -> DanglingPath<I, O> {
+impl<I, O, L> Default for DanglingPath<I, O, L> where I : Index, O : Output, L : Label {
+    fn default() -> DanglingPath<I, O, L> { DanglingPath::new() }
+}
+
+impl<I, O, L> DanglingPath<I, O, L> where I : Index, O : Output, L : Label {
+    fn new() -> DanglingPath<I, O, L> {
         let mut dangling = DanglingPath { stack : Vec::with_capacity(64) };
         dangling.append_empty();
         dangling
@@ -89,13 +125,13 @@ impl<I, O> DanglingPath<I, O> where I : Index, O : Output {
         self.stack.push(DanglingState::default());
     }
 
-    fn pop_empty(&mut self) -> State<I, O> {
+    fn pop_empty(&mut self) -> State<I, O, L> {
         let dangling = self.stack.pop().unwrap();
         assert!(dangling.last_arc.is_none());
         dangling.state
     }
 
-    fn pop_root(&mut self) -> State<I, O> {
+    fn pop_root(&mut self) -> State<I, O, L> {
         assert!(self.stack.len() == 1);
         assert!(self.stack[0].last_arc.is_none());
         self.stack.pop().unwrap().state
@@ -107,7 +143,7 @@ impl<I, O> DanglingPath<I, O> where I : Index, O : Output {
         self.stack[0].state.final_output = output;
     }
 
-    fn finalize(&mut self, index : I) -> State<I, O> {
+    fn finalize(&mut self, index : I) -> State<I, O, L> {
         let mut dangling = self.stack.pop().unwrap();
         dangling.affix_last(index);
         dangling.state
@@ -118,7 +154,7 @@ impl<I, O> DanglingPath<I, O> where I : Index, O : Output {
         self.stack[last].affix_last(index);
     }
 
-    fn add_suffix(&mut self, suffix : &[u8], output : O) {
+    fn add_suffix(&mut self, suffix : &[L], output : O) {
         if suffix.is_empty() { return; }
         let last = self.stack.len() - 1;
         assert!(self.stack[last].last_arc.is_none());
@@ -128,63 +164,367 @@ impl<I, O> DanglingPath<I, O> where I : Index, O : Output {
         self.stack.push(DanglingState::empty_terminal());
     }
 
-    fn redistribute_prefix(&mut self, key : &[u8], mut out : O) -> (usize, O) {
-        let mut i = 0;
-        while i < key.len() {
-            let diff = match self.stack[i].last_arc.as_mut() {
-                Some(ref mut t) if t.label == key[i] => {
-                    i += 1;
-                    let prefix = t.output.prefix(out);
-                    let diff = t.output.inverse(prefix);
-                    out.inverse_assign(prefix);
-                    t.output = prefix;
-                    diff
-                }
-                _ => break,
-            };
-            self.stack[i].redistribute_output(diff);
+    fn redistribute_prefix(&mut self, key : &[L], out : O) -> Option<(usize, O)> {
+        let shared = self.stack.iter().zip(key)
+            .take_while(|&(d, &l)| d.last_arc.is_some_and(|t| t.label == l))
+            .count();
+        self.redistribute_shared(shared, out).map(|out| (shared, out))
+    }
+
+    /// Push the outputs of the first `len` arcs, known to be shared with
+    /// the key, down to what they have in common with `out`. Returns `None`
+    /// on overflow, leaving the path inconsistent.
+    fn redistribute_shared(&mut self, len : usize, mut out : O) -> Option<O> {
+        for i in 0 .. len {
+            let t = self.stack[i].last_arc.as_mut().unwrap();
+            let prefix = t.output.prefix(out);
+            let diff = t.output.checked_inverse(prefix) ?;
+            out = out.checked_inverse(prefix) ?;
+            t.output = prefix;
+            self.stack[i + 1].redistribute_output(diff) ?;
         }
-        (i, out)
+        Some(out)
+    }
+
+    /// The value of the last key inserted, if it is `len` bytes long.
+    fn value_of_last(&self, len : usize) -> O {
+        let arcs = self.stack[.. len].iter().filter_map(|d| d.last_arc.map(|t| t.output));
+        arcs.fold(O::zero(), O::mappend).mappend(self.stack[len].state.final_output)
+    }
+
+    /// Set the value of the last key inserted, if it is `key`.
+    fn revalue_last(&mut self, key : &[L], value : O) -> Option<()> {
+        let (prefix_len, residual) = self.redistribute_prefix(key, value) ?;
+        assert!(prefix_len == key.len());
+        self.stack[prefix_len].state.final_output = residual;
+        Some(())
     }
 
     fn len(&self) -> usize { self.stack.len() }
 }
 
 
-type Registry<I, O> = FnvHashMap<State<I, O>, I>;
+/// The treatment of a key inserted twice in a row.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum DuplicatePolicy<O> {
+    /// Fail with `Error::Duplicate`.
+    #[default]
+    Error,
+    /// Keep the value first inserted.
+    KeepFirst,
+    /// Keep the value last inserted.
+    KeepLast,
+    /// Combine the value already present with the value inserted.
+    Merge(fn(O, O) -> O)
+}
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
-pub struct Builder<I, O> where I : Index, O : Output {
-    pub registry : Registry<I, O>,
-    dangling : DanglingPath<I, O>,
-    previous_key : Option<Vec<u8>>,
+// Function pointers do not compare meaningfully, so neither do merge policies.
+impl<O> PartialEq for DuplicatePolicy<O> {
+    fn eq(&self, other : &DuplicatePolicy<O>) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+    }
+}
+
+impl<O> Eq for DuplicatePolicy<O> {}
+
+
+/// The choice of base for each state placed in the Dart.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Placement {
+    /// Take the lowest base admitting the state.
+    #[default]
+    FirstFit,
+    /// For states of high out-degree, take the base leaving the fewest free
+    /// slots within the span of their labels, lest they go unused. Slower,
+    /// but the Dart is denser.
+    BestFit
+}
+
+/// How the Dart grows when placement runs out of room.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Growth {
+    /// Grow by half the capacity of the Dart, and at least a block, so that
+    /// large Darts are reallocated seldom.
+    #[default]
+    Geometric,
+    /// Grow by a block at a time, never reserving more than needed.
+    Linear
+}
+
+/// The order in which states are placed in the Dart, each settled as it is
+/// first reached and its transitions laid out in turn.
+///
+/// States placed close together are read close together: depth-first keeps
+/// a state near its first child, breadth-first near its siblings.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Order {
+    /// Lay out the last state reached first.
+    #[default]
+    DepthFirst,
+    /// Lay out states in the order they were reached, level by level.
+    BreadthFirst,
+    /// Lay out states in reverse order of registration with the builder,
+    /// which registers each state after its destinations.
+    Registration
+}
+
+/// The layout of the Dart as states are placed, for `FST::from_builder_with`
+/// and `Builder::into_fst_with`.
+///
+/// The Dart is registered for placement a block at a time, and bases are
+/// sought in the last 16 blocks. Wider blocks thus leave more of the Dart
+/// open to placement, making for a denser layout at the cost of longer
+/// scans, but the Dart may end with up to a block of vacant slots, as
+/// trimmed by `FST::shrink_to_fit`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct BuildOptions {
+    /// The slots in a block, at least one more than the labels of the
+    /// alphabet and by default exactly so.
+    pub block_size : Option<usize>,
+    /// The slots reserved for the Dart before placement; by default, one
+    /// for every transition of the builder and for the root.
+    pub initial_capacity : Option<usize>,
+    pub growth : Growth,
+    pub order : Order
+}
+
+impl BuildOptions {
+    pub fn new() -> BuildOptions { BuildOptions::default() }
+
+    pub fn block_size(mut self, slots : usize) -> BuildOptions {
+        self.block_size = Some(slots);
+        self
+    }
+
+    pub fn initial_capacity(mut self, slots : usize) -> BuildOptions {
+        self.initial_capacity = Some(slots);
+        self
+    }
+
+    pub fn growth(mut self, growth : Growth) -> BuildOptions {
+        self.growth = growth;
+        self
+    }
+
+    pub fn order(mut self, order : Order) -> BuildOptions {
+        self.order = order;
+        self
+    }
+}
+
+
+/// Buffers kept from one build to the next, for building many small FSTs
+/// without allocating anew for each.
+///
+/// Builders made `with_context` take up its builder buffers, and return
+/// them once laid out `into_fst_in`; placement reuses the context's own.
+/// Every buffer is emptied as it is taken up, so that nothing of a build is
+/// seen by the next. The memory held is that of the largest build so far.
+#[derive(Clone, Debug)]
+pub struct BuildContext<I, O, L = u8> where I : Index, O : Output, L : Label {
+    arena : Arena<I, O, L>,
+    registry : Registry<I, O, L>,
+    dangling : Vec<DanglingState<I, O, L>>,
+    placement : Intermediary<I, O, L>,
+    options : BuildOptions
+}
+
+impl<I, O, L> Default for BuildContext<I, O, L> where I : Index, O : Output, L : Label {
+    fn default() -> BuildContext<I, O, L> { BuildContext::new() }
+}
+
+impl<I, O, L> BuildContext<I, O, L> where I : Index, O : Output, L : Label {
+    pub fn new() -> BuildContext<I, O, L> { BuildContext::with_options(BuildOptions::default()) }
+
+    /// A context laying out Darts as set by `options`.
+    pub fn with_options(options : BuildOptions) -> BuildContext<I, O, L> {
+        BuildContext {
+            arena : Arena::default(),
+            registry : Registry::default(),
+            dangling : Vec::new(),
+            placement : Intermediary::with_options(options),
+            options
+        }
+    }
+
+    /// Take back the buffers of a builder no longer needed.
+    pub fn recycle(&mut self, builder : Builder<I, O, L>) {
+        self.arena = builder.arena;
+        self.registry = builder.registry;
+        self.dangling = builder.dangling.stack;
+    }
+
+    /// The intermediary for a placement, emptied.
+    pub(crate) fn placement(&mut self) -> &mut Intermediary<I, O, L> {
+        self.placement.reset(self.options);
+        &mut self.placement
+    }
+}
+
+
+/// The construction phase reported by a progress callback.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Phase {
+    /// Keys are being inserted in the builder.
+    Insertion,
+    /// Builder states are being placed in the Dart.
+    Placement
+}
+
+/// A snapshot of construction, as reported to progress callbacks.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BuildProgress {
+    pub phase : Phase,
+    /// The number of keys inserted.
+    pub keys : usize,
+    /// The number of states registered.
+    pub states : usize,
+    /// The number of transitions registered.
+    pub transitions : usize,
+    /// The number of states placed in the Dart.
+    pub placed : usize,
+    /// The length of the Dart, once placement has begun.
+    pub dart_len : usize
+}
+
+/// A progress callback, invoked every so many keys or placed states.
+#[derive(Clone)]
+pub(crate) struct Progress {
+    every : usize,
+    hook : Arc<Mutex<dyn FnMut(BuildProgress) + Send>>
+}
+
+impl Progress {
+    #[inline]
+    pub fn is_due(&self, count : usize) -> bool { count.is_multiple_of(self.every) }
+
+    pub fn report(&self, progress : BuildProgress) {
+        let mut hook = self.hook.lock().unwrap_or_else(|e| e.into_inner());
+        (*hook)(progress)
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Progress").field("every", &self.every).finish()
+    }
+}
+
+impl PartialEq for Progress {
+    fn eq(&self, other : &Progress) -> bool {
+        self.every == other.every && Arc::ptr_eq(&self.hook, &other.hook)
+    }
+}
+
+impl Eq for Progress {}
+
+
+/// A key rejected during construction.
+#[derive(Clone, Debug)]
+pub struct Rejection {
+    /// The position of the offending pair in the input.
+    pub index : usize,
+    /// For repeated keys, the position of their first occurrence.
+    pub previous : Option<usize>,
+    pub error : Error
+}
+
+/// Statistics on the states and keys of a builder.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct BuilderStats {
+    /// The number of distinct states registered.
+    pub states : usize,
+    /// The number of transitions across registered states.
+    pub transitions : usize,
+    /// The number of keys inserted, excluding repeats.
+    pub keys : usize,
+    /// The total length of the keys inserted, excluding repeats.
+    pub key_bytes : usize,
+    /// The greatest number of transitions leaving a single state.
+    pub max_out_degree : usize
+}
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Builder<I, O, L = u8> where I : Index, O : Output, L : Label {
+    arena : Arena<I, O, L>,
+    registry : Registry<I, O, L>,
+    /// Whether states are kept as is, without minimization.
+    trie : bool,
+    dangling : DanglingPath<I, O, L>,
+    previous_key : Option<Vec<L>>,
+    duplicates : DuplicatePolicy<O>,
+    /// Whether a key repeated with its previous value is ignored.
+    dedup_identical : bool,
     transition_count : usize,
-    usable_index : usize,
     language_size : usize,
+    /// The number of insertions accepted, repeats included: the index of
+    /// the next key.
+    inserted : usize,
+    key_bytes : usize,
+    max_out_degree : usize,
     root : I,
+    finished : bool,
+    max_key_length : Option<usize>,
+    placement : Placement,
+    progress : Option<Progress>,
 }
 
-impl<I, O> Builder<I, O> where I : Index, O : Output {
-    fn register(&mut self, state : State<I, O>) -> Result<I> {
-        let idx = &mut self.usable_index;
-        let trans_r = &mut self.transition_count;
-        let trans_s = state.transitions.len();
+impl<I, O, L> Default for Builder<I, O, L> where I : Index, O : Output, L : Label {
+    fn default() -> Builder<I, O, L> {
+        Builder::with_parts(Arena::default(), Registry::default(), DanglingPath::new())
+    }
+}
+
+impl<I, O, L> Builder<I, O, L> where I : Index, O : Output, L : Label {
+    /// An empty builder over the given buffers, which must be empty.
+    fn with_parts(arena : Arena<I, O, L>, registry : Registry<I, O, L>, dangling : DanglingPath<I, O, L>) -> Builder<I, O, L> {
+        Builder {
+            arena,
+            registry,
+            trie : false,
+            dangling,
+            previous_key : None,
+            duplicates : DuplicatePolicy::default(),
+            dedup_identical : false,
+            transition_count : 0,
+            language_size : 0,
+            inserted : 0,
+            key_bytes : 0,
+            max_out_degree : 0,
+            root : I::zero(),
+            finished : false,
+            max_key_length : None,
+            placement : Placement::default(),
+            progress : None
+        }
+    }
 
-        match self.registry.entry(state) {
-            Entry::Occupied(e) => Ok(*e.get()),
-            Entry::Vacant(e) => {
-                let s_i = *idx;
-                *idx += 1;
-                *trans_r += trans_s;
-                match s_i > I::bound() || *trans_r > I::bound() {
-                    true => Err(Error::OutOfBounds {
-                        reached : cmp::max(s_i, *trans_r),
-                        maximum : I::max_value().as_usize()
-                    }),
-                    false => Ok(*e.insert(I::as_index(s_i)))
+    fn register(&mut self, state : State<I, O, L>) -> Result<I> {
+        let signature = match self.trie {
+            true => None,
+            false => {
+                let signature = registry::signature(&state);
+                if let Some(s_i) = self.registry.find(&self.arena, signature, &state) {
+                    return Ok(s_i)
                 }
+                Some(signature)
             }
-        }
+        };
+
+        let trans_s = state.transitions.len();
+        self.transition_count += trans_s;
+        self.max_out_degree = cmp::max(self.max_out_degree, trans_s);
+        // The state's index, and that of every transition in the arena.
+        let (s_i, transitions) = (self.arena.len(), self.transition_count);
+        let s_i = match (I::try_as_index(s_i), I::try_as_index(transitions)) {
+            (Some(s_i), Some(_)) => s_i,
+            _ => return Err(Error::OutOfBounds { reached : cmp::max(s_i, transitions), maximum : I::bound(), at_key : None })
+        };
+
+        self.arena.push(&state);
+        if let Some(signature) = signature { self.registry.insert(signature, state, s_i) }
+        Ok(s_i)
     }
 
     fn finalize_subpath(&mut self, path_start : usize) -> Result<()> {
@@ -206,57 +546,667 @@ impl<I, O> Builder<I, O> where I : Index, O : Output {
         self.register(root)
     }
 
-    fn validate_key<'a>(&mut self, key : &'a [u8]) -> Result<&'a [u8]> {
+    /// Check that the key follows the previous one, returning whether
+    /// it repeats it.
+    fn validate_key(&mut self, key : &[L]) -> Result<bool> {
+        self.check_length(key) ?;
         match self.previous_key {
-            Some(ref prev) if key == prev.as_slice() =>
-                Err(Error::Duplicate(key.to_vec())),
+            Some(ref prev) if key == prev.as_slice() => Ok(true),
             Some(ref prev) if key <  prev.as_slice() =>
-                Err(Error::OutOfOrder(key.to_vec(), prev.to_vec())),
+                Err(Error::OutOfOrder { key : L::key_bytes(key), previous : L::key_bytes(prev), index : self.inserted }),
             _ => {
-                self.previous_key = key.to_vec().into();
-                Ok(key)
+                self.remember_key(key);
+                Ok(false)
             }
         }
     }
 
-    pub fn insert(&mut self, key : &[u8], value : O) -> Result<()> {
-        let key = self.validate_key(key) ?;
+    fn check_length(&self, key : &[L]) -> Result<()> {
+        match self.max_key_length {
+            Some(limit) if key.len() > limit => Err(Error::KeyTooLong {
+                length : key.len(),
+                limit,
+                preview : L::key_bytes(&key[.. cmp::min(key.len(), KEY_PREVIEW)])
+            }),
+            _ => Ok(())
+        }
+    }
+
+    /// Record the key as the previous one, reusing the buffer already held.
+    fn remember_key(&mut self, key : &[L]) {
+        match self.previous_key {
+            Some(ref mut prev) => {
+                prev.clear();
+                prev.extend_from_slice(key);
+            },
+            None => self.previous_key = Some(key.to_vec())
+        }
+    }
+
+    /// An empty builder, rejecting duplicate keys.
+    pub fn new() -> Builder<I, O, L> { Builder::default() }
+
+    /// An empty builder, as with `new`, over the buffers of the context.
+    pub fn with_context(context : &mut BuildContext<I, O, L>) -> Builder<I, O, L> {
+        let mut arena = mem::take(&mut context.arena);
+        let mut registry = mem::take(&mut context.registry);
+        let mut dangling = DanglingPath { stack : mem::take(&mut context.dangling) };
+        arena.clear();
+        registry.clear();
+        dangling.stack.clear();
+        dangling.append_empty();
+        Builder::with_parts(arena, registry, dangling)
+    }
+
+    /// Set the treatment of keys inserted twice in a row.
+    pub fn duplicate_policy(mut self, policy : DuplicatePolicy<O>) -> Builder<I, O, L> {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Set whether a key repeated with the value it was last inserted with is
+    /// ignored, whatever the duplicate policy. Off by default.
+    pub fn dedup_identical(mut self, dedup : bool) -> Builder<I, O, L> {
+        self.dedup_identical = dedup;
+        self
+    }
+
+    /// Set whether equivalent states are merged as they are finalized, which
+    /// is the default. Without minimization, each state is kept as is: the
+    /// builder is faster but the FST larger.
+    ///
+    /// Must be set before inserting any key.
+    pub fn minimize(mut self, minimize : bool) -> Builder<I, O, L> {
+        debug_assert!(self.arena.is_empty(), "minimization set after insertion");
+        self.trie = !minimize;
+        self
+    }
+
+    /// Reject keys longer than `limit` labels with `KeyTooLong`, leaving the
+    /// builder as it was. Keys are unlimited by default.
+    pub fn max_key_length(mut self, limit : usize) -> Builder<I, O, L> {
+        self.max_key_length = Some(limit);
+        self
+    }
+
+    /// Set how states are placed in the Dart. First-fit by default.
+    pub fn placement(mut self, placement : Placement) -> Builder<I, O, L> {
+        self.placement = placement;
+        self
+    }
+
+    /// Invoke `f` every `every_n_keys` keys inserted, and as often while
+    /// placing states in the Dart.
+    pub fn on_progress<F>(mut self, every_n_keys : usize, f : F) -> Builder<I, O, L>
+        where F : FnMut(BuildProgress) + Send + 'static
+    {
+        self.progress = Some(Progress {
+            every : cmp::max(1, every_n_keys),
+            hook : Arc::new(Mutex::new(f))
+        });
+        self
+    }
+
+    pub(crate) fn progress(&self) -> Option<&Progress> { self.progress.as_ref() }
+
+    pub(crate) fn placement_policy(&self) -> Placement { self.placement }
+
+    pub(crate) fn snapshot(&self, phase : Phase) -> BuildProgress {
+        BuildProgress {
+            phase,
+            keys : self.language_size,
+            states : self.size(),
+            transitions : self.transition_count,
+            placed : 0,
+            dart_len : 0
+        }
+    }
+
+    /// Insert a key following the previous one.
+    ///
+    /// Should outputs overflow as they are redistributed along the key, fails
+    /// with `OutputOverflow`, after which the builder must be discarded.
+    pub fn insert(&mut self, key : &[L], value : O) -> Result<()> {
+        if self.finished { return Err(Error::Finished) }
+        let inserted = match self.validate_key(key) ? {
+            true => self.insert_duplicate(key, value),
+            false => self.insert_fresh(key, value)
+        };
+        self.count(inserted)
+    }
+
+    /// Count the insertion, if accepted.
+    fn count(&mut self, inserted : Result<()>) -> Result<()> {
+        if inserted.is_ok() { self.inserted += 1 }
+        inserted
+    }
+
+    /// Insert a key without checking that it follows the previous one.
+    ///
+    /// Keys must be inserted in strictly increasing order: if they are not,
+    /// the resulting FST is incorrect, although still memory-safe. The order
+    /// is only checked in debug builds.
+    pub fn insert_unchecked(&mut self, key : &[L], value : O) -> Result<()> {
+        if self.finished { return Err(Error::Finished) }
+        self.check_length(key) ?;
+        if cfg!(debug_assertions) {
+            debug_assert!(self.previous_key.as_ref().is_none_or(|prev| prev.as_slice() < key),
+                          "key inserted out of order: {:?}", key);
+            self.remember_key(key);
+        }
+        let inserted = self.insert_fresh(key, value);
+        self.count(inserted)
+    }
+
+    fn insert_fresh(&mut self, key : &[L], value : O) -> Result<()> {
         if key.is_empty() {
             self.dangling.set_root_output(value);
             self.language_size = 1;
             return Ok(());
         }
-        let (prefix_len, output) = self.dangling.redistribute_prefix(key, value);
-        self.finalize_subpath(prefix_len) ?;
+        let (prefix_len, output) = self.dangling.redistribute_prefix(key, value)
+            .ok_or_else(|| overflow(key, value)) ?;
+        self.insert_suffix(key, prefix_len, output)
+    }
+
+    /// Insert the rest of a key whose first `prefix_len` bytes already lie
+    /// on the dangling path, their outputs redistributed.
+    fn insert_suffix(&mut self, key : &[L], prefix_len : usize, output : O) -> Result<()> {
+        self.finalize_subpath(prefix_len).map_err(|e| e.at_key(L::key_bytes(key))) ?;
         let suffix = &key[prefix_len ..];
         self.dangling.add_suffix(suffix, output);
         self.language_size += 1;
+        self.key_bytes += key.len();
+        if let Some(ref p) = self.progress {
+            if p.is_due(self.language_size) { p.report(self.snapshot(Phase::Insertion)) }
+        }
+        Ok(())
+    }
+
+    fn insert_duplicate(&mut self, key : &[L], value : O) -> Result<()> {
+        if self.dedup_identical && self.dangling.value_of_last(key.len()) == value {
+            return Ok(())
+        }
+        let value = match self.duplicates {
+            DuplicatePolicy::Error => return Err(Error::Duplicate {
+                key : L::key_bytes(key),
+                index : self.inserted,
+                existing_hint : Some(format!("{:?}", self.dangling.value_of_last(key.len()))),
+                inserted_hint : Some(format!("{:?}", value))
+            }),
+            DuplicatePolicy::KeepFirst => return Ok(()),
+            DuplicatePolicy::KeepLast => value,
+            DuplicatePolicy::Merge(f) => f(self.dangling.value_of_last(key.len()), value)
+        };
+        // The previous value has already been distributed along the path,
+        // and must be pushed back down before settling the new one.
+        self.dangling.revalue_last(key, value).ok_or_else(|| overflow(key, value))
+    }
+
+    /// Insert every pair in order.
+    pub fn extend<K, T>(&mut self, iter : T) -> Result<()>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        for (k, v) in iter { self.insert(k.as_ref(), v) ? }
+        Ok(())
+    }
+
+    /// Insert sorted pairs, exactly as `extend` would, but comparing each
+    /// key with the previous one in a single pass and reusing the length of
+    /// their common prefix along the dangling path. Worthwhile when keys
+    /// share long prefixes, as sorted paths or URLs do.
+    pub fn insert_all<K>(&mut self, sorted : &[(K, O)]) -> Result<()>
+        where K : AsRef<[L]>
+    {
+        let (first, rest) = match sorted.split_first() {
+            Some(split) => split,
+            None => return Ok(())
+        };
+        self.insert(first.0.as_ref(), first.1) ?;
+
+        let mut previous = first.0.as_ref();
+        for &(ref key, value) in rest {
+            let key = key.as_ref();
+            let inserted = self.insert_after(previous, key, value);
+            if let Err(e) = self.count(inserted) {
+                self.remember_key(previous);
+                return Err(e)
+            }
+            previous = key;
+        }
+        self.remember_key(previous);
         Ok(())
     }
 
+    /// Insert a key following `previous`, the last key inserted.
+    fn insert_after(&mut self, previous : &[L], key : &[L], value : O) -> Result<()> {
+        self.check_length(key) ?;
+        let shared = previous.iter().zip(key).take_while(|&(p, k)| p == k).count();
+        let index = self.inserted;
+        let disorder = || Error::OutOfOrder { key : L::key_bytes(key), previous : L::key_bytes(previous), index };
+        match (key.get(shared), previous.get(shared)) {
+            (None, None) => self.insert_duplicate(key, value),
+            (None, Some(_)) => Err(disorder()),
+            (Some(k), Some(p)) if k < p => Err(disorder()),
+            _ => {
+                let output = self.dangling.redistribute_shared(shared, value)
+                    .ok_or_else(|| overflow(key, value)) ?;
+                self.insert_suffix(key, shared, output)
+            }
+        }
+    }
+
+    /// Register the remaining states, returning the root. Finishing a
+    /// builder again returns the same root; inserting in it fails.
     pub fn finish(&mut self) -> Result<I> {
+        if self.finished { return Ok(self.root) }
         self.finalize_subpath(0)
             .and_then(|_| self.finalize_root())
             .map(|i| {
                 self.root = i;
+                self.finished = true;
                 i
             })
     }
 
-    pub fn from_iter<K, T>(iter : T) -> Result<Builder<I, O>>
-        where K : AsRef<[u8]>
+    pub fn from_iter<K, T>(iter : T) -> Result<Builder<I, O, L>>
+        where K : AsRef<[L]>
             , T : IntoIterator<Item = (K, O)>
     {
-        let mut builder = Builder { dangling : DanglingPath::new(), ..Builder::default() };
-        for (k, v) in iter { builder.insert(k.as_ref(), v) ? }
+        let mut builder = Builder::new();
+        builder.extend(iter) ?;
+        builder.finish() ?;
+
+        Ok(builder)
+    }
+
+    /// Build from pairs sorted by key, converting each value to the output
+    /// type and failing with `OutputOverflow` if it does not fit.
+    pub fn from_iter_converting<K, V, T>(iter : T) -> Result<Builder<I, O, L>>
+        where K : AsRef<[L]>
+            , V : TryInto<O> + Clone + Debug
+            , T : IntoIterator<Item = (K, V)>
+    {
+        let mut builder = Builder::new();
+        for (k, v) in iter {
+            let key = k.as_ref();
+            // The value is formatted only if it does not fit.
+            match v.clone().try_into() {
+                Ok(o) => builder.insert(key, o) ?,
+                Err(_) => return Err(Error::OutputOverflow {
+                    key : L::key_bytes(key),
+                    value : format!("{:?}", v)
+                })
+            }
+        }
         builder.finish() ?;
 
         Ok(builder)
     }
 
+    /// Build from keys sorted in lexicographic order, valuing each with its
+    /// ordinal, so that the FST maps keys to their rank.
+    ///
+    /// Fails with `OutputOverflow` once an ordinal does not fit the output type.
+    pub fn from_keys<K, T>(keys : T) -> Result<Builder<I, O, L>>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = K>
+            , usize : TryInto<O>
+    {
+        Builder::from_iter_converting(keys.into_iter().zip(0usize ..))
+    }
+
+    /// Build from fallible pairs sorted by key, stopping at the first failure
+    /// of either the source or the builder.
+    pub fn try_from_fallible_iter<K, T, E>(iter : T) -> result::Result<Builder<I, O, L>, BuildOrSourceError<E>>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = result::Result<(K, O), E>>
+    {
+        let mut builder = Builder::new();
+        let mut consumed = 0;
+        for pair in iter {
+            let (k, v) = pair.map_err(|error| BuildOrSourceError::Source { consumed, error }) ?;
+            consumed += 1;
+            builder.insert(k.as_ref(), v)
+                .map_err(|error| BuildOrSourceError::Build { consumed, error }) ?;
+        }
+        builder.finish().map_err(|error| BuildOrSourceError::Build { consumed, error }) ?;
+
+        Ok(builder)
+    }
+
+    /// Build from pairs sorted by key, skipping keys which are out of order or
+    /// repeated, and returning the builder along with every key rejected.
+    ///
+    /// Errors unrelated to a key's order, such as `OutOfBounds`, are fatal.
+    pub fn from_iter_skipping<K, T>(iter : T) -> Result<(Builder<I, O, L>, Vec<Rejection>)>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        let (built, rejected) = Builder::build_skipping(iter);
+        built.map(|b| (b, rejected)).map_err(|fatal| fatal.error)
+    }
+
+    /// Build from pairs sorted by key as with `from_iter_skipping`, failing
+    /// with every key rejected, if any.
+    ///
+    /// A fatal error ends the list, at the position where it occurred.
+    pub fn try_from_iter_collecting<K, T>(iter : T) -> result::Result<Builder<I, O, L>, Vec<Rejection>>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        match Builder::build_skipping(iter) {
+            (Ok(builder), ref rejected) if rejected.is_empty() => Ok(builder),
+            (Ok(_), rejected) => Err(rejected),
+            (Err(fatal), mut rejected) => {
+                rejected.push(fatal);
+                Err(rejected)
+            }
+        }
+    }
+
+    fn build_skipping<K, T>(iter : T) -> (Built<I, O, L>, Vec<Rejection>)
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        let mut builder = Builder::new();
+        let mut rejected = Vec::new();
+        let mut last_accepted = None;
+        let mut consumed = 0;
+        for (index, (k, v)) in iter.into_iter().enumerate() {
+            consumed = index + 1;
+            match builder.insert(k.as_ref(), v) {
+                Ok(()) => last_accepted = Some(index),
+                Err(error @ Error::Duplicate { .. }) =>
+                    rejected.push(Rejection { index, previous : last_accepted, error }),
+                Err(error @ Error::OutOfOrder { .. }) =>
+                    rejected.push(Rejection { index, previous : None, error }),
+                Err(error) =>
+                    return (Err(Rejection { index, previous : None, error }), rejected)
+            }
+        }
+
+        match builder.finish() {
+            Ok(_) => (Ok(builder), rejected),
+            Err(error) => (Err(Rejection { index : consumed, previous : None, error }), rejected)
+        }
+    }
+
+    /// Build from pairs without checking their order, as with `insert_unchecked`.
+    pub fn from_sorted_iter_unchecked<K, T>(iter : T) -> Result<Builder<I, O, L>>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        let mut builder = Builder::new();
+        for (k, v) in iter { builder.insert_unchecked(k.as_ref(), v) ? }
+        builder.finish() ?;
+
+        Ok(builder)
+    }
+
+    /// Build from pairs in any order, which are buffered and sorted first.
+    ///
+    /// Duplicate keys are rejected, as with `from_iter`.
+    pub fn from_unsorted_iter<K, T>(iter : T) -> Result<Builder<I, O, L>>
+        where K : Into<Vec<L>>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        Builder::from_iter(sorted(iter))
+    }
+
+    /// Build from pairs in any order, which are buffered and sorted first,
+    /// combining the values of duplicate keys with `merge`.
+    ///
+    /// Duplicates are not merged in any particular order.
+    pub fn from_unsorted_iter_merging<K, T, F>(iter : T, mut merge : F) -> Result<Builder<I, O, L>>
+        where K : Into<Vec<L>>
+            , T : IntoIterator<Item = (K, O)>
+            , F : FnMut(O, O) -> O
+    {
+        let mut pairs = sorted(iter);
+        pairs.dedup_by(|next, kept| match next.0 == kept.0 {
+            true => { kept.1 = merge(kept.1, next.1); true },
+            false => false
+        });
+        Builder::from_iter(pairs)
+    }
+
+    /// Build from pairs sorted by key, building the keys under each first
+    /// byte on a separate worker before grafting them onto a common root.
+    ///
+    /// States shared across shards are merged while grafting, so the result
+    /// is as minimal as with `from_iter`. Should the input be invalid, the
+    /// error reported may not be the first one in order.
+    #[cfg(feature = "rayon")]
+    pub fn par_from_iter<K, T>(iter : T) -> Result<Builder<I, O, L>>
+        where K : AsRef<[L]> + Sync
+            , T : IntoIterator<Item = (K, O)>
+            , I : Send
+            , O : Send + Sync
+            , L : Send + Sync
+    {
+        use rayon::prelude::*;
+
+        let pairs : Vec<(K, O)> = iter.into_iter().collect();
+        let empty = pairs.iter().take_while(|p| p.0.as_ref().is_empty()).count();
+        if empty > 1 {
+            return Err(Error::Duplicate { key : Vec::new(), index : 1, existing_hint : None, inserted_hint : None })
+        }
+
+        // Split the input into runs sharing their first byte.
+        let mut runs = Vec::new();
+        let mut start = empty;
+        for i in empty + 1 ..= pairs.len() {
+            if i < pairs.len() {
+                let (prev, key) = (pairs[i - 1].0.as_ref(), pairs[i].0.as_ref());
+                if key.is_empty() || key[0] < prev[0] {
+                    return Err(Error::OutOfOrder { key : L::key_bytes(key), previous : L::key_bytes(prev), index : i })
+                }
+                if key[0] == prev[0] { continue }
+            }
+            if start < i { runs.push(start .. i) }
+            start = i;
+        }
+
+        let shards = runs.into_par_iter()
+            .map(|run| Builder::from_iter(pairs[run.clone()].iter().map(|(k, v)| (k.as_ref(), *v)))
+                               .map_err(|e| e.shifted(run.start)))
+            .collect::<Result<Vec<Builder<I, O, L>>>>() ?;
+
+        let mut builder = Builder::new();
+        if let Some((_, value)) = pairs.first().filter(|_| empty == 1) {
+            builder.insert(&[], *value) ?;
+        }
+        let mut root = builder.dangling.pop_root();
+        for shard in shards {
+            root.transitions.push(builder.graft(shard) ?);
+        }
+        builder.root = builder.register(root) ?;
+        builder.finished = true;
+        if let Some((key, _)) = pairs.last() { builder.remember_key(key.as_ref()) }
+
+        Ok(builder)
+    }
+
+    /// Register the states of a finished builder holding keys with a common
+    /// first byte, returning the transition of its root.
+    #[cfg(feature = "rayon")]
+    fn graft(&mut self, shard : Builder<I, O, L>) -> Result<Transition<I, O, L>> {
+        let root = shard.root.as_usize();
+        let fresh_root = root + 1 == shard.size();
+        self.language_size += shard.language_size;
+        self.inserted += shard.inserted;
+        self.key_bytes += shard.key_bytes;
+
+        // States are registered after their destinations, so their indices
+        // are in topological order. The root comes last unless shared.
+        let mut translation = Vec::with_capacity(shard.size());
+        let mut arc = None;
+        for s_i in 0 .. shard.size() {
+            let mut state = shard.arena.get(s_i).to_state();
+            for t in &mut state.transitions {
+                t.destination = translation[t.destination.as_usize()];
+            }
+            if s_i == root { arc = state.transitions.first().cloned() }
+            if s_i == root && fresh_root {
+                translation.push(I::zero());
+            } else {
+                translation.push(self.register(state) ?);
+            }
+        }
+
+        Ok(arc.expect("a shard's root has exactly one transition"))
+    }
+
+    /// Lay out the finished builder as an FST, releasing its states as
+    /// they are placed.
+    pub fn into_fst(self) -> Result<FST<I, O, L>> {
+        self.into_fst_with(BuildOptions::default())
+    }
+
+    /// Lay out the finished builder as an FST, as with `into_fst`, under
+    /// the given options.
+    pub fn into_fst_with(self, options : BuildOptions) -> Result<FST<I, O, L>> {
+        let mut repr = Intermediary::with_options(options);
+        repr.from_owned_builder(self) ?;
+        Ok(repr.into_dart())
+    }
+
+    /// Lay out the finished builder as an FST with the buffers and options
+    /// of the context, to which the builder's own are then returned.
+    pub fn into_fst_in(self, context : &mut BuildContext<I, O, L>) -> Result<FST<I, O, L>> {
+        let fst = FST::from_builder_in(&self, context) ?;
+        context.recycle(self);
+        Ok(fst)
+    }
+
+    /// Write the state of the builder, so that construction may resume from
+    /// it with `resume`.
+    ///
+    /// The treatment of duplicates, key length limit, placement, and progress
+    /// callback are not recorded.
+    #[cfg(feature = "serialization")]
+    pub fn checkpoint<W>(&self, w : W) -> Result<()>
+        where W : io::Write
+            , I : Serialize
+            , O : Serialize
+            , L : Serialize
+    {
+        let checkpoint = Checkpoint {
+            arena : Cow::Borrowed(&self.arena),
+            registry : Cow::Borrowed(&self.registry),
+            trie : self.trie,
+            dangling : Cow::Borrowed(&self.dangling),
+            previous_key : Cow::Borrowed(&self.previous_key),
+            counts : [self.transition_count, self.language_size, self.key_bytes, self.max_out_degree, self.inserted],
+            root : self.root,
+            finished : self.finished
+        };
+        bincode::serialize_into(w, &checkpoint).map_err(Error::serialization)
+    }
+
+    /// Restore a builder from a checkpoint. Insertion resumes after the last
+    /// key inserted before the checkpoint; for inputs without repeats, `len`
+    /// is the number of pairs to skip.
+    #[cfg(feature = "serialization")]
+    pub fn resume<R>(r : R) -> Result<Builder<I, O, L>>
+        where R : io::Read
+            , I : DeserializeOwned
+            , O : DeserializeOwned
+            , L : DeserializeOwned
+    {
+        let checkpoint : Checkpoint<I, O, L> =
+            bincode::deserialize_from(r).map_err(Error::serialization) ?;
+        let [transition_count, language_size, key_bytes, max_out_degree, inserted] = checkpoint.counts;
+        Ok(Builder {
+            arena : checkpoint.arena.into_owned(),
+            registry : checkpoint.registry.into_owned(),
+            trie : checkpoint.trie,
+            dangling : checkpoint.dangling.into_owned(),
+            previous_key : checkpoint.previous_key.into_owned(),
+            transition_count,
+            language_size,
+            inserted,
+            key_bytes,
+            max_out_degree,
+            root : checkpoint.root,
+            finished : checkpoint.finished,
+            ..Builder::default()
+        })
+    }
+
+    /// Statistics on the states registered and keys inserted so far.
+    pub fn stats(&self) -> BuilderStats {
+        BuilderStats {
+            states : self.size(),
+            transitions : self.transition_count,
+            keys : self.language_size,
+            key_bytes : self.key_bytes,
+            max_out_degree : self.max_out_degree
+        }
+    }
+
+    /// An estimate of the memory held by the builder, by capacity.
+    ///
+    /// The size of the registry's tables is an estimate.
+    pub fn approx_memory(&self) -> MemoryUsage {
+        let transitions = |s : &State<I, O, L>| memory::small_vec_bytes(&s.transitions);
+        let mut usage = MemoryUsage::new();
+        usage.add("arena", self.arena.bytes());
+        usage.add("registry", self.registry.approx_bytes());
+        usage.add("dangling", memory::vec_bytes(&self.dangling.stack)
+                            + self.dangling.stack.iter().map(|d| transitions(&d.state)).sum::<usize>());
+        usage.add("previous_key", self.previous_key.as_ref().map_or(0, memory::vec_bytes));
+        usage
+    }
+
     pub fn root(&self) -> I { self.root }
 
-    pub fn size(&self) -> usize { self.registry.len() }
+    pub fn size(&self) -> usize { self.arena.len() }
+
+    /// The registered states, by index.
+    pub(crate) fn arena(&self) -> &Arena<I, O, L> { &self.arena }
+
+    /// The registered states, releasing the registry.
+    pub(crate) fn into_arena(self) -> Arena<I, O, L> { self.arena }
 
     pub fn len(&self) -> usize { self.language_size }
+
+    pub fn is_empty(&self) -> bool { self.language_size == 0 }
+}
+
+
+/// A builder, or the rejection which ended its construction.
+type Built<I, O, L> = result::Result<Builder<I, O, L>, Rejection>;
+
+/// The recorded state of a builder, borrowed when written and owned when read.
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<'a, I, O, L> where I : Index, O : Output, L : Label {
+    arena : Cow<'a, Arena<I, O, L>>,
+    registry : Cow<'a, Registry<I, O, L>>,
+    trie : bool,
+    dangling : Cow<'a, DanglingPath<I, O, L>>,
+    previous_key : Cow<'a, Option<Vec<L>>>,
+    /// Transitions, keys, key bytes, greatest out-degree, and insertions.
+    counts : [usize; 5],
+    root : I,
+    finished : bool
+}
+
+
+/// Outputs overflowed while redistributed along the key.
+fn overflow<O, L>(key : &[L], value : O) -> Error where O : Output, L : Label {
+    Error::OutputOverflow { key : L::key_bytes(key), value : format!("{:?}", value) }
+}
+
+fn sorted<K, O, L, T>(iter : T) -> Vec<(Vec<L>, O)>
+    where K : Into<Vec<L>>
+        , L : Label
+        , T : IntoIterator<Item = (K, O)>
+{
+    let mut pairs : Vec<_> = iter.into_iter().map(|(k, v)| (k.into(), v)).collect();
+    pairs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    pairs
 }
@@ -0,0 +1,413 @@
+//! A compact representation of an FST, as a list of states encoded in bytes
+//! and addressed by offset, trading lookup speed for memory.
+//!
+//! Each state is written after its destinations, and addressed by the end
+//! of its encoding, which is read backwards from a header byte holding its
+//! finality, whether it has a final output, and up to 30 transitions. A
+//! state with a single transition of no output, whose destination is the
+//! state written just before it, takes only its label besides. Any other
+//! state holds, from its header down,
+//!
+//! - the number of its transitions, should the header not hold it;
+//! - the widths in bytes of its destinations and of its outputs;
+//! - its final output, if any, less its trailing zero bytes;
+//! - the labels of its transitions, in order;
+//! - their destinations, each as the distance back from the start of the
+//!   state, in as many bytes as the farthest takes;
+//! - their outputs, each in as many bytes as the widest takes once the
+//!   trailing zero bytes are cut.
+//!
+//! Transitions are thus found by binary search over the labels, and read
+//! at fixed widths within their state. Numbers are little-endian, and the
+//! varints of the header are written backwards.
+
+use smallvec::SmallVec;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+use fst::{FST, Output};
+use fst::builder::Builder;
+use fst::error::Result;
+use fst::format::Codec;
+use fst::memory::{self, MemoryUsage};
+use index::Index;
+use label::Label;
+
+
+const TERMINAL : u8 = 0b001;
+const FINAL_OUTPUT : u8 = 0b010;
+/// A single transition, of no output, to the state written just before.
+const NEXT : u8 = 0b100;
+/// The transitions a header holds, beyond which their number follows it.
+const INLINE_LEN : usize = 30;
+/// The output width a header holds, beyond which the width follows it.
+const INLINE_WIDTH : usize = 15;
+
+/// An FST stored as a list of variable-length states, smaller than the
+/// double array of an `FST` but several times slower to search.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompactFst<O, L = u8> {
+    bytes : Vec<u8>,
+    /// The address of the root, if any.
+    root : Option<usize>,
+    /// The output decoded from zero bytes, borne by transitions of no width.
+    blank : O,
+    labels : PhantomData<L>
+}
+
+/// A state, its header read.
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    /// The first byte of the state, from which destinations are measured.
+    start : usize,
+    len : usize,
+    terminal : bool,
+    /// The offset and width of the final output, if any.
+    final_output : Option<(usize, usize)>,
+    labels : usize,
+    target_width : usize,
+    output_width : usize
+}
+
+impl<O, L> Default for CompactFst<O, L> where O : Output + Codec, L : Label + Codec {
+    fn default() -> CompactFst<O, L> { CompactFst::with_bytes(Vec::new(), None) }
+}
+
+impl<O, L> CompactFst<O, L> where O : Output + Codec, L : Label + Codec {
+    fn with_bytes(bytes : Vec<u8>, root : Option<usize>) -> CompactFst<O, L> {
+        let blank = O::decode(&vec![0; O::WIDTH]).unwrap_or_else(O::zero);
+        CompactFst { bytes, root, blank, labels : PhantomData }
+    }
+
+    /// Encode the states of a finished builder.
+    pub fn from_builder<I>(builder : &Builder<I, O, L>) -> CompactFst<O, L>
+        where I : Index
+    {
+        let arena = builder.arena();
+        let mut bytes = Vec::new();
+        let mut addresses = Vec::with_capacity(arena.len());
+        let mut outputs = Vec::new();
+        let mut encoded = Vec::with_capacity(O::WIDTH);
+        for s_i in 0 .. arena.len() {
+            let state = arena.get(s_i);
+            let start = bytes.len();
+            let terminal = if state.terminal { TERMINAL } else { 0 };
+            let final_output = state.terminal && !state.final_output.is_zero();
+
+            outputs.clear();
+            for t in state.transitions { t.output.encode(&mut outputs) }
+            let output_width = outputs.chunks(O::WIDTH.max(1)).map(width).max().unwrap_or(0);
+
+            match state.transitions {
+                [t] if !final_output && output_width == 0 && addresses[t.destination.as_usize()] == start => {
+                    t.label.encode(&mut bytes);
+                    bytes.push(terminal | NEXT);
+                },
+                transitions => {
+                    let distances : SmallVec<[usize; 8]> = transitions.iter()
+                        .map(|t| start - addresses[t.destination.as_usize()])
+                        .collect();
+                    let target_width = distances.iter().map(|&d| width(&d.to_le_bytes())).max().unwrap_or(0);
+
+                    if output_width > 0 {
+                        for output in outputs.chunks(O::WIDTH) { bytes.extend_from_slice(&output[.. output_width]) }
+                    }
+                    for &d in &distances { bytes.extend_from_slice(&d.to_le_bytes()[.. target_width]) }
+                    for t in transitions { t.label.encode(&mut bytes) }
+                    if final_output {
+                        encoded.clear();
+                        state.final_output.encode(&mut encoded);
+                        let final_width = width(&encoded);
+                        bytes.extend_from_slice(&encoded[.. final_width]);
+                        put_backwards(final_width, &mut bytes);
+                    }
+                    if !transitions.is_empty() {
+                        if output_width >= INLINE_WIDTH { put_backwards(output_width, &mut bytes) }
+                        bytes.push((target_width | output_width.min(INLINE_WIDTH) << 4) as u8);
+                    }
+                    let len = transitions.len();
+                    if len > INLINE_LEN { put_backwards(len, &mut bytes) }
+                    let flags = terminal | if final_output { FINAL_OUTPUT } else { 0 };
+                    bytes.push(flags | (len.min(INLINE_LEN + 1) as u8) << 3);
+                }
+            }
+            addresses.push(bytes.len());
+        }
+        bytes.shrink_to_fit();
+
+        let root = addresses.get(builder.root().as_usize()).copied();
+        CompactFst::with_bytes(bytes, root)
+    }
+
+    /// Build a compact FST from pairs sorted by key, as with
+    /// `Builder::from_iter`.
+    pub fn from_iter<K, T>(iter : T) -> Result<CompactFst<O, L>>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        let builder : Builder<usize, O, L> = Builder::from_iter(iter) ?;
+        Ok(CompactFst::from_builder(&builder))
+    }
+
+    /// Lay out the keys and values as a double-array FST.
+    pub fn to_fst<I>(&self) -> Result<FST<I, O, L>>
+        where I : Index
+    {
+        FST::from_iter(self.iter())
+    }
+
+    /// Get the value associated to the key, if any.
+    pub fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[L]>
+    {
+        let mut node = self.node(self.root ?);
+        let mut out = O::zero();
+        for &label in key.as_ref() {
+            let k = self.find(&node, label) ?;
+            out.mappend_assign(self.output(&node, k));
+            node = self.node(self.target(&node, k));
+        }
+
+        self.value(&node, out)
+    }
+
+    /// Returns whether the key is accepted by the FST.
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[L]>
+    {
+        let Some(mut node) = self.root.map(|root| self.node(root)) else { return false };
+        for &label in key.as_ref() {
+            match self.find(&node, label) {
+                Some(k) => node = self.node(self.target(&node, k)),
+                None => return false
+            }
+        }
+
+        node.terminal
+    }
+
+    /// Returns an iterator over the keys and values of the FST, in
+    /// lexicographic order.
+    pub fn iter(&self) -> CompactIter<'_, O, L> {
+        let root = self.root.map(|root| self.node(root));
+        CompactIter {
+            fst : self,
+            key : Vec::new(),
+            stack : root.into_iter().map(|node| (node, 0, O::zero())).collect(),
+            origin : root.and_then(|node| self.value(&node, O::zero()))
+        }
+    }
+
+    /// The memory held by the encoded states.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::new();
+        usage.add("states", memory::vec_bytes(&self.bytes));
+        usage
+    }
+
+    /// The length of the encoded states, in bytes.
+    pub fn len(&self) -> usize { self.bytes.len() }
+
+    /// Whether the FST holds no states, not even an empty root.
+    pub fn is_empty(&self) -> bool { self.bytes.is_empty() }
+
+    /// Read the header of the state ending at the address.
+    #[inline]
+    fn node(&self, address : usize) -> Node {
+        let mut pos = address - 1;
+        let header = self.bytes[pos];
+        let terminal = header & TERMINAL != 0;
+        if header & NEXT != 0 {
+            let labels = pos - L::WIDTH;
+            return Node {
+                start : labels, len : 1, terminal, final_output : None,
+                labels, target_width : 0, output_width : 0
+            }
+        }
+
+        let len = match (header >> 3) as usize {
+            len if len > INLINE_LEN => take_backwards(&self.bytes, &mut pos),
+            len => len
+        };
+        let (target_width, output_width) = match len {
+            0 => (0, 0),
+            _ => {
+                pos -= 1;
+                let widths = self.bytes[pos] as usize;
+                match widths >> 4 {
+                    INLINE_WIDTH => (widths & 0xF, take_backwards(&self.bytes, &mut pos)),
+                    output_width => (widths & 0xF, output_width)
+                }
+            }
+        };
+        let final_output = match header & FINAL_OUTPUT {
+            0 => None,
+            _ => {
+                let width = take_backwards(&self.bytes, &mut pos);
+                pos -= width;
+                Some((pos, width))
+            }
+        };
+        let labels = pos - len * L::WIDTH;
+        Node {
+            start : labels - len * (target_width + output_width),
+            len, terminal, final_output, labels, target_width, output_width
+        }
+    }
+
+    #[inline]
+    fn label(&self, node : &Node, k : usize) -> L {
+        let at = node.labels + k * L::WIDTH;
+        L::decode(&self.bytes[at .. at + L::WIDTH]).unwrap_or_default()
+    }
+
+    /// The transition of the node with the given label, if any.
+    #[inline]
+    fn find(&self, node : &Node, label : L) -> Option<usize> {
+        let (mut low, mut high) = (0, node.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.label(node, mid).cmp(&label) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid)
+            }
+        }
+        None
+    }
+
+    /// The address of the destination of a transition.
+    #[inline]
+    fn target(&self, node : &Node, k : usize) -> usize {
+        let at = node.labels - (node.len - k) * node.target_width;
+        node.start - little_endian(&self.bytes[at .. at + node.target_width])
+    }
+
+    #[inline]
+    fn output(&self, node : &Node, k : usize) -> O {
+        match node.output_width {
+            0 => self.blank,
+            width => self.decode(node.start + k * width, width)
+        }
+    }
+
+    /// Decode an output cut to the given width.
+    #[inline]
+    fn decode(&self, at : usize, width : usize) -> O {
+        let bytes = &self.bytes[at .. at + width];
+        let decoded = match O::WIDTH {
+            0 ..= 16 => {
+                let mut encoded = [0; 16];
+                encoded[.. width].copy_from_slice(bytes);
+                O::decode(&encoded[.. O::WIDTH])
+            },
+            _ => {
+                let mut encoded = bytes.to_vec();
+                encoded.resize(O::WIDTH, 0);
+                O::decode(&encoded)
+            }
+        };
+        decoded.unwrap_or_else(O::zero)
+    }
+
+    /// The value of a key reaching the node with the given output, if the
+    /// node is final.
+    #[inline]
+    fn value(&self, node : &Node, out : O) -> Option<O> {
+        match (node.terminal, node.final_output) {
+            (false, _) => None,
+            (true, None) => Some(out),
+            (true, Some((at, width))) => Some(out.mappend(self.decode(at, width)))
+        }
+    }
+}
+
+impl<I, O, L> FST<I, O, L> where I : Index, O : Output + Codec, L : Label + Codec {
+    /// Encode the FST compactly, as a list of states.
+    pub fn to_compact(&self) -> CompactFst<O, L> {
+        CompactFst::from_iter(self.iter()).expect("the keys of an FST are sorted and distinct")
+    }
+}
+
+impl<'a, O, L> IntoIterator for &'a CompactFst<O, L> where O : Output + Codec, L : Label + Codec {
+    type Item = (Vec<L>, O);
+    type IntoIter = CompactIter<'a, O, L>;
+
+    fn into_iter(self) -> CompactIter<'a, O, L> { self.iter() }
+}
+
+
+/// An iterator over the keys and values of a compact FST, in lexicographic
+/// order.
+#[derive(Clone, Debug)]
+pub struct CompactIter<'a, O, L> where O : 'a, L : 'a {
+    fst : &'a CompactFst<O, L>,
+    key : Vec<L>,
+    /// The states along the key, with their next transition and output.
+    stack : Vec<(Node, usize, O)>,
+    origin : Option<O>
+}
+
+impl<'a, O, L> Iterator for CompactIter<'a, O, L> where O : Output + Codec, L : Label + Codec {
+    type Item = (Vec<L>, O);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(out) = self.origin.take() { return Some((Vec::new(), out)) }
+
+        let fst = self.fst;
+        while let Some((node, k, out)) = self.stack.last_mut() {
+            if *k == node.len {
+                self.stack.pop();
+                self.key.pop();
+                continue
+            }
+            let (node, transition) = (*node, *k);
+            *k += 1;
+            let output = out.mappend(fst.output(&node, transition));
+            let next = fst.node(fst.target(&node, transition));
+            self.key.push(fst.label(&node, transition));
+            self.stack.push((next, 0, output));
+            if let Some(value) = fst.value(&next, output) {
+                return Some((self.key.clone(), value))
+            }
+        }
+        None
+    }
+}
+
+
+/// The bytes of a little-endian value, less its trailing zeros.
+fn width(bytes : &[u8]) -> usize {
+    bytes.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1)
+}
+
+#[inline]
+fn little_endian(bytes : &[u8]) -> usize {
+    bytes.iter().rev().fold(0, |n, &b| n << 8 | b as usize)
+}
+
+/// Append a varint to be read backwards, its last byte first.
+fn put_backwards(n : usize, buf : &mut Vec<u8>) {
+    let at = buf.len();
+    let mut n = n;
+    loop {
+        let group = (n & 0x7F) as u8;
+        n >>= 7;
+        buf.push(group | if n == 0 { 0 } else { 0x80 });
+        if n == 0 { break }
+    }
+    buf[at ..].reverse();
+}
+
+/// Read a varint ending at `pos`, moving `pos` to its first byte.
+#[inline]
+fn take_backwards(bytes : &[u8], pos : &mut usize) -> usize {
+    let (mut n, mut shift) = (0, 0);
+    loop {
+        *pos -= 1;
+        let byte = bytes[*pos];
+        n |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 { return n }
+        shift += 7;
+    }
+}
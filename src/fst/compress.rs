@@ -0,0 +1,143 @@
+//! The compressed encoding of the `next` and `output` sections.
+//!
+//! Values are written as LEB128 varints of their little-endian encoding, so
+//! that zeros take a byte and small values few more. Destinations are
+//! delta-encoded within blocks of `BLOCK` slots: a vacant slot is written as
+//! zero, and any other as one more than the zigzagged difference from the
+//! last destination of the block, the first being measured from the slot
+//! which begins it.
+
+use std::result;
+
+use fst::error::DecodeError;
+use fst::format::Codec;
+use fst::Output;
+use index::Index;
+
+
+/// The number of slots over which destinations are delta-encoded.
+pub const BLOCK : usize = 256;
+
+/// Append the bytes, read as a little-endian integer, as a varint.
+pub fn put_varint(bytes : &[u8], buf : &mut Vec<u8>) {
+    let bits = match bytes.iter().rposition(|&b| b != 0) {
+        Some(last) => 8 * last + 8 - bytes[last].leading_zeros() as usize,
+        None => 0
+    };
+    let group = |at : usize| -> u8 {
+        let low = bytes[at / 8] as u16;
+        let high = bytes.get(at / 8 + 1).map_or(0, |&b| b as u16);
+        (((low | high << 8) >> (at % 8)) & 0x7F) as u8
+    };
+
+    let mut at = 0;
+    loop {
+        let last = at + 7 >= bits;
+        buf.push(if bits == 0 { 0 } else { group(at) } | if last { 0 } else { 0x80 });
+        if last { return }
+        at += 7;
+    }
+}
+
+/// Read a varint at `pos` into `out`, as a little-endian integer of its
+/// width, advancing `pos`; `None` should the input end, or the value not fit.
+pub fn take_varint(input : &[u8], pos : &mut usize, out : &mut [u8]) -> Option<()> {
+    for b in out.iter_mut() { *b = 0 }
+    let mut at = 0;
+    loop {
+        let byte = *input.get(*pos) ?;
+        *pos += 1;
+        let bits = ((byte & 0x7F) as u16) << (at % 8);
+        for (i, &part) in [bits as u8, (bits >> 8) as u8].iter().enumerate() {
+            match out.get_mut(at / 8 + i) {
+                Some(b) => *b |= part,
+                None if part != 0 => return None,
+                None => ()
+            }
+        }
+        if byte & 0x80 == 0 { return Some(()) }
+        at += 7;
+        if at >= 8 * out.len() + 7 { return None }
+    }
+}
+
+/// Append a block of destinations, the first being that of slot `first`.
+pub fn encode_next<I>(block : &[I], first : usize, buf : &mut Vec<u8>)
+    where I : Index
+{
+    let mut reference = first;
+    for &n in block {
+        let token = match n.as_usize() {
+            0 => 0,
+            n => {
+                let delta = n as i128 - reference as i128;
+                reference = n;
+                (((delta << 1) ^ (delta >> 127)) as u128) + 1
+            }
+        };
+        put_varint(&token.to_le_bytes(), buf);
+    }
+}
+
+/// Decode `slots` destinations, reporting invalid values at their offset
+/// from `base`.
+pub fn decode_next<I>(bytes : &[u8], slots : usize, base : usize) -> result::Result<Vec<I>, DecodeError>
+    where I : Index
+{
+    let (mut pos, mut reference) = (0, 0);
+    let mut token = [0; 16];
+    let mut next = Vec::with_capacity(slots.min(bytes.len()));
+    for e in 0 .. slots {
+        if e % BLOCK == 0 { reference = e }
+        let invalid = DecodeError::Invalid { section : "next", offset : base + pos };
+        take_varint(bytes, &mut pos, &mut token).ok_or(invalid.clone()) ?;
+        let n = match u128::from_le_bytes(token) {
+            0 => 0,
+            t => {
+                let zigzag = t - 1;
+                let delta = (zigzag >> 1) as i128 ^ -((zigzag & 1) as i128);
+                let n = reference as i128 + delta;
+                if n <= 0 || n > I::bound() as i128 { return Err(invalid) }
+                reference = n as usize;
+                reference
+            }
+        };
+        next.push(I::as_index(n));
+    }
+    match pos == bytes.len() {
+        true => Ok(next),
+        false => Err(DecodeError::Invalid { section : "next", offset : base + pos })
+    }
+}
+
+/// Append the outputs as varints; outputs without width take no bytes.
+pub fn encode_output<O>(output : &[O], buf : &mut Vec<u8>)
+    where O : Output + Codec
+{
+    if O::WIDTH == 0 { return }
+    let mut encoded = Vec::with_capacity(O::WIDTH);
+    for o in output {
+        encoded.clear();
+        o.encode(&mut encoded);
+        put_varint(&encoded, buf);
+    }
+}
+
+/// Decode `slots` outputs, reporting invalid values at their offset from
+/// `base`.
+pub fn decode_output<O>(bytes : &[u8], slots : usize, base : usize) -> result::Result<Vec<O>, DecodeError>
+    where O : Output + Codec
+{
+    let mut pos = 0;
+    let mut encoded = vec![0; O::WIDTH];
+    let mut output = Vec::with_capacity(slots.min(bytes.len().max(1)));
+    for _ in 0 .. slots {
+        let invalid = DecodeError::Invalid { section : "output", offset : base + pos };
+        if O::WIDTH > 0 { take_varint(bytes, &mut pos, &mut encoded).ok_or(invalid.clone()) ? }
+        output.push(O::decode(&encoded).ok_or(invalid) ?);
+    }
+    match pos == bytes.len() {
+        true => Ok(output),
+        false => Err(DecodeError::Invalid { section : "output", offset : base + pos })
+    }
+}
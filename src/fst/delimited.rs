@@ -0,0 +1,122 @@
+//! Construction from delimited text, such as two-column TSV files.
+
+use std::fmt::Display;
+use std::io::BufRead;
+use std::result;
+use std::str;
+
+use fst::builder::Builder;
+use fst::error::{Error, Result};
+use fst::output::Output;
+use index::Index;
+
+
+/// Which delimiter of a line separates its key from its value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Split {
+    /// The key is the first column, and the value may contain the delimiter.
+    First,
+    /// The value is the last column, and the key may contain the delimiter.
+    Last
+}
+
+/// The layout of lines holding a key and a value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Delimited {
+    delimiter : u8,
+    split : Split,
+    skip_empty : bool
+}
+
+impl Default for Delimited {
+    fn default() -> Delimited { Delimited::tsv() }
+}
+
+impl Delimited {
+    /// Tab-separated lines, split at the first tab, skipping empty lines.
+    pub fn tsv() -> Delimited {
+        Delimited { delimiter : b'\t', split : Split::First, skip_empty : true }
+    }
+
+    /// Comma-separated lines, split at the first comma, skipping empty lines.
+    pub fn csv() -> Delimited { Delimited { delimiter : b',', ..Delimited::tsv() } }
+
+    pub fn delimiter(mut self, delimiter : u8) -> Delimited {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn split(mut self, split : Split) -> Delimited {
+        self.split = split;
+        self
+    }
+
+    /// Set whether empty lines are skipped, or rejected with `Parse`.
+    pub fn skip_empty_lines(mut self, skip : bool) -> Delimited {
+        self.skip_empty = skip;
+        self
+    }
+
+    /// Split a line, without its terminator, into key and value.
+    fn split_line<'l>(&self, line : &'l [u8]) -> Option<(&'l [u8], &'l [u8])> {
+        let at = match self.split {
+            Split::First => line.iter().position(|&b| b == self.delimiter),
+            Split::Last => line.iter().rposition(|&b| b == self.delimiter)
+        } ?;
+        Some((&line[.. at], &line[at + 1 ..]))
+    }
+}
+
+
+impl<I, O> Builder<I, O> where I : Index, O : Output {
+    /// Build from tab-separated lines of keys and values, sorted by key.
+    pub fn from_tsv<R, F, E>(reader : R, parse_value : F) -> Result<Builder<I, O>>
+        where R : BufRead
+            , F : Fn(&str) -> result::Result<O, E>
+            , E : Display
+    {
+        Builder::from_delimited(reader, Delimited::tsv(), parse_value)
+    }
+
+    /// Build from delimited lines of keys and values, sorted by key.
+    ///
+    /// Lines may end in `\n` or `\r\n`. Lines which cannot be split or whose
+    /// value cannot be parsed are reported with `Parse`, by line number.
+    pub fn from_delimited<R, F, E>(mut reader : R, format : Delimited, parse_value : F)
+        -> Result<Builder<I, O>>
+        where R : BufRead
+            , F : Fn(&str) -> result::Result<O, E>
+            , E : Display
+    {
+        let mut builder = Builder::new();
+        let mut buffer = Vec::new();
+        let mut line = 0;
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer).map_err(Error::from) ? == 0 {
+                break
+            }
+            line += 1;
+            let parse_error = |message : String| Error::Parse { line, message };
+
+            let mut text = buffer.as_slice();
+            if text.ends_with(b"\n") { text = &text[.. text.len() - 1] }
+            if text.ends_with(b"\r") { text = &text[.. text.len() - 1] }
+            if text.is_empty() {
+                match format.skip_empty {
+                    true => continue,
+                    false => return Err(parse_error("empty line".to_owned()))
+                }
+            }
+
+            let (key, value) = format.split_line(text)
+                .ok_or_else(|| parse_error("missing delimiter".to_owned())) ?;
+            let value = str::from_utf8(value).map_err(|e| parse_error(e.to_string())) ?;
+            let value = parse_value(value).map_err(|e| parse_error(e.to_string())) ?;
+            builder.insert(key, value) ?;
+        }
+        builder.finish() ?;
+
+        Ok(builder)
+    }
+}
@@ -0,0 +1,39 @@
+//! FSTs serialized with postcard, for embedded targets loading a prebuilt
+//! FST from flash.
+//!
+//! The serde representation makes no assumption of `std`: the Dart is a set
+//! of parallel arrays, and the state outputs a sequence of pairs in order of
+//! state, which a target without `std` may collect into any container of its
+//! own, such as a `BTreeMap` or a sorted `Vec`, as `FST` itself does.
+
+use postcard;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use fst::{FST, Output};
+use fst::error::{Error, Result};
+use index::Index;
+use label::Label;
+
+
+impl<I, O, L> FST<I, O, L>
+    where I : Index + Serialize + DeserializeOwned
+        , O : Output + Serialize + DeserializeOwned
+        , L : Label + Serialize + DeserializeOwned
+{
+    /// Serialize the FST with postcard into `buf`, without allocating, and
+    /// return the part of `buf` written.
+    ///
+    /// Fails with `Serialization` should `buf` be too small.
+    pub fn to_postcard<'b>(&self, buf : &'b mut [u8]) -> Result<&'b mut [u8]> {
+        postcard::to_slice(self, buf).map_err(Error::serialization)
+    }
+
+    /// Deserialize an FST serialized with `to_postcard`, allocating only its
+    /// own vectors and table.
+    ///
+    /// Malformed or truncated input fails with `Serialization`.
+    pub fn from_postcard(bytes : &[u8]) -> Result<FST<I, O, L>> {
+        postcard::from_bytes(bytes).map_err(Error::serialization)
+    }
+}
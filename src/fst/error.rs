@@ -1,27 +1,93 @@
 use std::error;
 use std::fmt;
+use std::io;
+use std::ops::{Deref, RangeInclusive};
 use std::result;
 use std::str;
+use std::sync::Arc;
+
+use fst::format::TypeTag;
 
 
 pub type Result<T> = result::Result<T, Error>;
 
-#[derive(Clone, Debug)]
+/// Errors of construction and of operations on FSTs.
+///
+/// Keys are reported as bytes: those over wider labels, in big-endian order.
+/// The `index` of a key is its position among those inserted, from zero.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Error {
-    /// A duplicate key was inserted in the FST builder.
-    Duplicate(Vec<u8>),
-    /// A key was inserted out of order in the FST builder.
-    OutOfOrder(Vec<u8>, Vec<u8>),
-    /// The length of the Dart exceeds its index size.
-    OutOfBounds { reached : usize, maximum : usize }
+    /// A duplicate key was inserted in the FST builder, with the value
+    /// already present and the one inserted, where known.
+    Duplicate { key : Vec<u8>, index : usize, existing_hint : Option<String>, inserted_hint : Option<String> },
+    /// A key was inserted out of order in the FST builder, after the greater
+    /// `previous`.
+    OutOfOrder { key : Vec<u8>, previous : Vec<u8>, index : usize },
+    /// The length of the Dart exceeds its index size, if while inserting a
+    /// key, at that key.
+    OutOfBounds { reached : usize, maximum : usize, at_key : Option<Vec<u8>> },
+    /// A value does not fit in the output type.
+    OutputOverflow { key : Vec<u8>, value : String },
+    /// A key was inserted in a finished FST builder.
+    Finished,
+    /// A line of delimited input could not be read as a key and value.
+    Parse { line : usize, message : String },
+    /// Reading input or writing output failed, as the underlying error tells.
+    Io(Shared<io::Error>),
+    /// A key longer than the builder's limit was inserted; only its first
+    /// labels are kept.
+    KeyTooLong { length : usize, limit : usize, preview : Vec<u8> },
+    /// A key to be removed is not present in the FST.
+    Absent(Vec<u8>),
+    /// Rebuilding a filtered FST failed, after `kept` of `seen` entries were
+    /// retained.
+    Filtered { seen : usize, kept : usize, error : Box<Error> },
+    /// A key maps to an ordinal past the end of its payload table.
+    OrdinalOutOfRange { key : Vec<u8>, ordinal : usize, payloads : usize },
+    /// A builder checkpoint, a payload map or an FST could not be serialized
+    /// or deserialized.
+    Serialization(Shared<dyn error::Error + Send + Sync>),
+    /// An encoded FST could not be decoded.
+    Decode(DecodeError),
+    /// An FST could not be converted to the format of another library.
+    Export(String),
+    /// A lookup needed a section of the encoded FST which was not loaded.
+    Unloaded(&'static str),
+    /// A key ends in a final state whose inner output is missing, as in a
+    /// corrupted FST.
+    MissingStateOutput { key : Vec<u8>, state : usize }
 }
 
 impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
-            Error::Duplicate(_) => "a duplicate key was inserted in the FST builder",
-            Error::OutOfOrder(_, _) => "a key was inserted out of order in the FST builder",
+            Error::Duplicate { .. } => "a duplicate key was inserted in the FST builder",
+            Error::OutOfOrder { .. } => "a key was inserted out of order in the FST builder",
             Error::OutOfBounds { .. } => "the Dart has grown too large for its index type",
+            Error::OutputOverflow { .. } => "a value does not fit in the output type",
+            Error::Finished => "a key was inserted in a finished FST builder",
+            Error::Parse { .. } => "a line of input could not be read as a key and value",
+            Error::Io(_) => "reading input or writing output failed",
+            Error::KeyTooLong { .. } => "a key longer than the limit was inserted in the FST builder",
+            Error::Absent(_) => "a key to be removed is not present in the FST",
+            Error::Filtered { .. } => "rebuilding a filtered FST failed",
+            Error::OrdinalOutOfRange { .. } => "a key maps to an ordinal with no payload",
+            Error::Serialization(_) => "a checkpoint, a payload map or an FST could not be serialized or deserialized",
+            Error::Decode(_) => "an encoded FST could not be decoded",
+            Error::Export(_) => "an FST could not be converted to another format",
+            Error::Unloaded(_) => "a lookup needed a section which was not loaded",
+            Error::MissingStateOutput { .. } => "a key ends in a final state without its state output",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Filtered { ref error, .. } => Some(&**error),
+            Error::Decode(ref error) => Some(error),
+            Error::Io(ref error) => Some(&**error),
+            Error::Serialization(ref error) => Some(&**error),
+            _ => None
         }
     }
 }
@@ -29,24 +95,290 @@ impl error::Error for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::Duplicate(ref k) => write!(f, "\
+            Error::Duplicate { ref key, index, ref existing_hint, ref inserted_hint } => {
+                write!(f, "\
 FST construction error: the key
 {}
-was already present. All keys must be unique.", format_bytes(&k)),
+inserted at index {} was already present.", format_bytes(key), index) ?;
+                if let (Some(existing), Some(inserted)) = (existing_hint, inserted_hint) {
+                    write!(f, "\nThe value present is {}, and the one inserted {}.", existing, inserted) ?;
+                }
+                write!(f, " All keys must be unique.")
+            },
 
-            Error::OutOfOrder(ref k1, ref k2) => write!(f, "\
-FST construction error: a key was inserted out of order.
+            Error::OutOfOrder { ref key, ref previous, index } => write!(f, "\
+FST construction error: a key was inserted out of order, at index {}.
 The lesser key
 {}
 was inserted after the greater key
 {}
-Keys must be inserted in lexicographic order.", format_bytes(&k2), format_bytes(&k1)),
+Keys must be inserted in lexicographic order.", index, format_bytes(key), format_bytes(previous)),
 
-            Error::OutOfBounds { reached, maximum } => write!(f, "\
+            Error::OutOfBounds { reached, maximum, ref at_key } => {
+                write!(f, "\
 FST construction error: the FST outgrew its index type.
 An FST with a maximum index of {} reached a state or transition that
-required an index of {}.", maximum, reached),
+required an index of {}.", maximum, reached) ?;
+                match *at_key {
+                    Some(ref key) => write!(f, "\nThe key being inserted was\n{}", format_bytes(key)),
+                    None => Ok(())
+                }
+            },
+
+            Error::OutputOverflow { ref key, ref value } => write!(f, "\
+FST construction error: the value {} of the key
+{}
+does not fit in the output type.", value, format_bytes(key)),
+
+            Error::Finished => write!(f, "\
+FST construction error: a key was inserted after the builder was finished."),
+
+            Error::Parse { line, ref message } => write!(f, "\
+FST input error: line {} could not be read as a key and value: {}", line, message),
+
+            Error::Io(ref e) => write!(f, "\
+FST I/O error: {}", e),
+
+            Error::KeyTooLong { length, limit, ref preview } => write!(f, "\
+FST construction error: a key of {} bytes exceeds the limit of {} bytes.
+The key begins with
+{}", length, limit, format_bytes(preview)),
+
+            Error::Absent(ref k) => write!(f, "\
+FST removal error: the key
+{}
+is not present in the FST.", format_bytes(k)),
+
+            Error::Filtered { seen, kept, ref error } => write!(f, "\
+FST filtering error: rebuilding failed after keeping {} of {} entries.
+{}", kept, seen, error),
+
+            Error::OrdinalOutOfRange { ref key, ordinal, payloads } => write!(f, "\
+FST payload error: the key
+{}
+maps to the ordinal {}, but the table holds {} payloads.", format_bytes(key), ordinal, payloads),
+
+            Error::Serialization(ref e) => write!(f, "\
+FST serialization error: {}", e),
+
+            Error::Decode(ref e) => write!(f, "{}", e),
+
+            Error::Export(ref e) => write!(f, "\
+FST export error: {}", e),
+
+            Error::Unloaded(section) => write!(f, "\
+FST lookup error: the {} section was not loaded.", section),
+
+            Error::MissingStateOutput { ref key, state } => write!(f, "\
+FST lookup error: the key
+{}
+ends in the final state {}, which has no state output.
+The FST is corrupted; see `FST::validate`.", format_bytes(key), state),
+        }
+    }
+}
+
+/// A failure of either the source of pairs or the builder they were fed to,
+/// after `consumed` pairs were read successfully.
+#[derive(Clone, Debug)]
+pub enum BuildOrSourceError<E> {
+    Source { consumed : usize, error : E },
+    Build { consumed : usize, error : Error }
+}
+
+impl<E> BuildOrSourceError<E> {
+    /// The number of pairs read successfully before the failure.
+    pub fn consumed(&self) -> usize {
+        match *self {
+            BuildOrSourceError::Source { consumed, .. } => consumed,
+            BuildOrSourceError::Build { consumed, .. } => consumed
+        }
+    }
+}
+
+impl<E> error::Error for BuildOrSourceError<E> where E : error::Error + 'static {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            BuildOrSourceError::Source { ref error, .. } => Some(error),
+            BuildOrSourceError::Build { ref error, .. } => Some(error)
+        }
+    }
+}
+
+impl<E> fmt::Display for BuildOrSourceError<E> where E : fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildOrSourceError::Source { consumed, ref error } =>
+                write!(f, "FST source error after {} pairs: {}", consumed, error),
+            BuildOrSourceError::Build { consumed, ref error } =>
+                write!(f, "FST build error after {} pairs:\n{}", consumed, error),
+        }
+    }
+}
+
+/// Errors of decoding an FST from its binary format, with offsets in bytes
+/// from the start of the encoding.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The input does not begin with the format's magic number.
+    Magic,
+    /// The input is of a format version this library cannot read.
+    UnsupportedVersion { found : u16, supported : RangeInclusive<u16> },
+    /// The width or kind of a type in the header differs from that expected.
+    TypeMismatch { of : &'static str, expected : TypeTag, found : TypeTag },
+    /// The index in the header is wider than this platform can address.
+    IndexTooWide { width : usize, platform : usize },
+    /// The input ends within a section.
+    Truncated { section : &'static str, offset : usize },
+    /// A section holds a value invalid for its type, or inconsistent with
+    /// the rest of the FST.
+    Invalid { section : &'static str, offset : usize },
+    /// Bytes remain after the last section.
+    Trailing { offset : usize },
+    /// A section does not match the checksum recorded in the header.
+    Checksum { section : &'static str },
+    /// The FST is compressed, and cannot be read in place.
+    Compressed
+}
+
+impl error::Error for DecodeError {}
+
+impl From<DecodeError> for Error {
+    fn from(e : DecodeError) -> Error { Error::Decode(e) }
+}
+
+impl From<io::Error> for Error {
+    fn from(e : io::Error) -> Error { Error::Io(Shared(Arc::new(e))) }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Magic => write!(f, "FST decoding error: not an encoded FST"),
+            DecodeError::UnsupportedVersion { found, ref supported } => write!(f, "\
+FST decoding error: unsupported format version {}; versions {} to {} are supported", found, supported.start(), supported.end()),
+            DecodeError::TypeMismatch { of, expected, found } => write!(f, "\
+FST decoding error: the {} type is {}, but the FST was encoded with {}", of, expected, found),
+            DecodeError::IndexTooWide { width, platform } => write!(f, "\
+FST decoding error: the index was encoded with {} bytes, wider than this platform's {}-byte usize", width, platform),
+            DecodeError::Truncated { section, offset } => write!(f, "\
+FST decoding error: truncated in {} section at byte {}", section, offset),
+            DecodeError::Invalid { section, offset } => write!(f, "\
+FST decoding error: invalid value in {} section at byte {}", section, offset),
+            DecodeError::Trailing { offset } => write!(f, "\
+FST decoding error: unexpected bytes after the FST at byte {}", offset),
+            DecodeError::Checksum { section } => write!(f, "\
+FST decoding error: checksum mismatch in {} section", section),
+            DecodeError::Compressed => write!(f, "\
+FST decoding error: the FST is compressed, and must be decoded whole"),
+        }
+    }
+}
+
+/// The inconsistencies found in an FST by `FST::validate`: the first few,
+/// in order of slot, and how many there are in all.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    pub violations : Vec<Violation>,
+    pub total : usize
+}
+
+/// An inconsistency of the Dart or of the state outputs, by slot or state.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Violation {
+    /// The destination of a slot lies outside the Dart.
+    NextOutOfRange { slot : usize, next : usize },
+    /// The check label of a slot places it in no state: its offset from the
+    /// label is neither the root nor the destination of a transition.
+    CheckOutOfPlace { slot : usize, check : usize },
+    /// A final transition with inner output, or the root, leads to a state
+    /// without a state output.
+    MissingStateOutput { slot : usize, state : usize },
+    /// A state output belongs to no state reached by a final transition with
+    /// inner output.
+    OrphanStateOutput { state : usize },
+    /// The transition of a slot leads back to a state on its path from the
+    /// root, so that traversals never end.
+    Cycle { slot : usize }
+}
+
+impl error::Error for ValidationError {}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FST validation error: {} inconsistencies, the first being:", self.total) ?;
+        for violation in &self.violations { write!(f, "\n{}", violation) ? }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::NextOutOfRange { slot, next } =>
+                write!(f, "slot {}: destination {} lies outside the Dart", slot, next),
+            Violation::CheckOutOfPlace { slot, check } =>
+                write!(f, "slot {}: check label {} places it in no state", slot, check),
+            Violation::MissingStateOutput { slot, state } =>
+                write!(f, "slot {}: state {} is final with inner output, but has none", slot, state),
+            Violation::OrphanStateOutput { state } =>
+                write!(f, "state {}: state output without a final transition with inner output", state),
+            Violation::Cycle { slot } =>
+                write!(f, "slot {}: transition leads back to a state on its path", slot),
+        }
+    }
+}
+
+/// An underlying error, shared so that `Error` can be cloned.
+///
+/// Two are equal if they are the same error, or display alike.
+#[derive(Debug)]
+pub struct Shared<E : ?Sized>(Arc<E>);
+
+impl<E : ?Sized> Clone for Shared<E> {
+    fn clone(&self) -> Shared<E> { Shared(self.0.clone()) }
+}
+
+impl<E : ?Sized> Deref for Shared<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E { &self.0 }
+}
+
+impl<E> PartialEq for Shared<E> where E : fmt::Display + ?Sized {
+    fn eq(&self, other : &Shared<E>) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl<E> fmt::Display for Shared<E> where E : fmt::Display + ?Sized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.0.fmt(f) }
+}
+
+impl Error {
+    /// A serialization error, keeping the error of the serializer.
+    #[cfg(any(feature = "serialization", feature = "postcard"))]
+    pub(crate) fn serialization<E>(e : E) -> Error where E : error::Error + Send + Sync + 'static {
+        Error::Serialization(Shared(Arc::new(e)))
+    }
+
+    /// The error with the index of its key, if it records one, moved ahead
+    /// by `offset`, as for keys inserted in a builder from the middle of a
+    /// longer input.
+    pub(crate) fn shifted(mut self, offset : usize) -> Error {
+        match self {
+            Error::Duplicate { ref mut index, .. } | Error::OutOfOrder { ref mut index, .. } => *index += offset,
+            _ => ()
+        }
+        self
+    }
+
+    /// The error, if it is `OutOfBounds` without a key, at the given key.
+    pub(crate) fn at_key(mut self, key : Vec<u8>) -> Error {
+        if let Error::OutOfBounds { ref mut at_key, .. } = self {
+            at_key.get_or_insert(key);
         }
+        self
     }
 }
 
@@ -0,0 +1,1063 @@
+//! A compact binary format for FSTs.
+//!
+//! An encoded FST is a header followed by four sections, all little-endian:
+//!
+//! - the header: the magic number `ATLF`, the format version (`u16`), the
+//!   widths in bytes of the label (`u8`), index (`u8`) and output (`u32`)
+//!   types, the number of Dart slots and of state outputs (`u64`), the
+//!   CRC-32 of each section (`u32`), the section table: the offset and
+//!   length in bytes of each section (`u64`), the flags (`u32`), then the
+//!   kinds of the label, index and output types (`u8`) and a zero byte;
+//! - `stipe`: for each slot, its check label and finality (`u8`);
+//! - `next`: for each slot, its destination;
+//! - `output`: for each slot, its output;
+//! - `state_output`: the state outputs, as pairs of state and output, in
+//!   order of state.
+//!
+//! The version decides the layout of the header; see `migrate`.
+//!
+//! Every section is an array of fixed-width values, so that the Dart can be
+//! read in place, and is found through the table, so that each can be read
+//! or mapped on its own. Sections follow each other in order, though they
+//! may be padded apart. Widths do not depend on the platform: `usize` and
+//! `isize` are encoded on 8 bytes, and an FST built on one target loads on
+//! another, provided its index fits the platform's `usize`.
+//!
+//! FSTs flagged `COMPRESSED` instead encode `next` and `output` as varints,
+//! as described in `compress`; they are smaller on disk, but must be decoded
+//! whole rather than read in place.
+//!
+//! Decoding checks the width and kind of each type against the header, so
+//! that an FST is not read as one of other types of the same width, and
+//! verifies the checksums unless told otherwise, so that a corrupted FST
+//! fails to load rather than answering wrongly. Checked decoding also
+//! rejects destinations outside the Dart, and transitions leading back to a
+//! state on their path, whence traversals would never end.
+
+use std::cmp;
+use std::convert::TryInto;
+use std::fmt;
+use std::iter::FromIterator;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read};
+use std::mem;
+use std::ops::{BitOr, BitOrAssign};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use fst::{Dart, FST, Output, StateOutputs, Stipe, Stipes, Terminal};
+use fst::analyze;
+use fst::compress;
+use fst::error::{DecodeError, Error, Result};
+use fst::migrate;
+use fst::output::{Bytes, F32, F64, Max, Min, Unshared};
+use fst::store::DartStore;
+use index::{Index, U24};
+use label::Label;
+
+
+pub const MAGIC : [u8; 4] = *b"ATLF";
+pub const VERSION : u16 = 1;
+
+/// The width of the header of the current version, in bytes.
+pub const HEADER_WIDTH : usize = 116;
+
+/// The offset of the section table in the header.
+const TABLE : usize = 44;
+
+/// The offset of the flags in the header.
+const FLAGS : usize = 108;
+
+/// The offset of the type kinds in the header.
+const KINDS : usize = 112;
+
+/// The flag of FSTs whose `next` and `output` sections are compressed.
+pub const COMPRESSED : u32 = 1;
+
+/// The number of bytes buffered at a time when streaming.
+const CHUNK : usize = 1 << 16;
+
+/// Values of a fixed width, encoded in little-endian order.
+pub trait Codec : Sized {
+    /// The width of an encoded value, in bytes.
+    const WIDTH : usize;
+
+    /// The kind of value, recorded in the header along with the width.
+    const KIND : Kind = Kind::Opaque;
+
+    /// Append the encoded value to `buf`.
+    fn encode(&self, buf : &mut Vec<u8>);
+
+    /// Decode a value from exactly `WIDTH` bytes, unless they encode none.
+    fn decode(bytes : &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_codec_int {
+    ($num:ty, $kind:ident) => {
+        impl Codec for $num {
+            const WIDTH : usize = ::std::mem::size_of::<$num>();
+            const KIND : Kind = Kind::$kind;
+
+            #[inline]
+            fn encode(&self, buf : &mut Vec<u8>) { buf.extend_from_slice(&self.to_le_bytes()) }
+
+            #[inline]
+            fn decode(bytes : &[u8]) -> Option<Self> { bytes.try_into().ok().map(<$num>::from_le_bytes) }
+        }
+    }
+}
+
+impl_codec_int! { u8,   Unsigned }
+impl_codec_int! { u16,  Unsigned }
+impl_codec_int! { u32,  Unsigned }
+impl_codec_int! { u64,  Unsigned }
+impl_codec_int! { u128, Unsigned }
+impl_codec_int! { i8,   Signed   }
+impl_codec_int! { i16,  Signed   }
+impl_codec_int! { i32,  Signed   }
+impl_codec_int! { i64,  Signed   }
+impl_codec_int! { i128, Signed   }
+
+impl Codec for U24 {
+    const WIDTH : usize = 3;
+    const KIND : Kind = Kind::Unsigned;
+
+    #[inline]
+    fn encode(&self, buf : &mut Vec<u8>) { buf.extend_from_slice(&self.to_le_bytes()) }
+
+    #[inline]
+    fn decode(bytes : &[u8]) -> Option<Self> { bytes.try_into().ok().map(U24::from_le_bytes) }
+}
+
+/// Pointer-sized integers are encoded on 64 bits, whatever the platform.
+impl Codec for usize {
+    const WIDTH : usize = 8;
+    const KIND : Kind = Kind::Unsigned;
+
+    #[inline]
+    fn encode(&self, buf : &mut Vec<u8>) { (*self as u64).encode(buf) }
+
+    #[inline]
+    fn decode(bytes : &[u8]) -> Option<Self> { u64::decode(bytes).and_then(|x| x.try_into().ok()) }
+}
+
+impl Codec for isize {
+    const WIDTH : usize = 8;
+    const KIND : Kind = Kind::Signed;
+
+    #[inline]
+    fn encode(&self, buf : &mut Vec<u8>) { (*self as i64).encode(buf) }
+
+    #[inline]
+    fn decode(bytes : &[u8]) -> Option<Self> { i64::decode(bytes).and_then(|x| x.try_into().ok()) }
+}
+
+impl Codec for () {
+    const WIDTH : usize = 0;
+
+    #[inline] fn encode(&self, _ : &mut Vec<u8>) {}
+    #[inline] fn decode(_ : &[u8]) -> Option<Self> { Some(()) }
+}
+
+impl<A, B> Codec for (A, B) where A : Codec, B : Codec {
+    const WIDTH : usize = A::WIDTH + B::WIDTH;
+
+    #[inline]
+    fn encode(&self, buf : &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+
+    #[inline]
+    fn decode(bytes : &[u8]) -> Option<Self> {
+        Some((A::decode(&bytes[.. A::WIDTH]) ?, B::decode(&bytes[A::WIDTH ..]) ?))
+    }
+}
+
+impl<const N : usize> Codec for [u8; N] {
+    const WIDTH : usize = N;
+
+    #[inline] fn encode(&self, buf : &mut Vec<u8>) { buf.extend_from_slice(self) }
+    #[inline] fn decode(bytes : &[u8]) -> Option<Self> { bytes.try_into().ok() }
+}
+
+/// A length byte, then the bytes, padded to `N`.
+impl<const N : usize> Codec for Bytes<N> {
+    const WIDTH : usize = N + 1;
+
+    #[inline]
+    fn encode(&self, buf : &mut Vec<u8>) {
+        buf.push(self.len() as u8);
+        buf.extend_from_slice(self.as_bytes());
+        buf.resize(buf.len() + N - self.len(), 0);
+    }
+
+    #[inline]
+    fn decode(bytes : &[u8]) -> Option<Self> {
+        let len = *bytes.first() ? as usize;
+        if len > N || bytes.len() != N + 1 { return None }
+        Bytes::new(&bytes[1 .. 1 + len])
+    }
+}
+
+impl Codec for F32 {
+    const WIDTH : usize = 4;
+    const KIND : Kind = Kind::Float;
+
+    #[inline] fn encode(&self, buf : &mut Vec<u8>) { self.get().to_bits().encode(buf) }
+    #[inline] fn decode(bytes : &[u8]) -> Option<Self> { F32::new(f32::from_bits(u32::decode(bytes) ?)) }
+}
+
+impl Codec for F64 {
+    const WIDTH : usize = 8;
+    const KIND : Kind = Kind::Float;
+
+    #[inline] fn encode(&self, buf : &mut Vec<u8>) { self.get().to_bits().encode(buf) }
+    #[inline] fn decode(bytes : &[u8]) -> Option<Self> { F64::new(f64::from_bits(u64::decode(bytes) ?)) }
+}
+
+macro_rules! impl_codec_wrapper {
+    ($name:ident) => {
+        impl<T> Codec for $name<T> where T : Codec {
+            const WIDTH : usize = T::WIDTH;
+            const KIND : Kind = T::KIND;
+
+            #[inline] fn encode(&self, buf : &mut Vec<u8>) { self.0.encode(buf) }
+            #[inline] fn decode(bytes : &[u8]) -> Option<Self> { T::decode(bytes).map($name) }
+        }
+    }
+}
+
+impl_codec_wrapper! { Min      }
+impl_codec_wrapper! { Max      }
+impl_codec_wrapper! { Unshared }
+
+impl Terminal {
+    #[inline]
+    pub(crate) fn encode(self) -> u8 {
+        match self {
+            Terminal::Not => 0,
+            Terminal::Empty => 1,
+            Terminal::Inner => 2
+        }
+    }
+
+    #[inline]
+    pub(crate) fn decode(byte : u8) -> Option<Terminal> {
+        match byte {
+            0 => Some(Terminal::Not),
+            1 => Some(Terminal::Empty),
+            2 => Some(Terminal::Inner),
+            _ => None
+        }
+    }
+}
+
+/// The kind of values of a type, which tells apart types of the same width.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// Values which are not numbers, or several of them, such as bytes and
+    /// pairs.
+    Opaque,
+    Unsigned,
+    Signed,
+    Float
+}
+
+impl Kind {
+    #[inline]
+    pub(crate) fn encode(self) -> u8 {
+        match self {
+            Kind::Opaque => 0,
+            Kind::Unsigned => 1,
+            Kind::Signed => 2,
+            Kind::Float => 3
+        }
+    }
+
+    #[inline]
+    pub(crate) fn decode(byte : u8) -> Option<Kind> {
+        match byte {
+            0 => Some(Kind::Opaque),
+            1 => Some(Kind::Unsigned),
+            2 => Some(Kind::Signed),
+            3 => Some(Kind::Float),
+            _ => None
+        }
+    }
+}
+
+/// The width and kind of a type, as recorded in the header.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TypeTag {
+    pub width : usize,
+    pub kind : Kind
+}
+
+impl TypeTag {
+    pub fn of<T>() -> TypeTag where T : Codec {
+        TypeTag { width : T::WIDTH, kind : T::KIND }
+    }
+
+    /// Whether values of this tag may be read as values of `expected`, of
+    /// the same width and kind.
+    pub fn admits(&self, expected : &TypeTag) -> bool { self == expected }
+}
+
+impl fmt::Display for TypeTag {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::Opaque => write!(f, "{}-byte opaque", self.width),
+            Kind::Unsigned => write!(f, "{}-byte unsigned", self.width),
+            Kind::Signed => write!(f, "{}-byte signed", self.width),
+            Kind::Float => write!(f, "{}-byte float", self.width)
+        }
+    }
+}
+
+
+/// The header of an encoded FST.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub version : u16,
+    pub label_width : usize,
+    pub index_width : usize,
+    pub output_width : usize,
+    /// The number of Dart slots.
+    pub slots : usize,
+    /// The number of state outputs.
+    pub state_outputs : usize,
+    /// The CRC-32 of each section, in order.
+    pub checksums : [u32; 4],
+    /// The offset and length in bytes of each section, in order.
+    pub table : [(usize, usize); 4],
+    pub flags : u32,
+    /// The kinds of the label, index and output types.
+    pub kinds : [Kind; 3]
+}
+
+impl Header {
+    /// The header of an FST of the given types, with checksums yet unset and
+    /// sections laid out one after the other.
+    pub fn of<I, O, L>(slots : usize, state_outputs : usize) -> Header
+        where I : Codec, O : Codec, L : Codec
+    {
+        let mut header = Header {
+            version : VERSION,
+            label_width : L::WIDTH,
+            index_width : I::WIDTH,
+            output_width : O::WIDTH,
+            slots,
+            state_outputs,
+            checksums : [0; 4],
+            table : [(0, 0); 4],
+            flags : 0,
+            kinds : [L::KIND, I::KIND, O::KIND]
+        };
+        header.lay_out();
+        header
+    }
+
+    /// Lay the sections out one after the other, following the header,
+    /// unless their widths overflow.
+    fn lay_out(&mut self) {
+        if let Some(sections) = self.sections() {
+            self.lay_out_as(sections.map(|(_, width)| width));
+        }
+    }
+
+    /// Lay out sections of the given lengths one after the other, following
+    /// the header.
+    fn lay_out_as(&mut self, lens : [usize; 4]) {
+        let mut offset = self.width();
+        for (entry, &len) in self.table.iter_mut().zip(&lens) {
+            *entry = (offset, len);
+            offset = offset.saturating_add(len);
+        }
+    }
+
+    /// Whether `next` and `output` are compressed.
+    pub fn is_compressed(&self) -> bool { self.flags & COMPRESSED != 0 }
+
+    /// The width of the header, in bytes, by its version.
+    pub fn width(&self) -> usize {
+        migrate::header_width(self.version).unwrap_or(HEADER_WIDTH)
+    }
+
+    pub fn encode(&self, buf : &mut Vec<u8>) {
+        buf.extend_from_slice(&MAGIC);
+        self.version.encode(buf);
+        (self.label_width as u8).encode(buf);
+        (self.index_width as u8).encode(buf);
+        (self.output_width as u32).encode(buf);
+        (self.slots as u64).encode(buf);
+        (self.state_outputs as u64).encode(buf);
+        for checksum in &self.checksums { checksum.encode(buf) }
+        for &(offset, len) in &self.table {
+            (offset as u64).encode(buf);
+            (len as u64).encode(buf);
+        }
+        self.flags.encode(buf);
+        for kind in &self.kinds { kind.encode().encode(buf) }
+        0u8.encode(buf);
+    }
+
+    /// Read a header, checking its magic number and version.
+    pub fn decode(bytes : &[u8]) -> result::Result<Header, DecodeError> {
+        let width = header_width(bytes) ?;
+        if bytes.len() < width { return Err(truncated("header", bytes.len())) }
+
+        Ok(Header {
+            version : u16::decode(&bytes[4 .. 6]).unwrap(),
+            label_width : bytes[6] as usize,
+            index_width : bytes[7] as usize,
+            output_width : u32::decode(&bytes[8 .. 12]).unwrap() as usize,
+            slots : count(bytes, 12) ?,
+            state_outputs : count(bytes, 20) ?,
+            checksums : checksums(bytes),
+            table : table(bytes) ?,
+            flags : flags(bytes) ?,
+            kinds : kinds(bytes) ?
+        })
+    }
+
+    /// Check that the widths and kinds are those of the given types.
+    ///
+    /// An index wider than both the expected type and the platform's `usize`
+    /// could not be loaded as any index type, and is reported as such.
+    pub fn expect<I, O, L>(&self) -> result::Result<(), DecodeError>
+        where I : Codec, O : Codec, L : Codec
+    {
+        let platform = mem::size_of::<usize>();
+        if self.index_width > I::WIDTH && self.index_width > platform {
+            return Err(DecodeError::IndexTooWide { width : self.index_width, platform })
+        }
+        let found = |width, i : usize| TypeTag { width, kind : self.kinds[i] };
+        let tags = [("label", TypeTag::of::<L>(), found(self.label_width, 0)),
+                    ("index", TypeTag::of::<I>(), found(self.index_width, 1)),
+                    ("output", TypeTag::of::<O>(), found(self.output_width, 2))];
+        match tags.iter().find(|&&(_, expected, found)| !found.admits(&expected)) {
+            Some(&(of, expected, found)) => Err(DecodeError::TypeMismatch { of, expected, found }),
+            None => Ok(())
+        }
+    }
+
+    /// The sections following the header, as their name and width in bytes,
+    /// or `None` should their widths overflow.
+    pub fn sections(&self) -> Option<[(&'static str, usize); 4]> {
+        Some([
+            ("stipe", self.slots.checked_mul(self.label_width + 1) ?),
+            ("next", self.slots.checked_mul(self.index_width) ?),
+            ("output", self.slots.checked_mul(self.output_width) ?),
+            ("state_output", self.state_outputs.checked_mul(self.index_width + self.output_width) ?)
+        ])
+    }
+
+    /// Check that the table lists the sections in order, after the header,
+    /// each as wide as the counts and widths declare, unless compressed.
+    pub fn check_table(&self) -> result::Result<(), DecodeError> {
+        let sections = self.sections().ok_or_else(|| truncated("stipe", self.width())) ?;
+        let mut end = self.width();
+        for (i, (&(offset, len), &(_, width))) in self.table.iter().zip(&sections).enumerate() {
+            let invalid = DecodeError::Invalid { section : "header", offset : TABLE + 16 * i };
+            let fixed = !(self.is_compressed() && (i == 1 || i == 2));
+            if offset < end || (fixed && len != width) { return Err(invalid) }
+            end = offset.checked_add(len).ok_or(invalid) ?;
+        }
+        Ok(())
+    }
+
+    /// Check that an encoding of `len` bytes holds every section, and
+    /// nothing after the last.
+    pub fn check_len(&self, len : usize) -> result::Result<(), DecodeError> {
+        for (&(offset, width), &section) in self.table.iter().zip(&SECTIONS) {
+            if len < offset + width { return Err(truncated(section, len)) }
+        }
+        match self.end() == len {
+            true => Ok(()),
+            false => Err(DecodeError::Trailing { offset : self.end() })
+        }
+    }
+
+    /// The offset past the last section.
+    pub fn end(&self) -> usize {
+        let (offset, len) = self.table[3];
+        offset + len
+    }
+
+    /// Check the bytes of the `i`th section against its checksum.
+    pub fn verify(&self, i : usize, bytes : &[u8]) -> result::Result<(), DecodeError> {
+        match crc32(0, bytes) == self.checksums[i] {
+            true => Ok(()),
+            false => Err(DecodeError::Checksum { section : SECTIONS[i] })
+        }
+    }
+}
+
+/// The width of the header beginning the bytes, checking its magic number
+/// and version.
+fn header_width(bytes : &[u8]) -> result::Result<usize, DecodeError> {
+    if bytes.len() < MAGIC.len() || bytes[.. MAGIC.len()] != MAGIC {
+        return Err(if MAGIC.starts_with(bytes) { truncated("header", bytes.len()) } else { DecodeError::Magic })
+    }
+    if bytes.len() < 6 { return Err(truncated("header", bytes.len())) }
+
+    let found = u16::decode(&bytes[4 .. 6]).unwrap();
+    migrate::header_width(found).ok_or(DecodeError::UnsupportedVersion { found, supported : migrate::SUPPORTED })
+}
+
+/// The count at the given offset of the header, which must fit in `usize`.
+fn count(bytes : &[u8], at : usize) -> result::Result<usize, DecodeError> {
+    u64::decode(&bytes[at .. at + 8]).unwrap().try_into()
+        .map_err(|_| DecodeError::Invalid { section : "header", offset : at })
+}
+
+/// The checksums of the header, which follow the counts.
+fn checksums(bytes : &[u8]) -> [u32; 4] {
+    let mut checksums = [0; 4];
+    for (i, checksum) in checksums.iter_mut().enumerate() {
+        *checksum = u32::decode(&bytes[28 + 4 * i .. 32 + 4 * i]).unwrap();
+    }
+    checksums
+}
+
+/// The section table of the header, which follows the checksums.
+fn table(bytes : &[u8]) -> result::Result<[(usize, usize); 4], DecodeError> {
+    let mut table = [(0, 0); 4];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = (count(bytes, TABLE + 16 * i) ?, count(bytes, TABLE + 16 * i + 8) ?);
+    }
+    Ok(table)
+}
+
+/// The flags of the header, which follow the section table; unknown flags
+/// are refused.
+fn flags(bytes : &[u8]) -> result::Result<u32, DecodeError> {
+    let flags = u32::decode(&bytes[FLAGS .. FLAGS + 4]).unwrap();
+    match flags & !COMPRESSED {
+        0 => Ok(flags),
+        _ => Err(DecodeError::Invalid { section : "header", offset : FLAGS })
+    }
+}
+
+/// The kinds of the label, index and output types, which follow the flags.
+fn kinds(bytes : &[u8]) -> result::Result<[Kind; 3], DecodeError> {
+    let mut kinds = [Kind::Opaque; 3];
+    for (i, kind) in kinds.iter_mut().enumerate() {
+        *kind = Kind::decode(bytes[KINDS + i]).ok_or(DecodeError::Invalid { section : "header", offset : KINDS + i }) ?;
+    }
+    match bytes[KINDS + 3] {
+        0 => Ok(kinds),
+        _ => Err(DecodeError::Invalid { section : "header", offset : KINDS + 3 })
+    }
+}
+
+/// The names of the sections, in order.
+pub(crate) const SECTIONS : [&str; 4] = ["stipe", "next", "output", "state_output"];
+
+
+/// The sizes of an FST as written, in bytes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WriteStats {
+    pub written : usize,
+    /// The size of the FST had it been written uncompressed.
+    pub uncompressed : usize
+}
+
+impl WriteStats {
+    /// The ratio of the uncompressed size to the size written, at least 1
+    /// for any compressed FST but the smallest.
+    pub fn compression_ratio(&self) -> f64 { self.uncompressed as f64 / self.written as f64 }
+}
+
+
+/// A set of sections, as loaded by `FST::load_sections`.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Sections(u8);
+
+impl Sections {
+    pub const NONE : Sections = Sections(0);
+    pub const STIPE : Sections = Sections(1);
+    pub const NEXT : Sections = Sections(1 << 1);
+    pub const OUTPUT : Sections = Sections(1 << 2);
+    pub const STATE_OUTPUT : Sections = Sections(1 << 3);
+    pub const ALL : Sections = Sections(0b1111);
+
+    /// The `i`th section, in order.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn nth(i : usize) -> Sections { Sections(1 << i) }
+
+    /// Whether every section of `other` is in the set.
+    pub fn contains(self, other : Sections) -> bool { self.0 & other.0 == other.0 }
+}
+
+impl BitOr for Sections {
+    type Output = Sections;
+
+    fn bitor(self, other : Sections) -> Sections { Sections(self.0 | other.0) }
+}
+
+impl BitOrAssign for Sections {
+    fn bitor_assign(&mut self, other : Sections) { self.0 |= other.0 }
+}
+
+/// The lookup table of CRC-32 (IEEE), one entry per byte.
+const CRC_TABLE : [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Extend the CRC-32 of some bytes with those following them.
+fn crc32(crc : u32, bytes : &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+fn truncated(section : &'static str, offset : usize) -> DecodeError {
+    DecodeError::Truncated { section, offset }
+}
+
+
+impl<I, O, L> FST<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    /// Encode the FST in the binary format of `fst::format`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.header().end());
+        self.write_to(&mut bytes).expect("writing to a vector cannot fail");
+        bytes
+    }
+
+    /// Encode the FST as with `to_bytes`, compressing `next` and `output`.
+    pub fn to_compressed_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_compressed_to(&mut bytes).expect("writing to a vector cannot fail");
+        bytes
+    }
+
+    /// Decode an FST encoded with `to_bytes` or `to_compressed_bytes`,
+    /// checking the widths of its types, the lengths and checksums of its
+    /// sections, and the validity of its values.
+    pub fn from_bytes(bytes : &[u8]) -> result::Result<FST<I, O, L>, DecodeError> {
+        let (header, sections) = split::<I, O, L>(bytes) ?;
+        verify(&header, &sections) ?;
+        let [stipe, next, output, state_output] = sections;
+        Decoder { header, consistent : true }.decode(stipe, next, output, state_output)
+    }
+
+    /// Decode as with `from_bytes`, for trusted input: neither checksums nor
+    /// the consistency of the FST are checked, only the widths of its types,
+    /// the lengths of its sections, and that its values decode.
+    ///
+    /// An inconsistent FST answers lookups wrongly, but safely; `validate`
+    /// checks it in full.
+    pub fn from_bytes_unchecked(bytes : &[u8]) -> result::Result<FST<I, O, L>, DecodeError> {
+        let (header, [stipe, next, output, state_output]) = split::<I, O, L>(bytes) ?;
+        Decoder { header, consistent : false }.decode(stipe, next, output, state_output)
+    }
+
+    /// Encode the FST as with `to_bytes`, writing it out section by section
+    /// rather than buffering it whole, and return its size.
+    ///
+    /// The sections are encoded twice: once for their checksums and lengths,
+    /// which lead in the header, then for writing.
+    pub fn write_to<W>(&self, w : W) -> Result<WriteStats>
+        where W : io::Write
+    {
+        self.write_sections(w, false)
+    }
+
+    /// Write the FST as with `write_to`, compressing `next` and `output`.
+    pub fn write_compressed_to<W>(&self, w : W) -> Result<WriteStats>
+        where W : io::Write
+    {
+        self.write_sections(w, true)
+    }
+
+    /// Decode an FST written with `write_to`, reading it section by section
+    /// and verifying each against its checksum.
+    ///
+    /// Fails with `Decode` errors positioned in the stream, and leaves the
+    /// reader after the last section.
+    pub fn read_from<R>(r : R) -> Result<FST<I, O, L>>
+        where R : io::Read
+    {
+        FST::read_sections(r, true)
+    }
+
+    /// Decode as with `read_from`, for trusted input, as `from_bytes_unchecked`
+    /// does.
+    pub fn read_from_unchecked<R>(r : R) -> Result<FST<I, O, L>>
+        where R : io::Read
+    {
+        FST::read_sections(r, false)
+    }
+
+    /// Write the FST to a file as with `write_to`, replacing the file
+    /// atomically: the FST is written to a temporary file beside it, synced,
+    /// then renamed over it, so that a failure midway leaves any previous
+    /// file whole.
+    pub fn write_to_path<P>(&self, path : P) -> Result<WriteStats>
+        where P : AsRef<Path>
+    {
+        let path = path.as_ref();
+        let temporary = temporary_path(path);
+        let written = File::create(&temporary).map_err(Error::from).and_then(|file| {
+            let mut w = BufWriter::new(file);
+            let stats = self.write_to(&mut w) ?;
+            let file = w.into_inner().map_err(|e| Error::from(e.into_error())) ?;
+            file.sync_all().map_err(Error::from) ?;
+            fs::rename(&temporary, path).map_err(Error::from) ?;
+            Ok(stats)
+        });
+        if written.is_err() { let _ = fs::remove_file(&temporary); }
+        written
+    }
+
+    /// Decode an FST from a file as with `read_from`, buffered.
+    ///
+    /// Fails with `Io` should the file not be read, and with `Decode` should
+    /// it not hold an FST of these types.
+    pub fn read_from_path<P>(path : P) -> Result<FST<I, O, L>>
+        where P : AsRef<Path>
+    {
+        FST::read_from(BufReader::new(File::open(path).map_err(Error::from) ?))
+    }
+
+    fn header(&self) -> Header {
+        Header::of::<I, O, L>(self.da.stipe.len(), self.state_output.len())
+    }
+
+    fn write_sections<W>(&self, mut w : W, compressed : bool) -> Result<WriteStats>
+        where W : io::Write
+    {
+        let mut header = self.header();
+        let uncompressed = header.end();
+        let mut lens = [0; 4];
+        self.encode_sections(compressed, |i, chunk| {
+            header.checksums[i] = crc32(header.checksums[i], chunk);
+            lens[i] += chunk.len();
+            Ok(())
+        }) ?;
+        if compressed { header.flags |= COMPRESSED }
+        header.lay_out_as(lens);
+
+        let mut buf = Vec::with_capacity(HEADER_WIDTH);
+        header.encode(&mut buf);
+        w.write_all(&buf).map_err(Error::from) ?;
+        self.encode_sections(compressed, |_, chunk| w.write_all(chunk).map_err(Error::from)) ?;
+        Ok(WriteStats { written : header.end(), uncompressed })
+    }
+
+    /// Encode the sections in order, passing them to `sink` by chunks along
+    /// with the index of their section.
+    fn encode_sections<F>(&self, compressed : bool, mut sink : F) -> Result<()>
+        where F : FnMut(usize, &[u8]) -> Result<()>
+    {
+        let mut buf = Vec::with_capacity(CHUNK);
+
+        for stipe in self.da.stipe.iter() {
+            stipe.check.encode(&mut buf);
+            buf.push(stipe.terminal.encode());
+            spill(&mut sink, 0, &mut buf, CHUNK) ?;
+        }
+        spill(&mut sink, 0, &mut buf, 0) ?;
+        if compressed {
+            for (b, block) in self.da.next.chunks(compress::BLOCK).enumerate() {
+                compress::encode_next(block, b * compress::BLOCK, &mut buf);
+                spill(&mut sink, 1, &mut buf, CHUNK) ?;
+            }
+        } else {
+            for next in &self.da.next {
+                next.encode(&mut buf);
+                spill(&mut sink, 1, &mut buf, CHUNK) ?;
+            }
+        }
+        spill(&mut sink, 1, &mut buf, 0) ?;
+        // A Dart without outputs is written as zeros, a block at a time.
+        let (len, has_outputs) = (self.len(), self.da.has_outputs());
+        let zeros = match has_outputs {
+            true => Vec::new(),
+            false => vec![O::zero(); cmp::min(compress::BLOCK, len)]
+        };
+        let blocks = (0 .. len).step_by(compress::BLOCK).map(|start| {
+            let end = cmp::min(start + compress::BLOCK, len);
+            match has_outputs {
+                true => &self.da.output[start .. end],
+                false => &zeros[.. end - start]
+            }
+        });
+        for block in blocks {
+            if compressed {
+                compress::encode_output(block, &mut buf);
+                spill(&mut sink, 2, &mut buf, CHUNK) ?;
+            } else {
+                for output in block {
+                    output.encode(&mut buf);
+                    spill(&mut sink, 2, &mut buf, CHUNK) ?;
+                }
+            }
+        }
+        spill(&mut sink, 2, &mut buf, 0) ?;
+        for (state, output) in self.state_output.iter() {
+            state.encode(&mut buf);
+            output.encode(&mut buf);
+            spill(&mut sink, 3, &mut buf, CHUNK) ?;
+        }
+
+        spill(&mut sink, 3, &mut buf, 0)
+    }
+
+    fn read_sections<R>(mut r : R, checked : bool) -> Result<FST<I, O, L>>
+        where R : io::Read
+    {
+        // The version, read first, decides the width of the header.
+        let mut bytes = read_section(&mut r, "header", 6, 0) ?;
+        let width = header_width(&bytes) ?;
+        bytes.extend(read_section(&mut r, "header", width - 6, 6) ?);
+        let header = Header::decode(&bytes) ?;
+        header.expect::<I, O, L>() ?;
+        header.check_table() ?;
+
+        let mut position = width;
+        let mut read = |i : usize| -> Result<Vec<u8>> {
+            let (offset, width) = header.table[i];
+            skip(&mut r, SECTIONS[i], offset - position, position) ?;
+            let bytes = read_section(&mut r, SECTIONS[i], width, offset) ?;
+            position = offset + width;
+            if checked { header.verify(i, &bytes) ? }
+            Ok(bytes)
+        };
+
+        // Each section is decoded before the next is read.
+        let decoder = Decoder { header, consistent : checked };
+        let stipe = decoder.stipe(&read(0) ?) ?;
+        let next = decoder.next(&read(1) ?) ?;
+        let output = decoder.output(&read(2) ?) ?;
+        let state_output = decoder.state_output(&read(3) ?) ?;
+        Ok(decoder.finish(stipe, next, output, state_output) ?)
+    }
+}
+
+/// Pass on the buffer of the given section once it holds at least `at` bytes.
+fn spill<F>(sink : &mut F, section : usize, buf : &mut Vec<u8>, at : usize) -> Result<()>
+    where F : FnMut(usize, &[u8]) -> Result<()>
+{
+    if buf.len() >= at {
+        sink(section, buf) ?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// Read a section of `width` bytes, starting at `offset` in the stream.
+fn read_section<R>(r : &mut R, section : &'static str, width : usize, offset : usize) -> Result<Vec<u8>>
+    where R : io::Read
+{
+    // Declared widths are not trusted with an allocation up front.
+    let mut bytes = Vec::with_capacity(width.min(CHUNK));
+    r.take(width as u64).read_to_end(&mut bytes).map_err(Error::from) ?;
+    match bytes.len() < width {
+        true => Err(Error::Decode(truncated(section, offset + bytes.len()))),
+        false => Ok(bytes)
+    }
+}
+
+/// Skip the `len` bytes of padding before a section, starting at `offset`
+/// in the stream.
+fn skip<R>(r : &mut R, section : &'static str, len : usize, offset : usize) -> Result<()>
+    where R : io::Read
+{
+    let skipped = io::copy(&mut r.take(len as u64), &mut io::sink()).map_err(Error::from) ? as usize;
+    match skipped < len {
+        true => Err(Error::Decode(truncated(section, offset + skipped))),
+        false => Ok(())
+    }
+}
+
+/// A temporary path beside `path`, unique within the process.
+fn temporary_path(path : &Path) -> PathBuf {
+    static WRITES : AtomicUsize = AtomicUsize::new(0);
+    let name = path.file_name().map_or("fst".into(), |name| name.to_string_lossy());
+    let n = WRITES.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{}.{}-{}.tmp", name, process::id(), n))
+}
+
+
+/// Read the header of an encoded FST of the given types, checking it and
+/// its section table against the types.
+pub(crate) fn header<I, O, L>(bytes : &[u8]) -> result::Result<Header, DecodeError>
+    where I : Codec, O : Codec, L : Codec
+{
+    let header = Header::decode(bytes) ?;
+    header.expect::<I, O, L>() ?;
+    header.check_table() ?;
+    Ok(header)
+}
+
+/// Read the header of an encoded FST of the given types, and split the rest
+/// into its four sections, each exactly as wide as the header declares.
+pub(crate) fn split<I, O, L>(bytes : &[u8]) -> result::Result<(Header, [&[u8]; 4]), DecodeError>
+    where I : Codec, O : Codec, L : Codec
+{
+    let header = header::<I, O, L>(bytes) ?;
+    header.check_len(bytes.len()) ?;
+    let mut parts = [&bytes[.. 0]; 4];
+    for (part, &(offset, width)) in parts.iter_mut().zip(&header.table) {
+        *part = &bytes[offset .. offset + width];
+    }
+    Ok((header, parts))
+}
+
+/// Check that the sections of an FST can be read in place, as those of a
+/// compressed FST cannot.
+pub(crate) fn in_place(header : &Header) -> result::Result<(), DecodeError> {
+    match header.is_compressed() {
+        true => Err(DecodeError::Compressed),
+        false => Ok(())
+    }
+}
+
+/// Check that every destination lies within the Dart, and that no transition
+/// leads back to a state on its path from the root.
+pub(crate) fn traversable<I, O, L, S>(header : &Header, store : &S) -> result::Result<(), DecodeError>
+    where I : Index + Codec, O : Output, L : Label, S : DartStore<I, O, L>
+{
+    match analyze::stray_next(store).or_else(|| analyze::cycle(store)) {
+        None => Ok(()),
+        Some(e) => {
+            // Compressed destinations have no fixed width to find them by.
+            let base = header.table[1].0;
+            let offset = if header.is_compressed() { base } else { base + e * I::WIDTH };
+            Err(DecodeError::Invalid { section : "next", offset })
+        }
+    }
+}
+
+/// Verify each section against its checksum.
+pub(crate) fn verify(header : &Header, sections : &[&[u8]; 4]) -> result::Result<(), DecodeError> {
+    sections.iter().enumerate().try_for_each(|(i, bytes)| header.verify(i, bytes))
+}
+
+/// Decodes the sections of an FST whose header was read, positioning errors
+/// by its section table.
+pub(crate) struct Decoder {
+    pub header : Header,
+    /// Whether to check, on finishing, that every final state with an inner
+    /// output has one, and that traversals end.
+    pub consistent : bool
+}
+
+impl Decoder {
+    /// Decode the four sections, each exactly as wide as the header declares.
+    pub fn decode<I, O, L>(self, stipe : &[u8], next : &[u8], output : &[u8], state_output : &[u8])
+                           -> result::Result<FST<I, O, L>, DecodeError>
+        where I : Index + Codec, O : Output + Codec, L : Label + Codec
+    {
+        let stipe = self.stipe(stipe) ?;
+        let next = self.next(next) ?;
+        let output = self.output(output) ?;
+        let state_output = self.state_output(state_output) ?;
+        self.finish(stipe, next, output, state_output)
+    }
+
+    pub fn stipe<L>(&self, bytes : &[u8]) -> result::Result<Stipes<L>, DecodeError>
+        where L : Label + Codec
+    {
+        self.values(bytes, self.header.slots, 0, |b| {
+            Some(Stipe { check : L::decode(&b[.. L::WIDTH]) ?, terminal : Terminal::decode(b[L::WIDTH]) ? })
+        })
+    }
+
+    pub fn next<I>(&self, bytes : &[u8]) -> result::Result<Vec<I>, DecodeError>
+        where I : Index + Codec
+    {
+        match self.header.is_compressed() {
+            true => compress::decode_next(bytes, self.header.slots, self.header.table[1].0),
+            false => self.values(bytes, self.header.slots, 1, I::decode)
+        }
+    }
+
+    pub fn output<O>(&self, bytes : &[u8]) -> result::Result<Vec<O>, DecodeError>
+        where O : Output + Codec
+    {
+        match self.header.is_compressed() {
+            true => compress::decode_output(bytes, self.header.slots, self.header.table[2].0),
+            false => self.values(bytes, self.header.slots, 2, O::decode)
+        }
+    }
+
+    /// Decode the state outputs, which must be in strictly increasing order
+    /// of state.
+    pub fn state_output<I, O>(&self, bytes : &[u8]) -> result::Result<StateOutputs<I, O>, DecodeError>
+        where I : Index + Codec, O : Output + Codec
+    {
+        let base = self.header.table[3].0;
+        let pairs : Vec<(I, O)> = self.values(bytes, self.header.state_outputs, 3, |b| {
+            Some((I::decode(&b[.. I::WIDTH]) ?, O::decode(&b[I::WIDTH ..]) ?))
+        }) ?;
+        let pair_width = I::WIDTH + O::WIDTH;
+        for (i, &(state, _)) in pairs.iter().enumerate() {
+            let ordered = i == 0 || pairs[i - 1].0.as_usize() < state.as_usize();
+            if !ordered { return Err(DecodeError::Invalid { section : "state_output", offset : base + i * pair_width }) }
+        }
+        Ok(StateOutputs::from_sorted(pairs))
+    }
+
+    /// Assemble the decoded sections, checking that every final state with
+    /// an inner output has one and that traversals end, unless told
+    /// otherwise.
+    pub fn finish<I, O, L>(&self, stipe : Stipes<L>, next : Vec<I>, output : Vec<O>, state_output : StateOutputs<I, O>)
+                           -> result::Result<FST<I, O, L>, DecodeError>
+        where I : Index + Codec, O : Output + Codec, L : Label + Codec
+    {
+        let mut da = Dart { stipe, next, output };
+        da.shed_zero_outputs();
+        if !self.consistent { return Ok(FST { da, state_output }) }
+        let Dart { ref stipe, ref next, .. } = da;
+
+        let (base, stipe_width) = (self.header.table[0].0, L::WIDTH + 1);
+        let root_inner = stipe.first().is_some_and(|s| s.terminal.is_inner());
+        if root_inner && !state_output.contains_key(I::zero()) {
+            return Err(DecodeError::Invalid { section : "stipe", offset : base })
+        }
+        for (e, &next) in next.iter().enumerate().skip(1) {
+            if stipe.terminal(e).is_inner() && !state_output.contains_key(next) {
+                return Err(DecodeError::Invalid { section : "stipe", offset : base + e * stipe_width })
+            }
+        }
+
+        let fst = FST { da, state_output };
+        traversable(&self.header, &fst) ?;
+        Ok(fst)
+    }
+
+    fn values<T, C, F>(&self, bytes : &[u8], count : usize, i : usize, decode : F)
+                       -> result::Result<C, DecodeError>
+        where C : FromIterator<T>
+            , F : Fn(&[u8]) -> Option<T>
+    {
+        let width = bytes.len() / count.max(1);
+        let (section, base) = (SECTIONS[i], self.header.table[i].0);
+        (0 .. count)
+            .map(|i| decode(&bytes[i * width .. (i + 1) * width])
+                 .ok_or(DecodeError::Invalid { section, offset : base + i * width }))
+            .collect()
+    }
+}
@@ -0,0 +1,104 @@
+//! Built FSTs frozen into boxed slices, without spare capacity nor means of
+//! mutation.
+
+use fst::{FST, Dart, Iter, Output, State, StateOutputs, Stipe, Stipes};
+use fst::memory::{self, MemoryUsage};
+use fst::store::DartStore;
+use index::Index;
+use label::Label;
+
+
+/// An FST frozen once built: the destinations, outputs, and state outputs
+/// are held in boxed slices, and the slots' labels without spare capacity.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrozenFst<I, O, L = u8> {
+    stipe : Stipes<L>,
+    next : Box<[I]>,
+    output : Box<[O]>,
+    state_output : Box<[(I, O)]>
+}
+
+impl<I, O, L> FST<I, O, L> where I : Index, O : Output, L : Label {
+    /// Freeze the FST, releasing the spare capacity of its Dart.
+    pub fn freeze(self) -> FrozenFst<I, O, L> {
+        let mut stipe = self.da.stipe;
+        stipe.shrink_to_fit();
+        FrozenFst {
+            stipe,
+            next : self.da.next.into_boxed_slice(),
+            output : self.da.output.into_boxed_slice(),
+            state_output : self.state_output.into_vec().into_boxed_slice()
+        }
+    }
+}
+
+impl<I, O, L> FrozenFst<I, O, L> where I : Index, O : Output, L : Label {
+    /// Get the value associated to the key, if any.
+    pub fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[L]>
+    {
+        DartStore::get(self, key)
+    }
+
+    /// Returns whether the key is accepted by the FST.
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[L]>
+    {
+        DartStore::contains(self, key)
+    }
+
+    /// Given a starting state and an input, returns the destination state, if any.
+    pub fn transition(&self, state : I, input : L) -> Option<State<I>> {
+        self.step(state, input)
+    }
+
+    /// Returns an iterator over the keys and values of the FST, in
+    /// lexicographic order.
+    pub fn iter(&self) -> Iter<'_, I, O, L, FrozenFst<I, O, L>> {
+        DartStore::iter(self)
+    }
+
+    /// The memory held by the Dart and the state outputs.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::new();
+        usage.add("stipe", self.stipe.bytes());
+        usage.add("next", memory::slice_bytes(&self.next));
+        usage.add("output", memory::slice_bytes(&self.output));
+        usage.add("state_output", memory::slice_bytes(&self.state_output));
+        usage
+    }
+
+    /// Thaw the FST, that it may be modified again.
+    pub fn thaw(self) -> FST<I, O, L> {
+        let state_output = StateOutputs::from_sorted(self.state_output.into_vec());
+        FST {
+            da : Dart {
+                stipe : self.stipe,
+                next : self.next.into_vec(),
+                output : self.output.into_vec()
+            },
+            state_output
+        }
+    }
+
+    /// The number of nodes in the internal double array, including surplus.
+    pub fn len(&self) -> usize { self.stipe.len() }
+
+    /// Whether the internal double array is empty.
+    pub fn is_empty(&self) -> bool { self.stipe.is_empty() }
+}
+
+impl<I, O, L> DartStore<I, O, L> for FrozenFst<I, O, L> where I : Index, O : Output, L : Label {
+    #[inline] fn slots(&self) -> usize { self.stipe.len() }
+    #[inline] fn stipe(&self, e : usize) -> Option<Stipe<L>> { self.stipe.get(e) }
+    #[inline] fn next(&self, e : usize) -> I { self.next[e] }
+    #[inline] fn output(&self, e : usize) -> O { if self.output.is_empty() { O::zero() } else { self.output[e] } }
+    #[inline] fn has_outputs(&self) -> bool { !self.output.is_empty() }
+
+    #[inline]
+    fn state_output(&self, state : I) -> Option<O> {
+        self.state_output.binary_search_by_key(&state.as_usize(), |&(s, _)| s.as_usize())
+            .ok()
+            .map(|i| self.state_output[i].1)
+    }
+}
@@ -0,0 +1,67 @@
+//! FSTs and queries generated by `arbitrary`, for fuzzing code which
+//! consumes FSTs.
+//!
+//! FSTs are built from arbitrary keys and values by the builder, so that
+//! they are as valid as any other; queries lean toward the keys of the FST
+//! they are run against, since arbitrary bytes would seldom reach past its
+//! first transitions.
+
+use arbitrary::{Arbitrary, Error, Unstructured};
+use std::collections::BTreeMap;
+
+use fst::{FST, Output};
+use index::Index;
+
+
+impl<'a, I, O> Arbitrary<'a> for FST<I, O>
+    where I : Index
+        , O : Output + Arbitrary<'a>
+{
+    /// Build an FST from an arbitrary map of keys to values, failing as an
+    /// incorrect format should the FST outgrow its types.
+    fn arbitrary(u : &mut Unstructured<'a>) -> Result<FST<I, O>, Error> {
+        let entries : BTreeMap<Vec<u8>, O> = u.arbitrary() ?;
+        FST::from_iter(entries).map_err(|_| Error::IncorrectFormat)
+    }
+}
+
+/// A query for an FST, resolved to a key by `key`.
+#[derive(Arbitrary, Clone, Debug, Eq, PartialEq)]
+pub enum Query {
+    /// A key of the FST, counted in order, modulo the number of keys.
+    Key(usize),
+    /// A prefix of a key of the FST, at most `len` labels long, followed by
+    /// arbitrary bytes.
+    Prefix { key : usize, len : usize, suffix : Vec<u8> },
+    /// Arbitrary bytes.
+    Bytes(Vec<u8>)
+}
+
+impl Query {
+    /// The key queried of the FST.
+    pub fn key<I, O>(&self, fst : &FST<I, O>) -> Vec<u8>
+        where I : Index, O : Output
+    {
+        match *self {
+            Query::Key(i) => nth_key(fst, i),
+            Query::Prefix { key, len, ref suffix } => {
+                let mut key = nth_key(fst, key);
+                key.truncate(len);
+                key.extend_from_slice(suffix);
+                key
+            },
+            Query::Bytes(ref bytes) => bytes.clone()
+        }
+    }
+}
+
+/// The `i`th key of the FST modulo its number of keys, or the empty key
+/// should it have none.
+fn nth_key<I, O>(fst : &FST<I, O>, i : usize) -> Vec<u8>
+    where I : Index, O : Output
+{
+    match fst.iter().count() {
+        0 => Vec::new(),
+        count => fst.iter().nth(i % count).map(|(key, _)| key).unwrap_or_default()
+    }
+}
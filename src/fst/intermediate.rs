@@ -1,53 +1,198 @@
 use fst::error::{Error, Result};
 use fst::{FST, Output, Stipe, Terminal};
-use fst::builder::{Builder, State};
+use fst::builder::{Builder, BuildOptions, BuildProgress, Growth, Order, Phase, Placement, Progress, Transition};
+use fst::registry::Arena;
 use index::Index;
+use label::Label;
 use segment::IndexSegments;
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::{BinaryHeap, VecDeque};
+use std::mem;
 
 
 type BuilderState = usize;
 
-#[derive(Clone, Debug, Default)]
-pub struct Intermediary<I, O> where I : Index, O : Output {
-    stack : Vec<BuilderState>,
+/// The number of states placed between releases of an owned arena.
+const RELEASE_EVERY : usize = 1 << 12;
+
+/// The states settled but not yet laid out, taken in the order of placement.
+#[derive(Clone, Debug)]
+enum Pending {
+    Stack(Vec<BuilderState>),
+    Queue(VecDeque<BuilderState>),
+    /// Builder states are registered after their destinations, so that the
+    /// greatest pending is never reached from another.
+    Heap(BinaryHeap<BuilderState>)
+}
+
+impl Pending {
+    fn new(order : Order) -> Pending {
+        match order {
+            Order::DepthFirst => Pending::Stack(Vec::new()),
+            Order::BreadthFirst => Pending::Queue(VecDeque::new()),
+            Order::Registration => Pending::Heap(BinaryHeap::new())
+        }
+    }
+
+    /// Empty the states pending, keeping their memory if the order is kept.
+    fn reset(&mut self, order : Order) {
+        match (self, order) {
+            (Pending::Stack(s), Order::DepthFirst) => s.clear(),
+            (Pending::Queue(q), Order::BreadthFirst) => q.clear(),
+            (Pending::Heap(h), Order::Registration) => h.clear(),
+            (pending, order) => *pending = Pending::new(order)
+        }
+    }
+
+    fn push(&mut self, s_i : BuilderState) {
+        match self {
+            Pending::Stack(s) => s.push(s_i),
+            Pending::Queue(q) => q.push_back(s_i),
+            Pending::Heap(h) => h.push(s_i)
+        }
+    }
+
+    fn pop(&mut self) -> Option<BuilderState> {
+        match self {
+            Pending::Stack(s) => s.pop(),
+            Pending::Queue(q) => q.pop_front(),
+            Pending::Heap(h) => h.pop()
+        }
+    }
+
+    /// The last pending state registered with the builder.
+    fn last(&self) -> Option<BuilderState> {
+        match self {
+            Pending::Stack(s) => s.iter().max().copied(),
+            Pending::Queue(q) => q.iter().max().copied(),
+            Pending::Heap(h) => h.peek().copied()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Intermediary<I, O, L> where I : Index, O : Output, L : Label {
+    pending : Pending,
     // Indexed by BuilderState
     registry : Vec<Option<I>>,
     segments : IndexSegments,
-    fst : FST<I, O>
+    /// The length of the Dart registered with the segments, and of the Dart
+    /// itself, whose capacity grows ahead of it. Slots are thus written only
+    /// once reached, while the states placed are released.
+    frontier : usize,
+    fst : FST<I, O, L>,
+    /// The inner outputs of placed states, sorted once placement is done.
+    inner : Vec<(I, O)>,
+    /// The labels of the state being settled, reused from one to the next.
+    labels : Vec<L>,
+    options : BuildOptions,
+    progress : Option<(Progress, BuildProgress)>
 }
 
-impl<I, O> Intermediary<I, O> where I : Index, O : Output {
-    pub fn into_dart(self) -> FST<I, O> { self.fst }
+impl<I, O, L> Default for Intermediary<I, O, L> where I : Index, O : Output, L : Label {
+    fn default() -> Intermediary<I, O, L> { Intermediary::new() }
+}
+
+impl<I, O, L> Intermediary<I, O, L> where I : Index, O : Output, L : Label {
+    pub fn new() -> Intermediary<I, O, L> { Intermediary::with_options(BuildOptions::default()) }
+
+    /// An intermediary laying out the Dart as set by `options`; blocks
+    /// narrower than the alphabet and one more slot are widened to it.
+    pub fn with_options(options : BuildOptions) -> Intermediary<I, O, L> {
+        Intermediary {
+            pending : Pending::new(options.order),
+            registry : Vec::new(),
+            segments : IndexSegments::with_block_size(Self::block_size(options)),
+            frontier : 0,
+            fst : FST::default(),
+            inner : Vec::new(),
+            labels : Vec::with_capacity(L::ALPHABET),
+            options,
+            progress : None
+        }
+    }
+
+    /// The block size set by the options, at least the alphabet and a slot.
+    fn block_size(options : BuildOptions) -> usize {
+        cmp::max(L::ALPHABET + 1, options.block_size.unwrap_or(0))
+    }
+
+    /// Empty the intermediary, as if new with the given options, keeping
+    /// the memory held by its buffers.
+    pub fn reset(&mut self, options : BuildOptions) {
+        self.pending.reset(options.order);
+        self.registry.clear();
+        self.segments.reset(Self::block_size(options));
+        self.frontier = 0;
+        self.fst = FST::default();
+        self.inner.clear();
+        self.labels.clear();
+        self.options = options;
+        self.progress = None;
+    }
+
+    pub fn into_dart(self) -> FST<I, O, L> { self.fst }
+
+    /// Take the Dart placed, leaving the intermediary to be reset.
+    pub fn take_dart(&mut self) -> FST<I, O, L> { mem::take(&mut self.fst) }
 
     /// Build an intermediate representation
-    pub fn from_builder(&mut self, fst : &Builder<I, O>) -> Result<()> {
-        self.reserve(fst.size());
-        self.registry.resize(fst.size(), None);
-        let eph = &State::default();
-        let mut states = vec![eph; fst.size()];
-        for (state, &s_i) in fst.registry.iter() { states[s_i.as_usize()] = state }
+    pub fn from_builder(&mut self, fst : &Builder<I, O, L>) -> Result<()> {
+        self.configure(fst);
+        self.place(Cow::Borrowed(fst.arena()), fst.root().as_usize())
+    }
+
+    /// Build an intermediate representation, consuming the builder so that
+    /// its registry is released before placement, and its states as they
+    /// are placed.
+    pub fn from_owned_builder(&mut self, fst : Builder<I, O, L>) -> Result<()> {
+        let root = fst.root().as_usize();
+        self.configure(&fst);
+        self.place(Cow::Owned(fst.into_arena()), root)
+    }
+
+    /// Build an intermediate representation from a builder of another
+    /// index type, whose states are copied with their indices widened.
+    pub fn from_builder_of<N>(&mut self, fst : &Builder<N, O, L>) -> Result<()> where N : Index {
+        self.configure(fst);
+        self.place(Cow::Owned(fst.arena().widen()), fst.root().as_usize())
+    }
+
+    fn configure<N>(&mut self, fst : &Builder<N, O, L>) where N : Index {
+        self.segments.set_best_fit(fst.placement_policy() == Placement::BestFit);
+        self.progress = fst.progress().map(|p| (p.clone(), fst.snapshot(Phase::Placement)));
+    }
+
+    fn place(&mut self, mut states : Cow<'_, Arena<I, O, L>>, root_idx : BuilderState) -> Result<()> {
+        // Every transition takes a slot, as does the root.
+        let slots = self.options.initial_capacity.unwrap_or(1 + states.transition_count());
+        self.presize(states.len(), slots);
+        self.registry.resize(states.len(), None);
 
         self.expand();
-        let root_idx = fst.root().as_usize();
-        let root_next = I::as_index(self.settle_root(states[root_idx]).unwrap());
+        let root = states.get(root_idx);
+        let root_next = self.settle_root(root.transitions).expect("the root is settled first");
+        let root_next = I::try_as_index(root_next).ok_or_else(|| out_of_bounds::<I>(root_next)) ?;
         self.fst.da.next[0] = root_next;
         self.registry[root_idx] = Some(root_next);
-        match (states[root_idx].terminal, states[root_idx].final_output) {
+        match (root.terminal, root.final_output) {
             (false, _) =>
-                self.fst.da.stipe[0].terminal = Terminal::Not,
+                self.fst.da.stipe.set_terminal(0, Terminal::Not),
             (true, out) if out.is_zero() =>
-                self.fst.da.stipe[0].terminal = Terminal::Empty,
+                self.fst.da.stipe.set_terminal(0, Terminal::Empty),
             (true, out) => {
-                self.fst.da.stipe[0].terminal = Terminal::Inner;
-                self.fst.state_output.insert(I::zero(), out);
+                self.fst.da.stipe.set_terminal(0, Terminal::Inner);
+                self.inner.push((I::zero(), out));
             }
         }
 
-        self.stack.push(root_idx);
-        while let Some(s_i) = self.stack.pop() {
-            for trans in &states[s_i].transitions {
+        self.pending.push(root_idx);
+        let mut popped = 0;
+        while let Some(s_i) = self.pending.pop() {
+            for &trans in states.get(s_i).transitions {
                 let t = trans.destination.as_usize();
-                let (is_final, final_output) = (states[t].terminal, states[t].final_output);
+                let (is_final, final_output) = (states.get(t).terminal, states.get(t).final_output);
                 let terminal = match (is_final, final_output.is_zero()) {
                     (false, _) => Terminal::Not,
                     (true, true) => Terminal::Empty,
@@ -55,71 +200,123 @@ impl<I, O> Intermediary<I, O> where I : Index, O : Output {
                 };
 
                 let label = trans.label;
-                let e = self.registry[s_i].unwrap().as_usize() + (1 + label as usize);
-                if e >= self.fst.len() { self.expand(); }
+                let e = self.registry[s_i].unwrap().as_usize() + (1 + label.as_usize());
+                if e >= self.len() { self.expand(); }
 
                 self.fst.da.output[e] = trans.output;
-                self.fst.da.stipe[e] = Stipe { check: label, terminal: terminal };
+                self.fst.da.stipe.set(e, Stipe { check: label, terminal });
                 self.fst.da.next[e] = match self.registry[t] {
                     Some(i) => i,
                     None => {
-                        let next = I::as_index( self.settle(&states[t]) ?);
+                        let next = self.settle(states.get(t).transitions) ?;
                         self.registry[t] = Some(next);
-                        self.stack.push(t);
+                        self.pending.push(t);
                         if terminal.is_inner() {
-                            self.fst.state_output.insert(next, final_output);
+                            self.inner.push((next, final_output));
                         }
                         next
                     }
                 };
             }
+            if let Some((ref p, ref mut snapshot)) = self.progress {
+                snapshot.placed += 1;
+                if p.is_due(snapshot.placed) {
+                    snapshot.dart_len = self.frontier;
+                    p.report(*snapshot);
+                }
+            }
+            popped += 1;
+            if let Cow::Owned(ref mut arena) = states {
+                if popped % RELEASE_EVERY == 0 { self.release_placed(arena) }
+            }
         }
+        self.fst.da.shed_zero_outputs();
+        self.fst.state_output = self.inner.drain(..).collect();
+        self.fst.release();
 
         Ok(())
     }
 
-    fn settle(&mut self, state : &State<I, O>) -> Result<usize> {
-        let inputs : Vec<_> = state.transitions.iter().map(|t| t.label).collect();
-        let base = self.first_available(&inputs);
-        match base > I::bound() {
-            true => Err(Error::OutOfBounds {
-                reached : base,
-                maximum : I::max_value().as_usize()
-            }),
-            false => Ok(base)
-        }
+    /// Drop the states no longer needed from an owned arena, and their
+    /// bases.
+    ///
+    /// States are registered after their destinations, and every state yet
+    /// to be placed is reached from one pending, so that none lies past the
+    /// last of those.
+    fn release_placed(&mut self, arena : &mut Arena<I, O, L>) {
+        let Some(live) = self.pending.last() else { return };
+        arena.truncate(live);
+        self.registry.truncate(live + 1);
+        if 2 * self.registry.len() <= self.registry.capacity() { self.registry.shrink_to_fit() }
+    }
+
+    fn settle(&mut self, transitions : &[Transition<I, O, L>]) -> Result<I> {
+        self.gather_labels(transitions);
+        let base = self.first_available() ?;
+        I::try_as_index(base).ok_or_else(|| out_of_bounds::<I>(base))
     }
 
-    fn settle_root(&mut self, state : &State<I, O>) -> Option<usize> {
-        let inputs : Vec<_> = state.transitions.iter().map(|t| t.label).collect();
+    fn settle_root(&mut self, transitions : &[Transition<I, O, L>]) -> Option<usize> {
+        self.gather_labels(transitions);
         self.expand();
-        self.segments.settle_index(&inputs, 0)
+        self.segments.settle_index(&self.labels, 0)
     }
 
-    fn first_available(&mut self, symbols : &[u8]) -> usize {
-        self.segments.settle(symbols).or_else(|| {
+    /// Collect the labels of the transitions into the scratch buffer.
+    fn gather_labels(&mut self, transitions : &[Transition<I, O, L>]) {
+        self.labels.clear();
+        self.labels.extend(transitions.iter().map(|t| t.label));
+    }
+
+    /// Settle the gathered labels at the lowest base admitting them,
+    /// expanding the Dart as many times as needed, until no base would fit
+    /// the index type.
+    fn first_available(&mut self) -> Result<usize> {
+        loop {
+            if let Some(base) = self.segments.settle(&self.labels) { return Ok(base) }
+            if self.frontier > I::bound() { return Err(out_of_bounds::<I>(self.frontier)) }
             self.expand();
-            self.segments.settle(symbols)
-        }).unwrap()
+        }
     }
 
+    /// Register a new block with the segments and add it to the Dart, whose
+    /// capacity grows as set by the options when the block would not fit.
     fn expand(&mut self) {
-        let old_length = self.len();
-        self.fst.resize(old_length + self.segments.block_size());
+        let (old_length, block) = (self.frontier, self.segments.block_size());
+        let capacity = self.fst.capacity();
+        if old_length + block > capacity {
+            let growth = match self.options.growth {
+                Growth::Geometric => cmp::max(block, capacity / 2),
+                Growth::Linear => block
+            };
+            self.fst.reserve_exact(capacity + growth - old_length);
+        }
+        self.fst.resize(old_length + block);
         self.segments.expand(old_length);
+        self.frontier += block;
     }
 
-    fn reserve(&mut self, n : usize) {
-        self.fst.reserve(n);
-        self.segments.reserve(n / 64);
-        self.registry.reserve(n);
+    /// Reserve the slots the Dart will need at the least.
+    fn presize(&mut self, states : usize, slots : usize) {
+        self.fst.reserve_exact(slots);
+        self.segments.reserve(slots);
+        self.registry.reserve(states);
     }
 
     pub fn len(&self) -> usize {
-        self.fst.len()
+        self.frontier
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frontier == 0
     }
 
     pub fn unfixed_count(&self) -> usize {
         self.segments.unfixed_count()
     }
 }
+
+
+fn out_of_bounds<I>(reached : usize) -> Error where I : Index {
+    Error::OutOfBounds { reached, maximum : I::bound(), at_key : None }
+}
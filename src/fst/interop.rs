@@ -0,0 +1,67 @@
+//! Conversions from and to the `Map` and `Set` of the `fst` crate.
+
+use fst_crate::{Map, MapBuilder, Set, Streamer};
+use std::convert::TryFrom;
+
+use fst::{FST, Output};
+use fst::builder::Builder;
+use fst::error::{Error, Result};
+use index::Index;
+
+
+impl<I, O> FST<I, O> where I : Index, O : Output + TryFrom<u64> {
+    /// Build from a map of the `fst` crate, streaming its pairs in order and
+    /// failing with `OutputOverflow` on the first value which does not fit
+    /// the output type.
+    pub fn from_fst_map<D>(map : &Map<D>) -> Result<FST<I, O>>
+        where D : AsRef<[u8]>
+    {
+        let mut builder = Builder::new();
+        let mut stream = map.stream();
+        while let Some((key, value)) = stream.next() {
+            match O::try_from(value) {
+                Ok(v) => builder.insert(key, v) ?,
+                Err(_) => return Err(Error::OutputOverflow { key : key.to_vec(), value : value.to_string() })
+            }
+        }
+        builder.finish() ?;
+
+        builder.into_fst()
+    }
+}
+
+impl<I, O> FST<I, O> where I : Index, O : Output, u64 : TryFrom<O> {
+    /// Write the FST out as a map of the `fst` crate, widening values to
+    /// `u64` and failing with `OutputOverflow` on those which do not fit,
+    /// such as negative ones.
+    pub fn to_fst_map(&self) -> Result<Map<Vec<u8>>> {
+        let export = |e : fst_crate::Error| Error::Export(e.to_string());
+        let mut builder = MapBuilder::memory();
+        // Both libraries order the empty key first.
+        for (key, value) in self.iter() {
+            match u64::try_from(value) {
+                Ok(v) => builder.insert(&key, v).map_err(export) ?,
+                Err(_) => return Err(Error::OutputOverflow { key, value : format!("{:?}", value) })
+            }
+        }
+
+        let bytes = builder.into_inner().map_err(export) ?;
+        Map::new(bytes).map_err(export)
+    }
+}
+
+impl<I> FST<I, ()> where I : Index {
+    /// Build from a set of the `fst` crate, streaming its keys in order.
+    pub fn from_fst_set<D>(set : &Set<D>) -> Result<FST<I, ()>>
+        where D : AsRef<[u8]>
+    {
+        let mut builder = Builder::new();
+        let mut stream = set.stream();
+        while let Some(key) = stream.next() {
+            builder.insert(key, ()) ?;
+        }
+        builder.finish() ?;
+
+        builder.into_fst()
+    }
+}
@@ -0,0 +1,84 @@
+//! Ordered traversal of the keys of an FST.
+
+use fst::{FST, Output, Terminal};
+use fst::store::DartStore;
+use index::Index;
+use label::Label;
+
+
+#[derive(Copy, Clone, Debug)]
+struct Frame<I, O> {
+    state : I,
+    /// The least label not yet explored from the state.
+    label : usize,
+    output : O
+}
+
+/// An iterator over the keys and values of an FST, in lexicographic order.
+///
+/// Keys are relative to the state the iteration started from. The Dart is
+/// that of an `FST`, unless `S` is another store, such as an `FstRef`.
+#[derive(Clone, Debug)]
+pub struct Iter<'a, I, O, L = u8, S = FST<I, O, L>>
+    where I : Index + 'a
+        , O : Output + 'a
+        , L : Label + 'a
+        , S : DartStore<I, O, L> + 'a
+{
+    fst : &'a S,
+    key : Vec<L>,
+    stack : Vec<Frame<I, O>>,
+    origin : Option<O>
+}
+
+impl<'a, I, O, L, S> Iter<'a, I, O, L, S>
+    where I : Index, O : Output, L : Label, S : DartStore<I, O, L>
+{
+    pub(crate) fn new(fst : &'a S, state : I, output : O, terminal : Terminal) -> Iter<'a, I, O, L, S> {
+        Iter {
+            fst,
+            key : Vec::new(),
+            stack : vec![Frame { state, label : 0, output }],
+            origin : fst.resolve(state, output, terminal)
+        }
+    }
+
+    pub(crate) fn empty(fst : &'a S) -> Iter<'a, I, O, L, S> {
+        Iter { fst, key : Vec::new(), stack : Vec::new(), origin : None }
+    }
+}
+
+impl<'a, I, O, L, S> Iterator for Iter<'a, I, O, L, S>
+    where I : Index, O : Output, L : Label, S : DartStore<I, O, L>
+{
+    type Item = (Vec<L>, O);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(out) = self.origin.take() { return Some((Vec::new(), out)) }
+
+        let fst = self.fst;
+        while let Some(frame) = self.stack.last_mut() {
+            let arc = (frame.label .. L::ALPHABET).map(L::from_usize)
+                .filter_map(|l| fst.arc(frame.state, l).map(|e| (l, e)))
+                .next();
+            match arc {
+                Some((label, e)) => {
+                    frame.label = label.as_usize() + 1;
+                    let output = frame.output.mappend(fst.output(e));
+                    let state = fst.next(e);
+                    self.key.push(label);
+                    self.stack.push(Frame { state, label : 0, output });
+                    if let Some(out) = fst.resolve(state, output, fst.stipe(e).unwrap().terminal) {
+                        return Some((self.key.clone(), out))
+                    }
+                },
+                None => {
+                    self.stack.pop();
+                    self.key.pop();
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,81 @@
+//! Estimates of the memory held by builders and FSTs.
+
+use smallvec::{Array, SmallVec};
+use std::fmt;
+use std::mem;
+
+
+/// The heap and inline memory held by a structure, broken down by part.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryUsage {
+    pub parts : Vec<(&'static str, usize)>
+}
+
+impl MemoryUsage {
+    pub fn new() -> MemoryUsage { MemoryUsage::default() }
+
+    pub fn add(&mut self, part : &'static str, bytes : usize) {
+        self.parts.push((part, bytes))
+    }
+
+    /// The total number of bytes, across all parts.
+    pub fn total(&self) -> usize {
+        self.parts.iter().map(|&(_, bytes)| bytes).sum()
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        for &(part, bytes) in &self.parts {
+            writeln!(f, "{:<16} {:>14} B", part, bytes) ?;
+        }
+        write!(f, "{:<16} {:>14} B", "total", self.total())
+    }
+}
+
+
+/// Hint that the element at `e` will be read soon, that it may be fetched
+/// into cache meanwhile. Elements out of bounds are not fetched.
+#[inline(always)]
+pub(crate) fn prefetch<T>(v : &[T], e : usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+        if e < v.len() {
+            // Prefetching only hints at the address, which is in bounds.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(v.as_ptr().add(e) as *const i8) }
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    { let _ = (v, e); }
+}
+
+/// The bytes held by a vector, by capacity rather than length.
+pub fn vec_bytes<T>(v : &Vec<T>) -> usize {
+    v.capacity() * mem::size_of::<T>()
+}
+
+/// The bytes a small vector holds on the heap, once spilled from inline.
+pub fn small_vec_bytes<A>(v : &SmallVec<A>) -> usize where A : Array {
+    match v.spilled() {
+        true => v.capacity() * mem::size_of::<A::Item>(),
+        false => 0
+    }
+}
+
+/// The bytes held by a slice, as in a box.
+pub fn slice_bytes<T>(s : &[T]) -> usize {
+    mem::size_of_val(s)
+}
+
+/// An estimate of the bytes held by a hash map of the given capacity.
+///
+/// The standard tables allocate a power-of-two number of buckets at a load
+/// factor of 7/8, each with one control byte; the layout is not guaranteed,
+/// and this is only an approximation.
+pub fn hash_map_bytes<K, V>(capacity : usize) -> usize {
+    if capacity == 0 { return 0 }
+    let buckets = (capacity * 8 / 7).next_power_of_two();
+    buckets * (mem::size_of::<(K, V)>() + 1)
+}
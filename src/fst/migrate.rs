@@ -0,0 +1,37 @@
+//! The versions of the binary format which can be read.
+//!
+//! Version 1 is the only version so far: a 116-byte header of widths,
+//! counts, checksums, the section table, flags and the kinds of the types.
+//! The version, read first, decides the width of the rest of the header; a
+//! later version adds its own here, along with the reading of older ones.
+
+use std::ops::RangeInclusive;
+use std::result;
+
+use fst::{FST, Output};
+use fst::error::DecodeError;
+use fst::format::{Codec, HEADER_WIDTH, VERSION};
+use index::Index;
+use label::Label;
+
+
+/// The format versions which can be read.
+pub const SUPPORTED : RangeInclusive<u16> = 1 ..= VERSION;
+
+/// The width of the header of a format version, in bytes, if supported.
+pub fn header_width(version : u16) -> Option<usize> {
+    match version {
+        VERSION => Some(HEADER_WIDTH),
+        _ => None
+    }
+}
+
+/// Re-encode an FST of any supported version in the current version,
+/// checking it as `FST::from_bytes` does.
+pub fn upgrade<I, O, L>(bytes : &[u8]) -> result::Result<Vec<u8>, DecodeError>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    FST::<I, O, L>::from_bytes(bytes).map(|fst| fst.to_bytes())
+}
@@ -0,0 +1,236 @@
+//! FSTs read in place from memory-mapped files, whole or section by section.
+
+use memmap2::{Mmap, MmapOptions};
+use std::fs::File;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use fst::{FST, FstRef, Output, State};
+use fst::error::{Error, Result};
+use fst::format::{self, Codec, Header, Sections};
+use fst::store::DartStore;
+use index::Index;
+use label::Label;
+
+
+/// An FST encoded by `FST::to_bytes` in a memory-mapped file, whose pages
+/// are only read as lookups reach them.
+///
+/// The file must not be modified while mapped.
+#[derive(Debug)]
+pub struct MmapFst<I, O, L = u8> {
+    map : Mmap,
+    header : Header,
+    types : PhantomData<(I, O, L)>
+}
+
+impl<I, O, L> FST<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    /// Map an encoded FST from a file, checking its header against the types
+    /// and the lengths of its sections against the file.
+    ///
+    /// The sections are verified against their checksums once, reading the
+    /// whole file, and the Dart checked to lead to no cycle; lookups do not
+    /// check them again.
+    pub fn open_mmap<P>(path : P) -> Result<MmapFst<I, O, L>>
+        where P : AsRef<Path>
+    {
+        FST::map(path, true)
+    }
+
+    /// Map an encoded FST as with `open_mmap`, without verifying checksums,
+    /// so that only the pages reached by lookups are read.
+    pub fn open_mmap_unchecked<P>(path : P) -> Result<MmapFst<I, O, L>>
+        where P : AsRef<Path>
+    {
+        FST::map(path, false)
+    }
+
+    /// Open an encoded FST whose sections are each mapped as a lookup first
+    /// needs them, and verified against their checksums as they are mapped.
+    ///
+    /// Membership tests only need the `stipe` and `next` sections, and `get`
+    /// only needs the state outputs on reaching a state which has one.
+    pub fn open_lazy<P>(path : P) -> Result<LazyFst<I, O, L>>
+        where P : AsRef<Path>
+    {
+        LazyFst::open(path, None)
+    }
+
+    /// Open an encoded FST, mapping and verifying the given sections only.
+    ///
+    /// Lookups which need any other section fail with `Error::Unloaded`.
+    pub fn load_sections<P>(path : P, sections : Sections) -> Result<LazyFst<I, O, L>>
+        where P : AsRef<Path>
+    {
+        LazyFst::open(path, Some(sections))
+    }
+
+    fn map<P>(path : P, checked : bool) -> Result<MmapFst<I, O, L>>
+        where P : AsRef<Path>
+    {
+        let file = File::open(path).map_err(Error::from) ?;
+        // Safety: the mapping is only read, and the file is documented not to
+        // be modified while mapped.
+        let map = unsafe { Mmap::map(&file) }.map_err(Error::from) ?;
+        let (header, sections) = format::split::<I, O, L>(&map) ?;
+        format::in_place(&header) ?;
+        if checked {
+            format::verify(&header, &sections) ?;
+            format::traversable(&header, &FstRef::<I, O, L>::from_sections(header, sections)) ?;
+        }
+        Ok(MmapFst { map, header, types : PhantomData })
+    }
+}
+
+impl<I, O, L> MmapFst<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    /// A view of the FST, for lookups and iteration.
+    pub fn view(&self) -> FstRef<'_, I, O, L> {
+        FstRef::new_unchecked(&self.map).expect("mapped FST checked on opening")
+    }
+
+    /// Get the value associated to the key, if any.
+    pub fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[L]>
+    {
+        self.view().get(key)
+    }
+
+    /// Returns whether the key is accepted by the FST.
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[L]>
+    {
+        self.view().contains(key)
+    }
+
+    pub fn header(&self) -> Header { self.header }
+
+    /// The encoded FST, as mapped.
+    pub fn as_bytes(&self) -> &[u8] { &self.map }
+}
+
+
+/// An FST encoded by `FST::to_bytes` in a file, whose sections are mapped
+/// separately: either all of a chosen set on opening, or each as lookups
+/// first need it.
+///
+/// The file must not be modified while open.
+#[derive(Debug)]
+pub struct LazyFst<I, O, L = u8> {
+    file : File,
+    header : Header,
+    /// The mapping of each section, once mapped; empty sections are not.
+    maps : [OnceLock<Option<Mmap>>; 4],
+    /// Whether sections not yet mapped are mapped on demand.
+    lazy : bool,
+    types : PhantomData<(I, O, L)>
+}
+
+impl<I, O, L> LazyFst<I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    fn open<P>(path : P, sections : Option<Sections>) -> Result<LazyFst<I, O, L>>
+        where P : AsRef<Path>
+    {
+        let mut file = File::open(path).map_err(Error::from) ?;
+        let len = file.metadata().map_err(Error::from) ?.len() as usize;
+        let mut bytes = Vec::with_capacity(format::HEADER_WIDTH);
+        (&mut file).take(format::HEADER_WIDTH as u64).read_to_end(&mut bytes).map_err(Error::from) ?;
+        let header = format::header::<I, O, L>(&bytes) ?;
+        header.check_len(len) ?;
+        format::in_place(&header) ?;
+
+        let fst = LazyFst { file, header, maps : Default::default(), lazy : sections.is_none(), types : PhantomData };
+        for i in 0 .. 4 {
+            if sections.is_some_and(|s| s.contains(Sections::nth(i))) { fst.section(i, true) ?; }
+        }
+        Ok(fst)
+    }
+
+    /// The bytes of the `i`th section, mapping it if need be and allowed.
+    fn section(&self, i : usize, load : bool) -> Result<&[u8]> {
+        if let Some(map) = self.maps[i].get() { return Ok(map.as_deref().unwrap_or(&[])) }
+        if !(load || self.lazy) { return Err(Error::Unloaded(format::SECTIONS[i])) }
+
+        let (offset, len) = self.header.table[i];
+        let map = match len {
+            0 => None,
+            _ => {
+                // Safety: the mapping is only read, and the file is documented
+                // not to be modified while open.
+                let map = unsafe { MmapOptions::new().offset(offset as u64).len(len).map(&self.file) }.map_err(Error::from) ?;
+                self.header.verify(i, &map) ?;
+                Some(map)
+            }
+        };
+        // Should another thread have mapped the section first, its mapping
+        // is kept.
+        Ok(self.maps[i].get_or_init(|| map).as_deref().unwrap_or(&[]))
+    }
+
+    /// A view of the sections needed, any other being left empty.
+    fn view(&self, needed : Sections) -> Result<FstRef<'_, I, O, L>> {
+        let mut sections = [&[][..]; 4];
+        for (i, section) in sections.iter_mut().enumerate() {
+            if needed.contains(Sections::nth(i)) { *section = self.section(i, false) ? }
+        }
+        Ok(FstRef::from_sections(self.header, sections))
+    }
+
+    /// Get the value associated to the key, if any.
+    ///
+    /// Fails with `Error::Unloaded` should the lookup need a section which
+    /// was not loaded.
+    pub fn get<K>(&self, key : K) -> Result<Option<O>>
+        where K : AsRef<[L]>
+    {
+        let view = self.view(Sections::STIPE | Sections::NEXT | Sections::OUTPUT) ?;
+        let root = (I::zero(), O::zero(), view.root_terminal());
+        match view.walk(root, key.as_ref()) {
+            Some((state, out, terminal)) if terminal.is_inner() => {
+                let view = self.view(Sections::ALL) ?;
+                Ok(view.resolve(state, out, terminal))
+            },
+            Some((state, out, terminal)) => Ok(view.resolve(state, out, terminal)),
+            None => Ok(None)
+        }
+    }
+
+    /// Returns whether the key is accepted by the FST, reading neither
+    /// outputs nor state outputs.
+    ///
+    /// Fails with `Error::Unloaded` should `stipe` or `next` not be loaded.
+    pub fn contains<K>(&self, key : K) -> Result<bool>
+        where K : AsRef<[L]>
+    {
+        let view = self.view(Sections::STIPE | Sections::NEXT) ?;
+        let mut terminal = view.root_terminal();
+        let mut state = I::zero();
+        for &label in key.as_ref() {
+            match view.step(state, label) {
+                Some(State { index, terminal : t }) => { state = index; terminal = t; },
+                None => return Ok(false)
+            }
+        }
+        Ok(terminal.is())
+    }
+
+    /// The sections mapped so far.
+    pub fn loaded(&self) -> Sections {
+        (0 .. 4).filter(|&i| self.maps[i].get().is_some())
+                .fold(Sections::NONE, |loaded, i| loaded | Sections::nth(i))
+    }
+
+    pub fn header(&self) -> Header { self.header }
+}
@@ -1,33 +1,102 @@
+pub mod analyze;
+#[cfg(feature = "rkyv")]
+pub mod archive;
 pub mod builder;
+pub mod compact;
+mod compress;
+#[cfg(feature = "io")]
+pub mod delimited;
+#[cfg(feature = "postcard")]
+mod embedded;
 pub mod error;
+pub mod format;
+pub mod frozen;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
 pub mod intermediate;
+#[cfg(feature = "fst-interop")]
+pub mod interop;
+pub mod iter;
+pub mod memory;
+pub mod migrate;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod ops;
 pub mod output;
-
-pub use self::builder::Builder;
-pub use self::error::Error;
+pub mod payload;
+mod registry;
+pub mod reversed;
+pub mod scanner;
+#[cfg(feature = "serde")]
+mod serial;
+pub mod set;
+pub mod state_outputs;
+pub mod stipes;
+pub mod store;
+pub mod subtree;
+pub mod wide;
+
+pub use self::analyze::MinimalityReport;
+#[cfg(feature = "rkyv")]
+pub use self::archive::ArchivedFST;
+pub use self::builder::{BuildContext, Builder, BuilderStats, BuildOptions, BuildProgress, DuplicatePolicy, Growth, Order, Phase, Placement, Rejection};
+pub use self::compact::{CompactFst, CompactIter};
+#[cfg(feature = "io")]
+pub use self::delimited::{Delimited, Split};
+pub use self::error::{BuildOrSourceError, DecodeError, Error, ValidationError, Violation};
+pub use self::frozen::FrozenFst;
 pub use self::output::Output;
-
-
-use fnv::FnvHashMap;
+pub use self::iter::Iter;
+pub use self::memory::MemoryUsage;
+#[cfg(feature = "mmap")]
+pub use self::mmap::{LazyFst, MmapFst};
+pub use self::payload::PayloadMap;
+pub use self::reversed::ReversedFst;
+pub use self::scanner::Scanner;
+#[cfg(feature = "serde")]
+pub use self::serial::Tagged;
+pub use self::set::Set;
+pub use self::state_outputs::StateOutputs;
+pub use self::stipes::Stipes;
+pub use self::store::{DartStore, FstRef};
+pub use self::subtree::Subtree;
+pub use self::wide::WideFst;
+
+
+use fnv::FnvHashSet;
+use std::borrow::Borrow;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::mem;
+use std::result;
 use std::slice;
 
 use fst::error::Result;
 use fst::intermediate::Intermediary;
 use index::Index;
+use label::Label;
+
+
+/// The fewest keys a worker looks up at once in parallel lookups, lest
+/// scheduling outweigh the walks.
+#[cfg(feature = "rayon")]
+const PAR_LOOKUPS : usize = 1 << 10;
 
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
-pub struct Stipe {
-    pub check : u8,
+pub struct Stipe<L = u8> {
+    pub check : L,
     pub terminal : Terminal
 }
 
 /// Finality of a transition's destination state.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum Terminal {
     /// The transition is not final.
+    #[default]
     Not,
     /// The transition is final and leads to a state with no inner output.
     Empty,
@@ -40,105 +109,614 @@ impl Terminal {
     #[inline] pub fn is_inner(self) -> bool { self == Terminal::Inner }
 }
 
-impl Default for Terminal {
-    fn default() -> Terminal { Terminal::Not }
-}
-
 
 /// Hybrid Dart representation for a finite subsequential transducer.
+///
+/// The default FST has an empty Dart, and accepts no key.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
-pub struct FST<I, O> where I : Index, O : Output {
-    pub da : Dart<I, O>,
-    pub state_output : FnvHashMap<I, O>
+pub struct FST<I, O, L = u8> where I : Index, O : Output, L : Label {
+    pub da : Dart<I, O, L>,
+    #[cfg_attr(feature = "serde", serde(with = "self::serial::state_output"))]
+    pub state_output : StateOutputs<I, O>
+}
+
+/// Statistics on a built FST.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FstStats {
+    /// The length of the Dart, including vacant slots.
+    pub slots : usize,
+    /// The number of slots occupied by transitions.
+    pub transitions : usize,
+    /// The number of states placed in the Dart.
+    pub states : usize,
+    /// The number of keys in the FST.
+    pub keys : usize,
+    /// The size of the Dart and of the state outputs, excluding spare capacity.
+    pub bytes : usize,
+    pub bytes_per_key : f64
+}
+
+/// The occupancy of the Dart, to compare placements and index types.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DartStats {
+    /// The length of the Dart, including vacant slots.
+    pub slots : usize,
+    /// The number of slots occupied by a transition of a settled base.
+    pub occupied : usize,
+    /// The number of distinct bases, the root's included.
+    pub bases : usize,
+    /// The last slot occupied by a transition, if any.
+    pub last_occupied : Option<usize>,
+    /// The share of slots occupied, from 0 to 1.
+    pub fill_ratio : f64
 }
 
 /// The double-array trie, holding the core state machine for the FST.
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+///
+/// The outputs are empty when every transition output is zero, as in an
+/// acceptor, rather than a vector of zeros as long as the Dart.
+///
+/// With the `serde` feature, human-readable formats such as JSON receive
+/// only its occupied slots, with their positions.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
-pub struct Dart<I, O> {
-    pub stipe : Vec<Stipe>,
+pub struct Dart<I, O, L = u8> {
+    pub stipe : Stipes<L>,
     pub next : Vec<I>,
     pub output : Vec<O>,
 }
 
+impl<I, O, L> Dart<I, O, L> where I : Index, O : Output, L : Label {
+    /// The output of the transition in a slot.
+    #[inline]
+    pub fn output(&self, e : usize) -> O {
+        match self.output.is_empty() {
+            true => O::zero(),
+            false => self.output[e]
+        }
+    }
+
+    /// Whether any transition has an output.
+    #[inline]
+    pub fn has_outputs(&self) -> bool { !self.output.is_empty() }
+
+    /// Hint that the slot will be read soon.
+    #[inline(always)]
+    pub(crate) fn prefetch(&self, e : usize) {
+        self.stipe.prefetch(e);
+        memory::prefetch(&self.next, e);
+        memory::prefetch(&self.output, e);
+    }
+
+    /// Drop the outputs, should every one be zero.
+    pub(crate) fn shed_zero_outputs(&mut self) {
+        if self.output.iter().all(|o| o.is_zero()) { self.output = Vec::new() }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub struct State<I> { pub index : I, pub terminal : Terminal }
 
-impl<I, O> FST<I, O> where I : Index, O : Output {
-    pub fn from_builder(builder : &builder::Builder<I, O>) -> Result<Self> {
-        let mut repr = Intermediary::default();
+impl<I, O, L> FST<I, O, L> where I : Index, O : Output, L : Label {
+    /// Build an FST from a finished builder.
+    ///
+    /// The builder is only read, so that should its Dart outgrow the index
+    /// type, failing with `OutOfBounds`, it can be placed again with a wider
+    /// one, as by `from_builder_widening`.
+    pub fn from_builder(builder : &builder::Builder<I, O, L>) -> Result<Self> {
+        FST::from_builder_with(builder, BuildOptions::default())
+    }
+
+    /// Build an FST from a finished builder, laying out the Dart as set by
+    /// `options`.
+    pub fn from_builder_with(builder : &builder::Builder<I, O, L>, options : BuildOptions) -> Result<Self> {
+        let mut repr = Intermediary::with_options(options);
         repr.from_builder(builder) ?;
         Ok(repr.into_dart())
     }
 
+    /// Build an FST from a finished builder, with the buffers and options of
+    /// the context.
+    pub fn from_builder_in(builder : &builder::Builder<I, O, L>, context : &mut BuildContext<I, O, L>) -> Result<Self> {
+        let repr = context.placement();
+        repr.from_builder(builder) ?;
+        Ok(repr.take_dart())
+    }
+
+    /// Build an FST from pairs sorted by key, as with `Builder::from_iter`.
+    pub fn from_iter<K, T>(iter : T) -> Result<Self>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        builder::Builder::from_iter(iter).and_then(builder::Builder::into_fst)
+    }
+
+    /// Build an FST mapping sorted keys to their ordinal, as with
+    /// `Builder::from_keys`.
+    pub fn from_keys<K, T>(keys : T) -> Result<Self>
+        where K : AsRef<[L]>
+            , T : IntoIterator<Item = K>
+            , usize : TryInto<O>
+    {
+        builder::Builder::from_keys(keys).and_then(builder::Builder::into_fst)
+    }
+
     /// Given a starting state and an input, returns the destination state, if any.
-    pub fn transition(&self, state : I, input : u8) -> Option<State<I>> {
-        let e = state.as_usize() + (1 + input as usize);
-        match self.da.stipe.get(e) {
-            Some(&Stipe { check, terminal })
-                if check == input => Some(State { index: self.da.next[e], terminal: terminal }),
-            _ => None
-        }
+    pub fn transition(&self, state : I, input : L) -> Option<State<I>> {
+        self.step(state, input)
     }
 
     /// Returns whether the key is present in the FST.
     pub fn contains<K>(&self, key : K) -> bool
-        where K : AsRef<[u8]>
+        where K : AsRef<[L]>
     {
-        let mut state = State::default();
-        for &label in key.as_ref() {
-            let to = self.transition(state.index, label);
-            match to {
-                Some(s) => state = s,
-                _ => return false
-            }
-        }
-
-        state.terminal.is()
+        DartStore::contains(self, key)
     }
 
     /// Get the value associated to the key, if any.
+    ///
+    /// A key ending in a final state whose state output is missing, as in a
+    /// corrupted FST, is not found; `get_checked` reports it instead.
     pub fn get<K>(&self, key : K) -> Option<O>
-        where K : AsRef<[u8]>
+        where K : AsRef<[L]>
+    {
+        DartStore::get(self, key)
+    }
+
+    /// As `get`, failing with `OutputOverflow` should the outputs along the
+    /// key overflow, or with `MissingStateOutput` should the key end in a
+    /// final state without its state output, as they may in a corrupted FST.
+    /// `validate` finds every such inconsistency up front.
+    pub fn get_checked<K>(&self, key : K) -> Result<Option<O>>
+        where K : AsRef<[L]>
     {
-        let mut out = O::zero();
-        let mut state = I::zero();
-        let mut terminal = self.da.stipe[0].terminal;
-        for &label in key.as_ref() {
-            let e = state.as_usize() + (1 + label as usize);
-            let stipe = self.da.stipe.get(e);
-            match stipe {
-                Some(stipe) if stipe.check == label => {
+        let key = key.as_ref();
+        let overflow = |out : O| Error::OutputOverflow { key : L::key_bytes(key), value : format!("{:?}", out) };
+        let mut terminal = self.da.stipe.first().map_or(Terminal::Not, |root| root.terminal);
+        let (mut state, mut out) = (I::zero(), O::zero());
+        for &label in key {
+            let e = state.as_usize() + (1 + label.as_usize());
+            match self.da.stipe.get(e) {
+                Some(stipe) if stipe.check == label && !self.da.next[e].is_zero() => {
                     terminal = stipe.terminal;
-                    out.mappend_assign(self.da.output[e]);
+                    out = out.checked_mappend(self.da.output(e)).ok_or_else(|| overflow(out)) ?;
                     state = self.da.next[e];
                 },
-                _ => return None
+                _ => return Ok(None)
             }
         }
 
         match terminal {
-            Terminal::Not   => None,
-            Terminal::Empty => Some(out),
-            Terminal::Inner => Some(out.mappend(self.state_output[&state]))
+            Terminal::Not   => Ok(None),
+            Terminal::Empty => Ok(Some(out)),
+            Terminal::Inner => {
+                let missing = || Error::MissingStateOutput { key : L::key_bytes(key), state : state.as_usize() };
+                let inner = self.state_output.get(state).ok_or_else(missing) ?;
+                out.checked_mappend(inner).map(Some).ok_or_else(|| overflow(out))
+            }
         }
     }
 
+    /// Get the values associated to a small batch of keys, looking them up
+    /// in lockstep so that the cache misses of each overlap with the others'.
+    /// The results are in the order of the keys.
+    pub fn get_pipelined<const N : usize>(&self, keys : [&[L]; N]) -> [Option<O>; N] {
+        let root = (I::zero(), O::zero(), self.root_terminal());
+        let mut lanes : [Option<(I, O, Terminal)>; N] = [Some(root); N];
+        let longest = keys.iter().map(|k| k.len()).max().unwrap_or(0);
+        let outputs = self.da.has_outputs();
+        for depth in 0 .. longest {
+            for (lane, key) in lanes.iter_mut().zip(&keys) {
+                let (Some((state, out, terminal)), Some(&label)) = (lane.as_mut(), key.get(depth)) else { continue };
+                let e = state.as_usize() + (1 + label.as_usize());
+                match self.da.stipe.get(e) {
+                    Some(stipe) if stipe.check == label && !self.da.next[e].is_zero() => {
+                        *terminal = stipe.terminal;
+                        if outputs { out.mappend_assign(self.da.output(e)) }
+                        *state = self.da.next[e];
+                        if let Some(&next) = key.get(depth + 1) {
+                            self.da.prefetch(state.as_usize() + (1 + next.as_usize()));
+                        }
+                    },
+                    _ => *lane = None
+                }
+            }
+        }
+
+        lanes.map(|lane| lane.and_then(|(state, out, terminal)| self.resolve(state, out, terminal)))
+    }
+
+    /// Get the values associated to many keys, looked up in parallel. The
+    /// results are in the order of the keys.
+    #[cfg(feature = "rayon")]
+    pub fn par_get_many<K>(&self, keys : &[K]) -> Vec<Option<O>>
+        where K : AsRef<[L]> + Sync
+            , I : Sync
+            , O : Send + Sync
+            , L : Sync
+    {
+        use rayon::prelude::*;
+
+        keys.par_iter().with_min_len(PAR_LOOKUPS).map(|k| self.get(k)).collect()
+    }
+
+    /// Returns whether each of many keys is present in the FST, looked up
+    /// in parallel. The results are in the order of the keys.
+    #[cfg(feature = "rayon")]
+    pub fn par_contains_many<K>(&self, keys : &[K]) -> Vec<bool>
+        where K : AsRef<[L]> + Sync
+            , I : Sync
+            , O : Sync
+            , L : Sync
+    {
+        use rayon::prelude::*;
+
+        keys.par_iter().with_min_len(PAR_LOOKUPS).map(|k| self.contains(k)).collect()
+    }
+
+    /// Returns a handle on the states reached through the prefix, if any,
+    /// from which suffixes of the prefix can be queried.
+    pub fn subtree<K>(&self, prefix : K) -> Option<Subtree<'_, I, O, L>>
+        where K : AsRef<[L]>
+    {
+        let root = (I::zero(), O::zero(), self.root_terminal());
+        self.walk(root, prefix.as_ref())
+            .map(|(state, output, terminal)| Subtree::new(self, state, output, terminal))
+    }
+
+    /// Convert the FST to another output type, keeping its layout.
+    ///
+    /// Fails with `OutputOverflow` if any value, or any part of one
+    /// distributed along the transitions, does not fit in the new type. A
+    /// part is blamed on the first key whose path holds it; one held by no
+    /// key is read by no lookup, and is dropped.
+    pub fn map_output_type<P>(&self) -> Result<FST<I, P, L>>
+        where P : Output + TryFrom<O>
+    {
+        // Parts may fit where their sum does not, so every value is checked.
+        if let Some((key, value)) = self.iter().find(|&(_, v)| P::try_from(v).is_err()) {
+            return Err(Error::OutputOverflow { key : L::key_bytes(&key), value : format!("{:?}", value) })
+        }
+
+        let convert = |o : O, holds : &dyn Fn(&[L]) -> bool| match P::try_from(o) {
+            Ok(p) => Ok(p),
+            Err(_) => match self.iter().find(|(key, _)| holds(key)) {
+                Some((key, _)) => Err(Error::OutputOverflow { key : L::key_bytes(&key), value : format!("{:?}", o) }),
+                None => Ok(P::zero())
+            }
+        };
+        let output = self.da.output.iter().enumerate()
+            .map(|(e, &o)| convert(o, &|key| self.path(key).any(|(slot, _)| slot == e)))
+            .collect::<Result<Vec<P>>>() ?;
+        let state_output = self.state_output.iter()
+            .map(|(i, o)| {
+                let ends_in = |key : &[L]| self.path(key).last().map_or(I::zero(), |(_, state)| state) == i;
+                convert(o, &ends_in).map(|p| (i, p))
+            })
+            .collect::<Result<StateOutputs<I, P>>>() ?;
+
+        Ok(FST {
+            da : Dart { stipe : self.da.stipe.clone(), next : self.da.next.clone(), output },
+            state_output
+        })
+    }
+
+    /// A new FST with each value transformed by `f`, given its key.
+    pub fn map_values<P, F>(&self, f : F) -> Result<FST<I, P, L>>
+        where P : Output
+            , F : Fn(&[L], O) -> P
+    {
+        let mut builder = builder::Builder::new();
+        for (key, value) in self.iter() { builder.insert(&key, f(&key, value)) ? }
+        builder.finish() ?;
+
+        builder.into_fst()
+    }
+
+    /// Transform each value by `f` in place, without rebuilding the FST.
+    ///
+    /// Values are distributed along transitions, so `f` is applied to each
+    /// part of them: it must distribute over `mappend` and be monotone, as is
+    /// scaling an unsigned value by a constant factor. Otherwise, the values
+    /// of the FST are garbled. Debug builds verify a sample of keys.
+    pub fn map_values_in_place<F>(&mut self, f : F)
+        where F : Fn(O) -> O
+    {
+        // Strided over the keys, not the Dart's slots, to span the whole range.
+        let sample : Vec<_> = match cfg!(debug_assertions) {
+            true => {
+                let keys = self.iter().count();
+                self.iter().step_by(cmp::max(1, keys / 64)).take(64).collect()
+            },
+            false => Vec::new()
+        };
+
+        for o in self.da.output.iter_mut() { *o = f(*o) }
+        for o in self.state_output.outputs_mut() { *o = f(*o) }
+
+        for (key, value) in sample {
+            debug_assert!(self.get(&key) == Some(f(value)),
+                          "map_values_in_place: f does not distribute over mappend at {:?}", key);
+        }
+    }
+
+    /// A new FST holding only the entries for which `pred` holds, streamed
+    /// into a fresh builder so that the result is minimal.
+    ///
+    /// Errors are wrapped in `Filtered`, along with the number of entries
+    /// seen and kept so far.
+    pub fn retain<F>(&self, pred : F) -> Result<FST<I, O, L>>
+        where F : Fn(&[L], O) -> bool
+    {
+        let (mut seen, mut kept) = (0, 0);
+        let wrap = |seen, kept| move |e| Error::Filtered { seen, kept, error : Box::new(e) };
+        let mut builder = builder::Builder::new();
+        for (key, value) in self.iter() {
+            seen += 1;
+            if pred(&key, value) {
+                kept += 1;
+                builder.insert(&key, value).map_err(wrap(seen, kept)) ?;
+            }
+        }
+        builder.finish().map_err(wrap(seen, kept)) ?;
+
+        builder.into_fst().map_err(wrap(seen, kept))
+    }
+
+    /// Collect all keys and their values into a map.
+    pub fn to_btree_map(&self) -> BTreeMap<Vec<L>, O> {
+        self.iter().collect()
+    }
+
+    /// Returns an iterator over all keys and their values, in lexicographic order.
+    pub fn iter(&self) -> Iter<'_, I, O, L> {
+        DartStore::iter(self)
+    }
+
+    /// The slots along the path of a key of the FST, with their destinations.
+    fn path<'a>(&'a self, key : &'a [L]) -> impl Iterator<Item = (usize, I)> + 'a {
+        key.iter().scan(I::zero(), move |state, &label| {
+            let e = state.as_usize() + (1 + label.as_usize());
+            *state = self.da.next[e];
+            Some((e, *state))
+        })
+    }
+
+    /// The transitions leaving a state, as pairs of label and Dart slot,
+    /// in label order.
+    fn arcs(&self, state : I) -> impl Iterator<Item = (L, usize)> + '_ {
+        (0 .. L::ALPHABET).map(L::from_usize)
+            .filter_map(move |label| self.arc(state, label).map(|e| (label, e)))
+    }
+
+    /// Statistics on the occupancy of the Dart and its cost per key.
+    ///
+    /// Keys are counted by traversal.
+    pub fn stats(&self) -> FstStats {
+        let mut states = FnvHashSet::default();
+        let mut transitions = 0;
+        if !self.is_empty() { states.insert(I::zero()); }
+        for &next in self.da.next.iter().filter(|next| !next.is_zero()) {
+            transitions += 1;
+            states.insert(next);
+        }
+
+        let bytes = self.len() * (mem::size_of::<L>() + mem::size_of::<I>())
+                  + self.len().div_ceil(4)
+                  + self.da.output.len() * mem::size_of::<O>()
+                  + self.state_output.len() * (mem::size_of::<I>() + mem::size_of::<O>());
+        let keys = self.iter().count();
+        FstStats {
+            slots : self.len(),
+            transitions,
+            states : states.len(),
+            keys,
+            bytes,
+            bytes_per_key : if keys == 0 { 0.0 } else { bytes as f64 / keys as f64 }
+        }
+    }
+
+    /// The share of Dart slots occupied by transitions, from 0 to 1.
+    pub fn fill_ratio(&self) -> f64 { self.dart_stats().fill_ratio }
+
+    /// Statistics on the occupancy of the Dart.
+    ///
+    /// A vacant slot reads as a transition on the least label, its check
+    /// being zero, so occupancy goes by destination instead: no transition
+    /// leads back to the root, whose base is zero. A slot counts only if its
+    /// check also leads back to a settled base.
+    pub fn dart_stats(&self) -> DartStats {
+        let mut bases = FnvHashSet::default();
+        if !self.is_empty() { bases.insert(0); }
+        bases.extend(self.da.next.iter().filter(|next| !next.is_zero()).map(|next| next.as_usize()));
+
+        let (mut occupied, mut last_occupied) = (0, None);
+        for (e, next) in self.da.next.iter().enumerate() {
+            let offset = 1 + self.da.stipe.check(e).as_usize();
+            if !next.is_zero() && e >= offset && bases.contains(&(e - offset)) {
+                occupied += 1;
+                last_occupied = Some(e);
+            }
+        }
+
+        DartStats {
+            slots : self.len(),
+            occupied,
+            bases : bases.len(),
+            last_occupied,
+            fill_ratio : if self.is_empty() { 0.0 } else { occupied as f64 / self.len() as f64 }
+        }
+    }
+
+    /// Returns whether no two states of the FST are equivalent, as should be
+    /// the case when built with minimization. See `analyze::minimality_report`
+    /// for the states at fault.
+    pub fn is_minimal(&self) -> bool {
+        analyze::minimality_report(self).is_minimal()
+    }
+
+    /// Check the consistency of the Dart and the state outputs, as FSTs
+    /// decoded unchecked, or whose Dart was put together by hand, may lack.
+    /// See `analyze::validate`.
+    pub fn validate(&self) -> result::Result<(), ValidationError> {
+        analyze::validate(self)
+    }
+
+    /// The memory held by the Dart and the state outputs, by capacity.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::new();
+        usage.add("stipe", self.da.stipe.bytes());
+        usage.add("next", memory::vec_bytes(&self.da.next));
+        usage.add("output", memory::vec_bytes(&self.da.output));
+        usage.add("state_output", memory::vec_bytes(self.state_output.pairs()));
+        usage
+    }
+
+    /// Cut the Dart after its last used slot, and release the spare capacity
+    /// of the Dart and of the state outputs, returning the bytes reclaimed.
+    ///
+    /// Lookups are unaffected, but the encoding of the FST changes with the
+    /// length of its Dart.
+    pub fn shrink_to_fit(&mut self) -> usize {
+        let before = self.memory_usage().total();
+        // The last slot holding a transition or a state's base, if any.
+        let used = self.da.next.iter().enumerate()
+            .filter(|(_, next)| !next.is_zero())
+            .map(|(e, next)| cmp::max(e, next.as_usize()) + 1)
+            .max()
+            .unwrap_or(cmp::min(1, self.len()));
+        self.truncate(used);
+        self.release();
+        before.saturating_sub(self.memory_usage().total())
+    }
+
+    /// The number of nodes in the internal double array, including surplus.
+    pub fn len(&self) -> usize {
+        assert!(self.da.next.len() == self.da.stipe.len());
+        assert!(!self.da.has_outputs() || self.da.next.len() == self.da.output.len());
+        self.da.stipe.len()
+    }
+
+    /// Whether the internal double array is empty.
+    pub fn is_empty(&self) -> bool { self.da.stipe.is_empty() }
+
+    // A zero-sized output, as in a `Set`, leaves its vector unallocated.
+    fn resize(&mut self, length : usize) {
+        self.da.stipe.resize(length);
+        self.da.next.resize(length, I::zero());
+        self.da.output.resize(length, O::zero());
+    }
+
+    /// The slots the Dart may hold without reallocating.
+    fn capacity(&self) -> usize { self.da.next.capacity() }
+
+    fn reserve_exact(&mut self, additional : usize) {
+        self.da.stipe.reserve_exact(additional);
+        self.da.next.reserve_exact(additional);
+        self.da.output.reserve_exact(additional);
+    }
+
+    fn truncate(&mut self, length : usize) {
+        self.da.stipe.resize(length);
+        self.da.next.truncate(length);
+        self.da.output.truncate(length);
+    }
+
+    fn release(&mut self) {
+        self.da.stipe.shrink_to_fit();
+        self.da.next.shrink_to_fit();
+        self.da.output.shrink_to_fit();
+        self.state_output.shrink_to_fit();
+    }
+}
+
+// Operations on FSTs over bytes, building on their order and on byte-wise
+// traversal of text.
+impl<I, O> FST<I, O> where I : Index, O : Output {
+    /// A new FST holding the entries of this one along with pairs sorted by
+    /// key, which are streamed into a fresh builder.
+    ///
+    /// Fails with `Duplicate` on a key already present.
+    pub fn merge_insert<K, T>(&self, delta : T) -> Result<FST<I, O>>
+        where K : AsRef<[u8]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        ops::merge_delta(self, delta, |key, x, y| Err(Error::Duplicate {
+            key : key.to_vec(),
+            index : 0,
+            existing_hint : Some(format!("{:?}", x)),
+            inserted_hint : Some(format!("{:?}", y))
+        }))
+    }
+
+    /// As `merge_insert`, combining the value of a key already present with
+    /// the value inserted.
+    pub fn merge_insert_with<K, T, F>(&self, delta : T, mut combine : F) -> Result<FST<I, O>>
+        where K : AsRef<[u8]>
+            , T : IntoIterator<Item = (K, O)>
+            , F : FnMut(O, O) -> O
+    {
+        ops::merge_delta(self, delta, |_, x, y| Ok(combine(x, y)))
+    }
+
+    /// A new FST lacking the given keys, which must be sorted, along with
+    /// those of them which were not present.
+    pub fn without<K, T>(&self, keys : T) -> Result<ops::Removal<I, O>>
+        where K : AsRef<[u8]>
+            , T : IntoIterator<Item = K>
+    {
+        ops::remove_keys(self, keys)
+    }
+
+    /// As `without`, failing with `Absent` on a key which was not present.
+    pub fn without_strict<K, T>(&self, keys : T) -> Result<FST<I, O>>
+        where K : AsRef<[u8]>
+            , T : IntoIterator<Item = K>
+    {
+        let removal = ops::remove_keys(self, keys) ?;
+        match removal.absent.into_iter().next() {
+            Some(key) => Err(Error::Absent(key)),
+            None => Ok(removal.fst)
+        }
+    }
+
+    /// Partition the FST by sorted boundary prefixes, as with `ops::shard`:
+    /// the keys below the first boundary, then those in `[b_i, b_{i+1})`.
+    pub fn shard_by_prefix(&self, boundaries : &[&[u8]]) -> Result<Vec<FST<I, O>>> {
+        ops::shard(self, boundaries)
+    }
+
+    /// Concatenate FSTs whose keys lie in successive, disjoint ranges, such
+    /// as the shards of `shard_by_prefix`.
+    pub fn from_shards<'a, T>(shards : T) -> Result<FST<I, O>>
+        where T : IntoIterator<Item = &'a FST<I, O>>
+            , I : 'a, O : 'a
+    {
+        ops::concatenate(shards)
+    }
+
     /// Returns an iterator producing the values associated to all prefixes
     /// of the query, including the empty string and the query itself.
-    pub fn reap<'a, 'q>(&'a self, query : &'q [u8]) -> Reaper<'a, 'q, I, O> {
-        let root_output = match self.da.stipe[0].terminal {
-            Terminal::Not   => None,
-            Terminal::Empty => Some((0, O::zero())),
-            Terminal::Inner => Some((0, self.state_output[&I::zero()]))
-        };
+    pub fn reap<'a, 'q>(&'a self, query : &'q [u8]) -> Reaper<'a, slice::Iter<'q, u8>, I, O> {
+        self.reap_iter(query)
+    }
+
+    /// Returns an iterator producing the values associated to all prefixes
+    /// of the query, including the empty string and the query itself.
+    ///
+    /// The query is consumed lazily, one match at a time, and left untouched
+    /// past the point where it leaves the FST.
+    pub fn reap_iter<'a, Q>(&'a self, query : Q) -> Reaper<'a, Q::IntoIter, I, O>
+        where Q : IntoIterator
+            , Q::Item : Borrow<u8>
+    {
+        let root_output = self.resolve(I::zero(), O::zero(), self.root_terminal()).map(|out| (0, out));
 
         Reaper {
-            query : query.into_iter(),
+            query : Some(query.into_iter()),
             position : 0,
-            fst : &self,
-            root_output : root_output,
+            fst : self,
+            root_output,
             output : O::zero(),
             state : I::zero()
         }
@@ -146,43 +724,67 @@ impl<I, O> FST<I, O> where I : Index, O : Output {
 
     /// Returns an iterator producing the values associated to all prefixes
     /// of the query, including the query itself but excluding the empty string.
-    pub fn reap_past_root<'a, 'q>(&'a self, query : &'q [u8]) -> RootlessReaper<'a, 'q, I, O> {
+    pub fn reap_past_root<'a, 'q>(&'a self, query : &'q [u8])
+        -> RootlessReaper<'a, slice::Iter<'q, u8>, I, O>
+    {
+        self.reap_past_root_iter(query)
+    }
+
+    /// Returns an iterator producing the values associated to all prefixes
+    /// of the query, including the query itself but excluding the empty string.
+    ///
+    /// The query is consumed as in `reap_iter`.
+    pub fn reap_past_root_iter<'a, Q>(&'a self, query : Q) -> RootlessReaper<'a, Q::IntoIter, I, O>
+        where Q : IntoIterator
+            , Q::Item : Borrow<u8>
+    {
         RootlessReaper {
-            query : query.into_iter(),
+            query : Some(query.into_iter()),
             position : 0,
-            fst : &self,
+            fst : self,
             output : O::zero(),
             state : I::zero()
         }
     }
 
-    /// The number of nodes in the internal double array, including surplus.
-    pub fn len(&self) -> usize {
-        assert!(self.da.next.len() == self.da.stipe.len());
-        assert!(self.da.next.len() == self.da.output.len());
-        self.da.stipe.len()
+    /// Returns a scanner finding every occurrence of the FST's keys in a text.
+    pub fn with_failure_links(&self) -> Scanner<O> {
+        Scanner::new(self)
     }
+}
 
-    fn resize(&mut self, length : usize) {
-        self.da.stipe.resize(length, Stipe::default());
-        self.da.next.resize(length, I::zero());
-        self.da.output.resize(length, O::zero());
-    }
 
-    fn reserve(&mut self, n : usize) {
-        self.da.stipe.reserve(n);
-        self.da.next.reserve(n);
-        self.da.output.reserve(n);
+macro_rules! impl_try_from_btree_map {
+    ($key:ty) => {
+        impl<I, O> TryFrom<BTreeMap<$key, O>> for FST<I, O> where I : Index, O : Output {
+            type Error = Error;
+
+            fn try_from(map : BTreeMap<$key, O>) -> Result<Self> {
+                FST::from_iter(map)
+            }
+        }
+
+        impl<'m, I, O> TryFrom<&'m BTreeMap<$key, O>> for FST<I, O> where I : Index, O : Output {
+            type Error = Error;
+
+            fn try_from(map : &'m BTreeMap<$key, O>) -> Result<Self> {
+                FST::from_iter(map.iter().map(|(k, &v)| (k, v)))
+            }
+        }
     }
 }
 
+impl_try_from_btree_map! { Vec<u8> }
+impl_try_from_btree_map! { String  }
+
 
 #[derive(Clone, Debug)]
-pub struct Reaper<'a, 'q, I, O>
+pub struct Reaper<'a, Q, I, O>
     where I : Index + 'a
         , O : Output + 'a
 {
-    query : slice::Iter<'q, u8>,
+    // Dropped as soon as the traversal leaves the FST.
+    query : Option<Q>,
     position : usize,
     fst : &'a FST<I, O>,
     root_output : Option<(usize, O)>,
@@ -197,56 +799,61 @@ pub struct Reaper<'a, 'q, I, O>
 //
 // Surely something is amiss.
 #[derive(Clone, Debug)]
-pub struct RootlessReaper<'a, 'q, I, O>
+pub struct RootlessReaper<'a, Q, I, O>
     where I : Index + 'a
         , O : Output + 'a
 {
-    query : slice::Iter<'q, u8>,
+    query : Option<Q>,
     position : usize,
     fst : &'a FST<I, O>,
     state : I,
     output : O
 }
 
-impl<'a, 'q, I, O> Iterator for RootlessReaper<'a, 'q, I, O>
-    where I : Index, O : Output
+impl<'a, Q, I, O> Iterator for RootlessReaper<'a, Q, I, O>
+    where Q : Iterator
+        , Q::Item : Borrow<u8>
+        , I : Index, O : Output
 {
     type Item = (usize, O);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut terminal = Terminal::Not;
-        let da = &self.fst.da;
-        for &label in self.query.by_ref() {
+        let fst = self.fst;
+        for label in self.query.as_mut() ?.by_ref() {
+            let label = *label.borrow();
             let e = self.state.as_usize() + (1 + label as usize);
-            let stipe = da.stipe.get(e);
-            match stipe {
-                Some(stipe) if stipe.check == label => {
-                    self.output.mappend_assign(da.output[e]);
-                    self.state = da.next[e];
+            match fst.da.stipe.get(e) {
+                Some(stipe) if stipe.check == label && !fst.da.next[e].is_zero() => {
+                    self.output.mappend_assign(fst.da.output(e));
+                    self.state = fst.da.next[e];
                     self.position += 1;
-                    terminal = stipe.terminal;
-                    if terminal.is() { break }
+                    // A final state missing its state output, as in a
+                    // corrupted FST, is passed over.
+                    if let Some(out) = fst.resolve(self.state, self.output, stipe.terminal) {
+                        return Some((self.position, out))
+                    }
                 },
-                _ => return None
+                _ => break
             }
         }
 
-        match terminal {
-            Terminal::Not   => None,
-            Terminal::Empty => Some((self.position, self.output)),
-            Terminal::Inner =>
-                Some((self.position,
-                      self.output.mappend(self.fst.state_output[&self.state])))
-        }
+        // The query is exhausted, or led nowhere.
+        self.query = None;
+        None
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, Some(self.query.len()))
+        match self.query {
+            Some(ref q) => (0, q.size_hint().1),
+            None => (0, Some(0))
+        }
     }
 }
 
-impl<'a, 'q, I, O> Iterator for Reaper<'a, 'q, I, O>
-    where I : Index, O : Output
+impl<'a, Q, I, O> Iterator for Reaper<'a, Q, I, O>
+    where Q : Iterator
+        , Q::Item : Borrow<u8>
+        , I : Index, O : Output
 {
     type Item = (usize, O);
 
@@ -255,35 +862,37 @@ impl<'a, 'q, I, O> Iterator for Reaper<'a, 'q, I, O>
         // the first match of any query.
         self.root_output.take()
             .or_else(|| {
-                let mut terminal = Terminal::Not;
-                let da = &self.fst.da;
-                for &label in self.query.by_ref() {
+                let fst = self.fst;
+                for label in self.query.as_mut() ?.by_ref() {
+                    let label = *label.borrow();
                     let e = self.state.as_usize() + (1 + label as usize);
-                    let stipe = da.stipe.get(e);
-                    match stipe {
-                        Some(stipe) if stipe.check == label => {
-                            self.output.mappend_assign(da.output[e]);
-                            self.state = da.next[e];
+                    match fst.da.stipe.get(e) {
+                        Some(stipe) if stipe.check == label && !fst.da.next[e].is_zero() => {
+                            self.output.mappend_assign(fst.da.output(e));
+                            self.state = fst.da.next[e];
                             self.position += 1;
-                            terminal = stipe.terminal;
-                            if terminal.is() { break }
+                            // A final state missing its state output, as in a
+                            // corrupted FST, is passed over.
+                            if let Some(out) = fst.resolve(self.state, self.output, stipe.terminal) {
+                                return Some((self.position, out))
+                            }
                         },
-                        _ => return None
+                        _ => break
                     }
                 }
 
-                match terminal {
-                    Terminal::Not   => None,
-                    Terminal::Empty => Some((self.position, self.output)),
-                    Terminal::Inner =>
-                        Some((self.position,
-                              self.output.mappend(self.fst.state_output[&self.state])))
-                }
+                // The query is exhausted, or led nowhere.
+                self.query = None;
+                None
             })
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         let from_root = if self.root_output.is_some() { 1 } else { 0 };
-        (from_root, Some(self.query.len() + from_root))
+        let from_query = match self.query {
+            Some(ref q) => q.size_hint().1,
+            None => Some(0)
+        };
+        (from_root, from_query.and_then(|n| n.checked_add(from_root)))
     }
 }
@@ -0,0 +1,352 @@
+//! Set operations over FSTs, streamed in key order into a fresh builder.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::iter::{FromIterator, Peekable};
+use std::mem;
+
+use fst::{FST, Iter, Output};
+use fst::builder::Builder;
+use fst::error::{Error, Result};
+use index::Index;
+
+
+/// A sorted merge of two ordered streams of keys and values, pairing the
+/// values of keys present in both.
+#[derive(Clone, Debug)]
+pub struct Merge<A, B, O>
+    where A : Iterator<Item = (Vec<u8>, O)>
+        , B : Iterator<Item = (Vec<u8>, O)>
+{
+    a : Peekable<A>,
+    b : Peekable<B>
+}
+
+impl<A, B, O> Merge<A, B, O>
+    where A : Iterator<Item = (Vec<u8>, O)>
+        , B : Iterator<Item = (Vec<u8>, O)>
+{
+    pub fn new(a : A, b : B) -> Merge<A, B, O> {
+        Merge { a : a.peekable(), b : b.peekable() }
+    }
+}
+
+impl<A, B, O> Iterator for Merge<A, B, O>
+    where A : Iterator<Item = (Vec<u8>, O)>
+        , B : Iterator<Item = (Vec<u8>, O)>
+{
+    type Item = (Vec<u8>, Option<O>, Option<O>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order = match (self.a.peek(), self.b.peek()) {
+            (Some(x), Some(y)) => x.0.cmp(&y.0),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None
+        };
+
+        match order {
+            Ordering::Less => self.a.next().map(|(k, x)| (k, Some(x), None)),
+            Ordering::Greater => self.b.next().map(|(k, y)| (k, None, Some(y))),
+            Ordering::Equal => {
+                let (k, x) = self.a.next() ?;
+                let y = self.b.next().map(|(_, y)| y);
+                Some((k, Some(x), y))
+            }
+        }
+    }
+}
+
+
+/// Merge two FSTs into a new one, keeping each key for which `select`
+/// returns a value, given the key's value in either FST.
+pub fn merge_with<I, O, F>(a : &FST<I, O>, b : &FST<I, O>, mut select : F) -> Result<FST<I, O>>
+    where I : Index, O : Output
+        , F : FnMut(Option<O>, Option<O>) -> Option<O>
+{
+    let mut builder = Builder::new();
+    for (key, x, y) in Merge::new(a.iter(), b.iter()) {
+        if let Some(value) = select(x, y) { builder.insert(&key, value) ? }
+    }
+    builder.finish() ?;
+
+    builder.into_fst()
+}
+
+/// Stream the entries of an FST, merged with pairs sorted by key, into a new
+/// FST, resolving the value of keys present in both with `collide`.
+///
+/// The index of a key recorded by an error of `collide` is moved ahead by
+/// that of the collision among the entries merged.
+pub fn merge_delta<I, O, K, T, F>(fst : &FST<I, O>, delta : T, mut collide : F) -> Result<FST<I, O>>
+    where I : Index, O : Output
+        , K : AsRef<[u8]>
+        , T : IntoIterator<Item = (K, O)>
+        , F : FnMut(&[u8], O, O) -> Result<O>
+{
+    let delta = delta.into_iter().map(|(k, v)| (k.as_ref().to_vec(), v));
+    let mut builder = Builder::new();
+    for (index, (key, x, y)) in Merge::new(fst.iter(), delta).enumerate() {
+        let value = match (x, y) {
+            (Some(x), Some(y)) => collide(&key, x, y).map_err(|e| e.shifted(index)) ?,
+            (Some(v), None) | (None, Some(v)) => v,
+            (None, None) => unreachable!()
+        };
+        builder.insert(&key, value) ?;
+    }
+    builder.finish() ?;
+
+    builder.into_fst()
+}
+
+/// An FST with keys removed, along with those of them which were absent.
+#[derive(Clone, Debug)]
+pub struct Removal<I, O> where I : Index, O : Output {
+    pub fst : FST<I, O>,
+    pub absent : Vec<Vec<u8>>
+}
+
+/// Stream the entries of an FST into a new FST, skipping the given keys,
+/// which must be sorted.
+pub fn remove_keys<I, O, K, T>(fst : &FST<I, O>, keys : T) -> Result<Removal<I, O>>
+    where I : Index, O : Output
+        , K : AsRef<[u8]>
+        , T : IntoIterator<Item = K>
+{
+    let mut entries = fst.iter().peekable();
+    let mut builder = Builder::new();
+    let mut absent = Vec::new();
+    let mut previous : Option<Vec<u8>> = None;
+    for (index, k) in keys.into_iter().enumerate() {
+        let key = k.as_ref();
+        match previous {
+            Some(ref prev) if key == prev.as_slice() =>
+                return Err(Error::Duplicate { key : key.to_vec(), index, existing_hint : None, inserted_hint : None }),
+            Some(ref prev) if key < prev.as_slice() =>
+                return Err(Error::OutOfOrder { key : key.to_vec(), previous : prev.clone(), index }),
+            _ => previous = Some(key.to_vec())
+        }
+
+        while let Some((entry, value)) = entries.next_if(|e| e.0.as_slice() < key) {
+            builder.insert(&entry, value) ?;
+        }
+        match entries.next_if(|e| e.0 == key) {
+            Some(_) => (),
+            None => absent.push(key.to_vec())
+        }
+    }
+    for (entry, value) in entries { builder.insert(&entry, value) ? }
+    builder.finish() ?;
+
+    Ok(Removal { fst : builder.into_fst() ?, absent })
+}
+
+/// Partition the entries of an FST by sorted boundaries, into one more FST
+/// than there are boundaries: the keys below the first boundary, then those
+/// from each boundary up to the next.
+pub fn shard<I, O>(fst : &FST<I, O>, boundaries : &[&[u8]]) -> Result<Vec<FST<I, O>>>
+    where I : Index, O : Output
+{
+    for (i, pair) in boundaries.windows(2).enumerate() {
+        let (key, previous, index) = (pair[1].to_vec(), pair[0].to_vec(), i + 1);
+        if key == previous { return Err(Error::Duplicate { key, index, existing_hint : None, inserted_hint : None }) }
+        if key < previous { return Err(Error::OutOfOrder { key, previous, index }) }
+    }
+
+    let count = boundaries.len() + 1;
+    let mut shards = Vec::with_capacity(count);
+    let mut boundaries = boundaries.iter().peekable();
+    let mut builder = Builder::new();
+    for (key, value) in fst.iter() {
+        while boundaries.next_if(|&&b| key.as_slice() >= b).is_some() {
+            builder.finish() ?;
+            shards.push(mem::take(&mut builder).into_fst() ?);
+        }
+        builder.insert(&key, value) ?;
+    }
+    while shards.len() < count {
+        builder.finish() ?;
+        shards.push(mem::take(&mut builder).into_fst() ?);
+    }
+
+    Ok(shards)
+}
+
+/// Concatenate FSTs whose keys lie in successive, disjoint ranges.
+///
+/// Fails with `OutOfOrder` or `Duplicate` if the ranges overlap.
+pub fn concatenate<'a, I, O, T>(shards : T) -> Result<FST<I, O>>
+    where I : Index + 'a, O : Output + 'a
+        , T : IntoIterator<Item = &'a FST<I, O>>
+{
+    let mut builder = Builder::new();
+    for shard in shards {
+        for (key, value) in shard.iter() { builder.insert(&key, value) ? }
+    }
+    builder.finish() ?;
+
+    builder.into_fst()
+}
+
+/// The keys of either FST, with the values of keys present in both
+/// combined by `combine`.
+pub fn union<I, O, F>(a : &FST<I, O>, b : &FST<I, O>, combine : F) -> Result<FST<I, O>>
+    where I : Index, O : Output
+        , F : Fn(O, O) -> O
+{
+    merge_with(a, b, |x, y| match (x, y) {
+        (Some(x), Some(y)) => Some(combine(x, y)),
+        (x, y) => x.or(y)
+    })
+}
+
+/// The keys present in both FSTs, with their values combined by `combine`.
+pub fn intersection<I, O, F>(a : &FST<I, O>, b : &FST<I, O>, combine : F) -> Result<FST<I, O>>
+    where I : Index, O : Output
+        , F : Fn(O, O) -> O
+{
+    merge_with(a, b, |x, y| match (x, y) {
+        (Some(x), Some(y)) => Some(combine(x, y)),
+        _ => None
+    })
+}
+
+/// The keys of `a` absent from `b`, with their values in `a`.
+pub fn difference<I, O>(a : &FST<I, O>, b : &FST<I, O>) -> Result<FST<I, O>>
+    where I : Index, O : Output
+{
+    merge_with(a, b, |x, y| match (x, y) {
+        (Some(x), None) => Some(x),
+        _ => None
+    })
+}
+
+/// The keys present in exactly one FST, with their values given by
+/// `combine_absent` from the value in either FST and the absence in the other.
+pub fn symmetric_difference<I, O, F>(a : &FST<I, O>, b : &FST<I, O>, combine_absent : F) -> Result<FST<I, O>>
+    where I : Index, O : Output
+        , F : Fn(Option<O>, Option<O>) -> O
+{
+    merge_with(a, b, |x, y| match (x, y) {
+        (Some(_), None) | (None, Some(_)) => Some(combine_absent(x, y)),
+        _ => None
+    })
+}
+
+
+/// The head of a stream in a k-way merge, ordered by key then stream.
+#[derive(Clone, Debug)]
+struct Head<O> {
+    key : Vec<u8>,
+    source : usize,
+    value : O
+}
+
+impl<O> PartialEq for Head<O> {
+    fn eq(&self, other : &Head<O>) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl<O> Eq for Head<O> {}
+
+impl<O> PartialOrd for Head<O> {
+    fn partial_cmp(&self, other : &Head<O>) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl<O> Ord for Head<O> {
+    fn cmp(&self, other : &Head<O>) -> Ordering {
+        (&self.key, self.source).cmp(&(&other.key, other.source))
+    }
+}
+
+
+/// A collection of FSTs to be merged in a single pass.
+#[derive(Clone, Debug, Default)]
+pub struct OpBuilder<'a, I, O>
+    where I : Index + 'a
+        , O : Output + 'a
+{
+    fsts : Vec<&'a FST<I, O>>
+}
+
+impl<'a, I, O> OpBuilder<'a, I, O> where I : Index, O : Output {
+    pub fn new() -> OpBuilder<'a, I, O> { OpBuilder { fsts : Vec::new() } }
+
+    /// Add an FST to the merge; its values are tagged with its position.
+    pub fn push(mut self, fst : &'a FST<I, O>) -> OpBuilder<'a, I, O> {
+        self.fsts.push(fst);
+        self
+    }
+
+    /// Returns a stream of every key of any FST, in order, along with the
+    /// values it has in each FST holding it.
+    pub fn union(self) -> Union<'a, I, O> {
+        let mut streams : Vec<_> = self.fsts.iter().map(|fst| fst.iter()).collect();
+        let mut heap = BinaryHeap::with_capacity(streams.len());
+        for (source, stream) in streams.iter_mut().enumerate() {
+            if let Some((key, value)) = stream.next() { heap.push(Reverse(Head { key, source, value })) }
+        }
+        Union { streams, heap }
+    }
+
+    /// Build the union of all FSTs, folding the values of common keys with
+    /// `combine` in the order the FSTs were added.
+    pub fn build<F>(self, combine : F) -> Result<FST<I, O>>
+        where F : Fn(O, O) -> O
+    {
+        let mut builder = Builder::new();
+        for (key, values) in self.union() {
+            let value = values.iter().map(|&(_, v)| v).fold(None, |acc, v| match acc {
+                Some(acc) => Some(combine(acc, v)),
+                None => Some(v)
+            });
+            if let Some(value) = value { builder.insert(&key, value) ? }
+        }
+        builder.finish() ?;
+
+        builder.into_fst()
+    }
+}
+
+impl<'a, I, O> FromIterator<&'a FST<I, O>> for OpBuilder<'a, I, O> where I : Index, O : Output {
+    fn from_iter<T>(iter : T) -> OpBuilder<'a, I, O>
+        where T : IntoIterator<Item = &'a FST<I, O>>
+    {
+        OpBuilder { fsts : iter.into_iter().collect() }
+    }
+}
+
+
+/// A k-way merge of the keys of several FSTs.
+#[derive(Clone, Debug)]
+pub struct Union<'a, I, O>
+    where I : Index + 'a
+        , O : Output + 'a
+{
+    streams : Vec<Iter<'a, I, O>>,
+    heap : BinaryHeap<Reverse<Head<O>>>
+}
+
+impl<'a, I, O> Iterator for Union<'a, I, O> where I : Index, O : Output {
+    type Item = (Vec<u8>, Vec<(usize, O)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse(first) = self.heap.pop() ?;
+        let mut values = vec![(first.source, first.value)];
+        self.advance(first.source);
+        while self.heap.peek().is_some_and(|Reverse(h)| h.key == first.key) {
+            let Reverse(head) = self.heap.pop().unwrap();
+            values.push((head.source, head.value));
+            self.advance(head.source);
+        }
+
+        Some((first.key, values))
+    }
+}
+
+impl<'a, I, O> Union<'a, I, O> where I : Index, O : Output {
+    fn advance(&mut self, source : usize) {
+        if let Some((key, value)) = self.streams[source].next() {
+            self.heap.push(Reverse(Head { key, source, value }))
+        }
+    }
+}
@@ -1,28 +1,136 @@
 use std::cmp;
-use std::fmt::Debug;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt::{self, Debug};
 use std::hash::Hash;
 
+use num_traits::Bounded;
 
-/// An additive abelian group with a prefix operation.
+
+/// The values of an FST: a monoid with a prefix operation, such as numbers
+/// under addition or byte strings under concatenation.
+///
+/// Outputs are accumulated along a key in order, so `mappend` need not be
+/// commutative.
 pub trait Output : Eq + Copy + Hash + Default + Debug {
     /// The identity element.
     fn zero() -> Self;
 
-    /// The additive operation under which the output forms an abelian group.
+    /// The associative operation accumulating outputs along a key.
     fn mappend(self, y : Self) -> Self;
 
-    /// The additive operation applied to the inverse of `y`.
+    /// The value less its prefix `y`, such that `y.mappend(self.inverse(y))`
+    /// is `self`.
     fn inverse(self, y : Self) -> Self;
 
     /// The longest common prefix of the given values.
     fn prefix(self, y : Self) -> Self;
 
+    /// `mappend`, or `None` if the result is not representable.
+    #[inline] fn checked_mappend(self, y : Self) -> Option<Self> { Some(self.mappend(y)) }
+
+    /// `inverse`, or `None` if the result is not representable.
+    #[inline] fn checked_inverse(self, y : Self) -> Option<Self> { Some(self.inverse(y)) }
+
     #[inline] fn is_zero(self) -> bool { self == Self::zero() }
 
     #[inline] fn mappend_assign(&mut self, y : Self) { *self = self.mappend(y) }
     #[inline] fn inverse_assign(&mut self, y : Self) { *self = self.inverse(y) }
 }
 
+/// The trivial group, for FSTs used as sets.
+impl Output for () {
+    #[inline] fn zero() -> Self {}
+    #[inline] fn mappend(self, _ : Self) -> Self {}
+    #[inline] fn inverse(self, _ : Self) -> Self {}
+    #[inline] fn prefix(self, _ : Self) -> Self {}
+}
+
+/// The product of two groups, whose operations apply to each component.
+impl<A, B> Output for (A, B) where A : Output, B : Output {
+    #[inline] fn zero() -> Self { (A::zero(), B::zero()) }
+    #[inline] fn mappend(self, y : Self) -> Self { (self.0.mappend(y.0), self.1.mappend(y.1)) }
+    #[inline] fn inverse(self, y : Self) -> Self { (self.0.inverse(y.0), self.1.inverse(y.1)) }
+    #[inline] fn prefix(self, y : Self) -> Self { (self.0.prefix(y.0), self.1.prefix(y.1)) }
+
+    #[inline]
+    fn checked_mappend(self, y : Self) -> Option<Self> {
+        Some((self.0.checked_mappend(y.0) ?, self.1.checked_mappend(y.1) ?))
+    }
+
+    #[inline]
+    fn checked_inverse(self, y : Self) -> Option<Self> {
+        Some((self.0.checked_inverse(y.0) ?, self.1.checked_inverse(y.1) ?))
+    }
+}
+
+/// Byte arrays, as `N` independent bytes added component-wise: the prefix of
+/// two arrays is their component-wise minimum, so arrays alike in some bytes
+/// share those along transitions. Implemented for the arrays that are
+/// `Default`, of up to 32 bytes.
+impl<const N : usize> Output for [u8; N] where [u8; N] : Default {
+    #[inline] fn zero() -> Self { [0; N] }
+    #[inline] fn mappend(self, y : Self) -> Self { zip_bytes(self, y, |a, b| a + b) }
+    #[inline] fn inverse(self, y : Self) -> Self { zip_bytes(self, y, |a, b| a - b) }
+    #[inline] fn prefix(self, y : Self) -> Self { zip_bytes(self, y, cmp::min) }
+
+    #[inline]
+    fn checked_mappend(self, y : Self) -> Option<Self> {
+        let mut out = self;
+        for (o, b) in out.iter_mut().zip(&y) { *o = o.checked_add(*b) ? }
+        Some(out)
+    }
+
+    #[inline]
+    fn checked_inverse(self, y : Self) -> Option<Self> {
+        let mut out = self;
+        for (o, b) in out.iter_mut().zip(&y) { *o = o.checked_sub(*b) ? }
+        Some(out)
+    }
+}
+
+#[inline]
+fn zip_bytes<F, const N : usize>(mut x : [u8; N], y : [u8; N], f : F) -> [u8; N]
+    where F : Fn(u8, u8) -> u8
+{
+    for (a, &b) in x.iter_mut().zip(&y) { *a = f(*a, b) }
+    x
+}
+
+/// Implement `Output` for a newtype by forwarding to the type it wraps, which
+/// must itself be an output, as in `impl_output_delegating!(Freq => u32)` for
+/// `struct Freq(u32)`.
+///
+/// The newtype must derive the other traits required of outputs.
+#[macro_export]
+macro_rules! impl_output_delegating {
+    ($newtype:ident => $inner:ty) => {
+        impl $crate::fst::Output for $newtype {
+            #[inline]
+            fn zero() -> Self { $newtype(<$inner as $crate::fst::Output>::zero()) }
+
+            #[inline]
+            fn mappend(self, y : Self) -> Self { $newtype($crate::fst::Output::mappend(self.0, y.0)) }
+
+            #[inline]
+            fn inverse(self, y : Self) -> Self { $newtype($crate::fst::Output::inverse(self.0, y.0)) }
+
+            #[inline]
+            fn prefix(self, y : Self) -> Self { $newtype($crate::fst::Output::prefix(self.0, y.0)) }
+
+            #[inline]
+            fn checked_mappend(self, y : Self) -> Option<Self> {
+                $crate::fst::Output::checked_mappend(self.0, y.0).map($newtype)
+            }
+
+            #[inline]
+            fn checked_inverse(self, y : Self) -> Option<Self> {
+                $crate::fst::Output::checked_inverse(self.0, y.0).map($newtype)
+            }
+        }
+    }
+}
+
 macro_rules! impl_output_unsigned {
     ($num:ty) => {
         impl Output for $num {
@@ -30,6 +138,8 @@ macro_rules! impl_output_unsigned {
             #[inline] fn mappend(self, y : Self) -> Self { self + y }
             #[inline] fn inverse(self, y : Self) -> Self { self - y }
             #[inline] fn prefix(self, y : Self) -> Self { cmp::min(self, y) }
+            #[inline] fn checked_mappend(self, y : Self) -> Option<Self> { self.checked_add(y) }
+            #[inline] fn checked_inverse(self, y : Self) -> Option<Self> { self.checked_sub(y) }
         }
     }
 }
@@ -40,6 +150,8 @@ macro_rules! impl_output_signed {
             #[inline] fn zero() -> Self { 0 }
             #[inline] fn mappend(self, y : Self) -> Self { self + y }
             #[inline] fn inverse(self, y : Self) -> Self { self - y }
+            #[inline] fn checked_mappend(self, y : Self) -> Option<Self> { self.checked_add(y) }
+            #[inline] fn checked_inverse(self, y : Self) -> Option<Self> { self.checked_sub(y) }
 
             #[inline]
             fn prefix(self, y : Self) -> Self {
@@ -57,10 +169,315 @@ impl_output_unsigned! { u8    }
 impl_output_unsigned! { u16   }
 impl_output_unsigned! { u32   }
 impl_output_unsigned! { u64   }
+impl_output_unsigned! { u128  }
 impl_output_unsigned! { usize }
 
 impl_output_signed! { i8    }
 impl_output_signed! { i16   }
 impl_output_signed! { i32   }
 impl_output_signed! { i64   }
+impl_output_signed! { i128  }
 impl_output_signed! { isize }
+
+
+/// A monoid of outputs without an inverse, such as `Min` and `Max`.
+///
+/// Such values cannot be split along the transitions of a key: wrapped in
+/// `Unshared`, each is stored whole, on the transition where its key's path
+/// parts from those of keys with other values. Only keys with equal values
+/// share them, so an FST over a monoid has more distinct states, and more
+/// nonzero outputs, than one over a group such as the integers.
+pub trait OutputMonoid : Eq + Copy + Hash + Default + Debug {
+    /// The identity element.
+    fn zero() -> Self;
+
+    /// The associative operation accumulating outputs along a key.
+    fn mappend(self, y : Self) -> Self;
+}
+
+/// The outputs of a monoid, stored whole rather than shared between keys.
+///
+/// The prefix of two values is the value itself if they are equal and zero
+/// otherwise, so that the builder never needs an inverse.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Unshared<M>(pub M);
+
+impl<M> Output for Unshared<M> where M : OutputMonoid {
+    #[inline] fn zero() -> Self { Unshared(M::zero()) }
+    #[inline] fn mappend(self, y : Self) -> Self { Unshared(self.0.mappend(y.0)) }
+
+    // A prefix is either zero or the value itself.
+    #[inline]
+    fn inverse(self, y : Self) -> Self {
+        match y == Unshared::zero() {
+            true => self,
+            false => Unshared::zero()
+        }
+    }
+
+    #[inline]
+    fn prefix(self, y : Self) -> Self {
+        match self == y {
+            true => self,
+            false => Unshared::zero()
+        }
+    }
+}
+
+macro_rules! impl_output_bound {
+    ($(#[$attr:meta])* $name:ident, $op:path, $zero:path) => {
+        $(#[$attr])*
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        #[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        pub struct $name<T>(pub T);
+
+        impl<T> Default for $name<T> where T : Bounded {
+            fn default() -> $name<T> { $name($zero()) }
+        }
+
+        impl<T> OutputMonoid for $name<T> where T : Ord + Bounded + Copy + Hash + Debug {
+            #[inline] fn zero() -> Self { $name($zero()) }
+            #[inline] fn mappend(self, y : Self) -> Self { $name($op(self.0, y.0)) }
+        }
+
+        impl<T> Output for $name<T> where T : Ord + Bounded + Copy + Hash + Debug {
+            #[inline] fn zero() -> Self { OutputMonoid::zero() }
+            #[inline] fn mappend(self, y : Self) -> Self { OutputMonoid::mappend(self, y) }
+            #[inline] fn inverse(self, y : Self) -> Self { Unshared(self).inverse(Unshared(y)).0 }
+            #[inline] fn prefix(self, y : Self) -> Self { Unshared(self).prefix(Unshared(y)).0 }
+        }
+    }
+}
+
+impl_output_bound! {
+    /// The least of the values along a key, as an unshared monoid output.
+    Min, cmp::min, T::max_value
+}
+
+impl_output_bound! {
+    /// The greatest of the values along a key, as an unshared monoid output.
+    Max, cmp::max, T::min_value
+}
+
+
+/// A floating-point value was NaN, which no float output admits.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NotANumber;
+
+impl fmt::Display for NotANumber {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NaN is not a valid output")
+    }
+}
+
+impl error::Error for NotANumber {}
+
+// Float outputs are compared and hashed by their bits, with `-0.0` folded into
+// `0.0`. Sums of floats are inexact, so the prefix of two values is the value
+// itself if they are equal and zero otherwise: each key then carries a single
+// non-zero part, and is read back bit for bit. Values are only shared along
+// transitions when identical.
+macro_rules! impl_output_float {
+    ($(#[$attr:meta])* $name:ident, $float:ty, $bits:ty) => {
+        $(#[$attr])*
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        #[derive(Copy, Clone, Default, Eq, Hash, PartialEq)]
+        pub struct $name($bits);
+
+        impl $name {
+            /// Wrap a value, unless it is NaN.
+            pub fn new(x : $float) -> Option<$name> {
+                match x.is_nan() {
+                    true => None,
+                    false => Some($name::wrap(x))
+                }
+            }
+
+            #[inline]
+            fn wrap(x : $float) -> $name {
+                $name(if x == 0.0 { 0 } else { x.to_bits() })
+            }
+
+            #[inline]
+            pub fn get(self) -> $float { <$float>::from_bits(self.0) }
+        }
+
+        impl Output for $name {
+            #[inline] fn zero() -> Self { $name(0) }
+            #[inline] fn mappend(self, y : Self) -> Self { $name::wrap(self.get() + y.get()) }
+
+            #[inline]
+            fn inverse(self, y : Self) -> Self {
+                match self == y {
+                    true => $name::zero(),
+                    false => $name::wrap(self.get() - y.get())
+                }
+            }
+
+            #[inline]
+            fn prefix(self, y : Self) -> Self {
+                match self == y {
+                    true => self,
+                    false => $name::zero()
+                }
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other : &$name) -> cmp::Ordering { self.get().total_cmp(&other.get()) }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other : &$name) -> Option<cmp::Ordering> { Some(self.cmp(other)) }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result { fmt::Debug::fmt(&self.get(), f) }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.get(), f) }
+        }
+
+        impl TryFrom<$float> for $name {
+            type Error = NotANumber;
+
+            fn try_from(x : $float) -> Result<$name, NotANumber> { $name::new(x).ok_or(NotANumber) }
+        }
+
+        impl From<$name> for $float {
+            fn from(x : $name) -> $float { x.get() }
+        }
+    }
+}
+
+impl_output_float! {
+    /// An `f32` output, such as a probability or a log-weight.
+    F32, f32, u32
+}
+
+impl_output_float! {
+    /// An `f64` output, such as a probability or a log-weight.
+    F64, f64, u64
+}
+
+
+/// A byte string of at most `N` bytes, concatenated along keys, for mapping
+/// keys to short strings such as lemmas or identifiers.
+///
+/// Outputs must be `Copy`, so the bytes are held inline: every slot of the
+/// Dart stores `N + 1` bytes, and `N` may not exceed 255.
+#[derive(Copy, Clone, Eq, Hash, PartialEq)]
+pub struct Bytes<const N : usize = 15> {
+    len : u8,
+    bytes : [u8; N]
+}
+
+impl<const N : usize> Bytes<N> {
+    /// The byte string, unless longer than `N` bytes.
+    pub fn new(bytes : &[u8]) -> Option<Bytes<N>> {
+        assert!(N <= u8::MAX as usize, "byte string outputs hold at most 255 bytes");
+        if bytes.len() > N { return None }
+        let mut b = Bytes::default();
+        b.bytes[.. bytes.len()].copy_from_slice(bytes);
+        b.len = bytes.len() as u8;
+        Some(b)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] { &self.bytes[.. self.len as usize] }
+
+    pub fn len(&self) -> usize { self.len as usize }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+}
+
+impl<const N : usize> Default for Bytes<N> {
+    fn default() -> Bytes<N> { Bytes { len : 0, bytes : [0; N] } }
+}
+
+impl<const N : usize> Output for Bytes<N> {
+    #[inline] fn zero() -> Self { Bytes::default() }
+
+    /// Concatenation, panicking past `N` bytes.
+    #[inline]
+    fn mappend(self, y : Self) -> Self {
+        self.checked_mappend(y).expect("byte string output longer than its capacity")
+    }
+
+    /// Strip the prefix `y`, which must be one.
+    #[inline]
+    fn inverse(self, y : Self) -> Self {
+        self.checked_inverse(y).expect("byte string output stripped of a non-prefix")
+    }
+
+    #[inline]
+    fn prefix(self, y : Self) -> Self {
+        let common = self.as_bytes().iter().zip(y.as_bytes()).take_while(|&(a, b)| a == b).count();
+        Bytes { len : common as u8, ..self }.canonical()
+    }
+
+    #[inline]
+    fn checked_mappend(self, y : Self) -> Option<Self> {
+        let (l, r) = (self.len(), y.len());
+        if l + r > N { return None }
+        let mut b = self;
+        b.bytes[l .. l + r].copy_from_slice(y.as_bytes());
+        b.len = (l + r) as u8;
+        Some(b)
+    }
+
+    #[inline]
+    fn checked_inverse(self, y : Self) -> Option<Self> {
+        match self.as_bytes().strip_prefix(y.as_bytes()) {
+            Some(rest) => Bytes::new(rest),
+            None => None
+        }
+    }
+}
+
+impl<const N : usize> Bytes<N> {
+    /// Zero the bytes past the end, on which equality and hashing depend.
+    #[inline]
+    fn canonical(mut self) -> Bytes<N> {
+        for b in &mut self.bytes[self.len as usize ..] { *b = 0 }
+        self
+    }
+}
+
+impl<const N : usize> fmt::Debug for Bytes<N> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bytes({:?})", String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+impl<const N : usize> AsRef<[u8]> for Bytes<N> {
+    fn as_ref(&self) -> &[u8] { self.as_bytes() }
+}
+
+impl<'a, const N : usize> TryFrom<&'a [u8]> for Bytes<N> {
+    type Error = &'a [u8];
+
+    fn try_from(bytes : &'a [u8]) -> Result<Bytes<N>, &'a [u8]> { Bytes::new(bytes).ok_or(bytes) }
+}
+
+impl<'a, const N : usize> TryFrom<&'a str> for Bytes<N> {
+    type Error = &'a str;
+
+    fn try_from(s : &'a str) -> Result<Bytes<N>, &'a str> { Bytes::new(s.as_bytes()).ok_or(s) }
+}
+
+#[cfg(feature = "serde")]
+impl<const N : usize> ::serde::Serialize for Bytes<N> {
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok, S::Error> where S : ::serde::Serializer {
+        self.as_bytes().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N : usize> ::serde::Deserialize<'de> for Bytes<N> {
+    fn deserialize<D>(deserializer : D) -> Result<Bytes<N>, D::Error> where D : ::serde::Deserializer<'de> {
+        let bytes = Vec::<u8>::deserialize(deserializer) ?;
+        Bytes::new(&bytes).ok_or_else(|| ::serde::de::Error::invalid_length(bytes.len(), &"at most N bytes"))
+    }
+}
@@ -0,0 +1,167 @@
+//! Values too large to be outputs, kept in a table indexed by ordinal.
+
+use std::convert::TryFrom;
+#[cfg(feature = "serialization")]
+use std::io;
+
+#[cfg(feature = "serialization")]
+use bincode;
+#[cfg(feature = "serialization")]
+use serde::{Serialize, de::DeserializeOwned};
+
+use fst::{FST, Iter};
+use fst::builder::Builder;
+use fst::error::{Error, Result};
+use index::Index;
+
+
+/// An FST mapping keys to ordinals into a table of payloads, for values such
+/// as strings or structs which `Output` cannot carry.
+///
+/// Every ordinal in the FST is an index into the table, as is checked when
+/// deserializing it.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Unchecked<I, T>"))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PayloadMap<I, T> where I : Index {
+    fst : FST<I, u32>,
+    payloads : Vec<T>
+}
+
+impl<I, T> PayloadMap<I, T> where I : Index {
+    /// Build from pairs sorted by key, numbering the payloads in order.
+    pub fn from_iter<K, It>(iter : It) -> Result<PayloadMap<I, T>>
+        where K : AsRef<[u8]>
+            , It : IntoIterator<Item = (K, T)>
+    {
+        let mut builder = Builder::new();
+        let mut payloads = Vec::new();
+        for (k, payload) in iter {
+            let key = k.as_ref();
+            let ordinal = match u32::try_from(payloads.len()) {
+                Ok(ordinal) => ordinal,
+                Err(_) => return Err(Error::OutputOverflow { key : key.to_vec(), value : payloads.len().to_string() })
+            };
+            builder.insert(key, ordinal) ?;
+            payloads.push(payload);
+        }
+        builder.finish() ?;
+
+        Ok(PayloadMap { fst : builder.into_fst() ?, payloads })
+    }
+
+    /// Build from pairs in any order, sorting them by key first.
+    pub fn from_unsorted<K>(mut pairs : Vec<(K, T)>) -> Result<PayloadMap<I, T>>
+        where K : AsRef<[u8]>
+    {
+        pairs.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        PayloadMap::from_iter(pairs)
+    }
+
+    /// Pair an FST of ordinals with its table, failing with
+    /// `OrdinalOutOfRange` if an ordinal has no payload.
+    pub fn from_parts(fst : FST<I, u32>, payloads : Vec<T>) -> Result<PayloadMap<I, T>> {
+        let map = PayloadMap { fst, payloads };
+        map.check() ?;
+        Ok(map)
+    }
+
+    /// Verify that every ordinal in the FST is an index into the table.
+    pub fn check(&self) -> Result<()> {
+        match self.fst.iter().find(|&(_, ordinal)| ordinal as usize >= self.payloads.len()) {
+            Some((key, ordinal)) =>
+                Err(Error::OrdinalOutOfRange { key, ordinal : ordinal as usize, payloads : self.payloads.len() }),
+            None => Ok(())
+        }
+    }
+
+    pub fn get<K>(&self, key : K) -> Option<&T>
+        where K : AsRef<[u8]>
+    {
+        self.fst.get(key).map(|ordinal| &self.payloads[ordinal as usize])
+    }
+
+    pub fn contains_key<K>(&self, key : K) -> bool
+        where K : AsRef<[u8]>
+    {
+        self.fst.contains(key)
+    }
+
+    /// Returns an iterator over keys and their payloads, in lexicographic
+    /// order of keys.
+    pub fn iter(&self) -> Payloads<'_, I, T> {
+        Payloads { iter : self.fst.iter(), payloads : &self.payloads }
+    }
+
+    /// The number of payloads in the table.
+    pub fn len(&self) -> usize { self.payloads.len() }
+
+    pub fn is_empty(&self) -> bool { self.payloads.is_empty() }
+
+    pub fn as_fst(&self) -> &FST<I, u32> { &self.fst }
+
+    pub fn payloads(&self) -> &[T] { &self.payloads }
+
+    pub fn into_parts(self) -> (FST<I, u32>, Vec<T>) { (self.fst, self.payloads) }
+
+    /// Write the FST and its table together.
+    #[cfg(feature = "serialization")]
+    pub fn write_to<W>(&self, w : W) -> Result<()>
+        where W : io::Write
+            , I : Serialize
+            , T : Serialize
+    {
+        bincode::serialize_into(w, self).map_err(Error::serialization)
+    }
+
+    /// Read an FST and its table written with `write_to`, checking that
+    /// every ordinal has a payload.
+    #[cfg(feature = "serialization")]
+    pub fn read_from<R>(r : R) -> Result<PayloadMap<I, T>>
+        where R : io::Read
+            , I : DeserializeOwned
+            , T : DeserializeOwned
+    {
+        // Checked apart, so as to fail with `OrdinalOutOfRange` itself.
+        let unchecked : Unchecked<I, T> =
+            bincode::deserialize_from(r).map_err(Error::serialization) ?;
+        PayloadMap::try_from(unchecked)
+    }
+}
+
+
+/// A payload map as deserialized, before its ordinals are checked.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "PayloadMap")]
+struct Unchecked<I, T> where I : Index {
+    fst : FST<I, u32>,
+    payloads : Vec<T>
+}
+
+#[cfg(feature = "serde")]
+impl<I, T> TryFrom<Unchecked<I, T>> for PayloadMap<I, T> where I : Index {
+    type Error = Error;
+
+    fn try_from(unchecked : Unchecked<I, T>) -> Result<PayloadMap<I, T>> {
+        PayloadMap::from_parts(unchecked.fst, unchecked.payloads)
+    }
+}
+
+
+/// An iterator over the keys of a payload map and their payloads, in
+/// lexicographic order of keys.
+#[derive(Clone, Debug)]
+pub struct Payloads<'a, I, T> where I : Index + 'a, T : 'a {
+    iter : Iter<'a, I, u32>,
+    payloads : &'a [T]
+}
+
+impl<'a, I, T> Iterator for Payloads<'a, I, T> where I : Index {
+    type Item = (Vec<u8>, &'a T);
+
+    fn next(&mut self) -> Option<(Vec<u8>, &'a T)> {
+        let payloads = self.payloads;
+        self.iter.next().map(|(key, ordinal)| (key, &payloads[ordinal as usize]))
+    }
+}
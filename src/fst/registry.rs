@@ -0,0 +1,221 @@
+//! Storage of the states registered by a builder.
+//!
+//! States are kept in an arena, with the transitions of all states stored
+//! contiguously. Minimization looks states up by a 128-bit signature rather
+//! than by the states themselves, and compares candidates in the arena to
+//! guard against collisions.
+
+use fnv::{FnvHashMap, FnvHasher};
+use std::collections::hash_map::Entry;
+use std::hash::{Hash, Hasher};
+
+use fst::builder::{State, Transition};
+use fst::memory;
+use fst::output::Output;
+use index::Index;
+use label::Label;
+
+
+/// A registered state, whose transitions lie in the arena.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+struct Packed<I, O> {
+    first : I,
+    /// Up to a whole alphabet of `u16` labels, one past `u16::MAX`.
+    len : u32,
+    terminal : bool,
+    final_output : O
+}
+
+/// A registered state, borrowed from the arena.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StateRef<'a, I, O, L> where I : 'a, O : 'a, L : 'a {
+    pub terminal : bool,
+    pub final_output : O,
+    pub transitions : &'a [Transition<I, O, L>]
+}
+
+impl<'a, I, O, L> StateRef<'a, I, O, L> where I : Index, O : Output, L : Label {
+    fn matches(self, state : &State<I, O, L>) -> bool {
+        self.terminal == state.terminal
+            && self.final_output == state.final_output
+            && self.transitions == state.transitions.as_slice()
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn to_state(self) -> State<I, O, L> {
+        State {
+            terminal : self.terminal,
+            final_output : self.final_output,
+            transitions : self.transitions.into()
+        }
+    }
+}
+
+
+/// The registered states of a builder, by index.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Arena<I, O, L> {
+    states : Vec<Packed<I, O>>,
+    transitions : Vec<Transition<I, O, L>>
+}
+
+impl<I, O, L> Arena<I, O, L> where I : Index, O : Output, L : Label {
+    /// Store a state, returning its index in the arena.
+    pub fn push(&mut self, state : &State<I, O, L>) -> usize {
+        self.states.push(Packed {
+            first : I::as_index(self.transitions.len()),
+            len : state.transitions.len() as u32,
+            terminal : state.terminal,
+            final_output : state.final_output
+        });
+        self.transitions.extend_from_slice(&state.transitions);
+        self.states.len() - 1
+    }
+
+    pub fn get(&self, s_i : usize) -> StateRef<'_, I, O, L> {
+        let packed = &self.states[s_i];
+        let first = packed.first.as_usize();
+        StateRef {
+            terminal : packed.terminal,
+            final_output : packed.final_output,
+            transitions : &self.transitions[first .. first + packed.len as usize]
+        }
+    }
+
+    pub fn len(&self) -> usize { self.states.len() }
+
+    pub fn is_empty(&self) -> bool { self.states.is_empty() }
+
+    /// The number of transitions, across all states.
+    pub fn transition_count(&self) -> usize { self.transitions.len() }
+
+    /// Drop every state, keeping the memory held.
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.transitions.clear();
+    }
+
+    /// Drop the states after `s_i`, releasing their memory once they hold
+    /// half the arena or more.
+    pub fn truncate(&mut self, s_i : usize) {
+        let Some(last) = self.states.get(s_i) else { return };
+        let end = last.first.as_usize() + last.len as usize;
+        self.states.truncate(s_i + 1);
+        self.transitions.truncate(end);
+        if 2 * self.transitions.len() <= self.transitions.capacity() {
+            self.states.shrink_to_fit();
+            self.transitions.shrink_to_fit();
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        memory::vec_bytes(&self.states) + memory::vec_bytes(&self.transitions)
+    }
+
+    /// The same states, indexed by another type, which must hold the index
+    /// of every state and transition.
+    pub fn widen<J>(&self) -> Arena<J, O, L> where J : Index {
+        let widen = |i : I| J::as_index(i.as_usize());
+        Arena {
+            states : self.states.iter()
+                .map(|p| Packed { first : widen(p.first), len : p.len, terminal : p.terminal, final_output : p.final_output })
+                .collect(),
+            transitions : self.transitions.iter()
+                .map(|t| Transition { label : t.label, output : t.output, destination : widen(t.destination) })
+                .collect()
+        }
+    }
+}
+
+
+/// A 128-bit hash of a state, as two words to spare the alignment of `u128`.
+pub type Signature = (u64, u64);
+
+/// A state with its signature, computed once when the state was finalized.
+/// It hashes as its signature alone, but compares in full.
+///
+/// It is serialized as the bare state, its signature being recomputed.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "State<I, O, L>", into = "State<I, O, L>"))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HashedState<I, O, L> where I : Index, O : Output, L : Label {
+    signature : Signature,
+    state : State<I, O, L>
+}
+
+impl<I, O, L> HashedState<I, O, L> where I : Index, O : Output, L : Label {
+    pub fn new(state : State<I, O, L>) -> HashedState<I, O, L> {
+        HashedState { signature : signature(&state), state }
+    }
+}
+
+impl<I, O, L> Hash for HashedState<I, O, L> where I : Index, O : Output, L : Label {
+    fn hash<H>(&self, h : &mut H) where H : Hasher { self.signature.hash(h) }
+}
+
+impl<I, O, L> From<State<I, O, L>> for HashedState<I, O, L> where I : Index, O : Output, L : Label {
+    fn from(state : State<I, O, L>) -> HashedState<I, O, L> { HashedState::new(state) }
+}
+
+impl<I, O, L> From<HashedState<I, O, L>> for State<I, O, L> where I : Index, O : Output, L : Label {
+    fn from(hashed : HashedState<I, O, L>) -> State<I, O, L> { hashed.state }
+}
+
+
+/// A map from state signatures to the index of the state in the arena.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Registry<I, O, L> where I : Index, O : Output, L : Label {
+    signatures : FnvHashMap<Signature, I>,
+    /// States whose signature was already taken by a different state.
+    collisions : FnvHashMap<HashedState<I, O, L>, I>
+}
+
+impl<I, O, L> Registry<I, O, L> where I : Index, O : Output, L : Label {
+    /// The index of a registered state equal to `state`, if any.
+    ///
+    /// Signatures seldom collide, so the state is cloned into a key of the
+    /// collisions only on the rare lookups reaching them.
+    pub fn find(&self, arena : &Arena<I, O, L>, signature : Signature, state : &State<I, O, L>) -> Option<I> {
+        let &s_i = self.signatures.get(&signature) ?;
+        match arena.get(s_i.as_usize()).matches(state) {
+            true => Some(s_i),
+            false => self.collisions.get(&HashedState { signature, state : state.clone() }).cloned()
+        }
+    }
+
+    /// Forget every state, keeping the memory held.
+    pub fn clear(&mut self) {
+        self.signatures.clear();
+        self.collisions.clear();
+    }
+
+    /// Register a state absent from the registry.
+    pub fn insert(&mut self, signature : Signature, state : State<I, O, L>, s_i : I) {
+        match self.signatures.entry(signature) {
+            Entry::Occupied(_) => { self.collisions.insert(HashedState { signature, state }, s_i); }
+            Entry::Vacant(e) => { e.insert(s_i); }
+        }
+    }
+
+    /// An estimate of the memory held by the registry, by capacity.
+    pub fn approx_bytes(&self) -> usize {
+        memory::hash_map_bytes::<Signature, I>(self.signatures.capacity())
+            + memory::hash_map_bytes::<HashedState<I, O, L>, I>(self.collisions.capacity())
+            + self.collisions.keys().map(|s| memory::small_vec_bytes(&s.state.transitions)).sum::<usize>()
+    }
+}
+
+
+/// The signature of a state, from two independently keyed hashes.
+pub fn signature<I, O, L>(state : &State<I, O, L>) -> Signature
+    where I : Index, O : Output, L : Label
+{
+    let mut low = FnvHasher::default();
+    let mut high = FnvHasher::with_key(0x6c62_272e_07bb_0142);
+    state.hash(&mut low);
+    state.hash(&mut high);
+    (high.finish(), low.finish())
+}
@@ -0,0 +1,108 @@
+//! Lookup of keys by suffix, through an FST over reversed keys.
+
+use fst::{FST, Subtree};
+use fst::builder::Builder;
+use fst::error::Result;
+use fst::iter::Iter;
+use fst::output::Output;
+use index::Index;
+
+
+/// An FST holding its keys reversed, so as to query them by suffix.
+///
+/// Keys are reversed on insertion and on query, and restored when yielded.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReversedFst<I, O> where I : Index, O : Output {
+    fst : FST<I, O>
+}
+
+impl<I, O> ReversedFst<I, O> where I : Index, O : Output {
+    /// Build from pairs in any order, since reversal does not preserve it.
+    pub fn from_iter<K, T>(iter : T) -> Result<ReversedFst<I, O>>
+        where K : AsRef<[u8]>
+            , T : IntoIterator<Item = (K, O)>
+    {
+        let reversed = iter.into_iter().map(|(k, v)| (reversed(k.as_ref()), v));
+        let fst = Builder::from_unsorted_iter(reversed).and_then(Builder::into_fst) ?;
+        Ok(ReversedFst { fst })
+    }
+
+    /// Get the value associated to the key, if any.
+    pub fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[u8]>
+    {
+        self.fst.get(reversed(key.as_ref()))
+    }
+
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[u8]>
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns whether any key ends with the suffix.
+    pub fn contains_suffix<K>(&self, suffix : K) -> bool
+        where K : AsRef<[u8]>
+    {
+        self.subtree(suffix.as_ref()).is_some_and(|s| s.iter().next().is_some())
+    }
+
+    /// Returns an iterator over the keys ending with the suffix, and their
+    /// values, in the lexicographic order of the reversed keys.
+    pub fn iter_suffixes<K>(&self, suffix : K) -> Suffixes<'_, I, O>
+        where K : AsRef<[u8]>
+    {
+        let suffix = suffix.as_ref();
+        Suffixes {
+            reversed_suffix : reversed(suffix),
+            iter : self.subtree(suffix).map(|s| s.iter())
+        }
+    }
+
+    /// The length and value of the longest key which is a suffix of the text.
+    pub fn get_suffix<K>(&self, text : K) -> Option<(usize, O)>
+        where K : AsRef<[u8]>
+    {
+        self.fst.reap_iter(text.as_ref().iter().rev()).last()
+    }
+
+    pub fn as_fst(&self) -> &FST<I, O> { &self.fst }
+
+    pub fn into_fst(self) -> FST<I, O> { self.fst }
+
+    fn subtree(&self, suffix : &[u8]) -> Option<Subtree<'_, I, O>> {
+        match self.fst.is_empty() {
+            true => None,
+            false => self.fst.subtree(reversed(suffix))
+        }
+    }
+}
+
+
+/// An iterator over the keys of a `ReversedFst` ending with a suffix.
+#[derive(Clone, Debug)]
+pub struct Suffixes<'a, I, O>
+    where I : Index + 'a
+        , O : Output + 'a
+{
+    reversed_suffix : Vec<u8>,
+    iter : Option<Iter<'a, I, O>>
+}
+
+impl<'a, I, O> Iterator for Suffixes<'a, I, O> where I : Index, O : Output {
+    type Item = (Vec<u8>, O);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (rest, value) = self.iter.as_mut() ?.next() ?;
+        let mut key = self.reversed_suffix.clone();
+        key.extend(rest);
+        key.reverse();
+        Some((key, value))
+    }
+}
+
+
+fn reversed(key : &[u8]) -> Vec<u8> {
+    key.iter().rev().cloned().collect()
+}
@@ -0,0 +1,159 @@
+//! Multi-pattern scanning of texts, after Aho and Corasick.
+//!
+//! A minimal FST shares states between distinct prefixes, so failure links
+//! cannot be attached to its states directly: a single state may be reached by
+//! prefixes with different longest proper suffixes. The scanner therefore
+//! unfolds the FST into its trie, in breadth-first order, and links that.
+
+use std::slice;
+
+use fst::{FST, Output, Terminal};
+use index::Index;
+
+
+#[derive(Copy, Clone, Debug)]
+struct Node<O> {
+    /// The label of the goto transition leading into the node.
+    label : u8,
+    depth : usize,
+    /// The children of a node occupy a contiguous range of nodes, in label order.
+    children : (usize, usize),
+    fail : usize,
+    /// The nearest node along the failure chain where a key ends.
+    dict : Option<usize>,
+    /// The value of the key ending at the node, if any.
+    value : Option<O>,
+}
+
+/// An Aho–Corasick automaton over the keys of an FST.
+#[derive(Clone, Debug)]
+pub struct Scanner<O> {
+    nodes : Vec<Node<O>>
+}
+
+impl<O> Scanner<O> where O : Output {
+    pub fn new<I>(fst : &FST<I, O>) -> Scanner<O>
+        where I : Index
+    {
+        let root = Node {
+            label : 0,
+            depth : 0,
+            children : (0, 0),
+            fail : 0,
+            dict : None,
+            // The empty key would match everywhere, and is never reported.
+            value : None
+        };
+        let mut nodes = vec![root];
+        let mut frontier = vec![(I::zero(), O::zero())];
+        if fst.is_empty() { return Scanner { nodes } }
+
+        let da = &fst.da;
+        let mut n = 0;
+        while n < nodes.len() {
+            let (state, out) = frontier[n];
+            let first = nodes.len();
+            for (label, e) in fst.arcs(state) {
+                let out = out.mappend(da.output(e));
+                let next = da.next[e];
+                let value = match da.stipe.terminal(e) {
+                    Terminal::Not   => None,
+                    Terminal::Empty => Some(out),
+                    Terminal::Inner => fst.state_output.get(next).map(|o| out.mappend(o))
+                };
+                nodes.push(Node { label, depth : nodes[n].depth + 1, value, ..root });
+                frontier.push((next, out));
+            }
+            nodes[n].children = (first, nodes.len());
+            n += 1;
+        }
+
+        let mut scanner = Scanner { nodes };
+        scanner.link();
+        scanner
+    }
+
+    /// Compute failure and dictionary links, layer by layer.
+    fn link(&mut self) {
+        for n in 0 .. self.nodes.len() {
+            let (first, last) = self.nodes[n].children;
+            for c in first .. last {
+                let fail = match n {
+                    0 => 0,
+                    _ => self.step(self.nodes[n].fail, self.nodes[c].label)
+                };
+                let dict = match self.nodes[fail].value {
+                    Some(_) => Some(fail),
+                    None => self.nodes[fail].dict
+                };
+                self.nodes[c].fail = fail;
+                self.nodes[c].dict = dict;
+            }
+        }
+    }
+
+    fn goto(&self, n : usize, label : u8) -> Option<usize> {
+        let (first, last) = self.nodes[n].children;
+        self.nodes[first .. last].binary_search_by_key(&label, |c| c.label)
+            .ok()
+            .map(|i| first + i)
+    }
+
+    fn step(&self, mut n : usize, label : u8) -> usize {
+        loop {
+            match self.goto(n, label) {
+                Some(c) => return c,
+                None if n == 0 => return 0,
+                None => n = self.nodes[n].fail
+            }
+        }
+    }
+
+    /// Returns an iterator producing the start, end, and value of every
+    /// occurrence of any nonempty key in the text, overlaps included.
+    ///
+    /// Occurrences are ordered by their end, then from longest to shortest.
+    pub fn find_overlapping<'s, 't>(&'s self, text : &'t [u8]) -> Overlapping<'s, 't, O> {
+        Overlapping {
+            scanner : self,
+            text : text.iter(),
+            position : 0,
+            node : 0,
+            pending : None
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Overlapping<'s, 't, O> where O : Output + 's {
+    scanner : &'s Scanner<O>,
+    text : slice::Iter<'t, u8>,
+    position : usize,
+    node : usize,
+    pending : Option<usize>
+}
+
+impl<'s, 't, O> Iterator for Overlapping<'s, 't, O> where O : Output {
+    type Item = (usize, usize, O);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let nodes = &self.scanner.nodes;
+        loop {
+            if let Some(m) = self.pending {
+                let node = &nodes[m];
+                self.pending = node.dict;
+                return node.value.map(|v| (self.position - node.depth, self.position, v));
+            }
+
+            let &label = self.text.next() ?;
+            self.position += 1;
+            self.node = self.scanner.step(self.node, label);
+            let node = &nodes[self.node];
+            self.pending = match node.value {
+                Some(_) => Some(self.node),
+                None => node.dict
+            };
+        }
+    }
+}
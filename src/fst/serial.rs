@@ -0,0 +1,241 @@
+//! Serde representations which the derived ones would not do well: state
+//! outputs, which must serialize identically for equal FSTs, and the Dart,
+//! which is mostly vacant slots; and `Tagged`, which checks the types of an
+//! FST on deserializing.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use std::fmt;
+use std::marker::PhantomData;
+
+use fst::{Dart, FST, Output, StateOutputs, Stipe, Stipes, Terminal};
+use fst::error::DecodeError;
+use fst::format::{Codec, Kind, TypeTag};
+use index::Index;
+use label::Label;
+
+
+/// The Dart as parallel arrays of every slot, for binary formats.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Dart")]
+struct Packed<S, N, P> {
+    stipe : S,
+    next : N,
+    output : P
+}
+
+/// The Dart as its length and its occupied slots, for human-readable
+/// formats; each slot is its position, check label, finality, destination,
+/// and output.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Dart")]
+struct Sparse<L, I, O> {
+    len : usize,
+    slots : Vec<(usize, L, Terminal, I, O)>
+}
+
+impl<I, O, L> Serialize for Dart<I, O, L>
+    where I : Index + Serialize
+        , O : Output + Serialize
+        , L : Label + Serialize
+{
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok, S::Error>
+        where S : Serializer
+    {
+        if !serializer.is_human_readable() {
+            return Packed { stipe : &self.stipe, next : &self.next, output : &self.output }.serialize(serializer)
+        }
+
+        let vacant = Stipe::default();
+        let slots = self.stipe.iter().enumerate()
+            .filter(|&(e, stipe)| stipe != vacant || !self.next[e].is_zero() || self.output(e) != O::zero())
+            .map(|(e, stipe)| (e, stipe.check, stipe.terminal, self.next[e], self.output(e)))
+            .collect();
+        Sparse { len : self.stipe.len(), slots }.serialize(serializer)
+    }
+}
+
+/// Slots' labels and finality, as a sequence of `Stipe`s whatever their
+/// packing in memory.
+impl<L> Serialize for Stipes<L> where L : Label + Serialize {
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok, S::Error>
+        where S : Serializer
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, L> Deserialize<'de> for Stipes<L> where L : Label + Deserialize<'de> {
+    fn deserialize<D>(deserializer : D) -> Result<Stipes<L>, D::Error>
+        where D : Deserializer<'de>
+    {
+        Vec::<Stipe<L>>::deserialize(deserializer).map(|stipes| stipes.into_iter().collect())
+    }
+}
+
+impl<'de, I, O, L> Deserialize<'de> for Dart<I, O, L>
+    where I : Index + Deserialize<'de>
+        , O : Output + Deserialize<'de>
+        , L : Label + Deserialize<'de>
+{
+    fn deserialize<D>(deserializer : D) -> Result<Dart<I, O, L>, D::Error>
+        where D : Deserializer<'de>
+    {
+        if !deserializer.is_human_readable() {
+            let Packed { stipe, next, output } = Packed::deserialize(deserializer) ?;
+            let mut dart = Dart { stipe, next, output };
+            dart.shed_zero_outputs();
+            return Ok(dart)
+        }
+
+        let Sparse { len, slots } = Sparse::<L, I, O>::deserialize(deserializer) ?;
+        let mut stipe = Stipes::new();
+        stipe.resize(len);
+        let mut dart = Dart {
+            stipe,
+            next : vec![I::zero(); len],
+            output : vec![O::zero(); len]
+        };
+        let mut previous = None;
+        for (e, check, terminal, next, output) in slots {
+            // Slots are listed once each, in order, within the Dart.
+            if e >= len || previous.is_some_and(|p| p >= e) {
+                return Err(de::Error::invalid_value(de::Unexpected::Unsigned(e as u64), &"a slot after the last, within the Dart"))
+            }
+            dart.stipe.set(e, Stipe { check, terminal });
+            dart.next[e] = next;
+            dart.output[e] = output;
+            previous = Some(e);
+        }
+        dart.shed_zero_outputs();
+        Ok(dart)
+    }
+}
+
+
+/// State outputs, as a sequence of pairs in order of state.
+///
+/// Maps, as state outputs were written when held in a hash table, are still
+/// read: bincode lays out a map as it does a sequence of pairs, and
+/// self-describing formats deliver it as a map.
+pub mod state_output {
+    use super::*;
+
+    pub fn serialize<I, O, S>(outputs : &StateOutputs<I, O>, serializer : S) -> Result<S::Ok, S::Error>
+        where I : Index + Serialize
+            , O : Copy + Serialize
+            , S : Serializer
+    {
+        serializer.collect_seq(outputs.iter())
+    }
+
+    pub fn deserialize<'de, I, O, D>(deserializer : D) -> Result<StateOutputs<I, O>, D::Error>
+        where I : Index + Deserialize<'de>
+            , O : Copy + Deserialize<'de>
+            , D : Deserializer<'de>
+    {
+        deserializer.deserialize_seq(Pairs(PhantomData))
+    }
+}
+
+/// Reads state outputs from a sequence of pairs, or from a map.
+struct Pairs<I, O>(PhantomData<(I, O)>);
+
+impl<'de, I, O> Visitor<'de> for Pairs<I, O>
+    where I : Index + Deserialize<'de>
+        , O : Copy + Deserialize<'de>
+{
+    type Value = StateOutputs<I, O>;
+
+    fn expecting(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of states and their outputs")
+    }
+
+    fn visit_seq<A>(self, mut seq : A) -> Result<StateOutputs<I, O>, A::Error>
+        where A : SeqAccess<'de>
+    {
+        let mut pairs = Vec::new();
+        while let Some(pair) = seq.next_element() ? { pairs.push(pair); }
+        Ok(pairs.into_iter().collect())
+    }
+
+    fn visit_map<A>(self, mut entries : A) -> Result<StateOutputs<I, O>, A::Error>
+        where A : MapAccess<'de>
+    {
+        let mut pairs = Vec::new();
+        while let Some(pair) = entries.next_entry() ? { pairs.push(pair); }
+        Ok(pairs.into_iter().collect())
+    }
+}
+
+
+/// An FST serialized after the width and kind of its label, index and output
+/// types, which are checked on deserializing before the FST itself is read.
+///
+/// Positional formats such as bincode would otherwise read an FST as one of
+/// other types, yielding garbage rather than an error.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tagged<F>(pub F);
+
+/// The tags of the label, index and output types, as their width and kind.
+fn tags<I, O, L>() -> [(&'static str, TypeTag); 3]
+    where I : Codec, O : Codec, L : Codec
+{
+    [("label", TypeTag::of::<L>()), ("index", TypeTag::of::<I>()), ("output", TypeTag::of::<O>())]
+}
+
+impl<I, O, L> Serialize for Tagged<FST<I, O, L>>
+    where I : Index + Codec + Serialize
+        , O : Output + Codec + Serialize
+        , L : Label + Codec + Serialize
+{
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok, S::Error>
+        where S : Serializer
+    {
+        let tags = tags::<I, O, L>().map(|(_, tag)| (tag.width as u32, tag.kind.encode()));
+        (tags, &self.0).serialize(serializer)
+    }
+}
+
+impl<'de, I, O, L> Deserialize<'de> for Tagged<FST<I, O, L>>
+    where I : Index + Codec + Deserialize<'de>
+        , O : Output + Codec + Deserialize<'de>
+        , L : Label + Codec + Deserialize<'de>
+{
+    fn deserialize<D>(deserializer : D) -> Result<Tagged<FST<I, O, L>>, D::Error>
+        where D : Deserializer<'de>
+    {
+        deserializer.deserialize_tuple(2, Checked(PhantomData))
+    }
+}
+
+/// Reads the tags of an FST, then the FST, should they match its types.
+struct Checked<I, O, L>(PhantomData<(I, O, L)>);
+
+impl<'de, I, O, L> Visitor<'de> for Checked<I, O, L>
+    where I : Index + Codec + Deserialize<'de>
+        , O : Output + Codec + Deserialize<'de>
+        , L : Label + Codec + Deserialize<'de>
+{
+    type Value = Tagged<FST<I, O, L>>;
+
+    fn expecting(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the type tags of an FST, then the FST")
+    }
+
+    fn visit_seq<A>(self, mut seq : A) -> Result<Tagged<FST<I, O, L>>, A::Error>
+        where A : SeqAccess<'de>
+    {
+        let found : [(u32, u8); 3] = seq.next_element() ?.ok_or_else(|| de::Error::invalid_length(0, &self)) ?;
+        for (&(of, expected), &(width, kind)) in tags::<I, O, L>().iter().zip(&found) {
+            let kind = Kind::decode(kind)
+                .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Unsigned(kind as u64), &"a type kind")) ?;
+            let found = TypeTag { width : width as usize, kind };
+            if !found.admits(&expected) {
+                return Err(de::Error::custom(DecodeError::TypeMismatch { of, expected, found }))
+            }
+        }
+        let fst = seq.next_element() ?.ok_or_else(|| de::Error::invalid_length(1, &self)) ?;
+        Ok(Tagged(fst))
+    }
+}
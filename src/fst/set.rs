@@ -0,0 +1,88 @@
+//! Sets of byte strings, as FSTs without outputs.
+
+use fst::{FST, Iter};
+use fst::builder::Builder;
+use fst::error::Result;
+use fst::ops;
+use index::Index;
+
+
+/// An FST whose keys carry no value.
+///
+/// With `()` as output, the Dart's output vector never allocates.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Set<I> where I : Index {
+    fst : FST<I, ()>
+}
+
+impl<I> Set<I> where I : Index {
+    /// Build a set from keys sorted in lexicographic order.
+    pub fn from_iter<K, T>(iter : T) -> Result<Set<I>>
+        where K : AsRef<[u8]>
+            , T : IntoIterator<Item = K>
+    {
+        let builder = Builder::from_iter(iter.into_iter().map(|k| (k, ()))) ?;
+        builder.into_fst().map(Set::from)
+    }
+
+    /// Returns whether the key is a member of the set.
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[u8]>
+    {
+        self.fst.contains(key)
+    }
+
+    /// Returns an iterator over the keys of the set, in lexicographic order.
+    pub fn iter(&self) -> Keys<'_, I> {
+        Keys { iter : self.fst.iter() }
+    }
+
+    /// The keys of either set.
+    pub fn union(&self, other : &Set<I>) -> Result<Set<I>> {
+        ops::union(&self.fst, &other.fst, |_, _| ()).map(Set::from)
+    }
+
+    /// The keys of both sets.
+    pub fn intersection(&self, other : &Set<I>) -> Result<Set<I>> {
+        ops::intersection(&self.fst, &other.fst, |_, _| ()).map(Set::from)
+    }
+
+    /// The keys of this set absent from the other.
+    pub fn difference(&self, other : &Set<I>) -> Result<Set<I>> {
+        ops::difference(&self.fst, &other.fst).map(Set::from)
+    }
+
+    /// The keys of exactly one of the sets.
+    pub fn symmetric_difference(&self, other : &Set<I>) -> Result<Set<I>> {
+        ops::symmetric_difference(&self.fst, &other.fst, |_, _| ()).map(Set::from)
+    }
+
+    pub fn as_fst(&self) -> &FST<I, ()> { &self.fst }
+
+    pub fn into_fst(self) -> FST<I, ()> { self.fst }
+
+    /// The number of keys in the set.
+    pub fn len(&self) -> usize { self.iter().count() }
+
+    pub fn is_empty(&self) -> bool { self.iter().next().is_none() }
+}
+
+impl<I> From<FST<I, ()>> for Set<I> where I : Index {
+    fn from(fst : FST<I, ()>) -> Set<I> { Set { fst } }
+}
+
+
+/// An iterator over the keys of a set, in lexicographic order.
+#[derive(Clone, Debug)]
+pub struct Keys<'a, I> where I : Index + 'a {
+    iter : Iter<'a, I, ()>
+}
+
+impl<'a, I> Iterator for Keys<'a, I> where I : Index {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.iter.next().map(|(key, ())| key)
+    }
+}
@@ -0,0 +1,130 @@
+//! The inner outputs of final states, kept as pairs sorted by state.
+
+use std::iter::{Copied, FromIterator};
+use std::mem;
+use std::ops;
+use std::slice;
+use std::vec;
+
+use index::Index;
+
+
+/// The inner outputs of final states, sorted by state and found by binary
+/// search.
+///
+/// States reached by common prefixes lie close in the Dart, and so do their
+/// outputs here, which a hash table would scatter.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StateOutputs<I, O> {
+    pairs : Vec<(I, O)>
+}
+
+impl<I, O> StateOutputs<I, O> where I : Index, O : Copy {
+    pub fn new() -> StateOutputs<I, O> { StateOutputs { pairs : Vec::new() } }
+
+    /// State outputs from pairs in strictly increasing order of state.
+    pub(crate) fn from_sorted(pairs : Vec<(I, O)>) -> StateOutputs<I, O> {
+        debug_assert!(pairs.windows(2).all(|w| w[0].0.as_usize() < w[1].0.as_usize()));
+        StateOutputs { pairs }
+    }
+
+    #[inline]
+    fn find(&self, state : I) -> Result<usize, usize> {
+        self.pairs.binary_search_by_key(&state.as_usize(), |&(s, _)| s.as_usize())
+    }
+
+    /// The inner output of a state, if it has one.
+    #[inline]
+    pub fn get(&self, state : I) -> Option<O> {
+        self.find(state).ok().map(|i| self.pairs[i].1)
+    }
+
+    pub fn contains_key(&self, state : I) -> bool { self.find(state).is_ok() }
+
+    /// Set the output of a state, returning the one it replaces.
+    ///
+    /// Outputs are kept in order, so that inserting many in no particular
+    /// order is best done by collecting them.
+    pub fn insert(&mut self, state : I, output : O) -> Option<O> {
+        match self.find(state) {
+            Ok(i) => Some(mem::replace(&mut self.pairs[i].1, output)),
+            Err(i) => { self.pairs.insert(i, (state, output)); None }
+        }
+    }
+
+    /// Remove the output of a state, returning it.
+    pub fn remove(&mut self, state : I) -> Option<O> {
+        self.find(state).ok().map(|i| self.pairs.remove(i).1)
+    }
+
+    pub fn clear(&mut self) { self.pairs.clear() }
+
+    /// The states and their outputs, in order of state.
+    pub fn iter(&self) -> Copied<slice::Iter<'_, (I, O)>> { self.pairs.iter().copied() }
+
+    /// The states with an inner output, in order.
+    pub fn states(&self) -> impl DoubleEndedIterator<Item = I> + '_ {
+        self.pairs.iter().map(|&(state, _)| state)
+    }
+
+    pub fn outputs_mut(&mut self) -> impl Iterator<Item = &mut O> {
+        self.pairs.iter_mut().map(|(_, output)| output)
+    }
+
+    pub fn as_slice(&self) -> &[(I, O)] { &self.pairs }
+
+    pub fn into_vec(self) -> Vec<(I, O)> { self.pairs }
+
+    pub fn len(&self) -> usize { self.pairs.len() }
+
+    pub fn is_empty(&self) -> bool { self.pairs.is_empty() }
+
+    pub(crate) fn pairs(&self) -> &Vec<(I, O)> { &self.pairs }
+
+    pub fn shrink_to_fit(&mut self) { self.pairs.shrink_to_fit() }
+
+    /// Restore the order of pairs appended in any order, the last output of
+    /// each state prevailing.
+    fn normalize(&mut self) {
+        self.pairs.reverse();
+        self.pairs.sort_by_key(|&(state, _)| state.as_usize());
+        self.pairs.dedup_by_key(|&mut (state, _)| state.as_usize());
+    }
+}
+
+impl<I, O> ops::Index<I> for StateOutputs<I, O> where I : Index, O : Copy {
+    type Output = O;
+
+    fn index(&self, state : I) -> &O {
+        match self.find(state) {
+            Ok(i) => &self.pairs[i].1,
+            Err(_) => panic!("no state output for state {}", state)
+        }
+    }
+}
+
+impl<I, O> FromIterator<(I, O)> for StateOutputs<I, O> where I : Index, O : Copy {
+    fn from_iter<T>(iter : T) -> StateOutputs<I, O>
+        where T : IntoIterator<Item = (I, O)>
+    {
+        let mut outputs = StateOutputs { pairs : iter.into_iter().collect() };
+        outputs.normalize();
+        outputs
+    }
+}
+
+impl<I, O> Extend<(I, O)> for StateOutputs<I, O> where I : Index, O : Copy {
+    fn extend<T>(&mut self, iter : T)
+        where T : IntoIterator<Item = (I, O)>
+    {
+        self.pairs.extend(iter);
+        self.normalize();
+    }
+}
+
+impl<I, O> IntoIterator for StateOutputs<I, O> {
+    type Item = (I, O);
+    type IntoIter = vec::IntoIter<(I, O)>;
+
+    fn into_iter(self) -> Self::IntoIter { self.pairs.into_iter() }
+}
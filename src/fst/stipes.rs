@@ -0,0 +1,130 @@
+//! The check labels and finality of the slots of a Dart.
+//!
+//! Finality takes two bits, yet stored beside each label it would take a
+//! byte, or more with padding for wider labels. It is packed instead four
+//! slots to a byte, apart from the labels. Unlike the spare high bits of the
+//! destinations, this holds whatever the index type.
+
+use std::iter::FromIterator;
+
+use fst::{Stipe, Terminal};
+use fst::memory;
+use label::Label;
+
+
+/// The number of slots whose finality is packed in a byte.
+const PER_BYTE : usize = 4;
+
+
+/// The check labels and finality of the slots of a Dart, read and written
+/// as `Stipe`s.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stipes<L = u8> {
+    check : Vec<L>,
+    /// The finality of each slot, in two bits; those past the last slot
+    /// are unset.
+    terminal : Vec<u8>
+}
+
+impl<L> Stipes<L> where L : Label {
+    pub fn new() -> Stipes<L> { Stipes { check : Vec::new(), terminal : Vec::new() } }
+
+    pub fn len(&self) -> usize { self.check.len() }
+
+    pub fn is_empty(&self) -> bool { self.check.is_empty() }
+
+    /// The check label and finality of a slot, if within the Dart.
+    #[inline]
+    pub fn get(&self, e : usize) -> Option<Stipe<L>> {
+        let &check = self.check.get(e) ?;
+        Some(Stipe { check, terminal : self.terminal(e) })
+    }
+
+    #[inline]
+    pub fn first(&self) -> Option<Stipe<L>> { self.get(0) }
+
+    #[inline]
+    pub fn check(&self, e : usize) -> L { self.check[e] }
+
+    #[inline]
+    pub fn terminal(&self, e : usize) -> Terminal {
+        match self.terminal[e / PER_BYTE] >> (2 * (e % PER_BYTE)) & 0b11 {
+            0 => Terminal::Not,
+            1 => Terminal::Empty,
+            _ => Terminal::Inner
+        }
+    }
+
+    /// Hint that the slot will be read soon.
+    #[inline(always)]
+    pub(crate) fn prefetch(&self, e : usize) {
+        memory::prefetch(&self.check, e);
+        memory::prefetch(&self.terminal, e / PER_BYTE);
+    }
+
+    pub fn set(&mut self, e : usize, stipe : Stipe<L>) {
+        self.check[e] = stipe.check;
+        self.set_terminal(e, stipe.terminal);
+    }
+
+    pub fn set_check(&mut self, e : usize, check : L) { self.check[e] = check }
+
+    pub fn set_terminal(&mut self, e : usize, terminal : Terminal) {
+        assert!(e < self.len(), "slot {} out of {}", e, self.len());
+        let (byte, shift) = (e / PER_BYTE, 2 * (e % PER_BYTE));
+        self.terminal[byte] = self.terminal[byte] & !(0b11 << shift) | terminal.encode() << shift;
+    }
+
+    pub fn push(&mut self, stipe : Stipe<L>) {
+        let e = self.len();
+        self.check.push(stipe.check);
+        if e.is_multiple_of(PER_BYTE) { self.terminal.push(0) }
+        self.set_terminal(e, stipe.terminal);
+    }
+
+    /// Lengthen with vacant slots, or cut off the last slots.
+    pub fn resize(&mut self, length : usize) {
+        self.check.resize(length, L::default());
+        self.terminal.resize(length.div_ceil(PER_BYTE), 0);
+        if !length.is_multiple_of(PER_BYTE) {
+            let kept = 2 * (length % PER_BYTE);
+            self.terminal[length / PER_BYTE] &= (1 << kept) - 1;
+        }
+    }
+
+    /// The slots, in order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Stipe<L>> + ExactSizeIterator + '_ {
+        (0 .. self.len()).map(move |e| Stipe { check : self.check[e], terminal : self.terminal(e) })
+    }
+
+    /// Reserve room for at least `additional` more slots.
+    pub fn reserve_exact(&mut self, additional : usize) {
+        self.check.reserve_exact(additional);
+        let slots = self.check.len() + additional;
+        self.terminal.reserve_exact(slots.div_ceil(PER_BYTE).saturating_sub(self.terminal.len()));
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.check.shrink_to_fit();
+        self.terminal.shrink_to_fit();
+    }
+
+    /// The bytes held, by capacity.
+    pub fn bytes(&self) -> usize {
+        memory::vec_bytes(&self.check) + memory::vec_bytes(&self.terminal)
+    }
+}
+
+impl<L> FromIterator<Stipe<L>> for Stipes<L> where L : Label {
+    fn from_iter<T>(iter : T) -> Stipes<L>
+        where T : IntoIterator<Item = Stipe<L>>
+    {
+        let iter = iter.into_iter();
+        let mut stipes = Stipes {
+            check : Vec::with_capacity(iter.size_hint().0),
+            terminal : Vec::with_capacity(iter.size_hint().0.div_ceil(PER_BYTE))
+        };
+        for stipe in iter { stipes.push(stipe) }
+        stipes
+    }
+}
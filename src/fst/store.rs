@@ -0,0 +1,281 @@
+//! Lookups over the slots of a Dart, whether owned by an `FST` or borrowed
+//! from its binary encoding by an `FstRef`.
+//!
+//! Each representation supplies the slots, and `DartStore` the lookups and
+//! traversals over them, so that code generic over the store reads any of
+//! them alike.
+
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::result;
+
+use fst::{FST, Iter, Output, State, Stipe, Terminal};
+use fst::error::DecodeError;
+use fst::format::{self, Codec, Header};
+use index::Index;
+use label::Label;
+
+
+/// Read access to the slots of a Dart, on which lookups and traversals are
+/// implemented once for every representation.
+pub trait DartStore<I, O, L = u8> where I : Index, O : Output, L : Label {
+    /// The number of slots, including vacant ones.
+    fn slots(&self) -> usize;
+
+    /// The check label and finality of a slot, if within the Dart.
+    fn stipe(&self, e : usize) -> Option<Stipe<L>>;
+
+    /// The destination of a slot, zero if vacant: no transition leads back
+    /// to the root.
+    fn next(&self, e : usize) -> I;
+
+    fn output(&self, e : usize) -> O;
+
+    /// The inner output of a final state, if it has one.
+    fn state_output(&self, state : I) -> Option<O>;
+
+    /// Whether any transition has an output, lest lookups accumulate zeros.
+    #[inline]
+    fn has_outputs(&self) -> bool { true }
+
+    /// The finality of the root, which decides the empty key.
+    #[inline]
+    fn root_terminal(&self) -> Terminal {
+        self.stipe(0).map_or(Terminal::Not, |root| root.terminal)
+    }
+
+    /// The Dart slot of the transition leaving a state with the given label.
+    #[inline]
+    fn arc(&self, state : I, label : L) -> Option<usize> {
+        let e = state.as_usize() + (1 + label.as_usize());
+        match self.stipe(e) {
+            // Vacant slots are zeroed, and no transition leads back to the root.
+            Some(stipe) if stipe.check == label && !self.next(e).is_zero() => Some(e),
+            _ => None
+        }
+    }
+
+    /// Given a starting state and an input, returns the destination state, if any.
+    #[inline]
+    fn step(&self, state : I, input : L) -> Option<State<I>> {
+        let e = state.as_usize() + (1 + input.as_usize());
+        match self.stipe(e) {
+            Some(Stipe { check, terminal }) if check == input => {
+                let index = self.next(e);
+                (!index.is_zero()).then_some(State { index, terminal })
+            },
+            _ => None
+        }
+    }
+
+    /// Follow the input from the given state, accumulating outputs along the
+    /// way, and return the state reached with its output and finality.
+    #[inline]
+    fn walk(&self, from : (I, O, Terminal), input : &[L]) -> Option<(I, O, Terminal)> {
+        self.traverse::<true>(from, input)
+    }
+
+    /// The traversal under `walk`. Unless `OUTPUTS`, the outputs are never
+    /// read, and the output returned is the one given.
+    #[inline(always)]
+    fn traverse<const OUTPUTS : bool>(&self, from : (I, O, Terminal), input : &[L]) -> Option<(I, O, Terminal)> {
+        let (mut state, mut out, mut terminal) = from;
+        let outputs = OUTPUTS && self.has_outputs();
+        for &label in input {
+            let e = state.as_usize() + (1 + label.as_usize());
+            match self.stipe(e) {
+                Some(stipe) if stipe.check == label => {
+                    // A vacant slot checks the least label, but leads back to
+                    // the root, which no transition does.
+                    let next = self.next(e);
+                    if next.is_zero() { return None }
+                    terminal = stipe.terminal;
+                    if outputs { out.mappend_assign(self.output(e)) }
+                    state = next;
+                },
+                _ => return None
+            }
+        }
+
+        Some((state, out, terminal))
+    }
+
+    /// The value of a key ending in the given state, if the state is final.
+    #[inline]
+    fn resolve(&self, state : I, out : O, terminal : Terminal) -> Option<O> {
+        match terminal {
+            Terminal::Not   => None,
+            Terminal::Empty => Some(out),
+            Terminal::Inner => self.state_output(state).map(|o| out.mappend(o))
+        }
+    }
+
+    /// Get the value associated to the key, if any.
+    #[inline]
+    fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[L]>, Self : Sized
+    {
+        let root = (I::zero(), O::zero(), self.root_terminal());
+        self.walk(root, key.as_ref())
+            .and_then(|(state, out, terminal)| self.resolve(state, out, terminal))
+    }
+
+    /// Returns whether the key is accepted by the FST.
+    #[inline]
+    fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[L]>, Self : Sized
+    {
+        let root = (I::zero(), O::zero(), self.root_terminal());
+        self.traverse::<false>(root, key.as_ref()).is_some_and(|(_, _, terminal)| terminal.is())
+    }
+
+    /// Returns an iterator over the keys and values of the FST, in
+    /// lexicographic order.
+    fn iter(&self) -> Iter<'_, I, O, L, Self> where Self : Sized {
+        match self.slots() {
+            0 => Iter::empty(self),
+            _ => Iter::new(self, I::zero(), O::zero(), self.root_terminal())
+        }
+    }
+}
+
+impl<I, O, L> DartStore<I, O, L> for FST<I, O, L> where I : Index, O : Output, L : Label {
+    #[inline] fn slots(&self) -> usize { self.da.stipe.len() }
+    #[inline] fn stipe(&self, e : usize) -> Option<Stipe<L>> { self.da.stipe.get(e) }
+    #[inline] fn next(&self, e : usize) -> I { self.da.next[e] }
+    #[inline] fn output(&self, e : usize) -> O { self.da.output(e) }
+    #[inline] fn state_output(&self, state : I) -> Option<O> { self.state_output.get(state) }
+    #[inline] fn has_outputs(&self) -> bool { self.da.has_outputs() }
+}
+
+
+/// An FST read in place from its binary encoding, as written by
+/// `FST::to_bytes`, without copying or decoding it whole.
+///
+/// Only the header, the lengths and checksums of the sections, and that
+/// traversals end are checked up front; values are decoded as they are read,
+/// and a slot which does not decode is treated as vacant. Use `to_fst` to decode and validate
+/// the whole FST.
+#[derive(Copy, Clone, Debug)]
+pub struct FstRef<'a, I, O, L = u8> {
+    header : Header,
+    stipe : &'a [u8],
+    next : &'a [u8],
+    output : &'a [u8],
+    state_output : &'a [u8],
+    types : PhantomData<(I, O, L)>
+}
+
+impl<'a, I, O, L> FstRef<'a, I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    /// View an encoded FST, checking its header against the types, its
+    /// sections against their checksums, and that its destinations lie
+    /// within the Dart and lead to no cycle.
+    ///
+    /// Compressed FSTs cannot be viewed, and fail with `Compressed`.
+    pub fn new(bytes : &'a [u8]) -> result::Result<FstRef<'a, I, O, L>, DecodeError> {
+        let (header, sections) = format::split::<I, O, L>(bytes) ?;
+        format::in_place(&header) ?;
+        format::verify(&header, &sections) ?;
+        let fst = FstRef::from_sections(header, sections);
+        format::traversable(&header, &fst) ?;
+        Ok(fst)
+    }
+
+    /// View an encoded FST as with `new`, without verifying checksums.
+    pub fn new_unchecked(bytes : &'a [u8]) -> result::Result<FstRef<'a, I, O, L>, DecodeError> {
+        let (header, sections) = format::split::<I, O, L>(bytes) ?;
+        format::in_place(&header) ?;
+        Ok(FstRef::from_sections(header, sections))
+    }
+
+    /// View sections split by the header, which lookups only read as far as
+    /// they are needed.
+    pub(crate) fn from_sections(header : Header, sections : [&'a [u8]; 4]) -> FstRef<'a, I, O, L> {
+        let [stipe, next, output, state_output] = sections;
+        FstRef { header, stipe, next, output, state_output, types : PhantomData }
+    }
+
+    pub fn header(&self) -> Header { self.header }
+
+    /// Get the value associated to the key, if any.
+    pub fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[L]>
+    {
+        DartStore::get(self, key)
+    }
+
+    /// Returns whether the key is accepted by the FST.
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[L]>
+    {
+        DartStore::contains(self, key)
+    }
+
+    /// Given a starting state and an input, returns the destination state, if any.
+    pub fn transition(&self, state : I, input : L) -> Option<State<I>> {
+        self.step(state, input)
+    }
+
+    /// Returns an iterator over the keys and values of the FST, in
+    /// lexicographic order.
+    pub fn iter(&self) -> Iter<'_, I, O, L, FstRef<'a, I, O, L>> {
+        DartStore::iter(self)
+    }
+
+    /// Decode the whole FST, validating its values.
+    pub fn to_fst(&self) -> result::Result<FST<I, O, L>, DecodeError> {
+        format::Decoder { header : self.header, consistent : true }
+            .decode(self.stipe, self.next, self.output, self.state_output)
+    }
+
+    /// Whether the internal double array is empty.
+    pub fn is_empty(&self) -> bool { self.header.slots == 0 }
+}
+
+impl<'a, I, O, L> DartStore<I, O, L> for FstRef<'a, I, O, L>
+    where I : Index + Codec
+        , O : Output + Codec
+        , L : Label + Codec
+{
+    #[inline]
+    fn slots(&self) -> usize { self.header.slots }
+
+    #[inline]
+    fn stipe(&self, e : usize) -> Option<Stipe<L>> {
+        if e >= self.header.slots { return None }
+        let width = L::WIDTH + 1;
+        let bytes = &self.stipe[e * width .. (e + 1) * width];
+        Some(Stipe { check : L::decode(&bytes[.. L::WIDTH]) ?, terminal : Terminal::decode(bytes[L::WIDTH]) ? })
+    }
+
+    #[inline]
+    fn next(&self, e : usize) -> I {
+        I::decode(&self.next[e * I::WIDTH .. (e + 1) * I::WIDTH]).unwrap_or_else(I::zero)
+    }
+
+    #[inline]
+    fn output(&self, e : usize) -> O {
+        O::decode(&self.output[e * O::WIDTH .. (e + 1) * O::WIDTH]).unwrap_or_else(O::zero)
+    }
+
+    /// State outputs are sorted by state, and found by binary search.
+    fn state_output(&self, state : I) -> Option<O> {
+        let width = I::WIDTH + O::WIDTH;
+        let pair = |i : usize| &self.state_output[i * width .. (i + 1) * width];
+        let (mut low, mut high) = (0, self.header.state_outputs);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let s = I::decode(&pair(mid)[.. I::WIDTH]) ?;
+            match s.as_usize().cmp(&state.as_usize()) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return O::decode(&pair(mid)[I::WIDTH ..])
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,53 @@
+//! Queries relative to the states reached through a prefix.
+
+use fst::{FST, Output, Terminal};
+use fst::iter::Iter;
+use fst::store::DartStore;
+use index::Index;
+use label::Label;
+
+
+/// A handle on the states of an FST reached through a given prefix.
+///
+/// Queries behave as if on an FST holding only the suffixes of that prefix,
+/// but their values remain those of the full keys.
+#[derive(Copy, Clone, Debug)]
+pub struct Subtree<'a, I, O, L = u8>
+    where I : Index + 'a
+        , O : Output + 'a
+        , L : Label + 'a
+{
+    fst : &'a FST<I, O, L>,
+    state : I,
+    output : O,
+    terminal : Terminal
+}
+
+impl<'a, I, O, L> Subtree<'a, I, O, L> where I : Index, O : Output, L : Label {
+    pub(crate) fn new(fst : &'a FST<I, O, L>, state : I, output : O, terminal : Terminal)
+        -> Subtree<'a, I, O, L>
+    {
+        Subtree { fst, state, output, terminal }
+    }
+
+    /// Returns whether the prefix followed by the suffix is a key of the FST.
+    pub fn contains<K>(&self, suffix : K) -> bool
+        where K : AsRef<[L]>
+    {
+        self.get(suffix).is_some()
+    }
+
+    /// Get the value associated to the prefix followed by the suffix, if any.
+    pub fn get<K>(&self, suffix : K) -> Option<O>
+        where K : AsRef<[L]>
+    {
+        self.fst.walk((self.state, self.output, self.terminal), suffix.as_ref())
+            .and_then(|(state, out, terminal)| self.fst.resolve(state, out, terminal))
+    }
+
+    /// Returns an iterator over the suffixes of the prefix which complete
+    /// a key, with the values of those keys, in lexicographic order.
+    pub fn iter(&self) -> Iter<'a, I, O, L> {
+        Iter::new(self.fst, self.state, self.output, self.terminal)
+    }
+}
@@ -0,0 +1,102 @@
+//! FSTs whose index type is chosen as placement requires, the narrowest
+//! first.
+
+use fst::{FST, Output};
+use fst::builder::{Builder, BuildOptions};
+use fst::error::{Error, Result};
+use fst::intermediate::Intermediary;
+use index::{Index, U24};
+use label::Label;
+
+
+/// An FST of whichever index type its Dart first fit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WideFst<O, L = u8> where O : Output, L : Label {
+    U16(FST<u16, O, L>),
+    U24(FST<U24, O, L>),
+    U32(FST<u32, O, L>),
+    #[cfg(target_pointer_width = "64")]
+    U64(FST<u64, O, L>)
+}
+
+macro_rules! each_width {
+    ($wide:expr, $fst:ident => $body:expr) => {
+        match $wide {
+            WideFst::U16($fst) => $body,
+            WideFst::U24($fst) => $body,
+            WideFst::U32($fst) => $body,
+            #[cfg(target_pointer_width = "64")]
+            WideFst::U64($fst) => $body
+        }
+    }
+}
+
+impl<O, L> WideFst<O, L> where O : Output, L : Label {
+    /// Get the value associated to the key, if any.
+    pub fn get<K>(&self, key : K) -> Option<O>
+        where K : AsRef<[L]>
+    {
+        each_width!(self, fst => fst.get(key))
+    }
+
+    /// Returns whether the key is accepted by the FST.
+    pub fn contains<K>(&self, key : K) -> bool
+        where K : AsRef<[L]>
+    {
+        each_width!(self, fst => fst.contains(key))
+    }
+
+    /// The width of the index type, in bytes.
+    pub fn index_width(&self) -> usize {
+        match *self {
+            WideFst::U16(_) => 2,
+            WideFst::U24(_) => 3,
+            WideFst::U32(_) => 4,
+            #[cfg(target_pointer_width = "64")]
+            WideFst::U64(_) => 8
+        }
+    }
+
+    /// The number of nodes in the internal double array, including surplus.
+    pub fn len(&self) -> usize { each_width!(self, fst => fst.len()) }
+
+    /// Whether the internal double array is empty.
+    pub fn is_empty(&self) -> bool { each_width!(self, fst => fst.is_empty()) }
+}
+
+impl<I, O, L> FST<I, O, L> where I : Index, O : Output, L : Label {
+    /// Build an FST from a finished builder, as with `from_builder`, with
+    /// the narrowest index type at least as wide as the builder's which the
+    /// Dart fits: `u16`, `U24`, `u32`, then `u64`.
+    ///
+    /// Each attempt copies the builder's states, widened to its index type.
+    /// Only the widest attempt fails with `OutOfBounds`.
+    pub fn from_builder_widening(builder : &Builder<I, O, L>) -> Result<WideFst<O, L>> {
+        FST::from_builder_widening_with(builder, BuildOptions::default())
+    }
+
+    /// Build an FST as with `from_builder_widening`, laying out the Dart as
+    /// set by `options`.
+    pub fn from_builder_widening_with(builder : &Builder<I, O, L>, options : BuildOptions) -> Result<WideFst<O, L>> {
+        let mut out_of_bounds = None;
+        macro_rules! attempt {
+            ($idx:ty, $variant:ident) => {
+                if <$idx>::bound() >= I::bound() {
+                    let mut repr : Intermediary<$idx, O, L> = Intermediary::with_options(options);
+                    match repr.from_builder_of(builder) {
+                        Ok(()) => return Ok(WideFst::$variant(repr.into_dart())),
+                        Err(e @ Error::OutOfBounds { .. }) => out_of_bounds = Some(e),
+                        Err(e) => return Err(e)
+                    }
+                }
+            }
+        }
+
+        attempt!(u16, U16);
+        attempt!(U24, U24);
+        attempt!(u32, U32);
+        #[cfg(target_pointer_width = "64")]
+        attempt!(u64, U64);
+        Err(out_of_bounds.expect("the widest index type is tried whatever the builder's"))
+    }
+}
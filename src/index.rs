@@ -1,12 +1,14 @@
-use std::fmt::{Debug, Display};
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Display};
 use std::hash::Hash;
-use std::ops::{AddAssign, SubAssign};
+use std::num::{ParseIntError, TryFromIntError};
+use std::ops::{self, AddAssign, SubAssign};
 
-use num_traits::{Unsigned, Bounded};
+use num_traits::{Bounded, Num, One, Unsigned, Zero};
 
 
-/// A minimal trait for unchecked casting of unsigned integers to `usize`,
-/// for indexing purposes.
+/// A minimal trait for casting unsigned integers to and from `usize`, for
+/// indexing purposes.
 pub trait Index
     : Unsigned + Bounded  // An unsigned integer—
     + Eq + Copy + Hash    // —with the properties we require—
@@ -14,10 +16,21 @@ pub trait Index
     + Debug + Display
 {
     fn as_usize(self) -> usize;
-    fn as_index(i : usize) -> Self;
 
+    /// The index of `i`, unless it exceeds the bound.
+    fn try_as_index(i : usize) -> Option<Self>;
+
+    /// The index of `i`, which must not exceed the bound: in debug builds
+    /// it panics if it does, and otherwise truncates.
     #[inline(always)]
-    fn bound() -> usize { Self::max_value().as_usize() }
+    fn as_index(i : usize) -> Self {
+        debug_assert!(i <= Self::bound(), "index {} exceeds the bound {}", i, Self::bound());
+        Self::try_as_index(i & Self::bound()).unwrap_or_else(Self::zero)
+    }
+
+    /// The greatest index, as a `usize`, or `usize::MAX` should the index
+    /// type be wider.
+    fn bound() -> usize;
 }
 
 macro_rules! impl_index {
@@ -27,7 +40,10 @@ macro_rules! impl_index {
             fn as_usize(self) -> usize { self as usize }
 
             #[inline(always)]
-            fn as_index(i : usize) -> $idx { i as $idx }
+            fn try_as_index(i : usize) -> Option<$idx> { <$idx>::try_from(i).ok() }
+
+            #[inline(always)]
+            fn bound() -> usize { usize::try_from(<$idx>::MAX).unwrap_or(usize::MAX) }
         }
     }
 }
@@ -37,3 +53,140 @@ impl_index! { u16   }
 impl_index! { u32   }
 #[cfg(target_pointer_width = "64")]
 impl_index! { u64   }
+
+
+/// An unsigned integer of 24 bits, stored in three bytes, for Darts too long
+/// for `u16` indices but well short of `u32` ones.
+///
+/// Arithmetic goes through `u64`, and like that of primitive integers panics
+/// on overflow in debug builds and wraps otherwise.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct U24([u8; 3]);
+
+impl U24 {
+    pub const MAX : U24 = U24([0xFF; 3]);
+
+    /// The low 24 bits of `n`.
+    #[inline(always)]
+    pub const fn new(n : u32) -> U24 {
+        let [a, b, c, _] = n.to_le_bytes();
+        U24([a, b, c])
+    }
+
+    #[inline(always)]
+    pub const fn get(self) -> u32 {
+        let [a, b, c] = self.0;
+        u32::from_le_bytes([a, b, c, 0])
+    }
+
+    /// The value as bytes, in little-endian order.
+    pub const fn to_le_bytes(self) -> [u8; 3] { self.0 }
+
+    pub const fn from_le_bytes(bytes : [u8; 3]) -> U24 { U24(bytes) }
+
+    /// The low 24 bits of `n`, which must fit in them in debug builds.
+    #[inline(always)]
+    fn wrapped(n : u64, op : &str) -> U24 {
+        debug_assert!(n <= U24::MAX.get() as u64, "attempt to {} with overflow", op);
+        U24::new(n as u32)
+    }
+}
+
+impl Index for U24 {
+    #[inline(always)]
+    fn as_usize(self) -> usize { self.get() as usize }
+
+    #[inline(always)]
+    fn try_as_index(i : usize) -> Option<U24> {
+        u32::try_from(i).ok().and_then(|n| U24::try_from(n).ok())
+    }
+
+    #[inline(always)]
+    fn bound() -> usize { U24::MAX.get() as usize }
+}
+
+impl Bounded for U24 {
+    fn min_value() -> U24 { U24([0; 3]) }
+    fn max_value() -> U24 { U24::MAX }
+}
+
+impl Zero for U24 {
+    fn zero() -> U24 { U24([0; 3]) }
+    fn is_zero(&self) -> bool { self.0 == [0; 3] }
+}
+
+impl One for U24 {
+    fn one() -> U24 { U24([1, 0, 0]) }
+}
+
+impl Num for U24 {
+    type FromStrRadixErr = ParseIntError;
+
+    fn from_str_radix(s : &str, radix : u32) -> Result<U24, ParseIntError> {
+        let n = u32::from_str_radix(s, radix) ?;
+        // Out of range values are reported as `u8` would report them.
+        if n > U24::MAX.get() { return Err("256".parse::<u8>().unwrap_err()) }
+        Ok(U24::new(n))
+    }
+}
+
+impl Unsigned for U24 {}
+
+macro_rules! impl_u24_op {
+    ($op:ident, $f:ident, $wrapping:ident, $name:expr) => {
+        impl ops::$op for U24 {
+            type Output = U24;
+
+            #[inline(always)]
+            fn $f(self, other : U24) -> U24 {
+                U24::wrapped((self.get() as u64).$wrapping(other.get() as u64), $name)
+            }
+        }
+    }
+}
+
+impl_u24_op! { Add, add, wrapping_add, "add"      }
+impl_u24_op! { Sub, sub, wrapping_sub, "subtract" }
+impl_u24_op! { Mul, mul, wrapping_mul, "multiply" }
+impl_u24_op! { Div, div, div,          "divide"   }
+impl_u24_op! { Rem, rem, rem,          "calculate the remainder" }
+
+impl AddAssign for U24 {
+    fn add_assign(&mut self, other : U24) { *self = *self + other }
+}
+
+impl SubAssign for U24 {
+    fn sub_assign(&mut self, other : U24) { *self = *self - other }
+}
+
+impl fmt::Debug for U24 {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result { fmt::Debug::fmt(&self.get(), f) }
+}
+
+impl fmt::Display for U24 {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.get(), f) }
+}
+
+impl From<u8> for U24 {
+    fn from(n : u8) -> U24 { U24([n, 0, 0]) }
+}
+
+impl From<u16> for U24 {
+    fn from(n : u16) -> U24 { U24::new(n as u32) }
+}
+
+impl From<U24> for u32 {
+    fn from(n : U24) -> u32 { n.get() }
+}
+
+impl TryFrom<u32> for U24 {
+    type Error = TryFromIntError;
+
+    fn try_from(n : u32) -> Result<U24, TryFromIntError> {
+        match n > U24::MAX.get() {
+            true => Err(u8::try_from(256u32).unwrap_err()),
+            false => Ok(U24::new(n))
+        }
+    }
+}
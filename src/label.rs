@@ -0,0 +1,48 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+
+
+/// The symbols labelling transitions: bytes by default, or wider integers
+/// for keys over a larger alphabet, such as a tokenized vocabulary.
+///
+/// Each Dart block holds one slot per label, and iteration tries every
+/// label at each state, so wider labels make for longer Darts and slower
+/// traversals; lookups are unaffected.
+pub trait Label
+    : Copy + Ord + Hash + Default + Debug
+{
+    /// The number of distinct labels.
+    const ALPHABET : usize;
+
+    fn as_usize(self) -> usize;
+    fn from_usize(l : usize) -> Self;
+
+    /// The key as bytes, in big-endian order, as reported in errors.
+    fn key_bytes(key : &[Self]) -> Vec<u8>;
+}
+
+impl Label for u8 {
+    const ALPHABET : usize = 1 << 8;
+
+    #[inline(always)]
+    fn as_usize(self) -> usize { self as usize }
+
+    #[inline(always)]
+    fn from_usize(l : usize) -> u8 { l as u8 }
+
+    fn key_bytes(key : &[u8]) -> Vec<u8> { key.to_vec() }
+}
+
+impl Label for u16 {
+    const ALPHABET : usize = 1 << 16;
+
+    #[inline(always)]
+    fn as_usize(self) -> usize { self as usize }
+
+    #[inline(always)]
+    fn from_usize(l : usize) -> u16 { l as u16 }
+
+    fn key_bytes(key : &[u16]) -> Vec<u8> {
+        key.iter().flat_map(|l| l.to_be_bytes()).collect()
+    }
+}
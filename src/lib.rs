@@ -1,9 +1,26 @@
 // Forsaken docs justly quibble the vexed programmer's waning zeal.
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
 extern crate fnv;
+#[cfg(feature = "fst-interop")]
+extern crate fst as fst_crate;
 extern crate num_traits;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serialization")]
+extern crate bincode;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "postcard")]
+extern crate postcard;
+#[cfg(feature = "rkyv")]
+extern crate rkyv;
 #[cfg(feature = "serde")]
 #[macro_use] extern crate serde;
+extern crate smallvec;
 
 mod segment;
+pub mod compat;
 pub mod fst;
 pub mod index;
+pub mod label;
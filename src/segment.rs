@@ -1,79 +1,317 @@
 //! Paging structures for fast insertion in a Dart.
+//!
+//! Slots free for a transition are threaded in ascending order through a
+//! doubly-linked list, as in darts-clone: a state's base is sought from the
+//! free slots which could hold its least label, and slots are unlinked in
+//! constant time as they are taken. Placing the same states thus always
+//! yields the same layout. Only the last few blocks are kept open, lest the
+//! scan crawl through slots too crowded to admit any state. With best-fit
+//! placement, states of high out-degree rather take the candidate leaving
+//! the fewest free slots amid their labels, where few others would fit.
+//!
+//! Which slots are free is kept in bitsets, so that a candidate base is
+//! tested with a mask of its labels for each word of slots they fall in.
+//! As only slots of open blocks are ever linked, their links are kept in
+//! rings as wide as the open blocks and the one being added, whatever the
+//! length of the Dart.
 
-use fnv::FnvHashSet;
+use label::Label;
+use smallvec::SmallVec;
+
+
+/// The number of blocks in which free slots are sought.
+const OPEN_BLOCKS : usize = 16;
+
+/// The least number of labels for which best-fit placement is sought.
+const BEST_FIT_DEGREE : usize = 4;
+
+/// The end of the free list.
+const NIL : usize = usize::MAX;
+
+/// The number of slots in a word of a bitset.
+const WORD : usize = 64;
+
+
+/// A set of slots, packed in words.
+#[derive(Clone, Debug, Default)]
+struct Bits {
+    words : Vec<u64>,
+    len : usize
+}
+
+impl Bits {
+    #[inline]
+    fn get(&self, i : usize) -> bool {
+        self.words.get(i / WORD).is_some_and(|&w| w >> (i % WORD) & 1 == 1)
+    }
+
+    #[inline]
+    fn clear(&mut self, i : usize) { self.words[i / WORD] &= !(1 << (i % WORD)) }
+
+    /// The 64 slots from `i` on, those past the end being unset.
+    #[inline]
+    fn window(&self, i : usize) -> u64 {
+        let (word, shift) = (i / WORD, i % WORD);
+        let low = self.words.get(word).map_or(0, |&w| w >> shift);
+        let high = match shift {
+            0 => 0,
+            _ => self.words.get(word + 1).map_or(0, |&w| w << (WORD - shift))
+        };
+        low | high
+    }
+
+    /// Append `n` set slots, a word at a time.
+    fn extend(&mut self, n : usize) {
+        let len = self.len + n;
+        self.words.resize(len.div_ceil(WORD), 0);
+        let mut i = self.len;
+        while i < len {
+            let bits = (len - i).min(WORD - i % WORD);
+            let ones = if bits == WORD { !0 } else { ((1 << bits) - 1) << (i % WORD) };
+            self.words[i / WORD] |= ones;
+            i += bits;
+        }
+        self.len = len;
+    }
+
+    fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    fn reserve(&mut self, n : usize) { self.words.reserve(n / WORD) }
+
+    /// Empty the set, keeping the memory held.
+    fn reset(&mut self) {
+        self.words.clear();
+        self.len = 0;
+    }
+}
 
 
 #[derive(Clone, Debug)]
 pub struct IndexSegments {
-    as_state : FnvHashSet<usize>,
-    as_trans : FnvHashSet<usize>,
+    /// The free slots following and preceding each free slot, by its place
+    /// in the rings.
+    next_free : Vec<usize>,
+    prev_free : Vec<usize>,
+    /// The width of the rings: a power of two spanning the open blocks and
+    /// the one added before the first is closed.
+    ring : usize,
+    /// The first and last free slots.
+    head : usize,
+    tail : usize,
+    /// The slots free for a transition.
+    free_trans : Bits,
+    /// The slots free as a base; those of closed blocks are not.
+    free_state : Bits,
     block_size : usize,
+    /// The start of the first open block.
+    open_from : usize,
+    /// Whether bases are chosen to leave the fewest free slots amid labels.
+    best_fit : bool,
 }
 
 impl IndexSegments {
+    /// Segments growing by blocks of the given size, which must exceed the
+    /// alphabet of the labels settled.
+    pub fn with_block_size(block_size : usize) -> IndexSegments {
+        IndexSegments {
+            next_free : Vec::new(),
+            prev_free : Vec::new(),
+            ring : ((OPEN_BLOCKS + 1) * block_size).next_power_of_two(),
+            head : NIL,
+            tail : NIL,
+            free_trans : Bits::default(),
+            free_state : Bits::default(),
+            block_size,
+            open_from : 0,
+            best_fit : false,
+        }
+    }
+
+    /// Empty the segments, as if new with the given block size, keeping the
+    /// memory held.
+    pub fn reset(&mut self, block_size : usize) {
+        self.next_free.clear();
+        self.prev_free.clear();
+        self.ring = ((OPEN_BLOCKS + 1) * block_size).next_power_of_two();
+        self.head = NIL;
+        self.tail = NIL;
+        self.free_trans.reset();
+        self.free_state.reset();
+        self.block_size = block_size;
+        self.open_from = 0;
+        self.best_fit = false;
+    }
+
+    /// Set whether states of high out-degree take the base leaving the
+    /// fewest free slots amid their labels, rather than the lowest.
+    pub fn set_best_fit(&mut self, best_fit : bool) { self.best_fit = best_fit }
+
     /// Settle the transitions labelled with `symbols` in the segments,
     /// returning their base index.
-    pub fn settle(&mut self, symbols : &[u8]) -> Option<usize> {
-        self.usher(symbols).map(|base| {
+    pub fn settle<L>(&mut self, symbols : &[L]) -> Option<usize> where L : Label {
+        self.usher(symbols).inspect(|&base| {
             self.affix_state(base);
-            for &s in symbols { self.affix_trans(base + (1 + s as usize)) }
-            base
+            for &s in symbols { self.affix_trans(base + (1 + s.as_usize())) }
         })
     }
 
-    pub fn settle_index(&mut self, symbols : &[u8], i : usize) -> Option<usize> {
-        self.as_state.get(&i).cloned()
-            .map(|base| {
+    pub fn settle_index<L>(&mut self, symbols : &[L], i : usize) -> Option<usize> where L : Label {
+        Some(i).filter(|&i| self.free_state.get(i))
+            .inspect(|&base| {
                 self.affix_state(base);
-                for &s in symbols { self.affix_trans(base + (1 + s as usize)) }
-                base
+                for &s in symbols { self.affix_trans(base + (1 + s.as_usize())) }
             })
     }
 
-    /// Find the first index admitting all symbols.
-    pub fn usher(&self, symbols : &[u8]) -> Option<usize> {
-        self.as_state.iter()
-            .find(|&&base|
-                symbols.iter().all(|&s| self.as_trans.contains(&(base + (1 + s as usize)))))
-            .cloned()
+    /// Find the lowest free index admitting all symbols or, with best-fit
+    /// placement, the one leaving the fewest free slots amid them.
+    ///
+    /// Candidates are the free slots which could hold the least symbol, in
+    /// order; states without transitions take the lowest free base.
+    pub fn usher<L>(&self, symbols : &[L]) -> Option<usize> where L : Label {
+        let least = match symbols.iter().min() {
+            Some(&least) => least.as_usize(),
+            None => return (self.open_from .. self.free_state.len).find(|&i| self.free_state.get(i))
+        };
+        // The labels by their offset from the least, as a mask of each word
+        // of slots they fall in.
+        let mut masks : SmallVec<[(usize, u64); 4]> = SmallVec::new();
+        for s in symbols {
+            let offset = s.as_usize() - least;
+            let (word, bit) = (offset - offset % WORD, 1 << (offset % WORD));
+            match masks.last_mut() {
+                Some(last) if last.0 == word => last.1 |= bit,
+                _ => masks.push((word, bit))
+            }
+        }
+        let single = match *masks {
+            [(0, mask)] => Some(mask),
+            _ => None
+        };
+        // Candidates are free for the least label; only the rest are tested.
+        masks[0].1 &= !1;
+        if masks[0].1 == 0 { masks.remove(0); }
+        let admits = |slot : usize| self.free_state.get(slot - (1 + least))
+            && masks.iter().all(|&(word, mask)| self.free_trans.window(slot + word) & mask == mask);
+        let mut candidates = self.free_slots().filter(|&slot| slot > least && admits(slot));
+
+        match single {
+            Some(mask) if self.best_fit && symbols.len() >= BEST_FIT_DEGREE => {
+                // The slots from the least label to the greatest.
+                let span = !0u64 >> mask.leading_zeros();
+                let holes = |slot : usize| (self.free_trans.window(slot) & span & !mask).count_ones();
+                let mut best = None;
+                for slot in candidates {
+                    let fit = holes(slot);
+                    if best.is_none_or(|(least_holes, _)| fit < least_holes) { best = Some((fit, slot)) }
+                    if fit == 0 { break }
+                }
+                best.map(|(_, slot)| slot - (1 + least))
+            },
+            _ => candidates.next().map(|slot| slot - (1 + least))
+        }
     }
 
+    /// The free slots, in ascending order.
+    fn free_slots(&self) -> FreeSlots<'_> { FreeSlots { segments : self, slot : self.head } }
+
     fn affix_state(&mut self, i : usize) {
-        let r = self.as_state.remove(&i);
-        assert!(r);
+        assert!(self.free_state.get(i));
+        self.free_state.clear(i);
     }
 
     fn affix_trans(&mut self, i : usize) {
-        let r = self.as_trans.remove(&i);
-        assert!(r);
+        assert!(self.free_trans.get(i));
+        self.unlink(i);
+    }
+
+    /// Take a slot out of the free list.
+    fn unlink(&mut self, i : usize) {
+        let (prev, next) = (self.prev_free[self.ring_slot(i)], self.next_free[self.ring_slot(i)]);
+        match prev {
+            NIL => self.head = next,
+            prev => { let p = self.ring_slot(prev); self.next_free[p] = next }
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => { let n = self.ring_slot(next); self.prev_free[n] = prev }
+        }
+        self.free_trans.clear(i);
     }
 
     /// Add a new block to the segments.
     pub fn expand(&mut self, old_length : usize) {
         let new_length = old_length + self.block_size;
-        self.as_state.extend(old_length .. new_length);
-        self.as_trans.extend(old_length .. new_length);
+        // The new slots follow every free slot, and are linked at the tail.
+        let tail = self.tail;
+        if self.next_free.len() < self.ring {
+            self.next_free.resize(new_length.min(self.ring), NIL);
+            self.prev_free.resize(new_length.min(self.ring), NIL);
+        }
+        for i in old_length .. new_length {
+            let r = self.ring_slot(i);
+            self.prev_free[r] = if i == old_length { tail } else { i - 1 };
+            self.next_free[r] = if i + 1 == new_length { NIL } else { i + 1 };
+        }
+        match tail {
+            NIL => self.head = old_length,
+            tail => { let t = self.ring_slot(tail); self.next_free[t] = old_length }
+        }
+        self.tail = new_length - 1;
+        self.free_trans.extend(self.block_size);
+        self.free_state.extend(self.block_size);
+        if new_length - self.open_from > OPEN_BLOCKS * self.block_size {
+            self.close_block();
+        }
+    }
+
+    /// Give up on the free slots of the first open block.
+    fn close_block(&mut self) {
+        let closed = self.open_from;
+        self.open_from += self.block_size;
+        for i in closed .. self.open_from {
+            if self.free_trans.get(i) { self.unlink(i) }
+            self.free_state.clear(i);
+        }
     }
 
+    /// The place of a slot in the rings.
+    #[inline]
+    fn ring_slot(&self, i : usize) -> usize { i & (self.ring - 1) }
+
     pub fn block_size(&self) -> usize { self.block_size }
 
     pub fn unfixed_count(&self) -> usize {
         use std::cmp;
-        cmp::min(self.as_trans.len(), self.as_state.len())
+        cmp::min(self.free_trans.count(), self.free_state.count())
     }
 
     pub fn reserve(&mut self, n : usize) {
-        self.as_state.reserve(n);
-        self.as_trans.reserve(n);
+        self.free_trans.reserve(n);
+        self.free_state.reserve(n);
     }
 }
 
-impl Default for IndexSegments {
-    fn default() -> Self {
-        IndexSegments {
-            as_state : FnvHashSet::default(),
-            as_trans : FnvHashSet::default(),
-            block_size : 257,
+
+/// The free slots of segments, in ascending order.
+struct FreeSlots<'a> {
+    segments : &'a IndexSegments,
+    slot : usize
+}
+
+impl<'a> Iterator for FreeSlots<'a> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        match self.slot {
+            NIL => None,
+            slot => {
+                self.slot = self.segments.next_free[self.segments.ring_slot(slot)];
+                Some(slot)
+            }
         }
     }
 }
@@ -0,0 +1,85 @@
+#!/usr/bin/env python3
+"""Write a darts-clone double array for a handful of keys.
+
+Follows the unit layout and offset rules of darts-clone's DoubleArrayBuilder:
+little-endian 32-bit units, in blocks of 256, with vacant units labelled so
+that they match no transition. As in its DAWG build, a state whose suffixes
+and values were already placed points to them rather than being placed anew.
+
+    python3 darts.py > tiny.darts
+"""
+
+import struct
+import sys
+
+KEYS = [
+    ("東京", 1), ("東京都", 2), ("東", 3), ("京都", 4), ("京都府", 5),
+    ("a", 6), ("ab", 7), ("abc", 8), ("b", 2147483647), ("ba", 0),
+    ("wy", 10), ("wyz", 9), ("xy", 10), ("xyz", 9), ("y", 10), ("yz", 9),
+]
+
+BLOCK = 256
+units = [0] * BLOCK
+occupied = {0}
+bases = set()
+placed = {}
+
+
+def set_offset(pos, offset):
+    assert offset < 1 << 29
+    units[pos] &= (1 << 31) | (1 << 8) | 0xFF
+    units[pos] |= offset << 10 if offset < 1 << 21 else (offset << 2) | (1 << 9)
+
+
+def valid(pos, base, labels):
+    if not encodable(pos, base):
+        return False
+    return base not in bases and all(base ^ l not in occupied for l in labels)
+
+
+def encodable(pos, base):
+    rel = pos ^ base
+    return not (rel & 0xFF and rel & (0xFF << 21))
+
+
+def build(pos, keys, depth):
+    labels = sorted({k[depth] if len(k) > depth else 0 for k, _ in keys})
+    suffixes = tuple((k[depth:], v) for k, v in keys)
+    if suffixes in placed and encodable(pos, placed[suffixes]):
+        set_offset(pos, pos ^ placed[suffixes])
+        if 0 in labels:
+            units[pos] |= 1 << 8
+        return
+    base = 1
+    while not valid(pos, base, labels):
+        base += 1
+    bases.add(base)
+    placed[suffixes] = base
+    while max(base ^ l for l in labels) >= len(units):
+        units.extend([0] * BLOCK)
+    set_offset(pos, pos ^ base)
+    for l in labels:
+        child = base ^ l
+        occupied.add(child)
+        if l == 0:
+            units[pos] |= 1 << 8
+            units[child] = [v for k, v in keys if len(k) == depth][0] | (1 << 31)
+        else:
+            units[child] = (units[child] & ~0xFF) | l
+    for l in labels:
+        if l:
+            build(base ^ l, [(k, v) for k, v in keys if len(k) > depth and k[depth] == l], depth + 1)
+
+
+keys = sorted((k.encode(), v) for k, v in KEYS)
+build(0, keys, 0)
+
+# Vacant units carry a label no transition expects: relative to an offset
+# which no state uses.
+for start in range(0, len(units), BLOCK):
+    unused = next(o for o in range(start, start + BLOCK) if o not in bases)
+    for pos in range(start, start + BLOCK):
+        if pos not in occupied:
+            units[pos] = (pos ^ unused) & 0xFF
+
+sys.stdout.buffer.write(b"".join(struct.pack("<I", u) for u in units))
@@ -1,12 +1,28 @@
 extern crate atlatl;
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "fst-interop")]
+extern crate fst as fst_crate;
+#[cfg(feature = "serialization")]
+extern crate bincode;
 extern crate rand;
 extern crate quickcheck;
+#[cfg(feature = "rkyv")]
+extern crate rkyv;
+#[cfg(feature = "serialization")]
+extern crate serde_json;
 
-use quickcheck::{quickcheck};
-use std::collections::BTreeMap;
+use quickcheck::{quickcheck, QuickCheck};
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::iter;
+use std::sync::{Arc, Mutex};
 
 use atlatl::*;
 use atlatl::fst::*;
+use atlatl::fst::format::{Kind, TypeTag};
+use atlatl::index::{Index, U24};
 
 
 #[test]
@@ -52,3 +68,2842 @@ fn fst_reap() {
     assert!(4 == reaped.len());
     assert!(vec![(0, 3), (1, 0), (2, 1), (3, 2)] == reaped);
 }
+
+
+#[test]
+fn empty_fsts_find_nothing() {
+    let default : FST<u32, i16> = FST::default();
+    let built : FST<u32, i16> = FST::from_iter(Vec::<(&[u8], i16)>::new()).unwrap();
+    assert_eq!((0, 0), (default.len(), default.stats().keys));
+    assert_eq!(0, built.stats().keys);
+
+    for fst in [&default, &built] {
+        assert!(fst.get("").is_none() && fst.get("a").is_none());
+        assert!(!fst.contains("") && !fst.contains("a"));
+        assert_eq!(0, fst.reap(b"abc").count());
+        assert_eq!(0, fst.reap_past_root(b"abc").count());
+        assert!(fst.iter().next().is_none());
+        assert_eq!(0, fst.with_failure_links().find_overlapping(b"abc").count());
+    }
+}
+
+#[test]
+fn empty_language_is_built_and_stored() {
+    let mut builder : Builder<u32, u32> = Builder::new();
+    builder.finish().unwrap();
+    let fst : FST<u32, u32> = FST::from_builder(&builder).unwrap();
+    assert_eq!(fst, builder.into_fst().unwrap());
+    assert_eq!(fst, FST::from_iter(std::iter::empty::<(&[u8], u32)>()).unwrap());
+
+    // Every label, the least included, leads nowhere from the root.
+    let probes : Vec<Vec<u8>> = vec![vec![], vec![0], vec![0, 0], vec![1], vec![255, 0]];
+    let finds_nothing = |fst : &FST<u32, u32>| {
+        probes.iter().all(|p| fst.get(p).is_none() && !fst.contains(p) && fst.reap(p).next().is_none())
+            && fst.iter().next().is_none()
+            && fst.stats().keys == 0
+    };
+    assert!(finds_nothing(&fst));
+    assert!(fst.validate().is_ok() && fst.is_minimal());
+    assert_eq!((0, None), (fst.dart_stats().occupied, fst.dart_stats().last_occupied));
+    let mut shrunk = fst.clone();
+    shrunk.shrink_to_fit();
+    assert!(finds_nothing(&shrunk) && shrunk.validate().is_ok());
+    assert_eq!(shrunk, FST::from_bytes(&shrunk.to_bytes()).unwrap());
+
+    let bytes = fst.to_bytes();
+    assert!(finds_nothing(&FST::from_bytes(&bytes).unwrap()));
+    assert_eq!(fst, FST::from_bytes(&bytes).unwrap());
+    assert_eq!(fst, FST::from_bytes(&fst.to_compressed_bytes()).unwrap());
+    let view : FstRef<u32, u32> = FstRef::new(&bytes).unwrap();
+    assert!(probes.iter().all(|p| view.get(p).is_none() && !view.contains(p)) && view.iter().next().is_none());
+    let mut written = Vec::new();
+    fst.write_to(&mut written).unwrap();
+    assert_eq!(fst, FST::read_from(&written[..]).unwrap());
+    assert_eq!(fst, fst.clone().freeze().thaw());
+    assert!(fst.to_compact().iter().next().is_none());
+
+    #[cfg(feature = "serialization")]
+    {
+        let decoded : FST<u32, u32> = bincode::deserialize(&bincode::serialize(&fst).unwrap()).unwrap();
+        assert_eq!(fst, decoded);
+        let decoded : FST<u32, u32> = serde_json::from_str(&serde_json::to_string(&fst).unwrap()).unwrap();
+        assert!(finds_nothing(&decoded));
+    }
+
+    // Empty sections are neither mapped nor verified.
+    #[cfg(feature = "mmap")]
+    {
+        let path = std::env::temp_dir().join(format!("atlatl-empty-{}.fst", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let mapped : MmapFst<u32, u32> = FST::open_mmap(&path).unwrap();
+        assert!(probes.iter().all(|p| mapped.get(p).is_none()) && mapped.view().iter().next().is_none());
+        let lazy : LazyFst<u32, u32> = FST::open_lazy(&path).unwrap();
+        assert!(probes.iter().all(|p| lazy.get(p).unwrap().is_none() && !lazy.contains(p).unwrap()));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn nul_labels_do_not_match_vacant_slots() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>, probes : Vec<Vec<u8>>) -> bool {
+        // Most bytes are zero, and the rest few.
+        let zeroed = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| b.saturating_sub(192) % 4).collect() };
+        let source : BTreeMap<Vec<u8>, u16> = btree.iter().map(|(k, &v)| (zeroed(k), v)).collect();
+        let fst : FST<u32, u16> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        let bytes = fst.to_bytes();
+        let view : FstRef<u32, u16> = FstRef::new(&bytes).unwrap();
+        let frozen = fst.clone().freeze();
+
+        // Misses are found next to hits, each key padded with zeros.
+        let keys : Vec<Vec<u8>> = source.keys().cloned()
+            .chain(probes.iter().map(zeroed))
+            .flat_map(|k| vec![[&[0][..], &k].concat(), [&k[..], &[0]].concat(), [&k[..], &[0, 0]].concat(), k])
+            .collect();
+        keys.iter().all(|k| {
+            let expected = source.get(k).cloned();
+            let reaped : Vec<(usize, u16)> = (0 ..= k.len())
+                .filter_map(|i| source.get(&k[.. i]).map(|&v| (i, v)))
+                .collect();
+            fst.get(k) == expected
+                && fst.contains(k) == expected.is_some()
+                && fst.get_checked(k).unwrap() == expected
+                && fst.get_pipelined([&k[..]]) == [expected]
+                && fst.reap(k).collect::<Vec<_>>() == reaped
+                && view.get(k) == expected && view.contains(k) == expected.is_some()
+                && frozen.get(k) == expected && frozen.contains(k) == expected.is_some()
+        })
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, Vec<Vec<u8>>) -> bool);
+
+    // A vacant slot checks the least label, and once led back to the root.
+    let fst : FST<u32, u32> = FST::from_iter(vec![(&b"a"[..], 1), (b"b", 2)]).unwrap();
+    for key in [&b"\0a"[..], b"\0\0b", b"a\0", b"\0"] {
+        assert_eq!((None, false), (fst.get(key), fst.contains(key)));
+    }
+    assert_eq!(None, fst.transition(0, 0));
+    assert_eq!(vec![(1, 1)], fst.reap(b"a\0a").collect::<Vec<_>>());
+}
+
+#[test]
+fn scanner_finds_overlapping_keys() {
+    let pairs = &[("he", 1), ("hers", 2), ("his", 3), ("she", 4)];
+    let b = fst::Builder::from_iter(pairs.iter().cloned()).unwrap();
+    let fst : FST<u32, u16> = FST::from_builder(&b).unwrap();
+    let scanner = fst.with_failure_links();
+
+    let found : Vec<_> = scanner.find_overlapping(b"ushers").collect();
+    assert_eq!(vec![(1, 4, 4), (2, 4, 1), (2, 6, 2)], found);
+}
+
+#[test]
+fn scanner_matches_naive_search() {
+    fn property(btree: BTreeMap<Vec<u8>, u16>, text: Vec<u8>) -> bool {
+        // A narrow alphabet, so that keys actually occur in the text.
+        let btree : BTreeMap<_, _> =
+            btree.into_iter().map(|(k, v)| (k.iter().map(|b| b % 3).collect::<Vec<u8>>(), v))
+                 .collect();
+        let text : Vec<u8> = text.iter().map(|b| b % 3).collect();
+        let b = fst::Builder::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let fst : FST<u32, u16> = FST::from_builder(&b).unwrap();
+
+        let mut naive = Vec::new();
+        for end in 1 ..= text.len() {
+            for start in 0 .. end {
+                if let Some(&v) = btree.get(&text[start .. end]) { naive.push((start, end, v)) }
+            }
+        }
+
+        let scanner = fst.with_failure_links();
+        naive == scanner.find_overlapping(&text).collect::<Vec<_>>()
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, Vec<u8>) -> bool);
+}
+
+#[test]
+fn fst_reap_iter_is_lazy() {
+    let pairs = &[("a", 0), ("ab", 1), ("abc", 2)];
+    let b = fst::Builder::from_iter(pairs.iter().cloned()).unwrap();
+    let fst : FST<u32, i16> = FST::from_builder(&b).unwrap();
+
+    let pulled = Cell::new(0);
+    let mut source = b"abxyz".iter().cloned().inspect(|_| pulled.set(pulled.get() + 1));
+    {
+        let mut reaper = fst.reap_iter(source.by_ref());
+        assert_eq!(Some((1, 0)), reaper.next());
+        assert_eq!(1, pulled.get());
+        assert_eq!(Some((2, 1)), reaper.next());
+        assert_eq!(2, pulled.get());
+        assert_eq!(None, reaper.next());
+        assert_eq!(None, reaper.next());
+        assert_eq!((0, Some(0)), reaper.size_hint());
+    }
+    // The traversal died at `x`, and went no further.
+    assert_eq!(vec![b'y', b'z'], source.collect::<Vec<_>>());
+
+    let unbounded = fst.reap_iter(iter::repeat(b'a'));
+    assert_eq!((0, None), unbounded.size_hint());
+}
+
+#[test]
+fn fst_iter_matches_source() {
+    fn property(btree: BTreeMap<Vec<u8>, u16>) -> bool {
+        let b = fst::Builder::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let fst : FST<u32, u16> = FST::from_builder(&b).unwrap();
+
+        fst.iter().eq(btree)
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+}
+
+#[test]
+fn subtree_matches_source_suffixes() {
+    fn property(btree: BTreeMap<Vec<u8>, u16>, prefix: Vec<u8>) -> bool {
+        let btree : BTreeMap<_, _> =
+            btree.into_iter().map(|(k, v)| (k.iter().map(|b| 1 + b % 4).collect::<Vec<u8>>(), v))
+                 .collect();
+        let prefix : Vec<u8> = prefix.iter().take(2).map(|b| 1 + b % 4).collect();
+        let b = fst::Builder::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let fst : FST<u32, u16> = FST::from_builder(&b).unwrap();
+
+        let suffixes : Vec<_> = btree.iter()
+            .filter(|&(k, _)| k.starts_with(&prefix))
+            .map(|(k, &v)| (k[prefix.len() ..].to_vec(), v))
+            .collect();
+        match fst.subtree(&prefix) {
+            None => suffixes.is_empty(),
+            Some(sub) =>
+                suffixes.iter().all(|(s, v)| sub.get(s) == Some(*v) && sub.contains(s))
+                    && sub.iter().collect::<Vec<_>>() == suffixes
+        }
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, Vec<u8>) -> bool);
+}
+
+#[test]
+fn subtree_rooted_in_inner_output() {
+    let pairs = &[("item:", 2), ("user:", 5), ("user:a", 1), ("user:b", 7)];
+    let b = fst::Builder::from_iter(pairs.iter().cloned()).unwrap();
+    let fst : FST<u32, u16> = FST::from_builder(&b).unwrap();
+
+    let users = fst.subtree("user:").unwrap();
+    assert_eq!(Some(5), users.get(""));
+    assert_eq!(Some(7), users.get("b"));
+    assert_eq!(None, users.get("c"));
+    assert!(!users.contains("a:"));
+    let expected = vec![(b"".to_vec(), 5), (b"a".to_vec(), 1), (b"b".to_vec(), 7)];
+    assert_eq!(expected, users.iter().collect::<Vec<_>>());
+    assert!(fst.subtree("users").is_none());
+}
+
+#[test]
+fn builder_from_unsorted_matches_sorted() {
+    fn property(source: HashMap<Vec<u8>, u16>) -> bool {
+        let btree : BTreeMap<_, _> = source.iter().map(|(k, &v)| (k.clone(), v)).collect();
+        let sorted = fst::Builder::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let unsorted = fst::Builder::from_unsorted_iter(source).unwrap();
+        let sorted : FST<u32, u16> = FST::from_builder(&sorted).unwrap();
+        let unsorted : FST<u32, u16> = FST::from_builder(&unsorted).unwrap();
+
+        sorted.iter().eq(unsorted.iter())
+    }
+
+    quickcheck(property as fn(HashMap<Vec<u8>, u16>) -> bool);
+}
+
+#[test]
+fn builder_from_unsorted_duplicates() {
+    let pairs = vec![("b", 1), ("a", 2), ("b", 3)];
+    match fst::Builder::<u32, u16>::from_unsorted_iter(pairs.clone()) {
+        Err(fst::Error::Duplicate { key, index, .. }) => assert_eq!((b"b".to_vec(), 2), (key, index)),
+        other => panic!("expected a duplicate key error, found {:?}", other)
+    }
+
+    let b = fst::Builder::from_unsorted_iter_merging(pairs, |x, y| x + y).unwrap();
+    let fst : FST<u32, u16> = FST::from_builder(&b).unwrap();
+    assert_eq!(vec![(b"a".to_vec(), 2), (b"b".to_vec(), 4)], fst.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn builder_duplicate_policies_match_fold() {
+    fn check(mut pairs: Vec<(Vec<u8>, u16)>, policy: DuplicatePolicy<u32>) -> bool {
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected : BTreeMap<Vec<u8>, u32> = BTreeMap::new();
+        let mut duplicated = false;
+        for (k, v) in pairs.iter().cloned() {
+            let v = v as u32;
+            match (expected.get(&k).cloned(), policy) {
+                (None, _) => { expected.insert(k, v); },
+                (Some(_), DuplicatePolicy::Error) => duplicated = true,
+                (Some(_), DuplicatePolicy::KeepFirst) => (),
+                (Some(_), DuplicatePolicy::KeepLast) => { expected.insert(k, v); },
+                (Some(old), DuplicatePolicy::Merge(f)) => { expected.insert(k, f(old, v)); }
+            }
+        }
+
+        let mut b = fst::Builder::new().duplicate_policy(policy);
+        let built = b.extend(pairs.iter().map(|(k, v)| (k, *v as u32))).and_then(|_| b.finish());
+        match built {
+            Err(fst::Error::Duplicate { .. }) => duplicated,
+            Err(_) => false,
+            Ok(_) => {
+                let fst : FST<u32, u32> = FST::from_builder(&b).unwrap();
+                !duplicated && fst.iter().eq(expected)
+            }
+        }
+    }
+
+    fn property(pairs: Vec<(Vec<u8>, u16)>) -> bool {
+        // Narrow keys, so that duplicates are common.
+        let pairs : Vec<_> =
+            pairs.into_iter().map(|(k, v)| (k.into_iter().take(2).map(|b| b % 3).collect(), v))
+                 .collect();
+        check(pairs.clone(), DuplicatePolicy::Error)
+            && check(pairs.clone(), DuplicatePolicy::KeepFirst)
+            && check(pairs.clone(), DuplicatePolicy::KeepLast)
+            && check(pairs, DuplicatePolicy::Merge(|x, y| x + y))
+    }
+
+    quickcheck(property as fn(Vec<(Vec<u8>, u16)>) -> bool);
+}
+
+#[test]
+fn builder_unchecked_matches_checked() {
+    fn property(btree: BTreeMap<Vec<u8>, u16>) -> bool {
+        let pairs = btree.iter().map(|(k, &v)| (k, v));
+        let checked = fst::Builder::from_iter(pairs.clone()).unwrap();
+        let unchecked = fst::Builder::from_sorted_iter_unchecked(pairs).unwrap();
+        let checked : FST<u32, u16> = FST::from_builder(&checked).unwrap();
+        let unchecked : FST<u32, u16> = FST::from_builder(&unchecked).unwrap();
+
+        checked.iter().eq(unchecked.iter())
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+}
+
+#[test]
+#[should_panic]
+#[cfg(debug_assertions)]
+fn builder_unchecked_catches_disorder_in_debug() {
+    let _ = fst::Builder::<u32, u16>::from_sorted_iter_unchecked(vec![("b", 1), ("a", 2)]);
+}
+
+#[test]
+fn builder_into_fst_matches_from_builder() {
+    fn property(btree: BTreeMap<Vec<u8>, u16>) -> bool {
+        let b = fst::Builder::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let borrowed : FST<u32, u16> = FST::from_builder(&b).unwrap();
+        let owned : FST<u32, u16> = b.into_fst().unwrap();
+        let direct : FST<u32, u16> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+
+        owned.iter().eq(btree.clone()) && borrowed.iter().eq(btree) && direct.iter().eq(owned.iter())
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    // Enough states for the owned builder to be released along the way,
+    // with suffixes shared between distant keys.
+    let pairs : BTreeMap<_, _> = (0 .. 5000u32)
+        .map(|i| ((i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15).to_be_bytes(), (i % 5) as u16))
+        .collect();
+    let b = fst::Builder::from_iter(pairs.clone()).unwrap();
+    let borrowed : FST<u32, u16> = FST::from_builder(&b).unwrap();
+    assert!(b.size() > 4 * 4096);
+    assert_eq!(borrowed, b.into_fst().unwrap());
+    assert!(pairs.iter().all(|(k, &v)| borrowed.get(k) == Some(v)));
+}
+
+#[test]
+fn fst_btree_map_round_trip() {
+    fn property(btree: BTreeMap<Vec<u8>, u16>) -> bool {
+        let borrowed = FST::<u32, u16>::try_from(&btree).unwrap();
+        let owned = FST::<u32, u16>::try_from(btree.clone()).unwrap();
+
+        borrowed.to_btree_map() == btree && owned.to_btree_map() == btree
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    let words : BTreeMap<String, u16> =
+        vec![("atlatl".to_owned(), 1), ("dart".to_owned(), 2)].into_iter().collect();
+    let fst = FST::<u32, u16>::try_from(&words).unwrap();
+    assert_eq!(Some(2), fst.get("dart"));
+}
+
+#[test]
+fn builder_reports_progress() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    let mut b = fst::Builder::<u32, u16>::new()
+        .on_progress(2, move |p| sink.lock().unwrap().push(p));
+    b.extend((0 .. 10u16).map(|i| (format!("key{}", i), i))).unwrap();
+    b.finish().unwrap();
+    let fst = b.into_fst().unwrap();
+    assert_eq!(Some(7), fst.get("key7"));
+
+    let reports = reports.lock().unwrap();
+    let inserted : Vec<_> = reports.iter()
+        .filter(|p| p.phase == fst::Phase::Insertion)
+        .map(|p| p.keys)
+        .collect();
+    assert_eq!(vec![2, 4, 6, 8, 10], inserted);
+    let placed : Vec<_> = reports.iter().filter(|p| p.phase == fst::Phase::Placement).collect();
+    assert!(!placed.is_empty());
+    assert!(placed.iter().all(|p| p.keys == 10 && p.dart_len > 0 && p.placed.is_multiple_of(2)));
+}
+
+#[test]
+fn builder_and_fst_stats() {
+    // Both keys lead to the same final state.
+    let b = fst::Builder::from_iter(vec![("a", 1), ("b", 1)]).unwrap();
+    let expected = BuilderStats { states : 2, transitions : 2, keys : 2, key_bytes : 2, max_out_degree : 2 };
+    assert_eq!(expected, b.stats());
+    let fst : FST<u32, u16> = b.into_fst().unwrap();
+    let stats = fst.stats();
+    assert_eq!((2, 2, 2), (stats.states, stats.transitions, stats.keys));
+    assert!(stats.slots >= 3 && stats.bytes_per_key > 0.0);
+
+    // The empty key is a final root, not a separate state.
+    let b = fst::Builder::from_iter(vec![("", 3), ("ab", 1)]).unwrap();
+    let expected = BuilderStats { states : 3, transitions : 2, keys : 2, key_bytes : 2, max_out_degree : 1 };
+    assert_eq!(expected, b.stats());
+    let fst : FST<u32, u16> = b.into_fst().unwrap();
+    let stats = fst.stats();
+    assert_eq!((3, 2, 2), (stats.states, stats.transitions, stats.keys));
+
+    let stats = FST::<u32, u16>::from_builder(&fst::Builder::from_iter(Vec::<(&str, u16)>::new()).unwrap())
+        .unwrap().stats();
+    assert_eq!((1, 0, 0), (stats.states, stats.transitions, stats.keys));
+}
+
+#[test]
+fn placement_is_deterministic() {
+    // Roots with many sparse labels, and long tails of single transitions.
+    let mut keys : Vec<Vec<u8>> = (0 .. 64u8)
+        .flat_map(|a| (0 .. 8u8).map(move |b| vec![a * 4, b * 31, a ^ b]))
+        .collect();
+    keys.sort();
+    let build = |placement| -> FST<u32, u16> {
+        let mut b = fst::Builder::new().placement(placement);
+        for (i, k) in keys.iter().enumerate() { b.insert(k, i as u16).unwrap(); }
+        b.finish().unwrap();
+        b.into_fst().unwrap()
+    };
+
+    for &placement in &[fst::Placement::FirstFit, fst::Placement::BestFit] {
+        let fst = build(placement);
+        assert_eq!(fst, build(placement));
+        assert!(keys.iter().enumerate().all(|(i, k)| fst.get(k) == Some(i as u16)));
+        let fill = fst.fill_ratio();
+        assert!(fill > 0.0 && fill <= 1.0);
+        assert_eq!(fst.stats().transitions as f64 / fst.len() as f64, fill);
+    }
+    assert_eq!(0.0, FST::<u32, u16>::default().fill_ratio());
+}
+
+#[test]
+fn build_options_keep_fsts_equivalent() {
+    let pairs : BTreeMap<_, _> = (0 .. 3000u32).map(|i| (i.wrapping_mul(2_654_435_761).to_be_bytes(), i as u16)).collect();
+    let builder = fst::Builder::from_iter(pairs.clone()).unwrap();
+    let default : FST<u32, u16> = FST::from_builder(&builder).unwrap();
+    let build = |options| FST::from_builder_with(&builder, options).unwrap();
+
+    // Capacity and growth only change the allocations along the way.
+    assert_eq!(default, build(BuildOptions::new().initial_capacity(1 << 22)));
+    assert_eq!(default, build(BuildOptions::new().initial_capacity(0).growth(Growth::Linear)));
+    // Blocks narrower than the alphabet are widened.
+    assert_eq!(default, build(BuildOptions::new().block_size(16)));
+
+    for block_size in [300, 4096] {
+        let wide = build(BuildOptions::new().block_size(block_size));
+        assert!(wide.len() % block_size == 0 && wide.iter().eq(default.iter()));
+        assert!(pairs.iter().all(|(k, &v)| wide.get(k) == Some(v)));
+    }
+    let owned : FST<u32, u16> = builder.into_fst_with(BuildOptions::new().growth(Growth::Linear)).unwrap();
+    assert_eq!(default, owned);
+}
+
+#[test]
+fn placement_orders_keep_fsts_equivalent() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>, probes : Vec<Vec<u8>>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, u16> = btree.iter().map(|(k, &v)| (narrow(k), v)).collect();
+        let builder = fst::Builder::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        let default : FST<u32, u16> = FST::from_builder(&builder).unwrap();
+        let keys : Vec<Vec<u8>> = source.keys().cloned().chain(probes.iter().map(narrow)).collect();
+
+        [Order::DepthFirst, Order::BreadthFirst, Order::Registration].iter().all(|&order| {
+            let options = BuildOptions::new().order(order);
+            let fst : FST<u32, u16> = FST::from_builder_with(&builder, options).unwrap();
+            let owned : FST<u32, u16> = builder.clone().into_fst_with(options).unwrap();
+            fst == owned
+                && fst.iter().eq(default.iter())
+                && keys.iter().all(|k| fst.get(k) == default.get(k) && fst.contains(k) == default.contains(k))
+        })
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, Vec<Vec<u8>>) -> bool);
+
+    // Wide enough that an owned arena is released along the way.
+    let pairs : BTreeMap<_, _> = (0 .. 20000u32).map(|i| (i.wrapping_mul(2_654_435_761).to_be_bytes(), i as u16)).collect();
+    let builder = fst::Builder::from_iter(pairs.clone()).unwrap();
+    for order in [Order::BreadthFirst, Order::Registration] {
+        let fst : FST<u32, u16> = builder.clone().into_fst_with(BuildOptions::new().order(order)).unwrap();
+        assert!(pairs.iter().all(|(k, &v)| fst.get(k) == Some(v)));
+        assert_eq!(fst, FST::from_builder_with(&builder, BuildOptions::new().order(order)).unwrap());
+    }
+}
+
+#[test]
+fn build_context_leaves_nothing_to_the_next_build() {
+    fn property(maps : Vec<BTreeMap<Vec<u8>, u16>>) -> bool {
+        let mut context = BuildContext::new();
+        maps.iter().take(4).all(|map| {
+            let pairs = || map.iter().map(|(k, &v)| (k, v));
+            // A build abandoned midway, whose buffers are recycled anyway.
+            let mut failed : Builder<u32, u16> = Builder::with_context(&mut context);
+            let _ = failed.extend(pairs().rev());
+            context.recycle(failed);
+
+            let fresh : FST<u32, u16> = FST::from_iter(pairs()).unwrap();
+            let mut builder = Builder::with_context(&mut context);
+            builder.extend(pairs()).unwrap();
+            builder.finish().unwrap();
+            FST::from_builder_in(&builder, &mut context).unwrap() == fresh
+                && builder.into_fst_in(&mut context).unwrap() == fresh
+        })
+    }
+
+    QuickCheck::new().tests(25).quickcheck(property as fn(Vec<BTreeMap<Vec<u8>, u16>>) -> bool);
+
+    let mut context = BuildContext::with_options(BuildOptions::new().block_size(300));
+    let mut builder : Builder<u32, u16> = Builder::with_context(&mut context).minimize(false);
+    builder.extend(vec![(b"ab", 1u16), (b"cb", 2)]).unwrap();
+    builder.finish().unwrap();
+    let fst = builder.into_fst_in(&mut context).unwrap();
+    assert_eq!((0, 5), (fst.len() % 300, fst.stats().states));
+}
+
+#[test]
+fn dart_stats_count_occupied_slots() {
+    // Keys on the least label, whose transitions check as vacant slots do,
+    // and vacant slots are not to be counted.
+    let pairs = vec![(vec![0u8], 1u16), (vec![0, 0], 2), (vec![0, 0, 0], 3), (vec![1], 4), (vec![2, 0], 5)];
+    let fst : FST<u32, u16> = FST::from_iter(pairs.clone()).unwrap();
+    let stats = fst.dart_stats();
+    let transitions = pairs.iter().flat_map(|(k, _)| (1 ..= k.len()).map(move |n| &k[.. n]))
+        .collect::<std::collections::BTreeSet<_>>().len();
+    assert_eq!((fst.len(), transitions), (stats.slots, stats.occupied));
+    assert_eq!(fst.stats().states, stats.bases);
+    assert_eq!(stats.occupied as f64 / fst.len() as f64, stats.fill_ratio);
+    let last = stats.last_occupied.unwrap();
+    assert!(!fst.da.next[last].is_zero() && fst.da.next[last + 1 ..].iter().all(|n| n.is_zero()));
+
+    let empty = FST::<u32, u16>::default().dart_stats();
+    assert_eq!((0, 0, 0, None, 0.0), (empty.slots, empty.occupied, empty.bases, empty.last_occupied, empty.fill_ratio));
+}
+
+#[test]
+fn placement_expands_until_a_base_fits() {
+    // States dense in the middle labels crowd the Dart, and the last takes
+    // only the outermost labels.
+    let dense = |states : u16| -> Vec<(Vec<u8>, u32)> {
+        let mut pairs : Vec<_> = (0 .. states)
+            .flat_map(|a| (1 .. 255u8).map(move |b| (vec![(a >> 8) as u8, a as u8, b], (a as u32 + 1) * b as u32)))
+            .collect();
+        pairs.push((vec![255, 255, 0], 1));
+        pairs.push((vec![255, 255, 255], 2));
+        pairs
+    };
+
+    let pairs = dense(64);
+    let fst : FST<u32, u32> = FST::from_iter(pairs.iter().map(|(k, v)| (k, *v))).unwrap();
+    assert!(pairs.iter().all(|(k, v)| fst.get(k) == Some(*v)));
+
+    match FST::<u16, u32>::from_iter(dense(512).iter().map(|(k, v)| (k, *v))) {
+        Err(fst::Error::OutOfBounds { maximum, .. }) => assert_eq!(u16::MAX as usize, maximum),
+        other => panic!("expected OutOfBounds, got {:?}", other.map(|fst| fst.len()))
+    }
+}
+
+#[test]
+fn index_conversions_are_checked() {
+    assert_eq!((Some(0xFFFF), None), (u16::try_as_index(0xFFFF), u16::try_as_index(0x10000)));
+    assert_eq!((Some(U24::MAX), None), (U24::try_as_index(U24::bound()), U24::try_as_index(U24::bound() + 1)));
+    assert_eq!((0xFFFF, usize::MAX), (u16::bound(), usize::bound()));
+    assert_eq!(u32::bound(), usize::try_from(u32::MAX).unwrap_or(usize::MAX));
+    #[cfg(target_pointer_width = "64")]
+    assert_eq!((usize::MAX, None), (u64::bound(), u32::try_as_index(1 << 32)));
+    assert_eq!(U24::new(70000), U24::as_index(70000));
+
+    // Out of range, the unchecked conversion panics in debug builds.
+    let wrapped = std::panic::catch_unwind(|| u16::as_index(0x10001));
+    assert_eq!(wrapped.ok(), if cfg!(debug_assertions) { None } else { Some(1) });
+
+    // A builder outgrowing its index type fails rather than wrapping.
+    let pairs : BTreeMap<_, _> = (0 .. 40_000u32).map(|i| (i.wrapping_mul(2_654_435_761).to_be_bytes(), 1u16)).collect();
+    match Builder::<u16, u16>::from_iter(pairs.iter().map(|(k, &v)| (k, v))) {
+        Err(Error::OutOfBounds { reached, maximum, .. }) => assert!(reached > maximum && maximum == 0xFFFF),
+        other => panic!("expected OutOfBounds, got {:?}", other.map(|b| b.len()))
+    }
+}
+
+#[test]
+fn u24_index_matches_u32() {
+    assert_eq!((1 << 24) - 1, U24::bound());
+    assert_eq!(U24::new(70000), U24::as_index(70000) + U24::from(0u8));
+    assert_eq!(0x123456, (U24::new(0x123400) + U24::new(0x56)).as_usize());
+    assert_eq!("16777215", U24::MAX.to_string());
+    assert!(U24::try_from(1u32 << 24).is_err() && U24::try_from((1u32 << 24) - 1) == Ok(U24::MAX));
+
+    // Past the range of `u16` indices.
+    let pairs : BTreeMap<_, _> = (0 .. 40_000u32).map(|i| (i.wrapping_mul(2_654_435_761).to_be_bytes(), i)).collect();
+    let wide : FST<u32, u32> = FST::from_iter(pairs.clone()).unwrap();
+    let packed : FST<U24, u32> = FST::from_iter(pairs.clone()).unwrap();
+    assert!(packed.len() > u16::MAX as usize);
+    assert!(pairs.iter().all(|(k, v)| packed.get(k) == Some(*v)) && packed.iter().eq(wide.iter()));
+    assert!(packed.da.next.iter().zip(&wide.da.next).all(|(&p, &w)| p.as_usize() == w as usize));
+    assert_eq!(FST::from_bytes(&packed.to_bytes()), Ok(packed.clone()));
+    assert_eq!(FST::from_bytes(&packed.to_compressed_bytes()), Ok(packed));
+
+    // A state may take every `u16` label.
+    let full : Vec<_> = (0 ..= u16::MAX).map(|b| (vec![0, b], b as u32 + 1)).collect();
+    let fst : FST<U24, u32, u16> = FST::from_iter(full.clone()).unwrap();
+    assert!(full.iter().all(|(k, v)| fst.get(k) == Some(*v)));
+}
+
+/// Builds nearly 2^24 transitions, for some minutes; run with `--release --ignored`.
+#[test]
+#[ignore]
+fn u24_index_reaches_its_bound() {
+    // Each first label leads to a state of its own, holding a whole block.
+    let blocks = |firsts : u16| (0 .. firsts)
+        .flat_map(|a| (0 ..= u16::MAX).map(move |b| (vec![a, b], (a as u32 + 1) * (b as u32 + 1))));
+
+    let fst : FST<U24, u32, u16> = FST::from_iter(blocks(255)).unwrap();
+    assert!(fst.len() > U24::bound() - (1 << 16) && fst.len() <= U24::bound() + (1 << 16));
+    assert!(blocks(255).step_by(4099).all(|(k, v)| fst.get(&k) == Some(v)));
+    drop(fst);
+
+    match FST::<U24, u32, u16>::from_iter(blocks(256)) {
+        Err(fst::Error::OutOfBounds { maximum, .. }) => assert_eq!(U24::bound(), maximum),
+        other => panic!("expected OutOfBounds, got {:?}", other.map(|fst| fst.len()))
+    }
+}
+
+#[test]
+fn builder_collects_rejected_keys() {
+    let pairs = vec![("b", 1), ("a", 2), ("c", 3), ("c", 4), ("d", 5), ("b", 6), ("e", 7)];
+    let rejected = fst::Builder::<u32, u16>::try_from_iter_collecting(pairs.clone()).unwrap_err();
+    let summary : Vec<_> = rejected.iter().map(|r| (r.index, r.previous)).collect();
+    assert_eq!(vec![(1, None), (3, Some(2)), (5, None)], summary);
+    // The index is that among the keys accepted.
+    let duplicate = fst::Error::Duplicate {
+        key : b"c".to_vec(),
+        index : 2,
+        existing_hint : Some("3".to_string()),
+        inserted_hint : Some("4".to_string())
+    };
+    assert_eq!(duplicate, rejected[1].error);
+
+    let (b, rejected) = fst::Builder::from_iter_skipping(pairs).unwrap();
+    assert_eq!(3, rejected.len());
+    let fst : FST<u32, u16> = b.into_fst().unwrap();
+    let expected = vec![("b", 1), ("c", 3), ("d", 5), ("e", 7)];
+    assert!(fst.iter().eq(expected.into_iter().map(|(k, v)| (k.as_bytes().to_vec(), v))));
+}
+
+#[test]
+fn builder_skipping_matches_filtered_input() {
+    fn property(pairs: Vec<(Vec<u8>, u16)>) -> bool {
+        let mut filtered : Vec<(Vec<u8>, u16)> = Vec::new();
+        for (k, v) in pairs.iter().cloned() {
+            if filtered.last().is_none_or(|last| last.0 < k) { filtered.push((k, v)) }
+        }
+
+        let (b, rejected) = fst::Builder::from_iter_skipping(pairs.clone()).unwrap();
+        let fst : FST<u32, u16> = b.into_fst().unwrap();
+        rejected.len() + filtered.len() == pairs.len() && fst.iter().eq(filtered)
+    }
+
+    quickcheck(property as fn(Vec<(Vec<u8>, u16)>) -> bool);
+}
+
+#[test]
+fn memory_usage_accounts_for_capacity() {
+    let b = fst::Builder::from_iter((0 .. 100u16).map(|i| (format!("{:03}", i), i))).unwrap();
+    let builder_usage = b.approx_memory();
+    assert!(builder_usage.total() >= b.size() * std::mem::size_of::<u32>());
+
+    let fst : FST<u32, u16> = b.into_fst().unwrap();
+    let usage = fst.memory_usage();
+    // A label, a destination and an output, and two bits of finality.
+    let per_slot = 1 + 4 + 2;
+    assert!(usage.total() >= fst.len() * per_slot + fst.len() / 4);
+    assert_eq!(usage.parts.iter().map(|p| p.1).sum::<usize>(), usage.total());
+    assert!(format!("{}", usage).contains("total"));
+}
+
+#[test]
+fn shrink_to_fit_trims_the_dart() {
+    let pairs : Vec<_> = (0 .. 1000u16).map(|i| (format!("{:04}", i * 7), i)).collect();
+    let built : FST<u32, u16> = FST::from_iter(pairs.clone()).unwrap();
+    // Placement leaves no spare capacity.
+    assert_eq!(built.len(), built.da.next.capacity());
+
+    let mut fst = built.clone();
+    let reclaimed = fst.shrink_to_fit();
+    assert!(fst.len() < built.len() && reclaimed > 0);
+    assert_eq!(built.memory_usage().total() - reclaimed, fst.memory_usage().total());
+    assert!(fst.fill_ratio() > built.fill_ratio());
+    assert!(pairs.iter().all(|(k, v)| fst.get(k) == Some(*v)));
+    assert!(fst.iter().eq(built.iter()));
+    assert_eq!(Ok(()), fst.validate());
+    assert_eq!(0, fst.shrink_to_fit());
+
+    let mut empty : FST<u32, u16> = FST::from_iter(Vec::<(&str, u16)>::new()).unwrap();
+    empty.shrink_to_fit();
+    assert_eq!((1, None), (empty.len(), empty.get("")));
+}
+
+#[test]
+fn stipes_pack_finality() {
+    let terminals = [Terminal::Not, Terminal::Empty, Terminal::Inner];
+    let slots : Vec<_> = (0 .. 11u8).map(|i| Stipe { check : i, terminal : terminals[i as usize % 3] }).collect();
+    let mut stipes : Stipes = slots.iter().cloned().collect();
+    assert!(stipes.iter().eq(slots.iter().cloned()));
+    assert_eq!((Some(slots[10]), None), (stipes.get(10), stipes.get(11)));
+
+    stipes.set(4, Stipe { check : 9, terminal : Terminal::Inner });
+    stipes.set_terminal(5, Terminal::Not);
+    assert_eq!((Stipe { check : 9, terminal : Terminal::Inner }, Terminal::Not), (stipes.get(4).unwrap(), stipes.terminal(5)));
+    assert_eq!(Terminal::Empty, stipes.terminal(7));
+
+    // Slots cut off and grown back are vacant.
+    stipes.resize(6);
+    stipes.resize(11);
+    assert!(stipes.iter().skip(6).all(|s| s == Stipe::default()));
+    let mut vacant : Stipes = slots[.. 6].iter().cloned().collect();
+    vacant.set(4, Stipe { check : 9, terminal : Terminal::Inner });
+    vacant.set_terminal(5, Terminal::Not);
+    vacant.resize(11);
+    assert_eq!(vacant, stipes);
+
+    // Finality takes two bits of each slot, beside a byte of label.
+    let fst : FST<u32, u32> = FST::from_iter((0 .. 500u32).map(|i| (format!("{:03}", i), i))).unwrap();
+    assert_eq!(fst.len() + fst.len().div_ceil(4), fst.da.stipe.bytes());
+}
+
+#[test]
+fn zero_outputs_are_shed() {
+    let keys : Vec<_> = (0 .. 300u32).map(|i| format!("{:03}", i)).collect();
+    let fst : FST<u32, u32> = FST::from_iter(keys.iter().map(|k| (k, 0))).unwrap();
+    assert!(fst.da.output.is_empty() && !fst.da.has_outputs());
+    assert_eq!(0, fst.memory_usage().parts.iter().find(|p| p.0 == "output").unwrap().1);
+    assert!(keys.iter().all(|k| fst.get(k) == Some(0) && fst.get_checked(k).unwrap() == Some(0)));
+    assert!(fst.iter().map(|(k, _)| k).eq(keys.iter().map(|k| k.as_bytes().to_vec())));
+    assert_eq!(None, fst.get("1000"));
+
+    // Zeros are still written out, and shed again when read.
+    let nonzero : FST<u32, u32> = FST::from_iter(keys.iter().map(|k| (k, 1))).unwrap();
+    assert_eq!(fst.to_bytes().len(), nonzero.to_bytes().len());
+    assert_eq!(FST::from_bytes(&fst.to_bytes()), Ok(fst.clone()));
+    assert_eq!(FST::from_bytes(&fst.to_compressed_bytes()), Ok(fst.clone()));
+    assert!(nonzero.da.has_outputs() && nonzero.get("042") == Some(1));
+}
+
+#[test]
+fn checked_output_conversion() {
+    let fits = vec![("a", 1u64), ("b", 65535)];
+    let b = fst::Builder::<u32, u16>::from_iter_converting(fits).unwrap();
+    let fst : FST<u32, u16> = b.into_fst().unwrap();
+    assert_eq!(Some(65535), fst.get("b"));
+
+    let overflows = vec![("a", 1u64), ("b", 65536)];
+    match fst::Builder::<u32, u16>::from_iter_converting(overflows) {
+        Err(fst::Error::OutputOverflow { key, value }) => {
+            assert_eq!(b"b".to_vec(), key);
+            assert_eq!("65536", value);
+        },
+        other => panic!("expected an output overflow, found {:?}", other)
+    }
+
+    let wide : FST<u32, u32> = FST::from_iter(vec![("a", 70000), ("ab", 65535), ("b", 3)]).unwrap();
+    match wide.map_output_type::<u16>() {
+        Err(fst::Error::OutputOverflow { key, .. }) => assert_eq!(b"a".to_vec(), key),
+        other => panic!("expected an output overflow, found {:?}", other)
+    }
+    let wide : FST<u32, u32> = FST::from_iter(vec![("a", 65535), ("ab", 65534), ("b", 0)]).unwrap();
+    let narrow : FST<u32, u16> = wide.map_output_type().unwrap();
+    assert_eq!(vec![(b"a".to_vec(), 65535), (b"ab".to_vec(), 65534), (b"b".to_vec(), 0)],
+               narrow.iter().collect::<Vec<_>>());
+
+    // Parts may not fit where the values they add up to do, and are blamed on
+    // the first key holding them: through its path, or as its state output.
+    let fst : FST<u32, i32> = FST::from_iter(vec![("a", 2), ("ab", 1)]).unwrap();
+    let (e, a) = (1 + b'a' as usize, fst.da.next[1 + b'a' as usize]);
+    let b = a as usize + (1 + b'b' as usize);
+    let overflow = |output : i32, state_output : i32, rest : i32| {
+        let mut parts = fst.clone();
+        parts.da.output[e] = output;
+        parts.state_output.insert(a, state_output);
+        parts.da.output[b] = rest;
+        assert!(parts.iter().all(|(_, v)| i8::try_from(v).is_ok()));
+        match parts.map_output_type::<i8>() {
+            Err(fst::Error::OutputOverflow { key, value }) => (key, value),
+            other => panic!("expected an output overflow, found {:?}", other)
+        }
+    };
+    assert_eq!(overflow(200, -201, -202), (b"a".to_vec(), "200".to_string()));
+    assert_eq!(overflow(100, -200, -101), (b"a".to_vec(), "-200".to_string()));
+}
+
+#[test]
+fn ops_union_matches_merged_source() {
+    fn property(x: BTreeMap<Vec<u8>, u16>, y: BTreeMap<Vec<u8>, u16>) -> bool {
+        let a : FST<u32, u32> = FST::from_iter(x.iter().map(|(k, &v)| (k, v as u32))).unwrap();
+        let b : FST<u32, u32> = FST::from_iter(y.iter().map(|(k, &v)| (k, v as u32))).unwrap();
+
+        let mut expected : BTreeMap<Vec<u8>, u32> = x.iter().map(|(k, &v)| (k.clone(), v as u32)).collect();
+        for (k, &v) in &y { *expected.entry(k.clone()).or_insert(0) += v as u32 }
+        fst::ops::union(&a, &b, |p, q| p + q).unwrap().iter().eq(expected)
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, BTreeMap<Vec<u8>, u16>) -> bool);
+}
+
+#[test]
+fn ops_set_algebra_matches_source() {
+    fn check(x: &BTreeMap<Vec<u8>, u16>, y: &BTreeMap<Vec<u8>, u16>) -> bool {
+        let a : FST<u32, u16> = FST::try_from(x).unwrap();
+        let b : FST<u32, u16> = FST::try_from(y).unwrap();
+
+        let only = |p: &BTreeMap<Vec<u8>, u16>, q: &BTreeMap<Vec<u8>, u16>| -> Vec<(Vec<u8>, u16)> {
+            p.iter().filter(|&(k, _)| !q.contains_key(k)).map(|(k, &v)| (k.clone(), v)).collect()
+        };
+        let both : BTreeMap<_, _> = x.iter()
+            .filter_map(|(k, &v)| y.get(k).map(|&w| (k.clone(), v.max(w))))
+            .collect();
+        // Values only in `b` are told apart from those only in `a`.
+        let mut exclusive : BTreeMap<_, _> = only(x, y).into_iter().collect();
+        exclusive.extend(only(y, x).into_iter().map(|(k, v)| (k, v ^ 1)));
+        let tag_absent = |p : Option<u16>, q : Option<u16>| p.unwrap_or_else(|| q.unwrap() ^ 1);
+
+        fst::ops::difference(&a, &b).unwrap().iter().eq(only(x, y))
+            && fst::ops::intersection(&a, &b, |p, q| p.max(q)).unwrap().iter().eq(both)
+            && fst::ops::symmetric_difference(&a, &b, tag_absent).unwrap().iter().eq(exclusive)
+    }
+
+    fn property(x: BTreeMap<Vec<u8>, u16>, y: BTreeMap<Vec<u8>, u16>) -> bool {
+        let subset : BTreeMap<_, _> = x.iter().step_by(2).map(|(k, &v)| (k.clone(), v)).collect();
+        check(&x, &y) && check(&x, &BTreeMap::new()) && check(&BTreeMap::new(), &y)
+            && check(&x, &subset) && check(&subset, &x)
+    }
+
+    // Each case builds a dozen FSTs.
+    QuickCheck::new().tests(25)
+        .quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, BTreeMap<Vec<u8>, u16>) -> bool);
+
+    let lexicon : BTreeMap<Vec<u8>, u16> =
+        vec![(b"a".to_vec(), 1), (b"ab".to_vec(), 2), (b"b".to_vec(), 3), (b"ba".to_vec(), 4)]
+        .into_iter().collect();
+    let blocked : BTreeMap<Vec<u8>, u16> =
+        vec![(b"ab".to_vec(), 7), (b"ba".to_vec(), 8)].into_iter().collect();
+    assert!(check(&lexicon, &BTreeMap::new()) && check(&BTreeMap::new(), &lexicon));
+    assert!(check(&lexicon, &blocked) && check(&blocked, &lexicon));
+}
+
+#[test]
+fn ops_multiway_union() {
+    fn property(shards: Vec<BTreeMap<Vec<u8>, u16>>) -> bool {
+        // Each shard is built separately, so keep the cases small.
+        let shards : Vec<BTreeMap<_, _>> = shards.into_iter().take(5)
+            .map(|s| s.into_iter().take(20).collect())
+            .collect();
+        let fsts : Vec<FST<u32, u32>> = shards.iter()
+            .map(|s| FST::from_iter(s.iter().map(|(k, &v)| (k, v as u32))).unwrap())
+            .collect();
+
+        let mut expected : BTreeMap<Vec<u8>, Vec<(usize, u32)>> = BTreeMap::new();
+        for (i, shard) in shards.iter().enumerate() {
+            for (k, &v) in shard { expected.entry(k.clone()).or_default().push((i, v as u32)) }
+        }
+        let summed : BTreeMap<_, _> =
+            expected.iter().map(|(k, vs)| (k.clone(), vs.iter().map(|v| v.1).sum::<u32>())).collect();
+
+        let streamed = fsts.iter().collect::<fst::ops::OpBuilder<_, _>>().union();
+        let built = fsts.iter().collect::<fst::ops::OpBuilder<_, _>>().build(|x, y| x + y).unwrap();
+        streamed.eq(expected) && built.iter().eq(summed)
+    }
+
+    QuickCheck::new().tests(25).quickcheck(property as fn(Vec<BTreeMap<Vec<u8>, u16>>) -> bool);
+
+    let a : FST<u32, u32> = FST::from_iter(vec![("a", 1), ("c", 3)]).unwrap();
+    let b : FST<u32, u32> = FST::from_iter(vec![("b", 2), ("d", 4)]).unwrap();
+    let disjoint = fst::ops::OpBuilder::new().push(&a).push(&b).build(|x, y| x + y).unwrap();
+    let keys : Vec<_> = disjoint.iter().map(|(k, _)| k).collect();
+    assert_eq!(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()], keys);
+}
+
+#[test]
+fn set_algebra_matches_btree_set() {
+    use std::collections::BTreeSet;
+
+    fn property(x : BTreeSet<Vec<u8>>, y : BTreeSet<Vec<u8>>) -> bool {
+        let a : Set<u32> = Set::from_iter(&x).unwrap();
+        let b : Set<u32> = Set::from_iter(&y).unwrap();
+
+        x.iter().all(|k| a.contains(k)) && a.len() == x.len()
+            && a.iter().eq(x.iter().cloned())
+            && a.union(&b).unwrap().iter().eq(x.union(&y).cloned())
+            && a.intersection(&b).unwrap().iter().eq(x.intersection(&y).cloned())
+            && a.difference(&b).unwrap().iter().eq(x.difference(&y).cloned())
+            && a.symmetric_difference(&b).unwrap().iter().eq(x.symmetric_difference(&y).cloned())
+    }
+
+    QuickCheck::new().tests(25)
+        .quickcheck(property as fn(BTreeSet<Vec<u8>>, BTreeSet<Vec<u8>>) -> bool);
+
+    let set : Set<u32> = Set::from_iter(vec!["a", "ab", "b"]).unwrap();
+    assert_eq!(0, set.as_fst().memory_usage().parts.iter().find(|p| p.0 == "output").unwrap().1);
+}
+
+#[test]
+fn fst_from_keys_assigns_ordinals() {
+    let keys = vec!["apple", "banana", "cherry"];
+    let fst : FST<u32, u32> = FST::from_keys(&keys).unwrap();
+    let ordinals : Vec<_> = keys.iter().map(|k| fst.get(k)).collect();
+    assert_eq!(vec![Some(0), Some(1), Some(2)], ordinals);
+
+    let many : Vec<Vec<u8>> = (0 .. 300u16).map(|n| n.to_be_bytes().to_vec()).collect();
+    match Builder::<u32, u8>::from_keys(&many) {
+        Err(Error::OutputOverflow { key, value }) => {
+            assert_eq!(many[256], key);
+            assert_eq!("256", value);
+        }
+        other => panic!("expected an overflow, got {:?}", other.map(|b| b.len()))
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn builder_parallel_matches_sequential() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>) -> bool {
+        let pairs : Vec<(Vec<u8>, u16)> = pairs.into_iter().collect();
+        let sequential : Builder<u32, u16> = Builder::from_iter(pairs.clone()).unwrap();
+        let parallel : Builder<u32, u16> = Builder::par_from_iter(pairs.clone()).unwrap();
+        let stats = parallel.stats();
+        let fst = parallel.into_fst().unwrap();
+
+        sequential.stats() == stats
+            && pairs.iter().all(|&(ref k, v)| fst.get(k) == Some(v))
+            && fst.iter().eq(pairs)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    let unordered = vec![(vec![2u8], 0u16), (vec![1], 1)];
+    assert!(Builder::<u32, u16>::par_from_iter(unordered).is_err());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn parallel_lookups_keep_key_order() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>, probes : Vec<Vec<u8>>) -> bool {
+        let fst : FST<u32, u32> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, u32::from(v)))).unwrap();
+        let keys : Vec<Vec<u8>> = probes.into_iter().chain(pairs.into_keys()).rev().collect();
+
+        fst.par_get_many(&keys) == keys.iter().map(|k| fst.get(k)).collect::<Vec<_>>()
+            && fst.par_contains_many(&keys) == keys.iter().map(|k| fst.contains(k)).collect::<Vec<_>>()
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, Vec<Vec<u8>>) -> bool);
+
+    let keys : Vec<[u8; 4]> = (0 .. 20_000u32).map(|i| i.to_be_bytes()).collect();
+    let fst : FST<u32, u32> = FST::from_iter(keys.iter().zip(0 ..)).unwrap();
+    let probes : Vec<[u8; 4]> = (0 .. 40_000u32).rev().map(|i| i.to_be_bytes()).collect();
+    let expected : Vec<Option<u32>> = (0 .. 40_000u32).rev().map(|i| Some(i).filter(|&i| i < 20_000)).collect();
+    assert_eq!(expected, fst.par_get_many(&probes));
+}
+
+#[test]
+fn pipelined_lookups_match_get() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>, probes : Vec<Vec<u8>>) -> bool {
+        let fst : FST<u32, u32> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, u32::from(v)))).unwrap();
+        let keys : Vec<Vec<u8>> = probes.into_iter().chain(pairs.into_keys()).collect();
+
+        keys.chunks(8).all(|batch| {
+            let batch : Vec<&[u8]> = (0 .. 8).map(|i| batch.get(i).map_or(&[][..], |k| k.as_slice())).collect();
+            let batch : [&[u8]; 8] = std::convert::TryInto::try_into(batch).unwrap();
+            fst.get_pipelined(batch) == batch.map(|k| fst.get(k))
+        })
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, Vec<Vec<u8>>) -> bool);
+
+    let fst : FST<u32, u32> = FST::from_iter(vec![(&b""[..], 1), (b"ab", 2), (b"abcd", 3)]).unwrap();
+    assert_eq!([Some(1), None, Some(2), None, Some(3)], fst.get_pipelined([b"", b"a", b"ab", b"abd", b"abcd"]));
+    assert_eq!([None, None], FST::<u32, u32>::default().get_pipelined([b"", b"a"]));
+    assert_eq!([None; 0], fst.get_pipelined::<0>([]));
+}
+
+#[test]
+fn fsts_are_send_and_sync() {
+    fn shared<T : Send + Sync>() {}
+
+    shared::<FST<u32, u32>>();
+    shared::<FST<usize, u64, u16>>();
+    shared::<FrozenFst<u32, u32>>();
+}
+
+#[test]
+fn builder_without_minimization_is_a_trie() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>) -> bool {
+        let mut builder : Builder<u32, u16> = Builder::new().minimize(false);
+        builder.extend(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+        builder.finish().unwrap();
+
+        let prefixes : std::collections::BTreeSet<&[u8]> =
+            pairs.keys().flat_map(|k| (1 ..= k.len()).map(move |n| &k[.. n])).collect();
+        let states = builder.stats().states;
+        let fst = builder.into_fst().unwrap();
+
+        states == 1 + prefixes.len() && fst.iter().eq(pairs)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+}
+
+#[test]
+fn fst_merge_insert_matches_source() {
+    fn property(base : BTreeMap<Vec<u8>, u16>, delta : BTreeMap<Vec<u8>, u16>) -> bool {
+        let fst : FST<u32, u32> = FST::from_iter(base.iter().map(|(k, &v)| (k, v as u32))).unwrap();
+        let delta : Vec<_> = delta.into_iter().map(|(k, v)| (k, v as u32)).collect();
+
+        let mut expected : BTreeMap<_, _> = base.iter().map(|(k, &v)| (k.clone(), v as u32)).collect();
+        for &(ref k, v) in &delta { *expected.entry(k.clone()).or_insert(0) += v }
+
+        let disjoint = delta.iter().all(|(k, _)| !base.contains_key(k));
+        let merged = fst.merge_insert(delta.clone());
+        let combined = fst.merge_insert_with(delta, |x, y| x + y).unwrap();
+
+        merged.is_ok() == disjoint && combined.iter().eq(expected)
+    }
+
+    QuickCheck::new().tests(25)
+        .quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, BTreeMap<Vec<u8>, u16>) -> bool);
+
+    let fst : FST<u32, u32> = FST::from_iter(vec![("a", 1), ("c", 3)]).unwrap();
+    match fst.merge_insert(vec![("b", 2), ("c", 4)]) {
+        Err(Error::Duplicate { key, index, existing_hint, inserted_hint }) =>
+            assert_eq!((b"c".to_vec(), 2, Some("3"), Some("4")), (key, index, existing_hint.as_deref(), inserted_hint.as_deref())),
+        other => panic!("expected a duplicate, got {:?}", other.map(|f| f.to_btree_map()))
+    }
+}
+
+#[test]
+fn fst_without_matches_source() {
+    fn property(base : BTreeMap<Vec<u8>, u16>, removed : std::collections::BTreeSet<Vec<u8>>) -> bool {
+        let fst : FST<u32, u16> = FST::from_iter(base.iter().map(|(k, &v)| (k, v))).unwrap();
+        let removal = fst.without(&removed).unwrap();
+
+        let expected = base.iter().filter(|(k, _)| !removed.contains(*k)).map(|(k, &v)| (k.clone(), v));
+        let absent = removed.iter().filter(|k| !base.contains_key(*k)).cloned();
+        removal.fst.iter().eq(expected) && removal.absent.into_iter().eq(absent)
+    }
+
+    QuickCheck::new().tests(25)
+        .quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, std::collections::BTreeSet<Vec<u8>>) -> bool);
+
+    let fst : FST<u32, u16> = FST::from_iter(vec![("", 1), ("a", 2), ("ab", 3)]).unwrap();
+    let rootless = fst.without_strict(vec![""]).unwrap();
+    assert!(!rootless.contains(""));
+    assert_eq!(Some(3), rootless.get("ab"));
+
+    let emptied = fst.without_strict(vec!["", "a", "ab"]).unwrap();
+    assert_eq!(0, emptied.iter().count());
+    assert!(!emptied.contains("") && emptied.get("a").is_none());
+
+    match fst.without_strict(vec!["a", "b"]) {
+        Err(Error::Absent(key)) => assert_eq!(b"b".to_vec(), key),
+        other => panic!("expected an absent key, got {:?}", other.map(|f| f.to_btree_map()))
+    }
+    assert!(fst.without(vec!["b", "a"]).is_err());
+}
+
+#[test]
+fn fst_map_values() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>) -> bool {
+        let mut fst : FST<u32, u32> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, v as u32))).unwrap();
+        let mapped : FST<u32, u64> = fst.map_values(|k, v| (k.len() as u64) << 32 | v as u64).unwrap();
+        let expected = pairs.iter().map(|(k, &v)| (k.clone(), (k.len() as u64) << 32 | v as u64));
+
+        fst.map_values_in_place(|v| v * 3);
+        let scaled = pairs.iter().map(|(k, &v)| (k.clone(), v as u32 * 3));
+        mapped.iter().eq(expected) && fst.iter().eq(scaled)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "does not distribute")]
+fn fst_map_values_in_place_samples_every_key_range() {
+    // Only the last keys carry values which `f` garbles.
+    let mut fst : FST<u32, u32> = FST::from_keys((0 .. 1024u16).map(|n| n.to_be_bytes())).unwrap();
+    fst.map_values_in_place(|v| if v >= 1000 { v + 1 } else { v });
+}
+
+#[test]
+fn fst_retain_matches_filtered_source() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>, threshold : u16) -> bool {
+        let fst : FST<u32, u16> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+        let kept = fst.retain(|k, v| v >= threshold && k.first() != Some(&0)).unwrap();
+        let expected = pairs.into_iter().filter(|(k, v)| *v >= threshold && k.first() != Some(&0));
+
+        kept.iter().eq(expected)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, u16) -> bool);
+
+    let error = Error::Filtered { seen : 5, kept : 3, error : Box::new(Error::Duplicate { key : b"k".to_vec(), index : 0, existing_hint : None, inserted_hint : None }) };
+    assert!(format!("{}", error).contains("after keeping 3 of 5 entries"));
+    assert!(std::error::Error::source(&error).is_some());
+}
+
+#[test]
+fn builder_rejects_use_after_finish() {
+    let mut builder : Builder<u32, u32> = Builder::new();
+    builder.insert(b"a", 1).unwrap();
+    let root = builder.finish().unwrap();
+
+    assert_eq!(root, builder.finish().unwrap());
+    assert!(matches!(builder.insert(b"b", 2), Err(Error::Finished)));
+    assert!(matches!(builder.insert_unchecked(b"b", 2), Err(Error::Finished)));
+    assert!(matches!(builder.extend(vec![("c", 3)]), Err(Error::Finished)));
+    assert_eq!(Some(1), builder.into_fst().unwrap().get("a"));
+
+    let mut empty : Builder<u32, u32> = Builder::new();
+    empty.finish().unwrap();
+    let fst = empty.into_fst().unwrap();
+    assert_eq!(0, fst.iter().count());
+    assert!(!fst.contains("") && fst.get("a").is_none());
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn builder_resumes_from_checkpoint() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>) -> bool {
+        let pairs : Vec<(Vec<u8>, u16)> = pairs.into_iter().collect();
+        let uninterrupted : FST<u32, u16> = FST::from_iter(pairs.clone()).unwrap();
+
+        let mut checkpoint = Vec::new();
+        {
+            let mut builder : Builder<u32, u16> = Builder::new();
+            builder.extend(pairs[.. pairs.len() / 2].iter().cloned()).unwrap();
+            builder.checkpoint(&mut checkpoint).unwrap();
+        }
+        let mut resumed : Builder<u32, u16> = Builder::resume(checkpoint.as_slice()).unwrap();
+        let consumed = resumed.len();
+        resumed.extend(pairs[consumed ..].iter().cloned()).unwrap();
+        resumed.finish().unwrap();
+
+        resumed.into_fst().unwrap() == uninterrupted
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    match Builder::<u32, u16>::resume(&b"garbage"[..]) {
+        Err(e @ Error::Serialization(_)) => assert!(std::error::Error::source(&e).is_some()),
+        other => panic!("expected a serialization error, found {:?}", other.map(|b| b.len()))
+    }
+}
+
+#[cfg(feature = "io")]
+#[test]
+fn builder_from_delimited_text() {
+    use std::io::{BufReader, Write};
+
+    let path = std::env::temp_dir().join(format!("atlatl-{}.tsv", std::process::id()));
+    {
+        let mut file = std::fs::File::create(&path).unwrap();
+        write!(file, "apple\t1\r\nbanana\t2\n\ncherry\t3\n").unwrap();
+    }
+    let file = std::fs::File::open(&path).unwrap();
+    let fst = Builder::<u32, u32>::from_tsv(BufReader::new(file), str::parse).unwrap().into_fst().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let expected = [("apple", 1), ("banana", 2), ("cherry", 3)];
+    assert!(fst.iter().eq(expected.iter().map(|&(k, v)| (k.as_bytes().to_vec(), v))));
+
+    let parse = |text : &str, format| Builder::<u32, u32>::from_delimited(text.as_bytes(), format, str::parse);
+    let keyed = parse("a,b,1\nc,2", Delimited::csv().split(Split::Last)).unwrap().into_fst().unwrap();
+    assert_eq!((Some(1), Some(2)), (keyed.get("a,b"), keyed.get("c")));
+
+    assert!(matches!(parse("a\t1\nb\tx\n", Delimited::tsv()), Err(Error::Parse { line : 2, .. })));
+    assert!(matches!(parse("a\t1\nb\n", Delimited::tsv()), Err(Error::Parse { line : 2, .. })));
+    assert!(matches!(parse("a\t1\n\nb\t2", Delimited::tsv().skip_empty_lines(false)),
+                     Err(Error::Parse { line : 2, .. })));
+    assert!(matches!(parse("b\t1\na\t2", Delimited::tsv()), Err(Error::OutOfOrder { index : 1, .. })));
+}
+
+#[test]
+fn reversed_fst_matches_suffix_scan() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>, queries : Vec<Vec<u8>>) -> bool {
+        // Labels are kept off NUL, which walks can mistake for a vacant slot.
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let pairs : BTreeMap<Vec<u8>, u16> = pairs.iter().map(|(k, &v)| (narrow(k), v)).collect();
+        let rev : ReversedFst<u32, u16> = ReversedFst::from_iter(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+
+        let mut queries : Vec<Vec<u8>> = queries.iter().map(narrow).collect();
+        queries.extend(pairs.keys().map(|k| k[k.len() / 2 ..].to_vec()));
+        pairs.iter().all(|(k, &v)| rev.get(k) == Some(v)) && queries.iter().all(|q| {
+            let mut ending : Vec<_> = pairs.iter().filter(|(k, _)| k.ends_with(q))
+                .map(|(k, &v)| (k.clone(), v)).collect();
+            let longest = pairs.iter().filter(|(k, _)| q.ends_with(k)).map(|(k, &v)| (k.len(), v)).max();
+            let mut found : Vec<_> = rev.iter_suffixes(q).collect();
+            ending.sort();
+            found.sort();
+
+            rev.contains_suffix(q) != ending.is_empty() && found == ending && rev.get_suffix(q) == longest
+        })
+    }
+
+    QuickCheck::new().tests(50)
+        .quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, Vec<Vec<u8>>) -> bool);
+}
+
+#[test]
+fn fst_shards_round_trip() {
+    fn property(pairs : BTreeMap<Vec<u8>, u16>, boundaries : std::collections::BTreeSet<Vec<u8>>) -> bool {
+        let fst : FST<u32, u16> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+        // Include an existing key among the boundaries, when there is one.
+        let mut boundaries : std::collections::BTreeSet<Vec<u8>> = boundaries.into_iter().take(4).collect();
+        boundaries.extend(pairs.keys().nth(pairs.len() / 2).cloned());
+        let boundaries : Vec<&[u8]> = boundaries.iter().map(|b| b.as_slice()).collect();
+
+        let shards = fst.shard_by_prefix(&boundaries).unwrap();
+        let within = shards.iter().enumerate().all(|(i, shard)| shard.iter().all(|(k, _)| {
+            (i == 0 || boundaries[i - 1] <= k.as_slice())
+                && (i == boundaries.len() || k.as_slice() < boundaries[i])
+        }));
+
+        shards.len() == boundaries.len() + 1 && within && FST::from_shards(&shards).unwrap() == fst
+    }
+
+    QuickCheck::new().tests(25)
+        .quickcheck(property as fn(BTreeMap<Vec<u8>, u16>, std::collections::BTreeSet<Vec<u8>>) -> bool);
+
+    let fst : FST<u32, u16> = FST::from_iter(vec![("a", 1), ("b", 2), ("c", 3)]).unwrap();
+    let shards = fst.shard_by_prefix(&[b"", b"b", b"bb", b"z"]).unwrap();
+    let sizes : Vec<_> = shards.iter().map(|s| s.iter().count()).collect();
+    assert_eq!(vec![0, 1, 1, 1, 0], sizes);
+
+    assert!(fst.shard_by_prefix(&[b"b", b"a"]).is_err());
+    assert!(FST::from_shards(vec![&shards[3], &shards[1]]).is_err());
+}
+
+#[test]
+fn builder_from_fallible_iter() {
+    use std::error::Error as StdError;
+    use std::io;
+
+    let ok : Vec<io::Result<(&str, u32)>> = vec![Ok(("a", 1)), Ok(("b", 2))];
+    let fst = Builder::<u32, u32>::try_from_fallible_iter(ok).unwrap().into_fst().unwrap();
+    assert_eq!(Some(2), fst.get("b"));
+
+    let broken = vec![Ok(("a", 1)), Err(io::Error::other("truncated")), Ok(("c", 3))];
+    let error = Builder::<u32, u32>::try_from_fallible_iter(broken).unwrap_err();
+    assert!(matches!(error, BuildOrSourceError::Source { consumed : 1, .. }));
+    assert_eq!("truncated", error.source().unwrap().to_string());
+
+    let unordered : Vec<io::Result<(&str, u32)>> = vec![Ok(("b", 1)), Ok(("a", 2))];
+    let error = Builder::<u32, u32>::try_from_fallible_iter(unordered).unwrap_err();
+    assert!(matches!(error, BuildOrSourceError::Build { consumed : 2, error : Error::OutOfOrder { index : 1, .. } }));
+    assert_eq!(2, error.consumed());
+}
+
+#[test]
+fn builder_rejects_overlong_keys() {
+    let mut builder : Builder<u32, u32> = Builder::new().max_key_length(4);
+    builder.insert(b"ab", 1).unwrap();
+    match builder.insert(&[b'z'; 100], 2) {
+        Err(Error::KeyTooLong { length, limit, preview }) => {
+            assert_eq!((100, 4), (length, limit));
+            assert!(preview.len() < length && preview.iter().all(|&b| b == b'z'));
+        },
+        other => panic!("expected an overlong key, got {:?}", other)
+    }
+    assert!(builder.insert_unchecked(b"abcdef", 3).is_err());
+
+    // The rejected key left no trace: "abc" still follows "ab".
+    builder.insert(b"abc", 4).unwrap();
+    builder.insert(b"abcd", 5).unwrap();
+    builder.finish().unwrap();
+    let fst = builder.into_fst().unwrap();
+    let expected : Vec<(Vec<u8>, u32)> = vec![(b"ab".to_vec(), 1), (b"abc".to_vec(), 4), (b"abcd".to_vec(), 5)];
+    assert!(fst.iter().eq(expected));
+}
+
+#[test]
+fn fst_layout_is_reproducible() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>) -> bool {
+        let builder : Builder<u32, u16> = Builder::from_iter(btree.clone()).unwrap();
+        let borrowed = FST::from_builder(&builder).unwrap();
+        let owned = builder.into_fst().unwrap();
+        let rebuilt : FST<u32, u16> = FST::from_iter(btree).unwrap();
+
+        borrowed.da == owned.da && owned.da == rebuilt.da && owned.state_output == rebuilt.state_output
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    #[cfg(feature = "serialization")]
+    {
+        let keys : Vec<String> = (0 .. 2000u32).map(|n| format!("{:x}", n * 7919)).collect();
+        let mut keys : Vec<_> = keys.iter().map(|k| (k.clone(), k.len() as u16)).collect();
+        keys.sort();
+        let first : FST<u32, u16> = FST::from_iter(keys.clone()).unwrap();
+        let second : FST<u32, u16> = FST::from_iter(keys).unwrap();
+        assert_eq!(bincode::serialize(&first).unwrap(), bincode::serialize(&second).unwrap());
+    }
+}
+
+#[test]
+fn builder_insert_all_matches_extend() {
+    fn check(pairs : &[(Vec<u8>, u16)], policy : DuplicatePolicy<u16>) -> bool {
+        let mut looped : Builder<u32, u16> = Builder::new().duplicate_policy(policy);
+        let mut bulk : Builder<u32, u16> = Builder::new().duplicate_policy(policy);
+        let expected = looped.extend(pairs.iter().cloned());
+        let actual = bulk.insert_all(pairs);
+        if format!("{:?}", expected) != format!("{:?}", actual) { return false }
+
+        // Both builders must also agree on what follows.
+        let next = looped.insert(b"\x05", 1).is_ok();
+        next == bulk.insert(b"\x05", 1).is_ok() && looped.finish().is_ok() && bulk.finish().is_ok()
+            && looped == bulk
+    }
+
+    fn property(pairs : Vec<(Vec<u8>, u16)>, sort : bool) -> bool {
+        let mut pairs : Vec<(Vec<u8>, u16)> = pairs.into_iter()
+            .map(|(k, v)| (k.iter().map(|b| 1 + b % 4).collect(), v))
+            .collect();
+        if sort { pairs.sort_by(|a, b| a.0.cmp(&b.0)) }
+
+        check(&pairs, DuplicatePolicy::Error)
+            && check(&pairs, DuplicatePolicy::KeepLast)
+            && check(&pairs, DuplicatePolicy::Merge(|a, b| a.wrapping_add(b)))
+    }
+
+    QuickCheck::new().tests(200).quickcheck(property as fn(Vec<(Vec<u8>, u16)>, bool) -> bool);
+}
+
+#[test]
+fn fst_over_wide_labels() {
+    fn property(btree : BTreeMap<Vec<u16>, u32>) -> bool {
+        let widen = |k : &Vec<u16>| -> Vec<u16> { k.iter().take(4).map(|l| 1 + l % 40000).collect() };
+        let source : BTreeMap<Vec<u16>, u32> = btree.iter().map(|(k, &v)| (widen(k), v)).collect();
+        let fst : FST<u32, u32, u16> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+
+        source.iter().all(|(k, &v)| fst.get(k) == Some(v))
+            && !fst.contains([40001u16])
+            && fst.iter().eq(source)
+    }
+
+    QuickCheck::new().tests(20).quickcheck(property as fn(BTreeMap<Vec<u16>, u32>) -> bool);
+
+    // Keys in errors are spelled out in big-endian bytes.
+    let mut builder : Builder<u32, u32, u16> = Builder::new();
+    builder.insert(&[0x0102], 1).unwrap();
+    assert_eq!(builder.insert(&[0x0101], 2), Err(Error::OutOfOrder { key : vec![1, 1], previous : vec![1, 2], index : 1 }));
+}
+
+#[test]
+fn builder_dedups_identical_pairs() {
+    let mut builder : Builder<u32, u16> = Builder::new().dedup_identical(true);
+    builder.extend(vec![("a", 1), ("a", 1), ("ab", 0), ("ab", 0), ("ab", 0), ("b", 3)]).unwrap();
+    assert_eq!(builder.len(), 3);
+
+    // A different value is still a duplicate.
+    match builder.insert(b"b", 4) {
+        Err(Error::Duplicate { key, index, existing_hint, .. }) => assert_eq!((key, index, existing_hint), (b"b".to_vec(), 6, Some("3".to_string()))),
+        other => panic!("expected a duplicate, got {:?}", other)
+    }
+    builder.insert(b"b", 3).unwrap();
+    builder.finish().unwrap();
+    assert_eq!(builder.len(), 3);
+
+    let fst = builder.into_fst().unwrap();
+    let expected : Vec<(Vec<u8>, u16)> = vec![(b"a".to_vec(), 1), (b"ab".to_vec(), 0), (b"b".to_vec(), 3)];
+    assert!(fst.iter().eq(expected));
+
+    // Without the option, identical pairs are duplicates as any other.
+    let mut strict : Builder<u32, u16> = Builder::new();
+    strict.insert(b"a", 1).unwrap();
+    assert!(matches!(strict.insert(b"a", 1), Err(Error::Duplicate { index : 1, .. })));
+}
+
+#[test]
+fn fst_minimality() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>) -> bool {
+        let fst : FST<u32, u16> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        fst.is_minimal()
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    // Without minimization, the common suffixes "-ing" are each kept apart.
+    let keys = vec![("bring", 0), ("sing", 0), ("wing", 0)];
+    let mut trie : Builder<u32, u16> = Builder::new().minimize(false);
+    trie.extend(keys.clone()).unwrap();
+    trie.finish().unwrap();
+    let report = analyze::minimality_report(&trie.into_fst().unwrap());
+    assert_eq!((report.states, report.redundant), (14, 8));
+    assert!(!report.is_minimal() && !report.examples.is_empty());
+
+    let minimal : FST<u32, u16> = FST::from_iter(keys).unwrap();
+    let report = analyze::minimality_report(&minimal);
+    assert!(report.is_minimal() && report.states == 6);
+}
+
+#[test]
+fn fst_validation() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>) -> bool {
+        let fst : FST<u32, u16> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let trie : FST<u16, u16> = match FST::from_iter(btree.iter().map(|(k, &v)| (k, v))) {
+            Ok(trie) => trie,
+            Err(_) => return true
+        };
+        fst.validate().is_ok() && trie.validate().is_ok()
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+    assert_eq!(FST::<u32, u16>::default().validate(), Ok(()));
+
+    let fst : FST<u32, u16> = FST::from_iter(vec![("a", 3), ("ab", 1), ("b", 2)]).unwrap();
+    let state = fst.state_output.states().next().unwrap();
+    let e = (1 .. fst.len()).find(|&e| fst.da.next[e] == state).unwrap();
+    let len = fst.len();
+
+    let mut far = fst.clone();
+    far.da.next[e] = len as u32 + 5;
+    let violations = far.validate().unwrap_err().violations;
+    assert!(violations.contains(&Violation::NextOutOfRange { slot : e, next : len + 5 }));
+    assert!(violations.contains(&Violation::MissingStateOutput { slot : e, state : len + 5 }));
+    assert!(violations.contains(&Violation::OrphanStateOutput { state : state as usize }));
+
+    let mut misplaced = fst.clone();
+    misplaced.da.stipe.set_check(e, e as u8);
+    assert_eq!(misplaced.validate().unwrap_err().violations, vec![Violation::CheckOutOfPlace { slot : e, check : e }]);
+
+    let mut orphan = fst.clone();
+    orphan.state_output.insert(9999, 4);
+    let error = orphan.validate().unwrap_err();
+    assert_eq!((error.violations, error.total), (vec![Violation::OrphanStateOutput { state : 9999 }], 1));
+
+    // Only the first few violations are kept.
+    let mut orphans = fst.clone();
+    orphans.state_output.extend((10_000 .. 10_020).map(|s| (s, 1)));
+    let error = orphans.validate().unwrap_err();
+    assert_eq!((error.violations.len(), error.total), (8, 20));
+    assert!(error.to_string().starts_with("FST validation error: 20 inconsistencies, the first being:\nstate 10000: "));
+
+    // Unchecked decoding leaves consistency to `validate`.
+    let mut missing = fst.clone();
+    missing.state_output.clear();
+    let bytes = missing.to_bytes();
+    assert!(matches!(FST::<u32, u16>::from_bytes(&bytes), Err(DecodeError::Invalid { section : "stipe", .. })));
+    let unchecked = FST::<u32, u16>::from_bytes_unchecked(&bytes).unwrap();
+    assert_eq!(unchecked.validate().unwrap_err().violations, vec![Violation::MissingStateOutput { slot : e, state : state as usize }]);
+    assert_eq!(FST::<u32, u16>::read_from_unchecked(bytes.as_slice()).ok(), Some(unchecked));
+}
+
+#[test]
+fn fst_validation_finds_cycles() {
+    let fst : FST<u32, u16> = FST::from_iter(vec![("ab", 1), ("b", 2)]).unwrap();
+    let a = fst.da.next[1 + b'a' as usize];
+    let e = a as usize + (1 + b'b' as usize);
+
+    // The transition on `b` from `a` leads back to `a`.
+    let mut cyclic = fst.clone();
+    cyclic.da.next[e] = a;
+    assert_eq!(cyclic.validate().unwrap_err().violations, vec![Violation::Cycle { slot : e }]);
+    assert_eq!(fst.validate(), Ok(()));
+}
+
+#[test]
+fn checked_decoding_refuses_endless_darts() {
+    let fst : FST<u32, u16> = FST::from_iter(vec![("ab", 1), ("b", 2)]).unwrap();
+    let a = fst.da.next[1 + b'a' as usize];
+    let e = a as usize + (1 + b'b' as usize);
+    let mut cyclic = fst.clone();
+    cyclic.da.next[e] = a;
+
+    // The checksums of the encoded FST hold, but iterating it never ends.
+    let bytes = cyclic.to_bytes();
+    let offset = FstRef::<u32, u16>::new_unchecked(&bytes).unwrap().header().table[1].0 + 4 * e;
+    let invalid = DecodeError::Invalid { section : "next", offset };
+    assert_eq!(FST::<u32, u16>::from_bytes(&bytes), Err(invalid.clone()));
+    assert_eq!(FstRef::<u32, u16>::new(&bytes).err(), Some(invalid));
+    assert!(matches!(FST::<u32, u16>::from_bytes(&cyclic.to_compressed_bytes()),
+                     Err(DecodeError::Invalid { section : "next", .. })));
+    assert_eq!(FST::<u32, u16>::from_bytes_unchecked(&bytes).ok(), Some(cyclic));
+
+    // Nor are destinations outside the Dart accepted.
+    let mut far = fst.clone();
+    far.da.next[e] = fst.len() as u32;
+    assert!(matches!(FST::<u32, u16>::from_bytes(&far.to_bytes()), Err(DecodeError::Invalid { section : "next", .. })));
+    assert!(FstRef::<u32, u16>::new(&fst.to_bytes()).is_ok());
+}
+
+#[test]
+fn state_outputs_stay_sorted() {
+    let mut outputs : StateOutputs<u32, u16> = vec![(9, 1), (3, 2), (9, 3)].into_iter().collect();
+    assert_eq!(&[(3, 2), (9, 3)], outputs.as_slice());
+    assert_eq!((Some(3), None), (outputs.get(9), outputs.get(4)));
+
+    assert_eq!(None, outputs.insert(4, 5));
+    assert_eq!(Some(2), outputs.insert(3, 6));
+    outputs.extend(vec![(1, 7), (9, 8)]);
+    assert_eq!(vec![(1, 7), (3, 6), (4, 5), (9, 8)], outputs.iter().collect::<Vec<_>>());
+    assert_eq!(5, outputs[4]);
+
+    // States with inner outputs are found in order.
+    let fst : FST<u32, u16> = FST::from_iter(vec![("a", 3), ("ab", 1), ("abc", 4), ("b", 2)]).unwrap();
+    let states : Vec<_> = fst.state_output.states().collect();
+    assert!(!states.is_empty() && states.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn fst_of_unit_is_an_acceptor() {
+    use std::collections::BTreeSet;
+
+    fn property(keys : BTreeSet<Vec<u8>>, probes : Vec<Vec<u8>>) -> bool {
+        let keys : BTreeSet<Vec<u8>> = keys.iter().map(|k| k.iter().map(|b| 1 + b % 4).collect()).collect();
+        let fst : FST<u32, ()> = FST::from_iter(keys.iter().map(|k| (k, ()))).unwrap();
+
+        // Every final state classifies as `Empty`, without a state output.
+        fst.state_output.is_empty()
+            && fst.da.stipe.iter().all(|s| !s.terminal.is_inner())
+            && keys.iter().all(|k| fst.contains(k))
+            && probes.iter()
+                .map(|p| p.iter().map(|b| 1 + b % 5).collect::<Vec<u8>>())
+                .all(|p| fst.contains(&p) == keys.contains(&p))
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeSet<Vec<u8>>, Vec<Vec<u8>>) -> bool);
+}
+
+#[test]
+fn fst_output_matches_source_128() {
+    fn property(btree : BTreeMap<Vec<u8>, (u64, u64, bool)>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let wide : BTreeMap<Vec<u8>, u128> = btree.iter()
+            .map(|(k, &(hi, lo, _))| (narrow(k), (hi as u128) << 64 | lo as u128))
+            .collect();
+        let signed : BTreeMap<Vec<u8>, i128> = btree.iter()
+            .map(|(k, &(hi, lo, neg))| {
+                let magnitude = ((hi >> 1) as i128) << 64 | lo as i128;
+                (narrow(k), if neg { -magnitude } else { magnitude })
+            })
+            .collect();
+
+        let fst_u : FST<u32, u128> = FST::from_iter(wide.iter().map(|(k, &v)| (k, v))).unwrap();
+        let fst_i : FST<u32, i128> = FST::from_iter(signed.iter().map(|(k, &v)| (k, v))).unwrap();
+        fst_u.iter().eq(wide.clone()) && wide.iter().all(|(k, &v)| fst_u.get(k) == Some(v))
+            && fst_i.iter().eq(signed.clone()) && signed.iter().all(|(k, &v)| fst_i.get(k) == Some(v))
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, (u64, u64, bool)>) -> bool);
+
+    #[cfg(feature = "serialization")]
+    {
+        let fst : FST<u32, u128> = FST::from_iter(vec![("a", u128::MAX), ("ab", 1 << 100), ("b", 3)]).unwrap();
+        let bytes = bincode::serialize(&fst).unwrap();
+        assert_eq!(bincode::deserialize::<FST<u32, u128>>(&bytes).unwrap(), fst);
+    }
+}
+
+#[test]
+fn fst_output_pairs() {
+    fn property(btree : BTreeMap<Vec<u8>, (u32, u16, i8)>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        // Zero out one component now and then.
+        let pairs : BTreeMap<Vec<u8>, (u32, u16)> = btree.iter()
+            .map(|(k, &(x, y, z))| (narrow(k), match z % 3 { 0 => (0, y), 1 => (x, 0), _ => (x, y) }))
+            .collect();
+        let nested : BTreeMap<Vec<u8>, (u32, (u16, i8))> = btree.iter()
+            .map(|(k, &(x, y, z))| (narrow(k), (x, (y, z))))
+            .collect();
+
+        let fst : FST<u32, (u32, u16)> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+        let fst_n : FST<u32, (u32, (u16, i8))> = FST::from_iter(nested.iter().map(|(k, &v)| (k, v))).unwrap();
+        pairs.iter().all(|(k, &v)| fst.get(k) == Some(v)) && fst.iter().eq(pairs.clone())
+            && nested.iter().all(|(k, &v)| fst_n.get(k) == Some(v)) && fst_n.iter().eq(nested.clone())
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, (u32, u16, i8)>) -> bool);
+}
+
+#[test]
+fn fst_float_outputs_are_exact() {
+    use atlatl::fst::output::{F32, F64};
+
+    fn property(btree : BTreeMap<Vec<u8>, (u32, u8)>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        // Non-negative values, with repeats so that some are shared.
+        let source : BTreeMap<Vec<u8>, f32> = btree.iter()
+            .map(|(k, &(bits, pick))| {
+                let x = f32::from_bits(bits).abs();
+                (narrow(k), if x.is_nan() || pick % 2 == 0 { (pick % 4) as f32 / 3.0 } else { x })
+            })
+            .collect();
+
+        let fst : FST<u32, F32> = FST::from_iter(source.iter().map(|(k, &v)| (k, F32::new(v).unwrap()))).unwrap();
+        let fst_64 : FST<u32, F64> = FST::from_iter(source.iter().map(|(k, &v)| (k, F64::new(v as f64 * 0.1).unwrap()))).unwrap();
+        source.iter().all(|(k, &v)| fst.get(k).map(|x| x.get().to_bits()) == Some(v.to_bits()))
+            && source.iter().all(|(k, &v)| fst_64.get(k).map(|x| x.get().to_bits()) == Some((v as f64 * 0.1).to_bits()))
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, (u32, u8)>) -> bool);
+
+    assert!(F32::new(f32::NAN).is_none() && F64::try_from(f64::NAN).is_err());
+    assert_eq!(F32::new(-0.0), F32::new(0.0));
+    assert_eq!(f32::from(F32::new(0.25).unwrap()), 0.25);
+}
+
+#[test]
+fn fst_byte_string_outputs() {
+    use atlatl::fst::output::Bytes;
+
+    fn property(btree : BTreeMap<Vec<u8>, (u8, u8)>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        // Short values over a small alphabet, so that many are prefixes of
+        // one another, and some are empty.
+        let source : BTreeMap<Vec<u8>, Vec<u8>> = btree.iter()
+            .map(|(k, &(seed, len))| {
+                let v = (0 .. len % 6).map(|i| b'a' + (seed >> i) % 2).collect();
+                (narrow(k), v)
+            })
+            .collect();
+
+        let fst : FST<u32, Bytes> = FST::from_iter(source.iter().map(|(k, v)| (k, Bytes::new(v).unwrap()))).unwrap();
+        source.iter().all(|(k, v)| fst.get(k).map(|b| b.as_bytes().to_vec()).as_ref() == Some(v))
+            && fst.iter().map(|(k, b)| (k, b.as_bytes().to_vec())).eq(source.clone())
+    }
+
+    QuickCheck::new().tests(100).quickcheck(property as fn(BTreeMap<Vec<u8>, (u8, u8)>) -> bool);
+
+    let pairs = [("a", "xyz"), ("ab", "x"), ("abc", ""), ("abd", "xy"), ("b", "")];
+    let fst : FST<u32, Bytes<4>> = FST::from_iter(pairs.iter().map(|&(k, v)| (k, Bytes::try_from(v).unwrap()))).unwrap();
+    assert!(pairs.iter().all(|&(k, v)| fst.get(k).map(|b| b.as_bytes() == v.as_bytes()) == Some(true)));
+    assert!(Bytes::<4>::new(b"toolong").is_none());
+    assert_eq!(Bytes::<4>::try_from("ab").unwrap().checked_mappend(Bytes::try_from("cde").unwrap()), None);
+}
+
+#[test]
+fn fst_output_matches_source_arrays() {
+    fn property(btree : BTreeMap<Vec<u8>, (u64, u64)>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, [u8; 16]> = btree.iter()
+            .map(|(k, &(hi, lo))| (narrow(k), ((hi as u128) << 64 | lo as u128).to_be_bytes()))
+            .collect();
+
+        let fst : FST<u32, [u8; 16]> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        source.iter().all(|(k, v)| fst.get(k).as_ref() == Some(v))
+            && fst.iter().eq(source.clone())
+    }
+
+    QuickCheck::new().tests(100).quickcheck(property as fn(BTreeMap<Vec<u8>, (u64, u64)>) -> bool);
+
+    let fst : FST<u32, [u8; 4]> = FST::from_iter(vec![("a", [255, 0, 7, 1]), ("ab", [255, 255, 0, 1])]).unwrap();
+    assert_eq!(fst.get("a"), Some([255, 0, 7, 1]));
+    assert_eq!(fst.get("ab"), Some([255, 255, 0, 1]));
+}
+
+#[test]
+fn payload_map_matches_source() {
+    fn property(btree : BTreeMap<Vec<u8>, String>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, String> = btree.into_iter().map(|(k, v)| (narrow(&k), v)).collect();
+        // Unsorted input, reversed.
+        let pairs : Vec<(Vec<u8>, String)> = source.clone().into_iter().rev().collect();
+        let map : PayloadMap<u32, String> = PayloadMap::from_unsorted(pairs).unwrap();
+
+        map.check().is_ok()
+            && map.len() == source.len()
+            && source.iter().all(|(k, v)| map.get(k) == Some(v))
+            && map.iter().map(|(k, v)| (k, v.clone())).eq(source.clone())
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, String>) -> bool);
+
+    let empty : PayloadMap<u32, String> = PayloadMap::from_iter(Vec::<(&str, String)>::new()).unwrap();
+    assert!(empty.is_empty() && empty.get("a").is_none());
+
+    let fst : FST<u32, u32> = FST::from_iter(vec![("a", 0), ("b", 2)]).unwrap();
+    match PayloadMap::from_parts(fst, vec!["x", "y"]) {
+        Err(Error::OrdinalOutOfRange { key, ordinal : 2, payloads : 2 }) => assert_eq!(key, b"b"),
+        other => panic!("expected an ordinal out of range, got {:?}", other)
+    }
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn payload_map_round_trips() {
+    let map : PayloadMap<u32, String> =
+        PayloadMap::from_iter(vec![("apple", "fruit".to_string()), ("apply", "verb".to_string())]).unwrap();
+    let mut bytes = Vec::new();
+    map.write_to(&mut bytes).unwrap();
+    let read : PayloadMap<u32, String> = PayloadMap::read_from(bytes.as_slice()).unwrap();
+    assert_eq!(read, map);
+    assert_eq!(read.get("apply").map(String::as_str), Some("verb"));
+
+    // A table missing a payload is rejected on reading.
+    let (fst, mut payloads) = map.into_parts();
+    payloads.pop();
+    let mut truncated = Vec::new();
+    bincode::serialize_into(&mut truncated, &(fst, payloads)).unwrap();
+    match PayloadMap::<u32, String>::read_from(truncated.as_slice()) {
+        Err(Error::OrdinalOutOfRange { .. }) => (),
+        other => panic!("expected an ordinal out of range, got {:?}", other)
+    }
+
+    // So it is through serde, whatever the format.
+    let mut json = serde_json::to_value(&read).unwrap();
+    assert_eq!(serde_json::from_value::<PayloadMap<u32, String>>(json.clone()).ok(), Some(read));
+    json["payloads"].as_array_mut().unwrap().pop();
+    let error = serde_json::from_value::<PayloadMap<u32, String>>(json).unwrap_err();
+    assert!(error.to_string().contains("ordinal"));
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn fst_serializes_deterministically() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>) -> bool {
+        let fst : FST<u32, u16> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        // The same state outputs, collected in reverse order.
+        let mut reversed = fst.clone();
+        reversed.state_output = fst.state_output.iter().rev().collect();
+
+        let bytes = bincode::serialize(&fst).unwrap();
+        reversed == fst && bincode::serialize(&reversed).unwrap() == bytes
+            && bincode::deserialize::<FST<u32, u16>>(&bytes).ok() == Some(fst)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn fst_deserializes_state_outputs_as_maps() {
+    let keys = ["a", "ab", "abc", "b", "ba", "bc", "c", "ca"];
+    let fst : FST<u32, u16> = FST::from_iter(keys.iter().zip(&[5, 1, 9, 2, 0, 3, 4, 1]).map(|(k, &v)| (k, v))).unwrap();
+    assert!(fst.state_output.len() > 1);
+
+    // State outputs were once serialized as a map, in order of hashing.
+    let legacy : HashMap<u32, u16> = fst.state_output.iter().collect();
+    let fixture = bincode::serialize(&(&fst.da, &legacy)).unwrap();
+    assert_eq!(bincode::deserialize::<FST<u32, u16>>(&fixture).unwrap(), fst);
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn fst_serde_round_trips_through_json_and_bincode() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>) -> bool {
+        let fst : FST<u32, u16> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let json = serde_json::to_string(&fst).unwrap();
+        let bytes = bincode::serialize(&fst).unwrap();
+        serde_json::from_str::<FST<u32, u16>>(&json).ok().as_ref() == Some(&fst)
+            && bincode::deserialize::<FST<u32, u16>>(&bytes).ok() == Some(fst)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    // Only occupied slots are written out.
+    let fst : FST<u32, u16> = FST::from_iter(vec![("a", 5), ("ab", 1), ("b", 2)]).unwrap();
+    let json : serde_json::Value = serde_json::to_value(&fst).unwrap();
+    assert_eq!(json["da"]["len"], fst.da.stipe.len());
+    assert!(json["da"]["slots"].as_array().unwrap().len() < 8);
+    assert!(json["state_output"].is_array());
+
+    let wide : FST<u32, u16, u16> = FST::from_iter(vec![(vec![1u16, 300], 1u16), (vec![1000], 2)]).unwrap();
+    assert_eq!(serde_json::from_str::<FST<u32, u16, u16>>(&serde_json::to_string(&wide).unwrap()).unwrap(), wide);
+
+    // Slots out of order, or beyond the Dart, are rejected.
+    let mut reordered = json.clone();
+    reordered["da"]["slots"].as_array_mut().unwrap().reverse();
+    assert!(serde_json::from_value::<FST<u32, u16>>(reordered).is_err());
+    let mut shortened = json;
+    shortened["da"]["len"] = 1.into();
+    assert!(serde_json::from_value::<FST<u32, u16>>(shortened).is_err());
+}
+
+#[test]
+fn fst_monoid_outputs() {
+    use atlatl::fst::output::{Max, Min, OutputMonoid, Unshared};
+
+    fn property(btree : BTreeMap<Vec<u8>, (u32, i16)>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, (u32, i16)> = btree.iter().map(|(k, &v)| (narrow(k), v)).collect();
+
+        let min : FST<u32, Min<u32>> = FST::from_iter(source.iter().map(|(k, &(v, _))| (k, Min(v)))).unwrap();
+        let max : FST<u32, Max<i16>> = FST::from_iter(source.iter().map(|(k, &(_, v))| (k, Max(v)))).unwrap();
+        source.iter().all(|(k, &(u, i))| min.get(k) == Some(Min(u)) && max.get(k) == Some(Max(i)))
+            && min.iter().map(|(k, v)| (k, v.0)).eq(source.iter().map(|(k, &(u, _))| (k.clone(), u)))
+    }
+
+    QuickCheck::new().tests(100).quickcheck(property as fn(BTreeMap<Vec<u8>, (u32, i16)>) -> bool);
+
+    // The best of repeated scores, merged under the monoid.
+    let scores = [("a", 3u8), ("a", 1), ("ab", 7), ("ab", 9), ("b", 4)];
+    let mut builder = Builder::new().duplicate_policy(DuplicatePolicy::Merge(Output::mappend));
+    builder.extend(scores.iter().map(|&(k, v)| (k, Min(v)))).unwrap();
+    builder.finish().unwrap();
+    let fst : FST<u32, Min<u8>> = builder.into_fst().unwrap();
+    assert_eq!((fst.get("a"), fst.get("ab"), fst.get("b")), (Some(Min(1)), Some(Min(7)), Some(Min(4))));
+
+    // Any monoid, such as bitwise disjunction.
+    #[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+    struct Flags(u8);
+    impl OutputMonoid for Flags {
+        fn zero() -> Flags { Flags(0) }
+        fn mappend(self, y : Flags) -> Flags { Flags(self.0 | y.0) }
+    }
+    let flags = [("x", 0b011), ("xy", 0b001), ("xz", 0b110), ("y", 0)];
+    let fst : FST<u32, Unshared<Flags>> = FST::from_iter(flags.iter().map(|&(k, v)| (k, Unshared(Flags(v))))).unwrap();
+    assert!(flags.iter().all(|&(k, v)| fst.get(k) == Some(Unshared(Flags(v)))));
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+struct Freq(u32);
+impl_output_delegating!(Freq => u32);
+
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+struct Delta(i16);
+impl_output_delegating!(Delta => i16);
+
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+struct Posting((u32, i16));
+impl_output_delegating!(Posting => (u32, i16));
+
+#[test]
+fn fst_newtype_outputs() {
+    fn property(btree : BTreeMap<Vec<u8>, (u32, i16)>) -> bool {
+        let freq : FST<u32, Freq> = FST::from_iter(btree.iter().map(|(k, &(u, _))| (k, Freq(u)))).unwrap();
+        let delta : FST<u32, Delta> = FST::from_iter(btree.iter().map(|(k, &(_, i))| (k, Delta(i)))).unwrap();
+        let posting : FST<u32, Posting> = FST::from_iter(btree.iter().map(|(k, &v)| (k, Posting(v)))).unwrap();
+        btree.iter().all(|(k, &(u, i))| {
+            freq.get(k) == Some(Freq(u)) && delta.get(k) == Some(Delta(i)) && posting.get(k) == Some(Posting((u, i)))
+        })
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, (u32, i16)>) -> bool);
+
+    // The signed prefix is kept: values of opposite signs share nothing.
+    assert_eq!(Delta(-3).prefix(Delta(5)), Delta(0));
+    assert_eq!(Freq(3).prefix(Freq(5)), Freq(3));
+    assert_eq!(Freq(u32::MAX).checked_mappend(Freq(1)), None);
+}
+
+macro_rules! check_round_trip {
+    ($btree:expr, $($idx:ty, $out:ty);+) => {
+        $({
+            let fst : FST<$idx, $out> = FST::from_iter($btree.iter().map(|(k, &v)| (k, v as $out))).unwrap();
+            let decoded : FST<$idx, $out> = FST::from_bytes(&fst.to_bytes()).unwrap();
+            decoded == fst && $btree.iter().all(|(k, &v)| decoded.get(k) == Some(v as $out))
+        }) && +
+    }
+}
+
+#[test]
+fn fst_bytes_round_trip() {
+    use atlatl::fst::output::F32;
+
+    fn property(btree : BTreeMap<Vec<u8>, u8>) -> bool {
+        check_round_trip!(btree, u16, u8; u16, i64; u32, u16; u32, i8; u32, u128; u64, u32; u64, i16; usize, u64; usize, i32)
+    }
+
+    QuickCheck::new().tests(20).quickcheck(property as fn(BTreeMap<Vec<u8>, u8>) -> bool);
+
+    let pairs = [("a", (1u32, F32::new(0.5).unwrap())), ("ab", (2, F32::new(-3.0).unwrap())), ("b", (0, F32::new(0.0).unwrap()))];
+    let fst : FST<u32, (u32, F32)> = FST::from_iter(pairs.iter().cloned()).unwrap();
+    assert_eq!(FST::from_bytes(&fst.to_bytes()), Ok(fst));
+
+    let wide : FST<u32, u16, u16> = FST::from_iter(vec![(vec![1u16, 300], 1u16), (vec![1000], 2)]).unwrap();
+    assert_eq!(FST::from_bytes(&wide.to_bytes()), Ok(wide));
+
+    let set : FST<u32, ()> = FST::from_iter(vec![("", ()), ("a", ())]).unwrap();
+    assert_eq!(FST::from_bytes(&set.to_bytes()), Ok(set));
+}
+
+/// The error of reading a type of the `expected` width and kind as one of
+/// those `found`.
+fn mismatch(of : &'static str, expected : (usize, Kind), found : (usize, Kind)) -> DecodeError {
+    let tag = |(width, kind)| TypeTag { width, kind };
+    DecodeError::TypeMismatch { of, expected : tag(expected), found : tag(found) }
+}
+
+#[test]
+fn fst_bytes_reject_bad_input() {
+    let fst : FST<u32, u16> = FST::from_iter(vec![("", 3), ("ab", 7), ("ac", 9), ("b", 1)]).unwrap();
+    let bytes = fst.to_bytes();
+
+    for len in 0 .. bytes.len() {
+        match FST::<u32, u16>::from_bytes(&bytes[.. len]) {
+            Err(DecodeError::Truncated { offset, .. }) => assert_eq!(offset, len),
+            other => panic!("expected truncation at {}, got {:?}", len, other)
+        }
+    }
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(FST::<u32, u16>::from_bytes(&trailing), Err(DecodeError::Trailing { offset : bytes.len() }));
+    assert_eq!(FST::<u32, u32>::from_bytes(&bytes), Err(mismatch("output", (4, Kind::Unsigned), (2, Kind::Unsigned))));
+    assert_eq!(FST::<u64, u16>::from_bytes(&bytes), Err(mismatch("index", (8, Kind::Unsigned), (4, Kind::Unsigned))));
+    assert_eq!(FST::<u32, u16>::from_bytes(b"not an FST at all, clearly"), Err(DecodeError::Magic));
+
+    let mut version = bytes.clone();
+    version[4] = 9;
+    assert_eq!(FST::<u32, u16>::from_bytes(&version), Err(DecodeError::UnsupportedVersion { found : 9, supported : 1 ..= format::VERSION }));
+    version[4] = 0;
+    assert_eq!(FST::<u32, u16>::from_bytes(&version), Err(DecodeError::UnsupportedVersion { found : 0, supported : 1 ..= format::VERSION }));
+    assert_eq!(DecodeError::UnsupportedVersion { found : 9, supported : 1 ..= 3 }.to_string(),
+               "FST decoding error: unsupported format version 9; versions 1 to 3 are supported");
+
+    // A finality byte out of range, past the checksums.
+    let mut terminal = bytes.clone();
+    terminal[format::HEADER_WIDTH + 1] = 7;
+    assert_eq!(FST::<u32, u16>::from_bytes_unchecked(&terminal),
+               Err(DecodeError::Invalid { section : "stipe", offset : format::HEADER_WIDTH }));
+
+    // A declared length beyond the buffer.
+    let mut huge = bytes.clone();
+    huge[12 .. 20].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(FST::<u32, u16>::from_bytes(&huge).is_err());
+}
+
+#[test]
+fn fst_bytes_check_type_tags() {
+    use atlatl::fst::output::{F32, Max};
+
+    type Read = fn(&[u8]) -> Result<(), DecodeError>;
+
+    // An FST of each index and output type, and a reader for each.
+    macro_rules! outputs {
+        ($($o:ty),*) => {
+            vec![$((TypeTag::of::<$o>(),
+                    FST::<u32, $o>::from_iter(vec![("a", 1 as $o), ("b", 2 as $o)]).unwrap().to_bytes(),
+                    (|bytes| FST::<u32, $o>::from_bytes(bytes).map(|_| ())) as Read)),*]
+        }
+    }
+    macro_rules! indexes {
+        ($($i:ty),*) => {
+            vec![$((TypeTag::of::<$i>(),
+                    FST::<$i, u16>::from_iter(vec![("a", 1), ("b", 2)]).unwrap().to_bytes(),
+                    (|bytes| FST::<$i, u16>::from_bytes(bytes).map(|_| ())) as Read)),*]
+        }
+    }
+
+    let outputs = outputs!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+    let indexes = indexes!(u16, U24, u32, u64, usize);
+    for (of, types) in [("output", outputs), ("index", indexes)] {
+        for &(found, ref bytes, _) in &types {
+            for &(expected, _, read) in &types {
+                match read(bytes) {
+                    Ok(()) => assert_eq!(expected, found),
+                    Err(e) => assert_eq!(e, DecodeError::TypeMismatch { of, expected, found })
+                }
+            }
+        }
+    }
+
+    // Pointer-sized types are encoded as their 64-bit counterparts.
+    assert_eq!(TypeTag::of::<usize>(), TypeTag::of::<u64>());
+    assert_eq!(TypeTag::of::<isize>(), TypeTag::of::<i64>());
+    assert_eq!(TypeTag::of::<Max<F32>>(), TypeTag { width : 4, kind : Kind::Float });
+    assert_eq!(mismatch("output", (4, Kind::Signed), (4, Kind::Unsigned)).to_string(),
+               "FST decoding error: the output type is 4-byte signed, but the FST was encoded with 4-byte unsigned");
+
+    let bytes = FST::<u32, u16>::from_iter(vec![("a", 1)]).unwrap().to_bytes();
+    let mut unknown = bytes.clone();
+    unknown[113] = 9;
+    assert_eq!(FST::<u32, u16>::from_bytes(&unknown), Err(DecodeError::Invalid { section : "header", offset : 113 }));
+}
+
+#[cfg(feature = "postcard")]
+#[test]
+fn fst_postcard_round_trip() {
+    fn property(btree : BTreeMap<Vec<u8>, u16>) -> bool {
+        let fst : FST<u16, u16> = match FST::from_iter(btree.iter().map(|(k, &v)| (k, v))) {
+            Ok(fst) => fst,
+            Err(_) => return true
+        };
+        let mut buf = vec![0; 1 << 20];
+        let written = fst.to_postcard(&mut buf).unwrap().len();
+        FST::from_postcard(&buf[.. written]).ok() == Some(fst)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    let fst : FST<u16, u16> = FST::from_iter(vec![("", 3), ("ab", 7), ("ac", 9), ("b", 1)]).unwrap();
+    let mut flash = [0; 4096];
+    let written = fst.to_postcard(&mut flash).unwrap().len();
+    assert_eq!(FST::<u16, u16>::from_postcard(&flash[.. written]).ok(), Some(fst.clone()));
+
+    // Failures are reported rather than panicking.
+    assert!(matches!(fst.to_postcard(&mut [0; 16]), Err(Error::Serialization(_))));
+    for len in 0 .. written {
+        assert!(matches!(FST::<u16, u16>::from_postcard(&flash[.. len]), Err(Error::Serialization(_))));
+    }
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn fst_tagged_serde_checks_types() {
+    let fst : FST<u32, u64> = FST::from_iter(vec![("a", 1), ("ab", 1 << 40), ("b", 3)]).unwrap();
+    let bytes = bincode::serialize(&Tagged(fst.clone())).unwrap();
+    assert_eq!(bincode::deserialize::<Tagged<FST<u32, u64>>>(&bytes).unwrap(), Tagged(fst.clone()));
+
+    let narrow = bincode::deserialize::<Tagged<FST<u32, u32>>>(&bytes).unwrap_err();
+    assert_eq!(narrow.to_string(), mismatch("output", (4, Kind::Unsigned), (8, Kind::Unsigned)).to_string());
+    let signed = bincode::deserialize::<Tagged<FST<u32, i64>>>(&bytes).unwrap_err();
+    assert_eq!(signed.to_string(), mismatch("output", (8, Kind::Signed), (8, Kind::Unsigned)).to_string());
+
+    let json = serde_json::to_string(&Tagged(fst.clone())).unwrap();
+    assert_eq!(serde_json::from_str::<Tagged<FST<u32, u64>>>(&json).unwrap(), Tagged(fst));
+    assert!(serde_json::from_str::<Tagged<FST<u16, u64>>>(&json).is_err());
+}
+
+#[test]
+fn fst_bytes_detect_corruption() {
+    let fst : FST<u32, u16> = FST::from_iter(vec![("a", 5), ("ab", 1), ("b", 2)]).unwrap();
+    let bytes = fst.to_bytes();
+    let header = format::Header::decode(&bytes).unwrap();
+    assert!(header.state_outputs > 0);
+
+    let mut offset = format::HEADER_WIDTH;
+    for &(section, width) in &header.sections().unwrap() {
+        for &at in &[offset, offset + width - 1] {
+            let mut corrupt = bytes.clone();
+            corrupt[at] ^= 0x10;
+            let expected = DecodeError::Checksum { section };
+            assert_eq!(FST::<u32, u16>::from_bytes(&corrupt), Err(expected.clone()));
+            assert_eq!(FstRef::<u32, u16>::new(&corrupt).err(), Some(expected.clone()));
+            match FST::<u32, u16>::read_from(corrupt.as_slice()) {
+                Err(Error::Decode(e)) => assert_eq!(e, expected),
+                other => panic!("expected a checksum mismatch, got {:?}", other)
+            }
+            assert!(FstRef::<u32, u16>::new_unchecked(&corrupt).is_ok());
+        }
+        offset += width;
+    }
+
+    assert_eq!(DecodeError::Checksum { section : "next" }.to_string(),
+               "FST decoding error: checksum mismatch in next section");
+    assert_eq!(FST::from_bytes_unchecked(&bytes), Ok(fst.clone()));
+    assert_eq!(FST::<u32, u16>::read_from_unchecked(bytes.as_slice()).ok(), Some(fst));
+}
+
+#[test]
+fn fst_bytes_are_platform_independent() {
+    fn property(btree : BTreeMap<Vec<u8>, u32>) -> bool {
+        let pointer : FST<usize, u32> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let fixed : FST<u64, u32> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let bytes = pointer.to_bytes();
+        bytes == fixed.to_bytes() && bytes[7] == 8 && FST::from_bytes(&bytes) == Ok(pointer)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u32>) -> bool);
+
+    let fst : FST<u64, u16> = FST::from_iter(vec![("a", 1), ("ab", 2), ("b", 3)]).unwrap();
+    let bytes = fst.to_bytes();
+    let narrow = mismatch("index", (4, Kind::Unsigned), (8, Kind::Unsigned));
+    assert_eq!(FST::<u32, u16>::from_bytes(&bytes), Err(narrow.clone()));
+    assert_eq!(FstRef::<u32, u16>::new(&bytes).err(), Some(narrow));
+
+    let mut forged = bytes.clone();
+    forged[7] = 16;
+    let platform = std::mem::size_of::<usize>();
+    let wide = DecodeError::IndexTooWide { width : 16, platform };
+    assert_eq!(FST::<u64, u16>::from_bytes(&forged), Err(wide.clone()));
+    assert_eq!(FST::<u32, u16>::from_bytes(&forged), Err(wide.clone()));
+    assert!(matches!(FST::<u64, u16>::read_from(forged.as_slice()), Err(Error::Decode(DecodeError::IndexTooWide { .. }))));
+    assert_eq!(wide.to_string(), format!("\
+FST decoding error: the index was encoded with 16 bytes, wider than this platform's {}-byte usize", platform));
+}
+
+#[test]
+fn fst_bytes_follow_the_section_table() {
+    let fst : FST<u32, u16> = FST::from_iter(vec![("a", 5), ("ab", 1), ("b", 2)]).unwrap();
+    let bytes = fst.to_bytes();
+    let header = format::Header::decode(&bytes).unwrap();
+    assert_eq!(header.end(), bytes.len());
+
+    // Sections padded apart are found through the table.
+    let (next, _) = header.table[1];
+    let mut padded = bytes[.. next].to_vec();
+    padded.extend_from_slice(&[0xAA; 3]);
+    padded.extend_from_slice(&bytes[next ..]);
+    for i in 1 .. 4 {
+        let at = 44 + 16 * i;
+        let offset = header.table[i].0 as u64 + 3;
+        padded[at .. at + 8].copy_from_slice(&offset.to_le_bytes());
+    }
+    assert_eq!(FST::from_bytes(&padded), Ok(fst.clone()));
+    assert_eq!(FST::<u32, u16>::read_from(padded.as_slice()).ok(), Some(fst.clone()));
+    assert!(FstRef::<u32, u16>::new(&padded).unwrap().iter().eq(fst.iter()));
+    match FST::<u32, u16>::read_from(&padded[.. next + 2]) {
+        Err(Error::Decode(e)) => assert_eq!(e, DecodeError::Truncated { section : "next", offset : next + 2 }),
+        other => panic!("expected truncation, got {:?}", other)
+    }
+
+    // Overlapping sections, and lengths at odds with the counts, are refused.
+    let mut overlapping = bytes.clone();
+    overlapping[60 .. 68].copy_from_slice(&(next as u64 - 1).to_le_bytes());
+    assert_eq!(FST::<u32, u16>::from_bytes(&overlapping), Err(DecodeError::Invalid { section : "header", offset : 60 }));
+    let mut long = bytes.clone();
+    long[84] += 1;
+    assert_eq!(FST::<u32, u16>::from_bytes(&long), Err(DecodeError::Invalid { section : "header", offset : 76 }));
+}
+
+/// Encodings of the same FST, each written by `to_bytes` as the format stood
+/// at its version.
+const FORMAT_FIXTURES : [(u16, &[u8]); 1] = [
+    (1, include_bytes!("fixtures/format-v1.fst"))
+];
+
+#[test]
+fn fst_bytes_of_every_version_load() {
+    use atlatl::fst::migrate;
+
+    let pairs = vec![("", 7u16), ("a", 5), ("ab", 1), ("abc", 9), ("b", 2), ("ba", 300), ("日本", 12)];
+    let fst : FST<u32, u16> = FST::from_iter(pairs).unwrap();
+
+    // Every version has its fixture, the latest being what is written now.
+    assert_eq!(FORMAT_FIXTURES.len(), format::VERSION as usize);
+    assert!(FORMAT_FIXTURES.iter().all(|&(version, _)| migrate::SUPPORTED.contains(&version)));
+    assert_eq!(FORMAT_FIXTURES.last().unwrap().1, fst.to_bytes().as_slice());
+
+    for &(version, bytes) in &FORMAT_FIXTURES {
+        let header = format::Header::decode(bytes).unwrap();
+        assert_eq!((header.version, header.width()), (version, migrate::header_width(version).unwrap()));
+        assert_eq!(FST::from_bytes(bytes), Ok(fst.clone()));
+        assert_eq!(FST::<u32, u16>::read_from(bytes).ok(), Some(fst.clone()));
+        assert!(FstRef::<u32, u16>::new(bytes).unwrap().iter().eq(fst.iter()));
+        assert_eq!(migrate::upgrade::<u32, u16, u8>(bytes), Ok(fst.to_bytes()));
+        assert!(matches!(FST::<u32, u32>::from_bytes(bytes), Err(DecodeError::TypeMismatch { .. })));
+        assert!(matches!(FST::<u32, i16>::from_bytes(bytes), Err(DecodeError::TypeMismatch { .. })));
+
+        for len in 0 .. bytes.len() {
+            match FST::<u32, u16>::from_bytes(&bytes[.. len]) {
+                Err(DecodeError::Truncated { offset, .. }) => assert_eq!(offset, len),
+                other => panic!("expected truncation at {} of version {}, got {:?}", len, version, other)
+            }
+        }
+
+        let mut corrupt = bytes.to_vec();
+        corrupt[header.table[2].0] ^= 1;
+        assert_eq!(FST::<u32, u16>::from_bytes(&corrupt), Err(DecodeError::Checksum { section : "output" }));
+    }
+}
+
+#[test]
+fn fst_ref_matches_owned() {
+    fn property(btree : BTreeMap<Vec<u8>, u32>, probes : Vec<Vec<u8>>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, u32> = btree.iter().map(|(k, &v)| (narrow(k), v)).collect();
+        let fst : FST<u32, u32> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        let bytes = fst.to_bytes();
+        let view : FstRef<u32, u32> = FstRef::new(&bytes).unwrap();
+
+        source.iter().all(|(k, &v)| view.get(k) == Some(v) && view.contains(k))
+            && probes.iter().map(narrow).all(|p| view.get(&p) == fst.get(&p) && view.contains(&p) == fst.contains(&p))
+            && (1 ..= 4).all(|l| view.transition(0, l) == fst.transition(0, l))
+            && view.iter().eq(fst.iter())
+            && view.to_fst() == Ok(fst.clone())
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u32>, Vec<Vec<u8>>) -> bool);
+
+    let empty : FST<u32, u32> = FST::default();
+    let bytes = empty.to_bytes();
+    let view : FstRef<u32, u32> = FstRef::new(&bytes).unwrap();
+    assert!(view.is_empty() && view.get("").is_none() && view.iter().next().is_none());
+
+    let sets : FST<u16, (), u16> = FST::from_iter(vec![(vec![], ()), (vec![2, 700], ())]).unwrap();
+    let bytes = sets.to_bytes();
+    let view : FstRef<u16, (), u16> = FstRef::new(&bytes).unwrap();
+    assert!(view.contains([]) && view.contains([2, 700]) && !view.contains([2]));
+    assert_eq!(FstRef::<u32, (), u16>::new(&bytes).unwrap_err(), mismatch("index", (4, Kind::Unsigned), (2, Kind::Unsigned)));
+}
+
+#[test]
+fn stores_are_read_alike_through_their_trait() {
+    type Entries = (Vec<Option<u32>>, Vec<bool>, Vec<(Vec<u8>, u32)>);
+
+    fn entries<S>(store : &S, keys : &[Vec<u8>]) -> Entries
+        where S : DartStore<u32, u32>
+    {
+        let get = keys.iter().map(|k| DartStore::get(store, k)).collect();
+        let contains = keys.iter().map(|k| DartStore::contains(store, k)).collect();
+        (get, contains, DartStore::iter(store).collect())
+    }
+
+    fn property(btree : BTreeMap<Vec<u8>, u32>, probes : Vec<Vec<u8>>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, u32> = btree.iter().map(|(k, &v)| (narrow(k), v)).collect();
+        let fst : FST<u32, u32> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        let bytes = fst.to_bytes();
+        let keys : Vec<Vec<u8>> = source.into_keys().chain(probes.iter().map(narrow)).collect();
+        let owned = entries(&fst, &keys);
+
+        owned.0.iter().zip(&keys).all(|(v, k)| *v == fst.get(k))
+            && owned == entries(&FstRef::<u32, u32>::new(&bytes).unwrap(), &keys)
+            && owned == entries(&fst.freeze(), &keys)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u32>, Vec<Vec<u8>>) -> bool);
+}
+
+#[test]
+fn compact_fst_matches_dart() {
+    fn property(btree : BTreeMap<Vec<u8>, u32>, probes : Vec<Vec<u8>>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, u32> = btree.iter().map(|(k, &v)| (narrow(k), v)).collect();
+        let fst : FST<u32, u32> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        let compact = fst.to_compact();
+        let keys : Vec<Vec<u8>> = source.keys().cloned().chain(probes.iter().map(narrow)).collect();
+
+        compact == CompactFst::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap()
+            && keys.iter().all(|k| compact.get(k) == fst.get(k) && compact.contains(k) == fst.contains(k))
+            && compact.iter().eq(fst.iter())
+            && compact.to_fst::<u32>().unwrap() == fst
+    }
+
+    quickcheck(property as fn(BTreeMap<Vec<u8>, u32>, Vec<Vec<u8>>) -> bool);
+
+    // Wide labels, and outputs of negative values with no zero bytes to cut.
+    fn wide(btree : BTreeMap<Vec<u16>, i64>) -> bool {
+        let compact : CompactFst<i64, u16> = CompactFst::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        btree.iter().all(|(k, &v)| compact.get(k) == Some(v)) && compact.iter().eq(btree)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(wide as fn(BTreeMap<Vec<u16>, i64>) -> bool);
+
+    let empty = CompactFst::<u32>::default();
+    assert!(empty.is_empty() && empty.get([]).is_none() && !empty.contains([]) && empty.iter().next().is_none());
+    let compact = CompactFst::<u32>::from_iter(Vec::<(Vec<u8>, u32)>::new()).unwrap();
+    assert!(compact.get([]).is_none() && compact.iter().next().is_none());
+    let sets = CompactFst::<()>::from_iter(vec![(&b""[..], ()), (b"a", ())]).unwrap();
+    assert_eq!(vec![(vec![], ()), (b"a".to_vec(), ())], sets.iter().collect::<Vec<_>>());
+    let arrays = CompactFst::<[u8; 20]>::from_iter(vec![(&b"a"[..], [7; 20]), (b"ab", [0; 20]), (b"b", [255; 20])]).unwrap();
+    assert_eq!((Some([7; 20]), Some([255; 20]), None), (arrays.get(b"a"), arrays.get(b"b"), arrays.get(b"ac")));
+
+    // Paths are long chains of single transitions, each a pair of bytes.
+    let pairs : BTreeMap<Vec<u8>, u32> = (0 .. 5000u32)
+        .map(|i| (format!("/usr/share/{}/{:x}/item_{}.txt", i % 7, i / 97, i).into_bytes(), i))
+        .collect();
+    let fst : FST<u32, u32> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+    let compact = fst.to_compact();
+    assert!(pairs.iter().all(|(k, &v)| compact.get(k) == Some(v)));
+    assert!(2 * compact.memory_usage().total() < fst.memory_usage().total());
+}
+
+#[test]
+fn fst_frozen_matches_owned() {
+    fn property(btree : BTreeMap<Vec<u8>, u32>, probes : Vec<Vec<u8>>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, u32> = btree.iter().map(|(k, &v)| (narrow(k), v)).collect();
+        let fst : FST<u32, u32> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        let frozen = fst.clone().freeze();
+
+        source.iter().all(|(k, &v)| frozen.get(k) == Some(v) && frozen.contains(k))
+            && probes.iter().map(narrow).all(|p| frozen.get(&p) == fst.get(&p) && frozen.contains(&p) == fst.contains(&p))
+            && (1 ..= 4).all(|l| frozen.transition(0, l) == fst.transition(0, l))
+            && frozen.iter().eq(fst.iter())
+            && frozen.len() == fst.len()
+            && frozen.thaw() == fst
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u32>, Vec<Vec<u8>>) -> bool);
+
+    fn is_send_sync<T : Send + Sync>(_ : &T) {}
+    let pairs : Vec<_> = (0 .. 500u32).map(|i| (format!("{:04}", i), i)).collect();
+    let fst : FST<u32, u32> = FST::from_iter(pairs.clone()).unwrap();
+    let frozen = Arc::new(fst.clone().freeze());
+    is_send_sync(&frozen);
+    assert!(frozen.memory_usage().total() <= fst.memory_usage().total());
+    let workers : Vec<_> = (0 .. 4).map(|w| {
+        let (frozen, pairs) = (frozen.clone(), pairs.clone());
+        std::thread::spawn(move || pairs.iter().skip(w).step_by(4).all(|(k, v)| frozen.get(k) == Some(*v)))
+    }).collect();
+    assert!(workers.into_iter().all(|w| w.join().unwrap()));
+
+    let empty = FST::<u32, u32>::default().freeze();
+    assert!(empty.is_empty() && empty.get("").is_none() && empty.iter().next().is_none());
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn fst_mmap_matches_owned() {
+    fn is_send_sync<T : Send + Sync>(_ : &T) {}
+
+    let pairs : Vec<(String, u32)> = (0 .. 2000u32).map(|i| (format!("{:x}", i * 7919), i)).collect();
+    let mut sorted = pairs.clone();
+    sorted.sort();
+    let fst : FST<u32, u32> = FST::from_iter(sorted.iter().map(|(k, v)| (k, *v))).unwrap();
+
+    let path = std::env::temp_dir().join(format!("atlatl-{}.fst", std::process::id()));
+    std::fs::write(&path, fst.to_bytes()).unwrap();
+    let mapped : MmapFst<u32, u32> = FST::open_mmap(&path).unwrap();
+    is_send_sync(&mapped);
+
+    let mapped = Arc::new(mapped);
+    let workers : Vec<_> = (0 .. 4).map(|w| {
+        let (mapped, pairs) = (mapped.clone(), pairs.clone());
+        std::thread::spawn(move || pairs.iter().skip(w).step_by(4).all(|(k, v)| mapped.get(k) == Some(*v)))
+    }).collect();
+    assert!(workers.into_iter().all(|w| w.join().unwrap()));
+    assert!(mapped.get("zz").is_none() && mapped.view().iter().eq(fst.iter()));
+
+    // A file shorter than its header declares is refused.
+    let bytes = fst.to_bytes();
+    std::fs::write(&path, &bytes[.. bytes.len() - 1]).unwrap();
+    match FST::<u32, u32>::open_mmap(&path) {
+        Err(Error::Decode(DecodeError::Truncated { .. })) => (),
+        other => panic!("expected truncation, got {:?}", other.map(|m| m.header()))
+    }
+    assert!(matches!(FST::<u64, u32>::open_mmap(&path), Err(Error::Decode(DecodeError::TypeMismatch { .. }))));
+
+    // A corrupted file is refused on opening, unless unchecked.
+    let mut corrupt = bytes.clone();
+    corrupt[format::HEADER_WIDTH] ^= 1;
+    std::fs::write(&path, &corrupt).unwrap();
+    assert!(matches!(FST::<u32, u32>::open_mmap(&path),
+                     Err(Error::Decode(DecodeError::Checksum { section : "stipe" }))));
+    assert!(FST::<u32, u32>::open_mmap_unchecked(&path).is_ok());
+
+    // A compressed file cannot be mapped, but can be read.
+    std::fs::write(&path, fst.to_compressed_bytes()).unwrap();
+    assert!(matches!(FST::<u32, u32>::open_mmap(&path), Err(Error::Decode(DecodeError::Compressed))));
+    assert_eq!(FST::<u32, u32>::read_from_path(&path).ok(), Some(fst.clone()));
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(FST::<u32, u32>::open_mmap(&path), Err(Error::Io(ref e)) if e.kind() == std::io::ErrorKind::NotFound));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn fst_sections_load_lazily_or_selectively() {
+    use atlatl::fst::format::Sections;
+
+    // Keys followed by an extension of lesser value end in states which have
+    // state outputs.
+    let pairs : Vec<(String, u32)> = (0 .. 250u32)
+        .flat_map(|i| vec![(format!("{:04x}", i * 97), i + 1), (format!("{:04x}z", i * 97), 0)])
+        .collect();
+    let fst : FST<u32, u32> = FST::from_iter(pairs.iter().map(|(k, v)| (k, *v))).unwrap();
+    let probes : Vec<String> = (0 .. 500u32).map(|i| format!("{:04x}", i * 31)).collect();
+
+    let path = std::env::temp_dir().join(format!("atlatl-lazy-{}.fst", std::process::id()));
+    std::fs::write(&path, fst.to_bytes()).unwrap();
+
+    // Membership reads only the transitions.
+    let lazy : LazyFst<u32, u32> = FST::open_lazy(&path).unwrap();
+    assert_eq!(lazy.loaded(), Sections::NONE);
+    assert!(probes.iter().all(|k| lazy.contains(k).unwrap() == fst.contains(k)));
+    assert_eq!(lazy.loaded(), Sections::STIPE | Sections::NEXT);
+    assert!(probes.iter().chain(pairs.iter().map(|(k, _)| k)).all(|k| lazy.get(k).unwrap() == fst.get(k)));
+    assert_eq!(lazy.loaded(), Sections::ALL);
+
+    let membership : LazyFst<u32, u32> = FST::load_sections(&path, Sections::STIPE | Sections::NEXT).unwrap();
+    assert!(probes.iter().all(|k| membership.contains(k).unwrap() == fst.contains(k)));
+    assert!(matches!(membership.get(&pairs[0].0), Err(Error::Unloaded("output"))));
+    assert_eq!(membership.loaded(), Sections::STIPE | Sections::NEXT);
+    assert_eq!(Error::Unloaded("output").to_string(), "FST lookup error: the output section was not loaded.");
+
+    // Without state outputs, only keys ending in a state which has one fail.
+    let outputs : LazyFst<u32, u32> = FST::load_sections(&path, Sections::STIPE | Sections::NEXT | Sections::OUTPUT).unwrap();
+    let mut unloaded = 0;
+    for (k, v) in &pairs {
+        match outputs.get(k) {
+            Ok(found) => assert_eq!(found, Some(*v)),
+            Err(Error::Unloaded("state_output")) => unloaded += 1,
+            Err(e) => panic!("unexpected error {:?}", e)
+        }
+    }
+    assert!(unloaded > 0 && unloaded < pairs.len());
+
+    // Sections are verified as they are mapped.
+    let mut corrupt = fst.to_bytes();
+    let (output, _) = format::Header::decode(&corrupt).unwrap().table[2];
+    corrupt[output] ^= 1;
+    std::fs::write(&path, &corrupt).unwrap();
+    let lazy : LazyFst<u32, u32> = FST::open_lazy(&path).unwrap();
+    assert!(lazy.contains(&pairs[0].0).unwrap());
+    assert!(matches!(lazy.get(&pairs[0].0), Err(Error::Decode(DecodeError::Checksum { section : "output" }))));
+    assert!(FST::<u32, u32>::load_sections(&path, Sections::OUTPUT).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "rkyv")]
+#[test]
+fn fst_rkyv_archive_matches_owned() {
+    use rkyv::rancor;
+
+    fn property(btree : BTreeMap<Vec<u8>, u32>, probes : Vec<Vec<u8>>) -> bool {
+        let narrow = |k : &Vec<u8>| -> Vec<u8> { k.iter().map(|b| 1 + b % 4).collect() };
+        let source : BTreeMap<Vec<u8>, u32> = btree.iter().map(|(k, &v)| (narrow(k), v)).collect();
+        let fst : FST<u32, u32> = FST::from_iter(source.iter().map(|(k, &v)| (k, v))).unwrap();
+        let bytes = rkyv::to_bytes::<rancor::Error>(&fst).unwrap();
+        let archived = rkyv::access::<ArchivedFST<u32, u32>, rancor::Error>(&bytes).unwrap();
+        source.keys().cloned().chain(probes.iter().map(narrow))
+            .all(|k| archived.get(&k) == fst.get(&k) && archived.contains(&k) == fst.contains(&k))
+            && archived.view().iter().eq(fst.iter())
+            && rkyv::deserialize::<FST<u32, u32>, rancor::Error>(archived).ok() == Some(fst)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u32>, Vec<Vec<u8>>) -> bool);
+
+    // Archives are validated down to the checksums of the encoded FST.
+    let fst : FST<u32, u16> = FST::from_iter(vec![("a", 5), ("ab", 1), ("b", 2)]).unwrap();
+    let bytes = rkyv::to_bytes::<rancor::Error>(&fst).unwrap();
+    let encoded = fst.to_bytes();
+    let at = bytes.windows(encoded.len()).position(|w| w == encoded.as_slice()).unwrap();
+    let mut corrupt = bytes.to_vec();
+    corrupt[at + format::HEADER_WIDTH] ^= 1;
+    let mut aligned = rkyv::util::AlignedVec::<16>::new();
+    aligned.extend_from_slice(&corrupt);
+    assert!(rkyv::access::<ArchivedFST<u32, u16>, rancor::Error>(&aligned).is_err());
+    assert!(rkyv::access::<ArchivedFST<u32, u32>, rancor::Error>(&bytes).is_err());
+
+    // As is its Dart, lest iterating the archive never end.
+    let mut cyclic = fst.clone();
+    let a = cyclic.da.next[1 + b'a' as usize];
+    cyclic.da.next[a as usize + (1 + b'b' as usize)] = a;
+    let bytes = rkyv::to_bytes::<rancor::Error>(&cyclic).unwrap();
+    assert!(rkyv::access::<ArchivedFST<u32, u16>, rancor::Error>(&bytes).is_err());
+}
+
+#[cfg(feature = "fst-interop")]
+#[test]
+fn fst_imports_from_fst_crate() {
+    fn property(btree : BTreeMap<Vec<u8>, u64>) -> bool {
+        let map = fst_crate::Map::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let set = fst_crate::Set::from_iter(btree.keys()).unwrap();
+        let direct : FST<u32, u64> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let imported : FST<u32, u64> = FST::from_fst_map(&map).unwrap();
+        let keys : FST<u32, ()> = FST::from_fst_set(&set).unwrap();
+
+        imported == direct && imported.iter().eq(btree.iter().map(|(k, &v)| (k.clone(), v)))
+            && btree.iter().all(|(k, &v)| imported.get(k) == Some(v) && keys.contains(k))
+            && keys.iter().map(|(k, _)| k).eq(btree.keys().cloned())
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u64>) -> bool);
+
+    let map = fst_crate::Map::from_iter(vec![("a", 7u64), ("ab", 300), ("b", 1)]).unwrap();
+    let narrow : FST<u32, u16> = FST::from_fst_map(&map).unwrap();
+    assert_eq!(narrow.get("ab"), Some(300));
+    match FST::<u32, u8>::from_fst_map(&map) {
+        Err(Error::OutputOverflow { key, value }) => assert_eq!((key.as_slice(), value.as_str()), (&b"ab"[..], "300")),
+        other => panic!("expected an overflow, got {:?}", other)
+    }
+}
+
+#[cfg(feature = "fst-interop")]
+#[test]
+fn fst_exports_to_fst_crate() {
+    use fst_crate::Streamer;
+
+    fn pairs<D : AsRef<[u8]>>(map : &fst_crate::Map<D>) -> Vec<(Vec<u8>, u64)> {
+        let mut pairs = Vec::new();
+        let mut stream = map.stream();
+        while let Some((k, v)) = stream.next() { pairs.push((k.to_vec(), v)) }
+        pairs
+    }
+
+    fn property(btree : BTreeMap<Vec<u8>, u32>) -> bool {
+        let fst : FST<u32, u32> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let map = fst.to_fst_map().unwrap();
+        pairs(&map) == btree.iter().map(|(k, &v)| (k.clone(), v as u64)).collect::<Vec<_>>()
+            && FST::<u32, u32>::from_fst_map(&map).ok() == Some(fst)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u32>) -> bool);
+
+    // The empty key, alone or among others.
+    let only : FST<u32, u16> = FST::from_iter(vec![("", 4)]).unwrap();
+    assert_eq!(pairs(&only.to_fst_map().unwrap()), vec![(vec![], 4)]);
+    let leading : FST<u32, u16> = FST::from_iter(vec![("", 4), ("a", 1), ("ab", 9)]).unwrap();
+    assert_eq!(leading.to_fst_map().unwrap().get(""), Some(4));
+    let empty : FST<u32, u16> = FST::from_iter(Vec::<(&str, u16)>::new()).unwrap();
+    assert!(empty.to_fst_map().unwrap().is_empty());
+
+    let signed : FST<u32, i16> = FST::from_iter(vec![("a", 3), ("b", -2)]).unwrap();
+    match signed.to_fst_map() {
+        Err(Error::OutputOverflow { key, value }) => assert_eq!((key, value), (b"b".to_vec(), "-2".to_string())),
+        other => panic!("expected an overflow, got {:?}", other.map(|m| m.len()))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn fst_arbitrary_builds_valid_fsts() {
+    use arbitrary::{Arbitrary, Unstructured};
+    use atlatl::fst::fuzz::Query;
+
+    fn property(data : Vec<u8>) -> bool {
+        let mut u = Unstructured::new(&data);
+        let fst = match FST::<u32, u16>::arbitrary(&mut u) {
+            Ok(fst) => fst,
+            Err(_) => return true
+        };
+        let queries = Vec::<Query>::arbitrary(&mut u).unwrap_or_default();
+        let entries : Vec<(Vec<u8>, u16)> = fst.iter().collect();
+        let rebuilt : FST<u32, u16> = FST::from_iter(entries.iter().cloned()).unwrap();
+
+        rebuilt == fst
+            && entries.iter().all(|(k, v)| fst.get(k) == Some(*v))
+            && queries.iter().all(|q| match *q {
+                Query::Key(_) => entries.is_empty() || fst.contains(q.key(&fst)),
+                Query::Prefix { len, ref suffix, .. } => {
+                    let key = q.key(&fst);
+                    key.ends_with(suffix) && key.len() <= len.saturating_add(suffix.len())
+                },
+                Query::Bytes(ref bytes) => &q.key(&fst) == bytes
+            })
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(Vec<u8>) -> bool);
+
+    let data : Vec<u8> = (0 .. 4096u32).map(|i| (i % 251) as u8 | 1).collect();
+    let fst = FST::<u32, u16>::arbitrary(&mut Unstructured::new(&data)).unwrap();
+    assert!(fst.iter().count() > 0);
+    assert_eq!(Query::Key(usize::MAX).key(&FST::<u32, u16>::default()), Vec::<u8>::new());
+}
+
+#[test]
+fn darts_dictionaries_convert() {
+    use atlatl::compat::darts;
+
+    // Written by tests/fixtures/darts.py, the states after "w", "x" and at
+    // "y" being shared.
+    let bytes = include_bytes!("fixtures/tiny.darts");
+    let fst : FST<u32, u32> = darts::from_bytes(bytes).unwrap();
+    let mut expected = vec![("東京", 1), ("東京都", 2), ("東", 3), ("京都", 4), ("京都府", 5),
+                            ("a", 6), ("ab", 7), ("abc", 8), ("b", 2147483647), ("ba", 0),
+                            ("wy", 10), ("wyz", 9), ("xy", 10), ("xyz", 9), ("y", 10), ("yz", 9)];
+    expected.sort();
+    assert!(fst.iter().eq(expected.iter().map(|&(k, v)| (k.as_bytes().to_vec(), v))));
+    assert!(!fst.contains("東京府") && !fst.contains("c"));
+
+    let path = std::env::temp_dir().join(format!("atlatl-{}.darts", std::process::id()));
+    std::fs::write(&path, &bytes[..]).unwrap();
+    let read : atlatl::fst::error::Result<FST<u32, u32>> = darts::from_path(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.ok(), Some(fst));
+
+    // Arrays end on a block of 256 units.
+    let mut trailing = bytes.to_vec();
+    trailing.extend_from_slice(&[0; 4]);
+    assert!(matches!(darts::from_bytes::<u32>(&trailing), Err(Error::Decode(DecodeError::Trailing { offset : 1024 }))));
+    assert!(matches!(darts::from_bytes::<u32>(&bytes[.. 1020]), Err(Error::Decode(DecodeError::Truncated { .. }))));
+
+    // A key whose value unit is not a leaf.
+    let root = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let mut leafless = bytes.to_vec();
+    leafless[.. 4].copy_from_slice(&(root | 1 << 8).to_le_bytes());
+    assert!(matches!(darts::from_bytes::<u32>(&leafless), Err(Error::Decode(DecodeError::Invalid { .. }))));
+
+    // A transition on "a" leading back to its own state.
+    let mut cyclic = vec![0u8; 1024];
+    cyclic[.. 4].copy_from_slice(&(1u32 << 10).to_le_bytes());
+    cyclic[96 * 4 .. 97 * 4].copy_from_slice(&(97u32 << 10 | 97).to_le_bytes());
+    assert!(matches!(darts::from_bytes::<u32>(&cyclic), Err(Error::Decode(DecodeError::Invalid { offset : 384, .. }))));
+}
+
+/// A reader yielding at most one byte at a time.
+struct Trickle<'a>(&'a [u8]);
+
+impl<'a> std::io::Read for Trickle<'a> {
+    fn read(&mut self, buf : &mut [u8]) -> std::io::Result<usize> {
+        match (self.0.split_first(), buf.first_mut()) {
+            (Some((&b, rest)), Some(slot)) => { *slot = b; self.0 = rest; Ok(1) },
+            _ => Ok(0)
+        }
+    }
+}
+
+#[test]
+fn fst_streams_round_trip() {
+    fn property(btree : BTreeMap<Vec<u8>, i32>) -> bool {
+        let fst : FST<u32, i32> = FST::from_iter(btree.iter().map(|(k, &v)| (k, v))).unwrap();
+        let mut written = Vec::new();
+        fst.write_to(&mut written).unwrap();
+        written == fst.to_bytes() && FST::read_from(Trickle(&written)).ok() == Some(fst)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, i32>) -> bool);
+
+    let fst : FST<u32, u16> = FST::from_iter(vec![("", 3), ("ab", 7), ("ac", 9), ("b", 1)]).unwrap();
+    let bytes = fst.to_bytes();
+    let [_, (_, stipe), _, _] = format::Header::decode(&bytes).unwrap().sections().unwrap();
+    let cut = format::HEADER_WIDTH + stipe + 3;
+    match FST::<u32, u16>::read_from(Trickle(&bytes[.. cut])) {
+        Err(Error::Decode(e)) => {
+            assert_eq!(e, DecodeError::Truncated { section : "next", offset : cut });
+            assert_eq!(e.to_string(), format!("FST decoding error: truncated in next section at byte {}", cut));
+        },
+        other => panic!("expected truncation, got {:?}", other)
+    }
+
+    // The reader is left after the FST.
+    let mut followed = bytes.clone();
+    followed.extend_from_slice(b"rest");
+    let mut reader = followed.as_slice();
+    assert_eq!(FST::<u32, u16>::read_from(&mut reader).ok(), Some(fst.clone()));
+    assert_eq!(reader, b"rest");
+
+    let path = std::env::temp_dir().join(format!("atlatl-stream-{}.fst", std::process::id()));
+    fst.write_to_path(&path).unwrap();
+    let read = FST::<u32, u16>::read_from_path(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.ok(), Some(fst));
+    assert!(matches!(FST::<u32, u16>::read_from_path(&path), Err(Error::Io(_))));
+}
+
+#[test]
+fn fst_paths_replace_atomically() {
+    let dir = std::env::temp_dir().join(format!("atlatl-paths-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let listing = || {
+        let mut names : Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        names.sort();
+        names
+    };
+
+    let first : FST<u32, u16> = FST::from_iter(vec![("a", 1), ("b", 2)]).unwrap();
+    let second : FST<u32, u16> = FST::from_iter(vec![("ab", 3), ("c", 4)]).unwrap();
+    let path = dir.join("dict.fst");
+    assert_eq!(first.write_to_path(&path).unwrap().written, first.to_bytes().len());
+    assert_eq!(FST::read_from_path(&path).ok(), Some(first.clone()));
+    second.write_to_path(&path).unwrap();
+    assert_eq!(FST::read_from_path(&path).ok(), Some(second.clone()));
+    assert_eq!(listing(), vec!["dict.fst"]);
+
+    // A failed write leaves what was there, and no temporary file.
+    std::fs::create_dir(dir.join("taken")).unwrap();
+    assert!(matches!(first.write_to_path(dir.join("taken")), Err(Error::Io(_))));
+    assert!(dir.join("taken").is_dir());
+    assert!(matches!(first.write_to_path(dir.join("absent").join("dict.fst")), Err(Error::Io(_))));
+    assert_eq!(listing(), vec!["dict.fst", "taken"]);
+
+    // I/O failures and decoding failures are told apart.
+    match FST::<u32, u16>::read_from_path(dir.join("absent.fst")) {
+        Err(e @ Error::Io(_)) => {
+            assert!(matches!(e, Error::Io(ref io) if io.kind() == std::io::ErrorKind::NotFound));
+            assert!(std::error::Error::source(&e).is_some());
+        },
+        other => panic!("expected an I/O error, found {:?}", other.map(|f| f.len()))
+    }
+    assert!(matches!(FST::<u32, u32>::read_from_path(&path), Err(Error::Decode(DecodeError::TypeMismatch { .. }))));
+    std::fs::write(&path, b"not an FST").unwrap();
+    assert!(matches!(FST::<u32, u16>::read_from_path(&path), Err(Error::Decode(DecodeError::Magic))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fst_compressed_bytes_round_trip() {
+    fn agrees<I, O>(btree : &BTreeMap<Vec<u8>, O>) -> bool
+        where I : atlatl::index::Index + format::Codec,
+              O : Output + format::Codec + PartialEq + std::fmt::Debug
+    {
+        let keys = btree.iter().map(|(k, &v)| (k.iter().map(|b| 1 + b % 4).collect::<Vec<u8>>(), v));
+        let fst : FST<I, O> = match FST::from_iter(keys.collect::<BTreeMap<_, _>>()) {
+            Ok(fst) => fst,
+            Err(_) => return true
+        };
+        let compressed = fst.to_compressed_bytes();
+        let mut written = Vec::new();
+        let stats = fst.write_compressed_to(&mut written).unwrap();
+        stats.written == compressed.len() && stats.uncompressed == fst.to_bytes().len()
+            && written == compressed
+            && format::Header::decode(&compressed).unwrap().is_compressed()
+            && FST::<I, O>::from_bytes(&compressed).ok() == Some(fst.clone())
+            && FST::<I, O>::read_from(Trickle(&compressed)).ok() == Some(fst)
+    }
+
+    fn property(btree : BTreeMap<Vec<u8>, u16>) -> bool {
+        let wide : BTreeMap<_, _> = btree.iter().map(|(k, &v)| (k.clone(), v as i64 - 300)).collect();
+        let long : BTreeMap<_, _> = btree.iter().map(|(k, &v)| (k.clone(), (v as u64) << 40)).collect();
+        let unit : BTreeMap<_, _> = btree.keys().map(|k| (k.clone(), ())).collect();
+        agrees::<u32, u16>(&btree) && agrees::<u16, i64>(&wide)
+            && agrees::<usize, u64>(&long) && agrees::<u32, ()>(&unit)
+    }
+
+    QuickCheck::new().tests(50).quickcheck(property as fn(BTreeMap<Vec<u8>, u16>) -> bool);
+
+    let words : BTreeMap<String, u16> =
+        (0 .. 2000u32).map(|i| (format!("{:05}", i * 7), (i % 50) as u16)).collect();
+    let fst = FST::<u32, u16>::try_from(&words).unwrap();
+    let bytes = fst.to_bytes();
+    let stats = fst.write_to(&mut Vec::new()).unwrap();
+    assert_eq!((stats.written, stats.uncompressed, stats.compression_ratio()), (bytes.len(), bytes.len(), 1.0));
+    let compressed = fst.to_compressed_bytes();
+    let stats = fst.write_compressed_to(&mut Vec::new()).unwrap();
+    assert!(stats.compression_ratio() > 1.0 && compressed.len() < bytes.len());
+
+    // Compressed sections cannot be viewed in place.
+    assert_eq!(FstRef::<u32, u16>::new(&compressed).err(), Some(DecodeError::Compressed));
+    assert_eq!(FstRef::<u32, u16>::new_unchecked(&compressed).err(), Some(DecodeError::Compressed));
+    assert_eq!(DecodeError::Compressed.to_string(), "FST decoding error: the FST is compressed, and must be decoded whole");
+
+    let [_, (next, _), _, _] = format::Header::decode(&compressed).unwrap().table;
+    let mut corrupt = compressed.clone();
+    corrupt[next] ^= 0x40;
+    assert_eq!(FST::<u32, u16>::from_bytes(&corrupt), Err(DecodeError::Checksum { section : "next" }));
+
+    let mut flagged = compressed.clone();
+    flagged[108] |= 0x2;
+    assert_eq!(FST::<u32, u16>::from_bytes(&flagged), Err(DecodeError::Invalid { section : "header", offset : 108 }));
+}
+
+#[test]
+fn output_overflow_is_detected() {
+    // Values at the bounds of their type are redistributed without overflow.
+    let unsigned = vec![("a", 255u8), ("ab", 255), ("abc", 0), ("b", 254), ("ba", 1)];
+    let fst : FST<u32, u8> = FST::from_iter(unsigned.clone()).unwrap();
+    assert!(unsigned.iter().all(|&(k, v)| fst.get(k) == Some(v) && fst.get_checked(k).unwrap() == Some(v)));
+
+    let signed = vec![("a", -128i8), ("ab", 127), ("abc", -128), ("b", -1), ("ba", 127)];
+    let fst : FST<u32, i8> = FST::from_iter(signed.clone()).unwrap();
+    assert!(signed.iter().all(|&(k, v)| fst.get(k) == Some(v) && fst.get_checked(k).unwrap() == Some(v)));
+
+    // A corrupted FST overflows when walked.
+    let mut corrupted : FST<u32, u8> = FST::from_iter(vec![("ab", 200)]).unwrap();
+    for o in corrupted.da.output.iter_mut() { *o = 200 }
+    match corrupted.get_checked("ab") {
+        Err(Error::OutputOverflow { key, .. }) => assert_eq!(key, b"ab"),
+        other => panic!("expected an overflow, got {:?}", other)
+    }
+    assert_eq!(corrupted.get_checked("ac").unwrap(), None);
+
+    // An output whose prefix exceeds its operands underflows in the builder.
+    #[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+    struct Greedy(u8);
+
+    impl Output for Greedy {
+        fn zero() -> Greedy { Greedy(0) }
+        fn mappend(self, y : Greedy) -> Greedy { Greedy(self.0.wrapping_add(y.0)) }
+        fn inverse(self, y : Greedy) -> Greedy { Greedy(self.0.wrapping_sub(y.0)) }
+        fn prefix(self, y : Greedy) -> Greedy { Greedy(std::cmp::max(self.0, y.0)) }
+        fn checked_mappend(self, y : Greedy) -> Option<Greedy> { self.0.checked_add(y.0).map(Greedy) }
+        fn checked_inverse(self, y : Greedy) -> Option<Greedy> { self.0.checked_sub(y.0).map(Greedy) }
+    }
+
+    let mut builder : Builder<u32, Greedy> = Builder::new();
+    builder.insert(b"a", Greedy(1)).unwrap();
+    match builder.insert(b"ab", Greedy(5)) {
+        Err(Error::OutputOverflow { key, .. }) => assert_eq!(key, b"ab"),
+        other => panic!("expected an overflow, got {:?}", other)
+    }
+}
+
+#[test]
+fn placement_widens_the_index_as_needed() {
+    // The builder's states fit u16 indices, but its Dart does not.
+    let pairs : BTreeMap<_, _> = (0 .. 28000u32).map(|i| (i.wrapping_mul(2_654_435_761).to_be_bytes(), i as u16)).collect();
+    let builder : Builder<u16, u16> = Builder::from_iter(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+    match FST::from_builder(&builder) {
+        Err(Error::OutOfBounds { reached, maximum, at_key : None }) => assert!(reached > maximum && maximum == 0xFFFF),
+        other => panic!("expected the Dart to outgrow u16, got {:?}", other.map(|fst| fst.len()))
+    }
+
+    // The failed placement leaves the builder as it was.
+    let wide = FST::from_builder_widening(&builder).unwrap();
+    assert_eq!(3, wide.index_width());
+    assert!(pairs.iter().all(|(k, &v)| wide.get(k) == Some(v) && wide.contains(k)));
+    assert!(!wide.contains([0u8; 5]) && !wide.is_empty());
+    let narrowest : FST<U24, u16> = FST::from_iter(pairs.iter().map(|(k, &v)| (k, v))).unwrap();
+    assert_eq!(WideFst::U24(narrowest), wide);
+
+    // A Dart which fits keeps the builder's index type.
+    let small : Builder<u16, u16> = Builder::from_iter(vec![("a", 1), ("b", 2)]).unwrap();
+    assert!(matches!(FST::from_builder_widening(&small), Ok(WideFst::U16(ref fst)) if fst.get("b") == Some(2)));
+    let small : Builder<u32, u16> = Builder::from_iter(vec![("a", 1), ("b", 2)]).unwrap();
+    assert_eq!(4, FST::from_builder_widening(&small).unwrap().index_width());
+}
+
+#[test]
+fn errors_carry_the_key_at_fault() {
+    // The builder outgrows u16 indices while registering the states left
+    // behind by a key.
+    let keys : Vec<[u8; 4]> = (0 .. 40_000u32).map(|i| i.wrapping_mul(2_654_435_761).to_be_bytes()).collect::<BTreeSet<_>>().into_iter().collect();
+    let mut builder : Builder<u16, u16> = Builder::new();
+    let (index, error) = keys.iter().enumerate()
+        .find_map(|(i, k)| builder.insert(k, 1).err().map(|e| (i, e)))
+        .unwrap();
+    match error {
+        Error::OutOfBounds { maximum, at_key : Some(ref key), .. } => assert_eq!((0xFFFF, &keys[index][..]), (maximum, &key[..])),
+        ref other => panic!("expected OutOfBounds at a key, got {:?}", other)
+    }
+    assert!(error.to_string().ends_with(&format!("The key being inserted was\n{:?}", keys[index])));
+
+    let duplicate = Builder::<u32, u16>::from_iter(vec![("a", 1), ("b", 2), ("b", 3)]).unwrap_err();
+    assert_eq!(duplicate.to_string(), "\
+FST construction error: the key
+b
+inserted at index 2 was already present.
+The value present is 2, and the one inserted 3. All keys must be unique.");
+    let disorder = Builder::<u32, u16>::from_iter(vec![("a", 1), ("c", 2), ("b", 3)]).unwrap_err();
+    assert!(disorder.to_string().starts_with("FST construction error: a key was inserted out of order, at index 2.\nThe lesser key\nb\n"));
+    assert_ne!(disorder, duplicate);
+
+    // The index of a key in a shard is that in the whole input.
+    #[cfg(feature = "rayon")]
+    {
+        let pairs = vec![("a", 1), ("b", 2), ("ba", 3), ("bb", 4), ("bb", 5)];
+        match Builder::<u32, u16>::par_from_iter(pairs) {
+            Err(Error::Duplicate { key, index, .. }) => assert_eq!((key, index), (b"bb".to_vec(), 4)),
+            other => panic!("expected a duplicate, got {:?}", other.map(|b| b.len()))
+        }
+    }
+}
+
+#[test]
+fn missing_state_outputs_are_not_found() {
+    // "a" ends in a state with an inner output, its greater extension
+    // taking the shared part.
+    let pairs = vec![("a", 5u32), ("ab", 2), ("abc", 9), ("b", 7)];
+    let fst : FST<u32, u32> = FST::from_iter(pairs.clone()).unwrap();
+    let state = fst.transition(0, b'a').unwrap();
+    assert!(state.terminal.is_inner());
+
+    let mut corrupted = fst.clone();
+    assert_eq!(Some(3), corrupted.state_output.remove(state.index));
+    let slot = 1 + b'a' as usize;
+    assert_eq!(corrupted.validate().unwrap_err().violations,
+               vec![Violation::MissingStateOutput { slot, state : state.index as usize }]);
+
+    // Lookups pass over the key, as does every traversal; only the checked
+    // lookup reports it.
+    assert_eq!((None, true), (corrupted.get("a"), corrupted.contains("a")));
+    match corrupted.get_checked("a") {
+        Err(Error::MissingStateOutput { key, state : s }) => assert_eq!((key, s), (b"a".to_vec(), state.index as usize)),
+        other => panic!("expected a missing state output, got {:?}", other)
+    }
+    assert!(pairs[1 ..].iter().all(|&(k, v)| corrupted.get(k) == Some(v) && corrupted.get_checked(k).unwrap() == Some(v)));
+    assert_eq!(vec![(2, 2), (3, 9)], corrupted.reap(b"abcd").collect::<Vec<_>>());
+    assert_eq!(vec![(b"ab".to_vec(), 2), (b"abc".to_vec(), 9), (b"b".to_vec(), 7)], corrupted.iter().collect::<Vec<_>>());
+    assert_eq!(3, corrupted.with_failure_links().find_overlapping(b"abc").count());
+    corrupted.is_minimal();
+    let view_bytes = corrupted.to_bytes();
+    let view : FstRef<u32, u32> = FstRef::new_unchecked(&view_bytes).unwrap();
+    assert_eq!((None, Some(9)), (view.get("a"), view.get("abc")));
+    assert_eq!(None, corrupted.freeze().get("a"));
+}